@@ -0,0 +1,52 @@
+// Compares the old write path, a fresh `String` per message, with the
+// pooled buffers the transports write responses and notifications into.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use test_mcp::buffer;
+use test_mcp::config::Config;
+use test_mcp::server::McpServer;
+use tokio::runtime::Runtime;
+
+const LIST: &str = r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#;
+const CALL: &str = r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"echo","arguments":{"message":"benchmark"}}}"#;
+
+// What a transport writes for `message`, as JSON
+fn messages() -> Vec<(&'static str, serde_json::Value)> {
+    let runtime = Runtime::new().unwrap();
+    let _guard = runtime.enter();
+    let server = McpServer::new(Config::default());
+    let (session, _outbound) = server.connect();
+    let mut messages = vec![(
+        "notification",
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/progress",
+            "params": { "progressToken": "bench", "progress": 1, "total": 10 }
+        }),
+    )];
+    for (name, request) in [("call", CALL), ("list", LIST)] {
+        let response = runtime.block_on(server.handle_message(&session, request));
+        messages.push((name, serde_json::to_value(response).unwrap()));
+    }
+    messages
+}
+
+fn write_line(c: &mut Criterion) {
+    for (name, message) in messages() {
+        let mut group = c.benchmark_group(format!("write_line/{}", name));
+        group.bench_function("to_string", |b| {
+            b.iter(|| {
+                let mut line = serde_json::to_string(black_box(&message)).unwrap();
+                line.push('\n');
+                line
+            })
+        });
+        group.bench_function("pooled", |b| {
+            b.iter(|| buffer::json_line(black_box(&message)).unwrap())
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, write_line);
+criterion_main!(benches);
@@ -0,0 +1,69 @@
+// Criterion benches for the three stages of a request: parsing the
+// envelope, dispatching through the server, and serializing the response.
+// `cargo bench` keeps a baseline to compare refactors against; the `bench`
+// subcommand measures the same stages with the production middleware.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use test_mcp::config::Config;
+use test_mcp::server::McpServer;
+use test_mcp::session::Session;
+use test_mcp::{buffer, envelope};
+use tokio::runtime::Runtime;
+
+const CALL: &str = r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"echo","arguments":{"message":"benchmark"}}}"#;
+const LIST: &str = r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#;
+const PING: &str = r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#;
+
+const INITIALIZE: &str = r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"protocolVersion":"2025-06-18","capabilities":{},"clientInfo":{"name":"bench","version":"0"}}}"#;
+const INITIALIZED: &str = r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#;
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, message) in [("ping", PING), ("list", LIST), ("call", CALL)] {
+        group.bench_function(name, |b| b.iter(|| envelope::parse(black_box(message))));
+    }
+    group.finish();
+}
+
+// An initialized session on a server with the built-in tools
+fn connect(runtime: &Runtime) -> (McpServer, Session) {
+    let _guard = runtime.enter();
+    let server = McpServer::new(Config::default());
+    let (session, mut outbound) = server.connect();
+    runtime.block_on(async {
+        server.handle_message(&session, INITIALIZE).await;
+        server.handle_message(&session, INITIALIZED).await;
+    });
+    // Notifications go nowhere, but must not pile up in the channel
+    runtime.spawn(async move { while outbound.recv().await.is_some() {} });
+    (server, session)
+}
+
+fn dispatch(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let (server, session) = connect(&runtime);
+    let mut group = c.benchmark_group("dispatch");
+    for (name, message) in [("ping", PING), ("list", LIST), ("call", CALL)] {
+        group.bench_function(name, |b| {
+            b.to_async(&runtime)
+                .iter(|| server.handle_message(&session, black_box(message)))
+        });
+    }
+    group.finish();
+}
+
+fn serialize(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let (server, session) = connect(&runtime);
+    let mut group = c.benchmark_group("serialize");
+    for (name, message) in [("ping", PING), ("list", LIST), ("call", CALL)] {
+        let response = runtime.block_on(server.handle_message(&session, message));
+        group.bench_function(name, |b| {
+            b.iter(|| buffer::json_line(black_box(&response)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, dispatch, serialize);
+criterion_main!(benches);
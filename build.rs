@@ -0,0 +1,38 @@
+use std::process::Command;
+
+// Embeds the commit being built as `GIT_COMMIT`, reported in serverInfo.
+// Builds from a source tarball can set `GIT_COMMIT` themselves.
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    let commit = std::env::var("GIT_COMMIT").ok().or_else(|| {
+        let output = Command::new("git")
+            .args(["rev-parse", "--short=12", "HEAD"])
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    });
+    if let Some(commit) = commit.filter(|commit| !commit.is_empty()) {
+        println!("cargo:rustc-env=GIT_COMMIT={}", commit);
+    }
+
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+// Generates the gRPC service from `proto/`. protox parses the files, so
+// building doesn't need `protoc` installed.
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    println!("cargo:rerun-if-changed=proto");
+    let descriptors = protox::compile(["tools.proto"], ["proto"]).expect("invalid proto");
+    tonic_prost_build::configure()
+        .build_client(false)
+        .compile_fds(descriptors)
+        .expect("failed to generate gRPC code");
+}
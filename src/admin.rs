@@ -0,0 +1,382 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::info;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::{reload, Registry};
+
+use crate::cache::ToolCache;
+use crate::error::ServerError;
+use crate::events::ServerEvent;
+use crate::server::McpServer;
+use crate::tools::RegisteredTool;
+use crate::types::{CallToolResult, Tool};
+
+// Group the admin tools are registered in, for restricting who gets them
+pub const ADMIN_GROUP: &str = "admin";
+
+// Failed tool calls kept for `admin_recent_errors`
+const MAX_RECENT_ERRORS: usize = 100;
+
+// Audit entries kept for the admin endpoint
+const MAX_AUDIT_ENTRIES: usize = 500;
+
+/// Changes the level of the process-wide log filter.
+pub type LogLevelHandle = reload::Handle<LevelFilter, Registry>;
+
+struct SessionStats {
+    opened: Instant,
+    tool_calls: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentError {
+    pub tool: String,
+    pub session_id: String,
+    pub error: String,
+    // Seconds since the Unix epoch
+    pub at: u64,
+}
+
+/// One line of the audit trail: a session coming or going, a tool call, or
+/// a tool being switched on or off.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub at: u64,
+    pub event: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The `admin_*` tools, for managing a running server through MCP itself:
+/// listing sessions, reviewing recent tool errors, switching tools on and
+/// off, flushing the tool cache and changing the log level. Session, error
+/// and audit data comes from the server's event bus, and also backs the
+/// admin HTTP endpoint.
+pub struct Admin {
+    server: Weak<McpServer>,
+    cache: Arc<ToolCache>,
+    log_level: LogLevelHandle,
+    sessions: Mutex<HashMap<String, SessionStats>>,
+    errors: Mutex<VecDeque<RecentError>>,
+    audit: Mutex<VecDeque<AuditEntry>>,
+}
+
+impl Admin {
+    pub fn new(server: &Arc<McpServer>, cache: Arc<ToolCache>, log_level: LogLevelHandle) -> Self {
+        Self {
+            server: Arc::downgrade(server),
+            cache,
+            log_level,
+            sessions: Mutex::new(HashMap::new()),
+            errors: Mutex::new(VecDeque::new()),
+            audit: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    // Keeps session and error records up to date, until the bus closes
+    pub async fn follow(self: Arc<Self>, mut events: broadcast::Receiver<ServerEvent>) {
+        loop {
+            match events.recv().await {
+                Ok(event) => self.record(event),
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    fn record(&self, event: ServerEvent) {
+        self.audit(&event);
+        match event {
+            ServerEvent::SessionOpened { session_id } => {
+                self.sessions.lock().unwrap().insert(
+                    session_id,
+                    SessionStats {
+                        opened: Instant::now(),
+                        tool_calls: 0,
+                    },
+                );
+            }
+            ServerEvent::SessionClosed { session_id } => {
+                self.sessions.lock().unwrap().remove(&session_id);
+            }
+            ServerEvent::ToolCallCompleted {
+                session_id,
+                tool,
+                error,
+                ..
+            } => {
+                if let Some(stats) = self.sessions.lock().unwrap().get_mut(&session_id) {
+                    stats.tool_calls += 1;
+                }
+                if let Some(error) = error {
+                    let mut errors = self.errors.lock().unwrap();
+                    if errors.len() == MAX_RECENT_ERRORS {
+                        errors.pop_front();
+                    }
+                    errors.push_back(RecentError {
+                        tool,
+                        session_id,
+                        error,
+                        at: unix_now(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn sessions(&self) -> serde_json::Value {
+        let sessions: Vec<serde_json::Value> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, stats)| {
+                serde_json::json!({
+                    "id": id,
+                    "connectedSecs": stats.opened.elapsed().as_secs(),
+                    "toolCalls": stats.tool_calls
+                })
+            })
+            .collect();
+        serde_json::json!({ "sessions": sessions })
+    }
+
+    pub fn recent_errors(&self) -> Vec<RecentError> {
+        self.errors.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn audit(&self, event: &ServerEvent) {
+        let mut entry = AuditEntry {
+            at: unix_now(),
+            event: "",
+            session_id: None,
+            tool: None,
+            duration_ms: None,
+            error: None,
+        };
+        match event {
+            ServerEvent::SessionOpened { session_id } => {
+                entry.event = "session_opened";
+                entry.session_id = Some(session_id.clone());
+            }
+            ServerEvent::SessionClosed { session_id } => {
+                entry.event = "session_closed";
+                entry.session_id = Some(session_id.clone());
+            }
+            ServerEvent::ToolToggled { name, enabled } => {
+                entry.event = if *enabled {
+                    "tool_enabled"
+                } else {
+                    "tool_disabled"
+                };
+                entry.tool = Some(name.clone());
+            }
+            ServerEvent::ToolCallCompleted {
+                session_id,
+                tool,
+                duration,
+                error,
+            } => {
+                entry.event = "tool_call";
+                entry.session_id = Some(session_id.clone());
+                entry.tool = Some(tool.clone());
+                entry.duration_ms = Some(duration.as_millis() as u64);
+                entry.error = error.clone();
+            }
+            _ => return,
+        }
+        let mut audit = self.audit.lock().unwrap();
+        if audit.len() == MAX_AUDIT_ENTRIES {
+            audit.pop_front();
+        }
+        audit.push_back(entry);
+    }
+
+    // The audit trail, oldest first
+    pub fn audit_entries(&self) -> Vec<AuditEntry> {
+        self.audit.lock().unwrap().iter().cloned().collect()
+    }
+
+    // The admin tools, all in the `admin` group
+    pub fn tools(self: &Arc<Self>) -> Vec<RegisteredTool> {
+        let admin = self.clone();
+        let sessions = admin_tool(
+            "admin_sessions",
+            "Active Sessions",
+            "Lists connected sessions with how long they have been connected and how many tools they called",
+            serde_json::json!({}),
+            &[],
+            move |_| {
+                let admin = admin.clone();
+                async move { Ok(CallToolResult::text(admin.sessions().to_string())) }
+            },
+        );
+
+        let admin = self.clone();
+        let errors = admin_tool(
+            "admin_recent_errors",
+            "Recent Errors",
+            "Lists the most recent failed tool calls, oldest first",
+            serde_json::json!({}),
+            &[],
+            move |_| {
+                let admin = admin.clone();
+                async move {
+                    let errors = admin.recent_errors();
+                    Ok(CallToolResult::text(serde_json::to_string(&errors)?))
+                }
+            },
+        );
+
+        let admin = self.clone();
+        let toggle = admin_tool(
+            "admin_toggle_tool",
+            "Toggle Tool",
+            "Enables or disables a tool for every session",
+            serde_json::json!({
+                "name": { "type": "string", "description": "Tool to change" },
+                "enabled": { "type": "boolean" }
+            }),
+            &["name", "enabled"],
+            move |arguments| {
+                let admin = admin.clone();
+                async move {
+                    let name = string_argument(&arguments, "name")?;
+                    let enabled = arguments
+                        .get("enabled")
+                        .and_then(|v| v.as_bool())
+                        .ok_or_else(|| invalid("Missing boolean argument: enabled"))?;
+                    if name.starts_with("admin_") {
+                        return Err(invalid("Admin tools can't be disabled").into());
+                    }
+                    let server = admin.server()?;
+                    if !server.set_tool_enabled(&name, enabled) {
+                        return Err(ServerError::ToolNotFound { tool: name }.into());
+                    }
+                    let state = if enabled { "enabled" } else { "disabled" };
+                    Ok(CallToolResult::text(format!("Tool {} is {}", name, state)))
+                }
+            },
+        );
+
+        let admin = self.clone();
+        let flush = admin_tool(
+            "admin_flush_cache",
+            "Flush Cache",
+            "Drops cached tool results, for one tool or for all",
+            serde_json::json!({
+                "tool": { "type": "string", "description": "Only flush this tool's results" }
+            }),
+            &[],
+            move |arguments| {
+                let admin = admin.clone();
+                async move {
+                    let tool = arguments.get("tool").and_then(|v| v.as_str());
+                    let flushed = admin.cache.invalidate(tool);
+                    Ok(CallToolResult::text(format!(
+                        "Flushed {} cached results",
+                        flushed
+                    )))
+                }
+            },
+        );
+
+        let admin = self.clone();
+        let log_level = admin_tool(
+            "admin_set_log_level",
+            "Set Log Level",
+            "Changes the server's log level",
+            serde_json::json!({
+                "level": {
+                    "type": "string",
+                    "enum": ["trace", "debug", "info", "warn", "error", "off"]
+                }
+            }),
+            &["level"],
+            move |arguments| {
+                let admin = admin.clone();
+                async move {
+                    let level: LevelFilter = string_argument(&arguments, "level")?
+                        .parse()
+                        .map_err(|_| invalid("Unknown log level"))?;
+                    // Logged first, or a stricter level would swallow it
+                    info!("Setting log level to {}", level);
+                    admin.log_level.reload(level)?;
+                    Ok(CallToolResult::text(format!("Log level is {}", level)))
+                }
+            },
+        );
+
+        vec![sessions, errors, toggle, flush, log_level]
+    }
+
+    fn server(&self) -> Result<Arc<McpServer>> {
+        self.server
+            .upgrade()
+            .ok_or_else(|| anyhow::anyhow!("Server is shutting down"))
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+fn admin_tool<F, Fut>(
+    name: &str,
+    title: &str,
+    description: &str,
+    properties: serde_json::Value,
+    required: &[&str],
+    handler: F,
+) -> RegisteredTool
+where
+    F: Fn(HashMap<String, serde_json::Value>) -> Fut + Send + Sync + 'static,
+    Fut: std::future::Future<Output = Result<CallToolResult>> + Send + 'static,
+{
+    let tool = Tool {
+        name: name.to_string(),
+        title: Some(title.to_string()),
+        description: description.to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": properties,
+            "required": required
+        }),
+        output_schema: None,
+        annotations: None,
+        icons: Vec::new(),
+        meta: None,
+    };
+    RegisteredTool::new(tool, move |arguments, _| handler(arguments)).in_group(ADMIN_GROUP)
+}
+
+fn string_argument(arguments: &HashMap<String, serde_json::Value>, name: &str) -> Result<String> {
+    arguments
+        .get(name)
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| invalid(&format!("Missing string argument: {}", name)).into())
+}
+
+fn invalid(details: &str) -> ServerError {
+    ServerError::InvalidParams {
+        details: details.to_string(),
+    }
+}
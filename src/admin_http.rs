@@ -0,0 +1,106 @@
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{info, warn};
+
+use crate::admin::Admin;
+use crate::auth;
+use crate::config::AdminEndpointConfig;
+use crate::http::{self, HttpRequest};
+use crate::listener;
+use crate::server::McpServer;
+
+/// Read-only JSON view of the running server for ops dashboards, on its own
+/// listener so it never mixes with MCP traffic. Every request needs
+/// `Authorization: Bearer <token>`.
+///
+/// - `GET /sessions`: connected sessions
+/// - `GET /requests`: requests being handled
+/// - `GET /registry`: registered tools, resources, templates and prompts
+/// - `GET /audit`: recent session, tool call and toggle events
+/// - `GET /`: all of the above
+pub struct AdminEndpoint {
+    server: Arc<McpServer>,
+    admin: Arc<Admin>,
+    token: String,
+}
+
+impl AdminEndpoint {
+    pub fn new(
+        server: Arc<McpServer>,
+        admin: Arc<Admin>,
+        config: &AdminEndpointConfig,
+    ) -> Result<Self> {
+        if config.token.is_empty() {
+            anyhow::bail!("admin_endpoint needs a token");
+        }
+        Ok(Self {
+            server,
+            admin,
+            token: config.token.clone(),
+        })
+    }
+
+    // Unlike the MCP transports, this doesn't announce its address on
+    // stdout, which may be carrying stdio MCP traffic
+    pub async fn serve(self: Arc<Self>, addr: &str) -> Result<()> {
+        let addr = listener::normalize(addr);
+        let listener = TcpListener::bind(&addr)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to bind admin endpoint to {}: {}", addr, e))?;
+        info!("Admin endpoint listening on {}", listener.local_addr()?);
+
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let endpoint = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = endpoint.handle_connection(stream, peer).await {
+                    warn!("Admin connection from {} failed: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    async fn handle_connection(&self, stream: TcpStream, peer: SocketAddr) -> Result<()> {
+        let mut stream = BufReader::new(stream);
+        let Some(request) =
+            http::read_request(&mut stream, peer, self.server.max_message_bytes()).await?
+        else {
+            return Ok(());
+        };
+
+        if !self.authorized(&request) {
+            warn!("Rejected unauthenticated admin request from {}", peer);
+            let headers = [("WWW-Authenticate", "Bearer")];
+            return http::write_response(&mut stream, "401 Unauthorized", &headers, "").await;
+        }
+        if request.method != "GET" {
+            return http::write_response(&mut stream, "405 Method Not Allowed", &[], "").await;
+        }
+
+        let path = request.path.split('?').next().unwrap_or_default();
+        let body = match path {
+            "/sessions" => self.admin.sessions(),
+            "/requests" => self.server.active_requests(),
+            "/registry" => self.server.registry(),
+            "/audit" => serde_json::json!({ "entries": self.admin.audit_entries() }),
+            "/" => serde_json::json!({
+                "sessions": self.admin.sessions()["sessions"],
+                "requests": self.server.active_requests()["requests"],
+                "registry": self.server.registry(),
+                "audit": self.admin.audit_entries(),
+            }),
+            _ => return http::write_response(&mut stream, "404 Not Found", &[], "").await,
+        };
+        let headers = [("Content-Type", "application/json")];
+        http::write_response(&mut stream, "200 OK", &headers, &body.to_string()).await
+    }
+
+    fn authorized(&self, request: &HttpRequest) -> bool {
+        request
+            .bearer_token()
+            .is_some_and(|token| auth::constant_time_eq(token, &self.token))
+    }
+}
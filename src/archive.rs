@@ -0,0 +1,384 @@
+use anyhow::Result;
+use flate2::read::GzDecoder;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::error::ServerError;
+use crate::msgpack::base64_encode;
+use crate::tools::RegisteredTool;
+use crate::types::{CallToolResult, Content, ResourceContents, Tool, ToolAnnotations};
+use crate::uri_template::percent_encode_path;
+
+// Entries listed per call; `prefix` narrows larger archives
+const MAX_LISTED: usize = 1000;
+const DEFAULT_MAX_BYTES: u64 = 100_000;
+const MAX_BYTES: u64 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl Format {
+    // Sniffed from the first bytes, since artifacts are often misnamed
+    fn detect(file: &mut File) -> Result<Self> {
+        let mut header = [0u8; 262];
+        let mut read = 0;
+        while read < header.len() {
+            match file.read(&mut header[read..])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        file.seek(SeekFrom::Start(0))?;
+        let header = &header[..read];
+        if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+            Ok(Format::Zip)
+        } else if header.starts_with(&[0x1f, 0x8b]) {
+            Ok(Format::TarGz)
+        } else if header.get(257..262) == Some(b"ustar") {
+            Ok(Format::Tar)
+        } else {
+            Err(invalid(
+                "The file isn't a zip, tar or tar.gz archive".to_string(),
+            ))
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Entry {
+    name: String,
+    // Uncompressed size in bytes
+    size: u64,
+    kind: &'static str,
+}
+
+/// The start of an entry's contents, cut at `max_bytes`.
+struct Extracted {
+    bytes: Vec<u8>,
+    size: u64,
+}
+
+fn invalid(details: String) -> anyhow::Error {
+    ServerError::InvalidParams { details }.into()
+}
+
+fn open(path: &Path) -> Result<(File, Format)> {
+    let mut file =
+        File::open(path).map_err(|e| invalid(format!("Can't open {}: {}", path.display(), e)))?;
+    let format = Format::detect(&mut file)?;
+    Ok((file, format))
+}
+
+fn tar_kind(entry_type: tar::EntryType) -> &'static str {
+    if entry_type.is_dir() {
+        "directory"
+    } else if entry_type.is_symlink() || entry_type.is_hard_link() {
+        "link"
+    } else if entry_type.is_file() {
+        "file"
+    } else {
+        "other"
+    }
+}
+
+// Calls `visit` with each tar entry until it returns false
+fn walk_tar<R: Read>(
+    reader: R,
+    mut visit: impl FnMut(&str, &mut tar::Entry<R>) -> Result<bool>,
+) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        if !visit(&name, &mut entry)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Entries whose names start with `prefix`, and how many there are in all
+fn list(path: &Path, prefix: &str) -> Result<(Vec<Entry>, usize)> {
+    let (file, format) = open(path)?;
+    let mut entries = Vec::new();
+    let mut total = 0;
+    let mut add = |entry: Entry| {
+        if entry.name.starts_with(prefix) {
+            total += 1;
+            if entries.len() < MAX_LISTED {
+                entries.push(entry);
+            }
+        }
+    };
+    match format {
+        Format::Zip => {
+            let mut archive = zip::ZipArchive::new(BufReader::new(file))?;
+            for index in 0..archive.len() {
+                // Raw access lists encrypted entries too
+                let file = archive.by_index_raw(index)?;
+                let kind = if file.is_dir() {
+                    "directory"
+                } else if file.is_symlink() {
+                    "link"
+                } else {
+                    "file"
+                };
+                add(Entry {
+                    name: file.name()?.into_owned(),
+                    size: file.size(),
+                    kind,
+                });
+            }
+        }
+        Format::Tar => walk_tar(BufReader::new(file), |name, entry| {
+            add(Entry {
+                name: name.to_string(),
+                size: entry.size(),
+                kind: tar_kind(entry.header().entry_type()),
+            });
+            Ok(true)
+        })?,
+        Format::TarGz => walk_tar(GzDecoder::new(BufReader::new(file)), |name, entry| {
+            add(Entry {
+                name: name.to_string(),
+                size: entry.size(),
+                kind: tar_kind(entry.header().entry_type()),
+            });
+            Ok(true)
+        })?,
+    }
+    Ok((entries, total))
+}
+
+// Reads at most `max_bytes` so a small archive can't expand into a huge
+// result
+fn read_limited(reader: impl Read, size: u64, max_bytes: u64) -> Result<Extracted> {
+    let mut bytes = Vec::new();
+    reader.take(max_bytes).read_to_end(&mut bytes)?;
+    Ok(Extracted { bytes, size })
+}
+
+fn extract(path: &Path, name: &str, max_bytes: u64) -> Result<Extracted> {
+    let (file, format) = open(path)?;
+    let not_found = || invalid(format!("No entry named '{}' in {}", name, path.display()));
+    let not_file = || invalid(format!("'{}' isn't a file", name));
+    let mut found = None;
+    let mut visit = |entry_name: &str, entry: &mut dyn TarEntry| {
+        if entry_name != name {
+            return Ok(true);
+        }
+        if !entry.is_file() {
+            return Err(not_file());
+        }
+        let size = entry.size();
+        found = Some(read_limited(entry.reader(), size, max_bytes)?);
+        Ok(false)
+    };
+    match format {
+        Format::Zip => {
+            let mut archive = zip::ZipArchive::new(BufReader::new(file))?;
+            let index = archive.index_for_name(name).ok_or_else(not_found)?;
+            let file = archive
+                .by_index(index)
+                .map_err(|e| invalid(format!("Can't read '{}': {}", name, e)))?;
+            if file.is_dir() {
+                return Err(not_file());
+            }
+            let size = file.size();
+            return read_limited(file, size, max_bytes);
+        }
+        Format::Tar => walk_tar(BufReader::new(file), |entry_name, entry| {
+            visit(entry_name, entry)
+        })?,
+        Format::TarGz => walk_tar(GzDecoder::new(BufReader::new(file)), |entry_name, entry| {
+            visit(entry_name, entry)
+        })?,
+    }
+    found.ok_or_else(not_found)
+}
+
+// What `extract` needs from a tar entry, whatever the reader beneath it
+trait TarEntry {
+    fn is_file(&self) -> bool;
+    fn size(&self) -> u64;
+    fn reader(&mut self) -> &mut dyn Read;
+}
+
+impl<R: Read> TarEntry for tar::Entry<'_, R> {
+    fn is_file(&self) -> bool {
+        self.header().entry_type().is_file()
+    }
+
+    fn size(&self) -> u64 {
+        tar::Entry::size(self)
+    }
+
+    fn reader(&mut self) -> &mut dyn Read {
+        self
+    }
+}
+
+fn listing_text(entries: &[Entry], total: usize) -> String {
+    let mut lines: Vec<String> = entries
+        .iter()
+        .map(|entry| match entry.kind {
+            "file" => format!("{:>10}  {}", entry.size, entry.name),
+            kind => format!("{:>10}  {}", format!("<{}>", kind), entry.name),
+        })
+        .collect();
+    if total > entries.len() {
+        lines.push(format!(
+            "Showing {} of {} entries; narrow with prefix",
+            entries.len(),
+            total
+        ));
+    }
+    lines.join("\n")
+}
+
+// Text entries come back as text and others as an embedded resource,
+// addressed `jar:file:///archive!/entry` as Java tooling does
+fn extracted_result(path: &Path, name: &str, extracted: Extracted) -> CallToolResult {
+    let shown = extracted.bytes.len() as u64;
+    let binary = |bytes: Vec<u8>| Content::Resource {
+        resource: ResourceContents {
+            uri: format!(
+                "jar:file://{}!/{}",
+                percent_encode_path(&path.to_string_lossy()),
+                percent_encode_path(name)
+            ),
+            mime_type: Some("application/octet-stream".to_string()),
+            text: None,
+            blob: Some(base64_encode(&bytes)),
+        },
+        annotations: None,
+    };
+    let content = match String::from_utf8(extracted.bytes) {
+        Ok(text) if !text.contains('\0') => Content::text(text),
+        Ok(text) => binary(text.into_bytes()),
+        // Cut off partway through a character by the limit
+        Err(e) if e.utf8_error().error_len().is_none() => {
+            let valid = e.utf8_error().valid_up_to();
+            let mut bytes = e.into_bytes();
+            bytes.truncate(valid);
+            Content::text(String::from_utf8_lossy(&bytes))
+        }
+        Err(e) => binary(e.into_bytes()),
+    };
+    let mut content = vec![content];
+    if shown < extracted.size {
+        content.push(Content::text(format!(
+            "Showing the first {} of {} bytes; raise max_bytes for more",
+            shown, extracted.size
+        )));
+    }
+    CallToolResult {
+        content,
+        is_error: false,
+        structured_content: None,
+        meta: None,
+    }
+}
+
+pub fn tool() -> RegisteredTool {
+    let tool = Tool {
+        name: "archive".to_string(),
+        title: Some("Archive".to_string()),
+        description:
+            "Lists the entries of a zip, tar or tar.gz file, or extracts one entry's contents"
+                .to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "operation": {
+                    "type": "string",
+                    "enum": ["list", "extract"]
+                },
+                "path": {
+                    "type": "string",
+                    "description": "The archive, which must be inside the allowed roots"
+                },
+                "entry": {
+                    "type": "string",
+                    "description": "extract: the entry's full name, as list shows it"
+                },
+                "prefix": {
+                    "type": "string",
+                    "description": "list: only entries whose names start with this"
+                },
+                "max_bytes": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": MAX_BYTES,
+                    "description": "extract: most bytes to return (default 100000)"
+                }
+            },
+            "required": ["operation", "path"]
+        }),
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(true),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    };
+
+    RegisteredTool::new(tool, |arguments, context| async move {
+        let operation = arguments
+            .get("operation")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'operation' argument"))?
+            .to_string();
+        let path = arguments
+            .get("path")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'path' argument"))?;
+        let path: PathBuf = context.roots.resolve_within(path).await?;
+        let entry = arguments
+            .get("entry")
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        let prefix = arguments
+            .get("prefix")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let max_bytes = arguments
+            .get("max_bytes")
+            .and_then(|v| v.as_u64())
+            .map_or(DEFAULT_MAX_BYTES, |max| max.clamp(1, MAX_BYTES));
+
+        // Decompression is CPU-bound and the reads block
+        tokio::task::spawn_blocking(move || match operation.as_str() {
+            "list" => {
+                let (entries, total) = list(&path, &prefix)?;
+                Ok(
+                    CallToolResult::text(listing_text(&entries, total)).with_structured_content(
+                        serde_json::json!({
+                            "entries": entries,
+                            "totalEntries": total,
+                            "truncated": total > entries.len()
+                        }),
+                    ),
+                )
+            }
+            "extract" => {
+                let name = entry.ok_or_else(|| invalid("extract needs 'entry'".to_string()))?;
+                let extracted = extract(&path, &name, max_bytes)?;
+                Ok(extracted_result(&path, &name, extracted))
+            }
+            other => Err(invalid(format!(
+                "Unknown operation '{}'; expected list or extract",
+                other
+            ))),
+        })
+        .await?
+    })
+}
@@ -0,0 +1,77 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// Who clients can authenticate as. Principals are what `tool_groups`,
+// `profile_principals`, `shutdown_principals` and rate limits refer to.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    // Principal name -> the bearer token that authenticates as it
+    pub principals: HashMap<String, Credential>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Credential {
+    pub token: Option<String>,
+    // Environment variable holding the token, to keep it out of the file
+    pub token_env: Option<String>,
+}
+
+impl Credential {
+    fn token(&self) -> Option<String> {
+        let from_env = || {
+            self.token_env
+                .as_deref()
+                .and_then(|name| std::env::var(name).ok())
+        };
+        self.token
+            .clone()
+            .or_else(from_env)
+            .filter(|token| !token.is_empty())
+    }
+}
+
+impl AuthConfig {
+    // Fails if a principal has no token to authenticate with
+    pub fn validate(&self) -> Result<()> {
+        for (principal, credential) in &self.principals {
+            if credential.token().is_none() {
+                anyhow::bail!(
+                    "auth principal '{}' needs a token, or a token_env that is set",
+                    principal
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn knows(&self, principal: &str) -> bool {
+        self.principals.contains_key(principal)
+    }
+
+    /// The principal `token` authenticates as, if any. Every token is
+    /// compared in full, so the time taken doesn't tell which one matched.
+    pub fn principal(&self, token: &str) -> Option<String> {
+        let mut found = None;
+        for (principal, credential) in &self.principals {
+            if credential
+                .token()
+                .is_some_and(|expected| constant_time_eq(token, &expected))
+            {
+                found = Some(principal.clone());
+            }
+        }
+        found
+    }
+}
+
+// Compares every byte so the time taken doesn't give a secret away
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
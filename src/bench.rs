@@ -0,0 +1,160 @@
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::buffer;
+use crate::envelope;
+use crate::server::McpServer;
+
+const DEFAULT_REQUESTS: usize = 10_000;
+
+// Time spent in each stage of one request
+#[derive(Default)]
+struct Timings {
+    parse: Vec<Duration>,
+    dispatch: Vec<Duration>,
+    serialize: Vec<Duration>,
+}
+
+/// `bench`: drives synthetic requests through the in-process server, with
+/// the same middleware as a real one, and prints throughput and latency
+/// percentiles for parsing, dispatch and serialization. Compare runs before
+/// and after a change to catch regressions.
+pub async fn run(args: &[String], server: Arc<McpServer>) -> Result<()> {
+    let requests = match crate::arg_value(args, "--requests")? {
+        Some(n) => n.parse()?,
+        None => DEFAULT_REQUESTS,
+    };
+    let concurrency: usize = match crate::arg_value(args, "--concurrency")? {
+        Some(n) => n.parse::<usize>()?.max(1),
+        None => 1,
+    };
+    let method = crate::arg_value(args, "--method")?.unwrap_or("call");
+    let request = match method {
+        "ping" => serde_json::json!({ "method": "ping" }),
+        "list" => serde_json::json!({ "method": "tools/list" }),
+        "call" => serde_json::json!({
+            "method": "tools/call",
+            "params": { "name": "echo", "arguments": { "message": "benchmark" } }
+        }),
+        _ => {
+            anyhow::bail!("Usage: bench [--requests N] [--concurrency N] [--method ping|list|call]")
+        }
+    };
+
+    let started = Instant::now();
+    let mut workers = Vec::new();
+    for worker in 0..concurrency {
+        // Spread the remainder over the first few sessions
+        let count = requests / concurrency + usize::from(worker < requests % concurrency);
+        workers.push(tokio::spawn(drive(server.clone(), request.clone(), count)));
+    }
+    let mut timings = Timings::default();
+    for worker in workers {
+        let done = worker.await??;
+        timings.parse.extend(done.parse);
+        timings.dispatch.extend(done.dispatch);
+        timings.serialize.extend(done.serialize);
+    }
+    let elapsed = started.elapsed();
+
+    println!(
+        "{} requests ({}) over {} sessions in {:.2?}: {:.0} requests/s",
+        requests,
+        request["method"].as_str().unwrap_or_default(),
+        concurrency,
+        elapsed,
+        requests as f64 / elapsed.as_secs_f64()
+    );
+    println!(
+        "{:<10} {:>10} {:>10} {:>10} {:>10}",
+        "", "p50", "p90", "p99", "max"
+    );
+    for (stage, samples) in [
+        ("parse", &mut timings.parse),
+        ("dispatch", &mut timings.dispatch),
+        ("serialize", &mut timings.serialize),
+    ] {
+        samples.sort();
+        println!(
+            "{:<10} {:>10} {:>10} {:>10} {:>10}",
+            stage,
+            format!("{:.1?}", percentile(samples, 50.0)),
+            format!("{:.1?}", percentile(samples, 90.0)),
+            format!("{:.1?}", percentile(samples, 99.0)),
+            format!("{:.1?}", samples.last().copied().unwrap_or_default()),
+        );
+    }
+    Ok(())
+}
+
+// One session sending `count` copies of `request`, one after another
+async fn drive(
+    server: Arc<McpServer>,
+    mut request: serde_json::Value,
+    count: usize,
+) -> Result<Timings> {
+    let (session, mut outbound) = server.connect();
+    // Notifications go nowhere, but must not pile up in the channel
+    tokio::spawn(async move { while outbound.recv().await.is_some() {} });
+
+    let initialize = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 0,
+        "method": "initialize",
+        "params": {
+            "protocolVersion": "2025-06-18",
+            "capabilities": {},
+            "clientInfo": { "name": "bench", "version": env!("CARGO_PKG_VERSION") }
+        }
+    });
+    server
+        .handle_message(&session, &initialize.to_string())
+        .await;
+    server
+        .handle_message(
+            &session,
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+        )
+        .await;
+
+    let mut timings = Timings::default();
+    request["jsonrpc"] = serde_json::json!("2.0");
+    for id in 1..=count {
+        request["id"] = serde_json::json!(id);
+        let message = request.to_string();
+
+        let started = Instant::now();
+        if envelope::parse(&message).is_err() {
+            anyhow::bail!("Can't parse {}", message);
+        }
+        timings.parse.push(started.elapsed());
+
+        // Parses again, as every transport does
+        let started = Instant::now();
+        let response = server.handle_message(&session, &message).await;
+        timings.dispatch.push(started.elapsed());
+        if let Some(error) = response
+            .as_ref()
+            .and_then(|response| response.error.as_ref())
+        {
+            anyhow::bail!("Request failed: {}", error.message);
+        }
+
+        let started = Instant::now();
+        // The transports' write path
+        drop(buffer::json_line(&response)?);
+        timings.serialize.push(started.elapsed());
+    }
+
+    server.disconnect(&session.id);
+    Ok(timings)
+}
+
+fn percentile(sorted: &[Duration], percent: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let index = ((sorted.len() - 1) as f64 * percent / 100.0).round() as usize;
+    sorted[index]
+}
@@ -0,0 +1,87 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::ops::Deref;
+use std::sync::Mutex;
+
+// Spare buffers kept for reuse; more than this are freed
+const MAX_POOLED: usize = 64;
+// Buffers that grew past this for one big message are freed rather than
+// pooled, so a single large result doesn't pin the memory
+const MAX_RETAINED_CAPACITY: usize = 64 * 1024;
+const INITIAL_CAPACITY: usize = 4 * 1024;
+
+static POOL: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+/// An outgoing message serialized into a pooled buffer. The buffer goes
+/// back to the pool when this is dropped, so writing responses and
+/// notifications doesn't allocate once the pool is warm. These are plain
+/// `Vec`s: serde_json writes in many small pieces, which a `Vec` takes about
+/// twice as fast as a `BytesMut`.
+pub struct Pooled(Vec<u8>);
+
+impl Pooled {
+    fn take() -> Self {
+        let buffer = POOL
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| Vec::with_capacity(INITIAL_CAPACITY));
+        Self(buffer)
+    }
+
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub fn push_json(&mut self, message: &impl Serialize) -> Result<()> {
+        serde_json::to_writer(&mut self.0, message)?;
+        Ok(())
+    }
+}
+
+impl Deref for Pooled {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for Pooled {
+    fn drop(&mut self) {
+        let mut buffer = std::mem::take(&mut self.0);
+        if buffer.capacity() > MAX_RETAINED_CAPACITY {
+            return;
+        }
+        buffer.clear();
+        let mut pool = POOL.lock().unwrap();
+        if pool.len() < MAX_POOLED {
+            pool.push(buffer);
+        }
+    }
+}
+
+// `message` as JSON
+pub fn json(message: &impl Serialize) -> Result<Pooled> {
+    let mut buffer = Pooled::take();
+    buffer.push_json(message)?;
+    Ok(buffer)
+}
+
+// `message` as JSON followed by a newline, for the line-delimited transports
+pub fn json_line(message: &impl Serialize) -> Result<Pooled> {
+    let mut buffer = json(message)?;
+    buffer.extend(b"\n");
+    Ok(buffer)
+}
+
+// An SSE event carrying `message` as its data
+pub fn sse_event(event_id: &str, message: &impl Serialize) -> Result<Pooled> {
+    let mut buffer = Pooled::take();
+    buffer.extend(b"id: ");
+    buffer.extend(event_id.as_bytes());
+    buffer.extend(b"\ndata: ");
+    buffer.push_json(message)?;
+    buffer.extend(b"\n\n");
+    Ok(buffer)
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+use crate::admin::ADMIN_GROUP;
+use crate::error::ServerError;
+use crate::middleware::{HandlerFuture, Layer, Next};
+use crate::session::Session;
+use crate::types::{JsonRpcRequest, McpResponse};
+
+// Expired entries are only swept once the cache grows past this
+const SWEEP_THRESHOLD: usize = 1024;
+
+/// Results of idempotent tools, keyed by tool name, profile and arguments.
+/// Only tools given a TTL are cached.
+pub struct ToolCache {
+    ttls: HashMap<String, Duration>,
+    entries: Mutex<HashMap<String, (Instant, serde_json::Value)>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+impl ToolCache {
+    pub fn new(ttls: HashMap<String, Duration>) -> Self {
+        Self {
+            ttls,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    // serde_json keeps object keys sorted, so serializing the arguments
+    // gives the same key regardless of the order the client sent them in.
+    fn key(tool: &str, scope: &str, arguments: &serde_json::Value) -> String {
+        format!("{}:{}:{}", tool, scope, arguments)
+    }
+
+    fn get(&self, key: &str) -> Option<serde_json::Value> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((expires, result)) if *expires > Instant::now() => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(result.clone())
+            }
+            Some(_) => {
+                entries.remove(key);
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    fn insert(&self, key: String, ttl: Duration, result: serde_json::Value) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= SWEEP_THRESHOLD {
+            let now = Instant::now();
+            entries.retain(|_, (expires, _)| *expires > now);
+        }
+        entries.insert(key, (Instant::now() + ttl, result));
+    }
+
+    // Drops cached results for one tool, or for all tools. Returns how many
+    // entries were removed.
+    pub fn invalidate(&self, tool: Option<&str>) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        match tool {
+            Some(tool) => {
+                let prefix = format!("{}:", tool);
+                entries.retain(|key, _| !key.starts_with(&prefix));
+            }
+            None => entries.clear(),
+        }
+        before - entries.len()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.lock().unwrap().len(),
+        }
+    }
+}
+
+/// Serves repeated `tools/call` requests for cached tools from the cache,
+/// once the server agrees the session may make the call. Also answers
+/// `cache/invalidate` (optional `tool` param) and `cache/stats` for sessions
+/// allowed to use the admin tools.
+pub struct CacheLayer {
+    cache: Arc<ToolCache>,
+}
+
+impl CacheLayer {
+    pub fn new(cache: Arc<ToolCache>) -> Self {
+        Self { cache }
+    }
+}
+
+impl Layer for CacheLayer {
+    fn call<'a>(
+        &'a self,
+        session: &'a Session,
+        request: JsonRpcRequest,
+        next: Next<'a>,
+    ) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let params = request.params.clone().unwrap_or_default();
+            let server = next.server();
+            match request.method.as_str() {
+                "cache/invalidate" | "cache/stats"
+                    if !server.group_allowed(session, ADMIN_GROUP) =>
+                {
+                    return Err(ServerError::MethodNotFound {
+                        method: request.method,
+                    }
+                    .into());
+                }
+                "cache/invalidate" => {
+                    let removed = self.cache.invalidate(params["tool"].as_str());
+                    info!("Invalidated {} cached tool results", removed);
+                    return Ok(Some(success(
+                        request,
+                        serde_json::json!({ "removed": removed }),
+                    )));
+                }
+                "cache/stats" => {
+                    let stats = serde_json::to_value(self.cache.stats())?;
+                    return Ok(Some(success(request, stats)));
+                }
+                "tools/call" => {}
+                _ => return next.run(session, request).await,
+            }
+
+            let Some(name) = params["name"].as_str() else {
+                return next.run(session, request).await;
+            };
+            // Calls the session can't make, or that need approval, are left
+            // to dispatch to refuse or hold. TTLs and keys go by the tool's
+            // own name, not the group-prefixed one clients call it by.
+            let Some((tool, scope)) = server.cache_scope(session, name) else {
+                return next.run(session, request).await;
+            };
+            let Some(ttl) = self.cache.ttls.get(&tool).copied() else {
+                return next.run(session, request).await;
+            };
+            let key = ToolCache::key(&tool, &scope, &params["arguments"]);
+            if let Some(result) = self.cache.get(&key) {
+                debug!("Cache hit for {}", tool);
+                return Ok(Some(success(request, result)));
+            }
+
+            let response = next.run(session, request).await?;
+            if let Some(result) = response.as_ref().and_then(|r| r.result.as_ref()) {
+                if result["isError"] != true {
+                    self.cache.insert(key, ttl, result.clone());
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+fn success(request: JsonRpcRequest, result: serde_json::Value) -> McpResponse {
+    McpResponse {
+        jsonrpc: "2.0".to_string(),
+        id: request.id,
+        result: Some(result),
+        error: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, ToolGroupsConfig};
+    use crate::server::McpServer;
+    use crate::tools::RegisteredTool;
+    use crate::types::{CallToolResult, Tool};
+
+    #[test]
+    fn invalidating_a_tool_leaves_tools_it_prefixes() {
+        let cache = ToolCache::new(HashMap::new());
+        let arguments = serde_json::json!({});
+        for tool in ["a", "ab"] {
+            let key = ToolCache::key(tool, "", &arguments);
+            cache.insert(key, Duration::from_secs(60), serde_json::json!({}));
+        }
+        assert_eq!(cache.invalidate(Some("a")), 1);
+        assert!(cache.get(&ToolCache::key("ab", "", &arguments)).is_some());
+    }
+
+    #[tokio::test]
+    async fn prefixed_names_get_the_tool_ttl() {
+        let config = Config {
+            tool_groups: ToolGroupsConfig {
+                prefix_names: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let ttls = HashMap::from([("count".to_string(), Duration::from_secs(60))]);
+        let cache = Arc::new(ToolCache::new(ttls));
+        let server = McpServer::new(config).layer(CacheLayer::new(cache.clone()));
+        let calls = Arc::new(AtomicU64::new(0));
+        let counted = calls.clone();
+        let tool = Tool {
+            name: "count".to_string(),
+            title: None,
+            description: "Counts its calls".to_string(),
+            input_schema: serde_json::json!({ "type": "object" }),
+            output_schema: None,
+            annotations: None,
+            icons: Vec::new(),
+            meta: None,
+        };
+        server.add_tool(
+            RegisteredTool::new(tool, move |_, _| {
+                let n = counted.fetch_add(1, Ordering::SeqCst) + 1;
+                async move { Ok(CallToolResult::text(n.to_string())) }
+            })
+            .in_group("math"),
+        );
+
+        let (session, _outbound) = server.connect();
+        let call = r#"{"jsonrpc": "2.0", "id": 1, "method": "tools/call",
+            "params": {"name": "math_count", "arguments": {}}}"#;
+        for _ in 0..2 {
+            server.handle_message(&session, call).await.unwrap();
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.invalidate(Some("count")), 1);
+    }
+}
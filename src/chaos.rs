@@ -0,0 +1,87 @@
+use serde::Deserialize;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+use tracing::debug;
+
+use crate::error::{ServerError, INTERNAL_ERROR};
+use crate::middleware::{HandlerFuture, Layer, Next};
+use crate::session::Session;
+use crate::types::JsonRpcRequest;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ChaosConfig {
+    // Also turned on with `--chaos`
+    pub enabled: bool,
+    // Each request is held for a random time in this range first
+    pub min_latency_ms: u64,
+    pub max_latency_ms: u64,
+    // Chance, from 0 to 1, that a notification to the client is lost
+    pub notification_drop_rate: f64,
+    // Chance, from 0 to 1, that a request fails with an internal error;
+    // `initialize` and notifications never do
+    pub error_rate: f64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_latency_ms: 0,
+            max_latency_ms: 500,
+            notification_drop_rate: 0.1,
+            error_rate: 0.05,
+        }
+    }
+}
+
+// From 0 up to 1, using the randomly keyed std hasher as a cheap source of
+// randomness
+pub fn random() -> f64 {
+    (RandomState::new().build_hasher().finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Makes the server unreliable on purpose: every request is delayed by a
+/// random amount, and some fail. Dropped notifications are handled by the
+/// sessions' notifiers; see `Session::set_notification_drop_rate`.
+pub struct ChaosLayer {
+    config: ChaosConfig,
+}
+
+impl ChaosLayer {
+    pub fn new(config: ChaosConfig) -> Self {
+        Self { config }
+    }
+
+    fn latency(&self) -> Duration {
+        let min = self.config.min_latency_ms;
+        let spread = self.config.max_latency_ms.saturating_sub(min);
+        Duration::from_millis(min + (random() * (spread + 1) as f64) as u64)
+    }
+}
+
+impl Layer for ChaosLayer {
+    fn call<'a>(
+        &'a self,
+        session: &'a Session,
+        request: JsonRpcRequest,
+        next: Next<'a>,
+    ) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let notification = request.id.is_none();
+            tokio::time::sleep(self.latency()).await;
+            if !notification && request.method != "initialize" && random() < self.config.error_rate
+            {
+                debug!("Chaos: failing {}", request.method);
+                return Err(ServerError::Injected {
+                    code: INTERNAL_ERROR,
+                    message: "Internal error: injected by chaos mode".to_string(),
+                    data: Some(serde_json::json!({ "chaos": true })),
+                }
+                .into());
+            }
+            next.run(session, request).await
+        })
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::notification::LogLevel;
+use crate::server::McpServer;
+
+// Log events waiting to be forwarded; more than this are dropped rather
+// than slowing down the code that logs them
+const CAPACITY: usize = 1024;
+
+pub struct LogRecord {
+    level: LogLevel,
+    logger: String,
+    data: serde_json::Value,
+}
+
+/// A tracing layer that copies the server's own log events (not those of
+/// its dependencies) into a channel. `forward` sends them on to clients as
+/// `notifications/message`, filtered by each client's `logging/setLevel`.
+pub struct ClientLogLayer {
+    records: mpsc::Sender<LogRecord>,
+}
+
+pub fn layer() -> (ClientLogLayer, mpsc::Receiver<LogRecord>) {
+    let (records, receiver) = mpsc::channel(CAPACITY);
+    (ClientLogLayer { records }, receiver)
+}
+
+pub async fn forward(server: Arc<McpServer>, mut records: mpsc::Receiver<LogRecord>) {
+    while let Some(record) = records.recv().await {
+        server.log_to_clients(record.level, &record.logger, &record.data);
+    }
+}
+
+impl<S: Subscriber> Layer<S> for ClientLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if !metadata.target().starts_with(env!("CARGO_CRATE_NAME")) {
+            return;
+        }
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let level = match *metadata.level() {
+            Level::ERROR => LogLevel::Error,
+            Level::WARN => LogLevel::Warning,
+            Level::INFO => LogLevel::Info,
+            Level::DEBUG | Level::TRACE => LogLevel::Debug,
+        };
+        let _ = self.records.try_send(LogRecord {
+            level,
+            logger: metadata.target().to_string(),
+            data: serde_json::Value::Object(fields.0),
+        });
+    }
+}
+
+// The event's message and any structured fields, as a JSON object
+#[derive(Default)]
+struct Fields(serde_json::Map<String, serde_json::Value>);
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
@@ -0,0 +1,352 @@
+use std::time::Duration;
+
+use crate::error::{ServerError, INTERNAL_ERROR};
+use crate::notification::{LogLevel, Notification};
+use crate::tools::RegisteredTool;
+use crate::types::{CallToolResult, Content, Tool, ToolAnnotations};
+
+const DEFAULT_PROGRESS_STEPS: u64 = 5;
+const MAX_PROGRESS_STEPS: u64 = 100;
+const DEFAULT_INTERVAL_MS: u64 = 100;
+const MAX_INTERVAL_MS: u64 = 10_000;
+const DEFAULT_SLEEP_MS: u64 = 5_000;
+const MAX_SLEEP_MS: u64 = 600_000;
+const DEFAULT_STEP_MS: u64 = 1_000;
+const MIN_STEP_MS: u64 = 10;
+const DEFAULT_OVERSIZED_BYTES: u64 = 5_000_000;
+const MAX_OVERSIZED_BYTES: u64 = 100_000_000;
+const DEFAULT_DELAY_MS: u64 = 10_000;
+
+/// Tools that make the server misbehave or talk on demand, for developing
+/// and testing MCP clients against it.
+pub fn tools() -> Vec<RegisteredTool> {
+    vec![trigger_notification(), sleep(), fail()]
+}
+
+fn invalid(details: String) -> anyhow::Error {
+    ServerError::InvalidParams { details }.into()
+}
+
+fn trigger_notification() -> RegisteredTool {
+    let tool = Tool {
+        name: "trigger_notification".to_string(),
+        title: Some("Trigger Notification".to_string()),
+        description: "Sends the calling client a notification of the chosen kind, for testing how it handles them".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "kind": {
+                    "type": "string",
+                    "enum": [
+                        "resource_updated",
+                        "tools_list_changed",
+                        "resources_list_changed",
+                        "prompts_list_changed",
+                        "log_message",
+                        "progress"
+                    ]
+                },
+                "uri": {
+                    "type": "string",
+                    "description": "resource_updated: the resource's URI (default test://resource)"
+                },
+                "level": {
+                    "type": "string",
+                    "enum": ["debug", "info", "notice", "warning", "error", "critical", "alert", "emergency"],
+                    "description": "log_message: the level (default info)"
+                },
+                "logger": {
+                    "type": "string",
+                    "description": "log_message: the logger name"
+                },
+                "data": {
+                    "description": "log_message: the message, any JSON (default a test string)"
+                },
+                "steps": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": MAX_PROGRESS_STEPS,
+                    "description": "progress: how many notifications to send (default 5)"
+                },
+                "interval_ms": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "maximum": MAX_INTERVAL_MS,
+                    "description": "progress: delay between notifications (default 100)"
+                }
+            },
+            "required": ["kind"]
+        }),
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(true),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    };
+
+    RegisteredTool::new(tool, |arguments, context| async move {
+        let kind = arguments
+            .get("kind")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'kind' argument"))?;
+        // Sent straight to this session, so they arrive even when the
+        // client's log level or initialization would otherwise hold them
+        let notification = match kind {
+            "resource_updated" => Notification::ResourceUpdated {
+                uri: arguments
+                    .get("uri")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("test://resource")
+                    .to_string(),
+            },
+            "tools_list_changed" => Notification::ToolListChanged { delta: None },
+            "resources_list_changed" => Notification::ResourceListChanged { delta: None },
+            "prompts_list_changed" => Notification::PromptListChanged { delta: None },
+            "log_message" => Notification::LogMessage {
+                level: match arguments.get("level") {
+                    None => LogLevel::Info,
+                    Some(level) => serde_json::from_value(level.clone())
+                        .map_err(|_| invalid(format!("Unknown log level {}", level)))?,
+                },
+                logger: arguments
+                    .get("logger")
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string),
+                data: arguments
+                    .get("data")
+                    .cloned()
+                    .unwrap_or_else(|| serde_json::json!("Test message from trigger_notification")),
+            },
+            "progress" => {
+                let progress_token = context.progress_token().cloned().ok_or_else(|| {
+                    invalid("progress needs a progressToken in the request's _meta".to_string())
+                })?;
+                let steps = arguments
+                    .get("steps")
+                    .and_then(|v| v.as_u64())
+                    .map_or(DEFAULT_PROGRESS_STEPS, |steps| {
+                        steps.clamp(1, MAX_PROGRESS_STEPS)
+                    });
+                let interval = Duration::from_millis(
+                    arguments
+                        .get("interval_ms")
+                        .and_then(|v| v.as_u64())
+                        .map_or(DEFAULT_INTERVAL_MS, |ms| ms.min(MAX_INTERVAL_MS)),
+                );
+                for step in 1..=steps {
+                    if step > 1 {
+                        tokio::time::sleep(interval).await;
+                    }
+                    context.notifier.notify(Notification::Progress {
+                        progress_token: progress_token.clone(),
+                        progress: step as f64,
+                        total: Some(steps as f64),
+                        message: Some(format!("Step {} of {}", step, steps)),
+                    })?;
+                }
+                return Ok(CallToolResult::text(format!(
+                    "Sent {} progress notifications",
+                    steps
+                )));
+            }
+            other => return Err(invalid(format!("Unknown notification kind '{}'", other))),
+        };
+        let method = notification.method();
+        context.notifier.notify(notification)?;
+        Ok(CallToolResult::text(format!("Sent {}", method)))
+    })
+}
+
+// Cancelling the request drops the call between steps, so it needs no
+// checks of its own
+fn sleep() -> RegisteredTool {
+    let tool = Tool {
+        name: "sleep".to_string(),
+        title: Some("Sleep".to_string()),
+        description: "Waits for a set time, reporting progress at each step, for testing client timeouts, progress and cancellation".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "duration_ms": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "maximum": MAX_SLEEP_MS,
+                    "description": "How long to wait (default 5000)"
+                },
+                "step_ms": {
+                    "type": "integer",
+                    "minimum": MIN_STEP_MS,
+                    "description": "Time between progress notifications (default 1000)"
+                }
+            }
+        }),
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(true),
+            idempotent_hint: Some(true),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    };
+
+    RegisteredTool::new(tool, |arguments, context| async move {
+        let duration = arguments
+            .get("duration_ms")
+            .and_then(|v| v.as_u64())
+            .map_or(DEFAULT_SLEEP_MS, |ms| ms.min(MAX_SLEEP_MS));
+        let step = arguments
+            .get("step_ms")
+            .and_then(|v| v.as_u64())
+            .map_or(DEFAULT_STEP_MS, |ms| ms.max(MIN_STEP_MS));
+        let mut slept = 0;
+        while slept < duration {
+            let next = step.min(duration - slept);
+            tokio::time::sleep(Duration::from_millis(next)).await;
+            slept += next;
+            context.report_progress(
+                slept as f64,
+                Some(duration as f64),
+                Some(format!("Slept {} of {} ms", slept, duration)),
+            )?;
+        }
+        Ok(CallToolResult::text(format!("Slept for {} ms", duration)))
+    })
+}
+
+fn fail() -> RegisteredTool {
+    let tool = Tool {
+        name: "fail".to_string(),
+        title: Some("Fail".to_string()),
+        description: "Fails on request in the chosen way, for hardening client error handling"
+            .to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "mode": {
+                    "type": "string",
+                    "enum": ["json_rpc_error", "tool_error", "oversized", "delayed", "malformed"],
+                    "description": "json_rpc_error: an error response; tool_error: an isError result; oversized: a huge result; delayed: a late result; malformed: odd-looking content"
+                },
+                "code": {
+                    "type": "integer",
+                    "description": "json_rpc_error: the error code (default -32603)"
+                },
+                "message": {
+                    "type": "string",
+                    "description": "json_rpc_error and tool_error: the error message"
+                },
+                "data": {
+                    "description": "json_rpc_error: the error's data, any JSON"
+                },
+                "size_bytes": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": MAX_OVERSIZED_BYTES,
+                    "description": "oversized: how large the text is (default 5000000)"
+                },
+                "delay_ms": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "maximum": MAX_SLEEP_MS,
+                    "description": "Wait this long before failing; for delayed, before succeeding (default 10000 for delayed, otherwise 0)"
+                },
+                "variant": {
+                    "type": "string",
+                    "enum": ["empty", "control_characters", "truncated_json", "bad_image"],
+                    "description": "malformed: no content blocks, text full of control and direction characters, text that is cut-off JSON, or an image that isn't valid base64 (default control_characters)"
+                }
+            },
+            "required": ["mode"]
+        }),
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(true),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    };
+
+    RegisteredTool::new(tool, |arguments, _| async move {
+        let mode = arguments
+            .get("mode")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'mode' argument"))?;
+        let message = arguments
+            .get("message")
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        let default_delay = if mode == "delayed" {
+            DEFAULT_DELAY_MS
+        } else {
+            0
+        };
+        let delay = arguments
+            .get("delay_ms")
+            .and_then(|v| v.as_u64())
+            .map_or(default_delay, |ms| ms.min(MAX_SLEEP_MS));
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+
+        let content = match mode {
+            "json_rpc_error" => {
+                return Err(ServerError::Injected {
+                    code: arguments
+                        .get("code")
+                        .and_then(|v| v.as_i64())
+                        .and_then(|code| i32::try_from(code).ok())
+                        .unwrap_or(INTERNAL_ERROR),
+                    message: message.unwrap_or_else(|| "Injected failure".to_string()),
+                    data: arguments.get("data").cloned(),
+                }
+                .into())
+            }
+            "tool_error" => {
+                let mut result = CallToolResult::text(
+                    message.unwrap_or_else(|| "Injected tool failure".to_string()),
+                );
+                result.is_error = true;
+                return Ok(result);
+            }
+            "oversized" => {
+                let size = arguments
+                    .get("size_bytes")
+                    .and_then(|v| v.as_u64())
+                    .map_or(DEFAULT_OVERSIZED_BYTES, |size| {
+                        size.clamp(1, MAX_OVERSIZED_BYTES)
+                    });
+                vec![Content::text("x".repeat(size as usize))]
+            }
+            "delayed" => vec![Content::text(format!("Answered after {} ms", delay))],
+            "malformed" => match arguments
+                .get("variant")
+                .and_then(|v| v.as_str())
+                .unwrap_or("control_characters")
+            {
+                "empty" => Vec::new(),
+                // NUL, ANSI escapes, a right-to-left override, zero-width
+                // characters and a byte order mark
+                "control_characters" => vec![Content::text(
+                    "\0null\u{1b}[31mred\u{1b}[0m \u{202e}desrever\u{202c} zero\u{200b}width\u{feff} bell\u{7}\r\nend",
+                )],
+                "truncated_json" => vec![Content::text(
+                    "{\"jsonrpc\": \"2.0\", \"result\": {\"items\": [1, 2, {\"name\": \"unterminated",
+                )],
+                "bad_image" => vec![Content::image(
+                    "this is not base64!",
+                    "image/x-unknown",
+                )],
+                other => return Err(invalid(format!("Unknown malformed variant '{}'", other))),
+            },
+            other => return Err(invalid(format!("Unknown mode '{}'", other))),
+        };
+        Ok(CallToolResult {
+            content,
+            is_error: false,
+            structured_content: None,
+            meta: None,
+        })
+    })
+}
@@ -0,0 +1,208 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::debug;
+
+use crate::middleware::{HandlerFuture, Layer, Next};
+use crate::session::Session;
+use crate::types::JsonRpcRequest;
+
+// Protocol versions this server can speak, oldest first
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2024-11-05", "2025-03-26", "2025-06-18"];
+pub const LATEST_PROTOCOL_VERSION: &str = "2025-06-18";
+
+// Versions are dates, so they compare correctly as strings
+const AUDIO_AND_ANNOTATIONS: &str = "2025-03-26";
+const TITLES_AND_STRUCTURED_CONTENT: &str = "2025-06-18";
+
+/// Picks the version to use with a client: the one it asked for if we
+/// support it, otherwise our latest, which the client may then reject.
+pub fn negotiate(requested: &str) -> &'static str {
+    SUPPORTED_PROTOCOL_VERSIONS
+        .iter()
+        .find(|version| **version == requested)
+        .copied()
+        .unwrap_or(LATEST_PROTOCOL_VERSION)
+}
+
+// Spellings older clients send, accepted whatever the config says
+const BUILTIN_INCOMING_ALIASES: &[(&str, &str)] = &[("initialized", "notifications/initialized")];
+
+/// Other names for notification methods, for clients written against
+/// something other than the spec. Incoming aliases are renamed to the spec's
+/// method before dispatch. Outgoing ones rename what the server sends to
+/// sessions on a given protocol version.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NotificationAliases {
+    // Alias -> spec method, on top of the built-in ones
+    pub incoming: HashMap<String, String>,
+    // Protocol version -> (spec method -> method to send instead)
+    pub outgoing: HashMap<String, HashMap<String, String>>,
+}
+
+impl NotificationAliases {
+    // The spec's name for an incoming method, if it is an alias
+    pub fn canonical(&self, method: &str) -> Option<&str> {
+        self.incoming.get(method).map(String::as_str).or_else(|| {
+            BUILTIN_INCOMING_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == method)
+                .map(|(_, canonical)| *canonical)
+        })
+    }
+
+    // The name to send `method` under to a session on `version`, if not the
+    // spec's
+    pub fn outgoing(&self, version: &str, method: &str) -> Option<&str> {
+        self.outgoing.get(version)?.get(method).map(String::as_str)
+    }
+}
+
+/// Which sessions get `apply_shims`: every one, or those whose
+/// `clientInfo.name` is listed.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ShimsConfig {
+    pub all: bool,
+    pub clients: Vec<String>,
+}
+
+impl ShimsConfig {
+    // Whether an `initialize` request comes from a listed client
+    pub fn matches(&self, initialize: &serde_json::Value) -> bool {
+        initialize["params"]["clientInfo"]["name"]
+            .as_str()
+            .is_some_and(|name| self.clients.iter().any(|client| client == name))
+    }
+}
+
+/// Fixes known client deviations in a raw message before it is validated
+/// and dispatched: a missing `"jsonrpc"`, a whole-number id sent as a float
+/// (`1.0`) and `tools/call` arguments sent as a JSON string.
+pub fn apply_shims(message: &mut serde_json::Value) {
+    let Some(object) = message.as_object_mut() else {
+        return;
+    };
+    if !object.contains_key("jsonrpc") {
+        debug!("Shim: added missing jsonrpc");
+        object.insert("jsonrpc".to_string(), serde_json::json!("2.0"));
+    }
+    if let Some(id) = object.get_mut("id") {
+        let whole = id
+            .as_f64()
+            .filter(|n| id.is_f64() && n.fract() == 0.0 && n.abs() < i64::MAX as f64);
+        if let Some(n) = whole {
+            debug!("Shim: float id {} sent as an integer", id);
+            *id = serde_json::json!(n as i64);
+        }
+    }
+    if object.get("method").and_then(|m| m.as_str()) == Some("tools/call") {
+        let arguments = object
+            .get_mut("params")
+            .and_then(|params| params.get_mut("arguments"));
+        if let Some(arguments) = arguments {
+            let parsed = arguments
+                .as_str()
+                .and_then(|text| serde_json::from_str::<serde_json::Value>(text).ok())
+                .filter(|parsed| parsed.is_object());
+            if let Some(parsed) = parsed {
+                debug!("Shim: parsed tools/call arguments sent as a string");
+                *arguments = parsed;
+            }
+        }
+    }
+}
+
+/// Strips fields from results that the session's negotiated protocol
+/// version doesn't define, so handlers can always build the newest shapes.
+/// Sessions that haven't initialized yet get results unchanged.
+pub struct CompatLayer;
+
+impl Layer for CompatLayer {
+    fn call<'a>(
+        &'a self,
+        session: &'a Session,
+        request: JsonRpcRequest,
+        next: Next<'a>,
+    ) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let method = request.method.clone();
+            let mut response = next.run(session, request).await?;
+            if let (Some(version), Some(response)) = (session.protocol_version(), &mut response) {
+                if let Some(result) = response.result.as_mut() {
+                    adapt_result(&version, &method, result);
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+fn adapt_result(version: &str, method: &str, result: &mut serde_json::Value) {
+    let before = |introduced: &str| version < introduced;
+    let mut removed: Vec<&str> = Vec::new();
+    if before(AUDIO_AND_ANNOTATIONS) {
+        removed.push("annotations");
+    }
+    if before(TITLES_AND_STRUCTURED_CONTENT) {
+        removed.extend(["title", "outputSchema", "structuredContent"]);
+    }
+
+    let mut unsupported_content: Vec<&str> = Vec::new();
+    if before(AUDIO_AND_ANNOTATIONS) {
+        unsupported_content.push("audio");
+    }
+    if before(TITLES_AND_STRUCTURED_CONTENT) {
+        unsupported_content.push("resource_link");
+    }
+
+    match method {
+        "tools/list" => strip_each(&mut result["tools"], &removed),
+        "resources/list" => strip_each(&mut result["resources"], &removed),
+        "resources/templates/list" => strip_each(&mut result["resourceTemplates"], &removed),
+        "prompts/list" => {
+            strip_each(&mut result["prompts"], &removed);
+            if let Some(prompts) = result["prompts"].as_array_mut() {
+                for prompt in prompts {
+                    strip_each(&mut prompt["arguments"], &removed);
+                }
+            }
+        }
+        "tools/call" => {
+            strip(result, &removed);
+            if let Some(content) = result["content"].as_array_mut() {
+                content.retain(|item| {
+                    !unsupported_content.contains(&item["type"].as_str().unwrap_or_default())
+                });
+                for item in content {
+                    strip(item, &removed);
+                }
+            }
+        }
+        "prompts/get" => {
+            if let Some(messages) = result["messages"].as_array_mut() {
+                messages.retain(|message| {
+                    !unsupported_content
+                        .contains(&message["content"]["type"].as_str().unwrap_or_default())
+                });
+            }
+        }
+        _ => {}
+    }
+}
+
+fn strip(value: &mut serde_json::Value, fields: &[&str]) {
+    if let Some(object) = value.as_object_mut() {
+        for field in fields {
+            object.remove(*field);
+        }
+    }
+}
+
+fn strip_each(values: &mut serde_json::Value, fields: &[&str]) {
+    if let Some(values) = values.as_array_mut() {
+        for value in values {
+            strip(value, fields);
+        }
+    }
+}
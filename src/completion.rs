@@ -0,0 +1,110 @@
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::types::{Completion, Meta};
+
+// The most values one `completion/complete` response may carry
+pub const MAX_VALUES: usize = 100;
+
+pub type CompletionFuture = Pin<Box<dyn Future<Output = Result<Vec<String>>> + Send>>;
+pub type CompletionHandler = Arc<dyn Fn(CompletionRequest) -> CompletionFuture + Send + Sync>;
+
+/// What a completion provider is asked: the partial value of one argument,
+/// along with the arguments the user has already filled in.
+#[derive(Debug, Clone)]
+pub struct CompletionRequest {
+    pub argument: String,
+    pub value: String,
+    pub arguments: HashMap<String, String>,
+    // The request's `_meta`, empty if the client sent none
+    pub meta: Meta,
+}
+
+pub fn handler<F, Fut>(provider: F) -> CompletionHandler
+where
+    F: Fn(CompletionRequest) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Vec<String>>> + Send + 'static,
+{
+    Arc::new(move |request| Box::pin(provider(request)))
+}
+
+/// Reuses a provider's candidates for `ttl`, for providers that enumerate
+/// something expensive such as a directory tree. Results are kept per set
+/// of other arguments, so the provider must return every candidate rather
+/// than only those matching the typed value; ranking filters them.
+pub fn cached(ttl: Duration, provider: CompletionHandler) -> CompletionHandler {
+    type Cache = HashMap<Vec<(String, String)>, (Instant, Vec<String>)>;
+    let cache: Arc<Mutex<Cache>> = Arc::new(Mutex::new(HashMap::new()));
+    Arc::new(move |request| {
+        let mut key: Vec<(String, String)> = request.arguments.clone().into_iter().collect();
+        key.sort();
+        if let Some((fetched, candidates)) = cache.lock().unwrap().get(&key) {
+            if fetched.elapsed() < ttl {
+                let candidates = candidates.clone();
+                return Box::pin(async move { Ok(candidates) });
+            }
+        }
+
+        let cache = cache.clone();
+        let provider = provider.clone();
+        Box::pin(async move {
+            let candidates = provider(request).await?;
+            let mut cache = cache.lock().unwrap();
+            cache.retain(|_, (fetched, _)| fetched.elapsed() < ttl);
+            cache.insert(key, (Instant::now(), candidates.clone()));
+            Ok(candidates)
+        })
+    })
+}
+
+// For arguments nobody provides completions for
+pub fn empty() -> Completion {
+    Completion {
+        values: Vec::new(),
+        total: None,
+        has_more: false,
+    }
+}
+
+/// Orders a provider's candidates by how well they match what was typed:
+/// prefix matches first, case-sensitive ones ahead of the rest, then
+/// candidates that merely contain it. Ties keep the provider's order.
+/// Non-matching candidates and duplicates are dropped, and at most
+/// `MAX_VALUES` are returned.
+pub fn rank(candidates: Vec<String>, value: &str) -> Completion {
+    let typed = value.to_lowercase();
+    let mut seen = HashSet::new();
+    let mut ranked: Vec<(u8, String)> = candidates
+        .into_iter()
+        .filter(|candidate| seen.insert(candidate.clone()))
+        .filter_map(|candidate| {
+            let lowered = candidate.to_lowercase();
+            let rank = if candidate.starts_with(value) {
+                0
+            } else if lowered.starts_with(&typed) {
+                1
+            } else if lowered.contains(&typed) {
+                2
+            } else {
+                return None;
+            };
+            Some((rank, candidate))
+        })
+        .collect();
+    ranked.sort_by_key(|(rank, _)| *rank);
+
+    let total = ranked.len();
+    Completion {
+        values: ranked
+            .into_iter()
+            .take(MAX_VALUES)
+            .map(|(_, candidate)| candidate)
+            .collect(),
+        total: Some(total),
+        has_more: total > MAX_VALUES,
+    }
+}
@@ -0,0 +1,52 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{self, Write};
+
+// zstd's default level: fast, and still smaller than gzip on JSON
+const ZSTD_LEVEL: i32 = 3;
+
+/// Content codings the server can produce, in order of preference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    pub fn encode(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Zstd => zstd::encode_all(data, ZSTD_LEVEL),
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// Picks an encoding from an `Accept-Encoding` header, honouring `q=0`.
+pub fn negotiate(accept_encoding: Option<&str>) -> Option<Encoding> {
+    let accept_encoding = accept_encoding?;
+    let accepts = |coding: &str| {
+        accept_encoding.split(',').any(|entry| {
+            let mut parts = entry.split(';');
+            let name = parts.next().unwrap_or_default().trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (name.eq_ignore_ascii_case(coding) || name == "*") && quality > 0.0
+        })
+    };
+    [Encoding::Zstd, Encoding::Gzip]
+        .into_iter()
+        .find(|encoding| accepts(encoding.name()))
+}
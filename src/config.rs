@@ -0,0 +1,360 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::auth::AuthConfig;
+use crate::chaos::ChaosConfig;
+use crate::compat::{NotificationAliases, ShimsConfig};
+use crate::confirm::ConfirmationConfig;
+use crate::files::FilesConfig;
+use crate::kv::KvConfig;
+use crate::locale::Translations;
+use crate::priority::Priority;
+use crate::proxy::ImportConfig;
+use crate::ratelimit::RateLimitConfig;
+use crate::retrieval::RetrievalConfig;
+use crate::roots::RootsConfig;
+use crate::sampling::SamplingConfig;
+use crate::telemetry::TelemetryConfig;
+use crate::transform::OutputTransform;
+use crate::translation::TranslationConfig;
+use crate::vector_search::VectorSearchConfig;
+use crate::weather::WeatherConfig;
+
+/// Server settings, loaded from the JSON file given with `--config`.
+/// Every field is optional and falls back to its default.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // Reject anything that isn't exactly to spec instead of doing our best
+    pub strict: bool,
+    // Random latency, errors and lost notifications; see `ChaosConfig`
+    pub chaos: ChaosConfig,
+    // Directory of `.md`/`.txt` prompt templates to load at startup
+    pub prompt_dir: Option<PathBuf>,
+    // Tools whose results may be cached, with the TTL in seconds
+    pub tool_cache: HashMap<String, u64>,
+    // Convert mistyped tool arguments (e.g. "5" for a number) before
+    // validating them against the tool's input schema
+    pub coerce_arguments: bool,
+    // Leave deprecated tools out of `tools/list`; they can still be called
+    pub hide_deprecated_tools: bool,
+    // Accept `resources/write` for resources that have a writer
+    pub writable_resources: bool,
+    // Register the `admin_*` tools; see `tool_groups.restricted`
+    pub admin_tools: bool,
+    // Serve the admin HTTP endpoint on its own listener
+    pub admin_endpoint: Option<AdminEndpointConfig>,
+    // `host:port` to serve the gRPC gateway on; needs the `grpc` feature
+    pub grpc_gateway: Option<String>,
+    // Serve documents from an SQLite database; needs the `knowledge-base`
+    // feature
+    pub knowledge_base: Option<KnowledgeBaseConfig>,
+    // Embed documents and offer `vector_search` over them
+    pub vector_search: Option<VectorSearchConfig>,
+    // Chunk documents and code for `retrieve_context`
+    pub retrieval: Option<RetrievalConfig>,
+    // Serve a directory's files as `file://` resources
+    pub files: Option<FilesConfig>,
+    // The service behind the `translate` tool
+    pub translation: Option<TranslationConfig>,
+    // Offer `geocode`, `current_weather` and `weather_forecast`
+    pub weather: Option<WeatherConfig>,
+    // Offer `kv_get`, `kv_set`, `kv_delete` and `kv_list` over a JSON file
+    pub kv: Option<KvConfig>,
+    // Offer the demo and client-testing tools, such as the per-session
+    // counter and todo list
+    pub demo_tools: bool,
+    // Opt-in per-tool usage reports, written locally or pushed
+    pub telemetry: Option<TelemetryConfig>,
+    // Manifests of tools served elsewhere, registered as proxies
+    pub imports: Vec<ImportConfig>,
+    // Accept `server/reload`, which re-scans `prompt_dir` like SIGUSR1
+    pub reload_method: bool,
+    // Accept `server/shutdown`, which drains and exits like SIGTERM
+    pub shutdown_method: bool,
+    // Authenticated principals allowed to call `server/shutdown`
+    pub shutdown_principals: Vec<String>,
+    // Bearer tokens clients authenticate with, by principal
+    pub auth: AuthConfig,
+    pub tool_groups: ToolGroupsConfig,
+    // Named sets of what a session is offered, for serving several teams
+    // from one process
+    pub profiles: HashMap<String, Profile>,
+    // Principal from `auth` -> profile name
+    pub profile_principals: HashMap<String, String>,
+    // Caps on simultaneous calls per tool, overriding limits set in code
+    pub tool_concurrency: HashMap<String, ConcurrencyLimit>,
+    // Post-processing for tool output, applied after any set in code
+    pub output_transforms: HashMap<String, Vec<OutputTransform>>,
+    pub retry: RetryConfig,
+    pub scheduling: SchedulingConfig,
+    // CPU-bound tool calls run at once on blocking threads (default: one
+    // per CPU)
+    pub cpu_workers: Option<usize>,
+    // Named request quotas per client; see `RateLimitConfig`
+    pub rate_limits: HashMap<String, RateLimitConfig>,
+    // How long list-changed notifications are held so that repeats within
+    // the window go out once, in milliseconds (default 50; 0 sends at once)
+    pub list_changed_delay_ms: Option<u64>,
+    // Other spellings of notification methods, in and out
+    pub notification_aliases: NotificationAliases,
+    // Clients whose known deviations from the spec are fixed up
+    pub client_shims: ShimsConfig,
+    // Who approves calls to destructive tools; unset runs them unasked
+    pub confirmation: Option<ConfirmationConfig>,
+    // How sampling requests from tools wait and fall back
+    pub sampling: SamplingConfig,
+    // Directories file tools are limited to, optionally narrowed to the
+    // client's roots
+    pub roots: RootsConfig,
+    // Longest accepted message on the stdio and TCP transports, in bytes
+    // (default 4 MiB). Longer lines are skipped with an error.
+    pub max_message_bytes: Option<usize>,
+    pub http: HttpConfig,
+    pub tcp: TcpConfig,
+    pub server_info: ServerInfoConfig,
+    // Usage guidance returned from initialize. `{{tools}}`, `{{resources}}`
+    // and `{{prompts}}` expand to lists of what is currently registered.
+    pub instructions: Option<String>,
+    // Refresh task name -> schedule, e.g. "*/5 * * * *" or "@every 30s"
+    pub schedules: HashMap<String, String>,
+    // Locale for descriptions when the client doesn't ask for one
+    pub locale: Option<String>,
+    // Translated descriptions, keyed by locale
+    pub translations: HashMap<String, Translations>,
+    // FNV-1a hash of the file this was loaded from, for telling deployments'
+    // configs apart; None when running on the defaults
+    #[serde(skip)]
+    pub checksum: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConcurrencyLimit {
+    pub max_concurrency: usize,
+    #[serde(default)]
+    pub on_limit: OnLimit,
+}
+
+// What happens to a call that arrives while a tool is at its limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnLimit {
+    #[default]
+    Queue,
+    Reject,
+}
+
+// Which tool groups are offered, and how their tools are named
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ToolGroupsConfig {
+    // Groups hidden from everyone
+    pub disabled: Vec<String>,
+    // Expose tools as `<group>_<name>`
+    pub prefix_names: bool,
+    // Groups each authenticated principal may use; principals not listed
+    // here get every enabled group
+    pub principals: HashMap<String, Vec<String>>,
+    // Groups only the listed principals get, e.g. {"admin": ["ops"]}
+    pub restricted: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct KnowledgeBaseConfig {
+    // Database file, created if missing
+    pub path: PathBuf,
+    // Directory of `.md`/`.txt` files stored at startup, replacing
+    // documents of the same name
+    #[serde(default)]
+    pub import_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AdminEndpointConfig {
+    // `host:port`, or a bare port for localhost
+    pub addr: String,
+    // Bearer token every request must carry
+    pub token: String,
+}
+
+// Overrides for the name and version reported in `serverInfo`, which
+// otherwise come from the package
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ServerInfoConfig {
+    pub name: Option<String>,
+    pub version: Option<String>,
+}
+
+// What sessions with this profile can see and use. A list that is left out
+// allows everything; entries ending in `*` match by prefix.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    // Tool names as clients see them, i.e. with any group prefix
+    pub tools: Option<Vec<String>>,
+    // Resource URIs and resource URI templates
+    pub resources: Option<Vec<String>>,
+    pub prompts: Option<Vec<String>>,
+}
+
+impl Profile {
+    pub fn allows(list: &Option<Vec<String>>, name: &str) -> bool {
+        let Some(list) = list else {
+            return true;
+        };
+        list.iter().any(|entry| match entry.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => entry == name,
+        })
+    }
+}
+
+// Where the HTTP transport serves its endpoints, for running behind a
+// gateway or reverse proxy
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    // Prefix for every path below, e.g. "/mcp/v1"
+    pub base_path: String,
+    // POST and DELETE
+    pub mcp_path: String,
+    // GET for the SSE stream; defaults to `mcp_path`
+    pub sse_path: Option<String>,
+    pub metrics_path: String,
+    // Extra MCP endpoints whose sessions get a profile, e.g.
+    // {"/team-a": "team-a"}; relative to `base_path`
+    pub profile_paths: HashMap<String, String>,
+    // Take the client address from `X-Forwarded-For`. Only enable this
+    // behind a proxy that sets it, or clients can claim any address.
+    pub trust_forwarded_headers: bool,
+    // Keep SSE events for replay in this SQLite database instead of in
+    // memory; needs the `sqlite-event-store` feature
+    pub event_store: Option<PathBuf>,
+    pub keep_alive: KeepAliveSettings,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            base_path: String::new(),
+            mcp_path: "/mcp".to_string(),
+            sse_path: None,
+            metrics_path: "/metrics".to_string(),
+            profile_paths: HashMap::new(),
+            trust_forwarded_headers: false,
+            event_store: None,
+            keep_alive: KeepAliveSettings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TcpConfig {
+    pub keep_alive: KeepAliveSettings,
+}
+
+// Keep-alive pings for one network transport; `--ping-interval` and
+// `--max-missed-pings` override them for the transport being served
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct KeepAliveSettings {
+    // 0 turns keep-alive off
+    pub ping_interval_secs: u64,
+    pub max_missed_pings: u32,
+}
+
+impl Default for KeepAliveSettings {
+    fn default() -> Self {
+        Self {
+            ping_interval_secs: 30,
+            max_missed_pings: 3,
+        }
+    }
+}
+
+// Caps how many requests are handled at once, across all sessions
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SchedulingConfig {
+    // Requests handled at once; unset means no limit
+    pub workers: Option<usize>,
+    // Tool name -> priority of its calls (default `low`)
+    pub tool_priorities: HashMap<String, Priority>,
+}
+
+// Retries for tool calls that fail with a retryable error
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    // Total attempts, including the first
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay_ms: 100,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read config {}: {}", path.display(), e))?;
+        let mut config: Config = serde_json::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))?;
+        let referenced = config
+            .profile_principals
+            .values()
+            .chain(config.http.profile_paths.values());
+        for profile in referenced {
+            if !config.profiles.contains_key(profile) {
+                anyhow::bail!(
+                    "Invalid config {}: unknown profile '{}'",
+                    path.display(),
+                    profile
+                );
+            }
+        }
+        config
+            .auth
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))?;
+        // A principal that can't authenticate would never get what it's given
+        let groups = &config.tool_groups;
+        let named = groups
+            .principals
+            .keys()
+            .chain(groups.restricted.values().flatten())
+            .chain(&config.shutdown_principals)
+            .chain(config.profile_principals.keys());
+        for principal in named {
+            if !config.auth.knows(principal) {
+                anyhow::bail!(
+                    "Invalid config {}: principal '{}' is not in auth.principals",
+                    path.display(),
+                    principal
+                );
+            }
+        }
+        config.checksum = Some(checksum(contents.as_bytes()));
+        Ok(config)
+    }
+}
+
+// 64-bit FNV-1a, which is stable across builds unlike the std hasher. It
+// tells configs apart but is no protection against deliberate collisions.
+fn checksum(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("fnv1a64:{:016x}", hash)
+}
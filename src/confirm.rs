@@ -0,0 +1,201 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{BufRead, Write};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::http_client::{self, HttpUrl};
+use crate::session::Session;
+
+pub const DEFAULT_TIMEOUT_SECS: u64 = 60;
+
+pub type ConfirmFuture<'a> = Pin<Box<dyn Future<Output = Result<Decision>> + Send + 'a>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Approved,
+    Denied(String),
+}
+
+/// A tool call waiting for approval.
+pub struct ConfirmationRequest<'a> {
+    pub session: &'a Session,
+    pub tool: &'a str,
+    pub arguments: &'a HashMap<String, serde_json::Value>,
+    // How long the policy has before the call is denied
+    pub timeout: Duration,
+}
+
+impl ConfirmationRequest<'_> {
+    fn question(&self) -> String {
+        format!(
+            "Allow {} to run with {}?",
+            self.tool,
+            serde_json::json!(self.arguments)
+        )
+    }
+}
+
+/// Decides whether a call to a destructive tool may go ahead. An error, or
+/// no decision within the timeout, denies the call.
+pub trait ConfirmationPolicy: Send + Sync {
+    fn confirm<'a>(&'a self, request: &'a ConfirmationRequest<'a>) -> ConfirmFuture<'a>;
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyKind {
+    Elicitation,
+    Webhook,
+    Tty,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConfirmationConfig {
+    pub policy: PolicyKind,
+    // `http(s)://host:port/path` to POST calls to, for the webhook policy
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    // Calls not decided within this many seconds are denied
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    // Tools that need confirmation besides those annotated as destructive
+    #[serde(default)]
+    pub tools: Vec<String>,
+}
+
+fn default_timeout_secs() -> u64 {
+    DEFAULT_TIMEOUT_SECS
+}
+
+pub fn from_config(config: &ConfirmationConfig) -> Result<Arc<dyn ConfirmationPolicy>> {
+    Ok(match config.policy {
+        PolicyKind::Elicitation => Arc::new(Elicitation),
+        PolicyKind::Webhook => {
+            let url = config.webhook_url.as_deref().ok_or_else(|| {
+                anyhow::anyhow!("The webhook confirmation policy needs a webhook_url")
+            })?;
+            Arc::new(Webhook::new(url)?)
+        }
+        PolicyKind::Tty => Arc::new(Tty::default()),
+    })
+}
+
+/// Asks the calling client with `elicitation/create`, so its user can
+/// approve or decline. Clients without elicitation support can't approve
+/// anything.
+pub struct Elicitation;
+
+impl ConfirmationPolicy for Elicitation {
+    fn confirm<'a>(&'a self, request: &'a ConfirmationRequest<'a>) -> ConfirmFuture<'a> {
+        Box::pin(async move {
+            if !request.session.client_supports_elicitation() {
+                return Ok(Decision::Denied(
+                    "the client doesn't support elicitation".to_string(),
+                ));
+            }
+            let params = serde_json::json!({
+                "message": request.question(),
+                "requestedSchema": {
+                    "type": "object",
+                    "properties": {
+                        "approve": {
+                            "type": "boolean",
+                            "title": "Approve",
+                            "description": format!("Run {}", request.tool)
+                        }
+                    },
+                    "required": ["approve"]
+                }
+            });
+            let response = request
+                .session
+                .request("elicitation/create", Some(params), request.timeout)
+                .await?;
+            if response["action"] == "accept" && response["content"]["approve"] == true {
+                return Ok(Decision::Approved);
+            }
+            Ok(Decision::Denied(match response["action"].as_str() {
+                Some("accept") | None => "the user didn't approve".to_string(),
+                Some(action) => format!("the user chose {}", action),
+            }))
+        })
+    }
+}
+
+/// POSTs each call as JSON (`tool`, `arguments`, `sessionId`, `principal`)
+/// to an approval service, which answers `{"approved": bool, "reason": ...}`.
+/// Plain HTTP only.
+pub struct Webhook {
+    url: HttpUrl,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self {
+            url: HttpUrl::parse(url)?,
+        })
+    }
+}
+
+impl ConfirmationPolicy for Webhook {
+    fn confirm<'a>(&'a self, request: &'a ConfirmationRequest<'a>) -> ConfirmFuture<'a> {
+        Box::pin(async move {
+            let body = serde_json::json!({
+                "tool": request.tool,
+                "arguments": request.arguments,
+                "sessionId": request.session.id,
+                "principal": request.session.principal(),
+            });
+            let answer = http_client::post_json(&self.url, &[], &body).await?;
+            if answer["approved"] == true {
+                return Ok(Decision::Approved);
+            }
+            Ok(Decision::Denied(
+                answer["reason"]
+                    .as_str()
+                    .unwrap_or("denied by the approval webhook")
+                    .to_string(),
+            ))
+        })
+    }
+}
+
+/// Asks on the terminal the server was started from, through `/dev/tty`
+/// so it works while stdin and stdout carry stdio MCP traffic. Prompts are
+/// shown one at a time.
+#[derive(Default)]
+pub struct Tty {
+    prompting: Arc<Mutex<()>>,
+}
+
+impl ConfirmationPolicy for Tty {
+    fn confirm<'a>(&'a self, request: &'a ConfirmationRequest<'a>) -> ConfirmFuture<'a> {
+        let question = request.question();
+        let prompting = self.prompting.clone();
+        Box::pin(async move {
+            // A prompt that times out keeps the terminal until it is
+            // answered; the answer is then ignored
+            let answer = tokio::task::spawn_blocking(move || -> Result<String> {
+                let _prompting = prompting.lock().unwrap();
+                let mut tty = std::fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open("/dev/tty")?;
+                write!(tty, "{} [y/N] ", question)?;
+                tty.flush()?;
+                let mut answer = String::new();
+                std::io::BufReader::new(tty).read_line(&mut answer)?;
+                Ok(answer)
+            })
+            .await??;
+            match answer.trim().to_lowercase().as_str() {
+                "y" | "yes" => Ok(Decision::Approved),
+                _ => Ok(Decision::Denied("declined at the terminal".to_string())),
+            }
+        })
+    }
+}
@@ -0,0 +1,203 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::server::McpServer;
+use crate::service;
+
+// How long in-flight tool calls get to finish once a stop is requested
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(8);
+
+// How long a `server/shutdown` response gets to reach the client
+const RESPONSE_GRACE: Duration = Duration::from_millis(100);
+
+// How long `stop` waits for the daemon to exit; longer than the drain
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Starts this binary again in the background with the same arguments minus
+/// `--daemon`, detached from the terminal, and returns once it's running.
+/// The child's output goes to `log_file`, or nowhere.
+#[cfg(unix)]
+pub fn spawn(args: &[String], log_file: Option<&str>) -> Result<u32> {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    let child_args: Vec<&String> = args.iter().filter(|arg| *arg != "--daemon").collect();
+    let output = || -> Result<Stdio> {
+        Ok(match log_file {
+            Some(path) => std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?
+                .into(),
+            None => Stdio::null(),
+        })
+    };
+    let child = Command::new(std::env::current_exe()?)
+        .args(child_args)
+        .stdin(Stdio::null())
+        .stdout(output()?)
+        .stderr(output()?)
+        // Its own process group, so Ctrl-C in the terminal doesn't reach it
+        .process_group(0)
+        .spawn()?;
+    Ok(child.id())
+}
+
+#[cfg(not(unix))]
+pub fn spawn(_args: &[String], _log_file: Option<&str>) -> Result<u32> {
+    anyhow::bail!("--daemon is only supported on Unix")
+}
+
+/// Writes our pid to `path`. Refuses to start if the file names a process
+/// that is still running.
+pub fn write_pid_file(path: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(path);
+    if let Some(pid) = read_pid(&path)? {
+        if is_running(pid) {
+            anyhow::bail!("Already running with pid {} ({})", pid, path.display());
+        }
+        warn!("Removing stale pid file {}", path.display());
+    }
+    std::fs::write(&path, format!("{}\n", std::process::id()))
+        .map_err(|e| anyhow::anyhow!("Failed to write pid file {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// Waits for a stop request (SIGTERM or Ctrl-C; on Windows also console
+/// close and shutdown, or the service being stopped; or `server/shutdown`),
+/// lets in-flight tool calls finish, removes the pid file and exits.
+pub async fn shutdown_on_signal(server: Arc<McpServer>, pid_file: Option<PathBuf>) {
+    tokio::select! {
+        _ = shutdown_signal() => {}
+        _ = service::stop_requested() => {}
+        _ = server.shutdown_requested() => {
+            // Give the response to `server/shutdown` time to go out
+            tokio::time::sleep(RESPONSE_GRACE).await;
+        }
+    }
+    info!("Shutting down");
+    service::report_stopping(DRAIN_TIMEOUT);
+    if !server.drain(DRAIN_TIMEOUT).await {
+        warn!("Tool calls still running after {:?}", DRAIN_TIMEOUT);
+    }
+    server.shutdown().await;
+    if let Some(path) = pid_file {
+        let _ = std::fs::remove_file(path);
+    }
+    service::report_stopped();
+    std::process::exit(0);
+}
+
+/// Reloads the registries from disk on every SIGUSR1.
+#[cfg(unix)]
+pub async fn reload_on_signal(server: Arc<McpServer>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut reload = match signal(SignalKind::user_defined1()) {
+        Ok(reload) => reload,
+        Err(e) => {
+            warn!("Can't listen for SIGUSR1: {}", e);
+            return;
+        }
+    };
+    while reload.recv().await.is_some() {
+        info!("Received SIGUSR1, reloading");
+        if let Err(e) = server.reload() {
+            warn!("Reload failed: {}", e);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn reload_on_signal(_server: Arc<McpServer>) {}
+
+#[cfg(unix)]
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            tokio::select! {
+                _ = terminate.recv() => {}
+                _ = tokio::signal::ctrl_c() => {}
+            }
+        }
+        Err(_) => {
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(windows)]
+async fn shutdown_signal() {
+    use tokio::signal::windows::{ctrl_break, ctrl_close, ctrl_shutdown};
+    let (Ok(mut close), Ok(mut shutdown), Ok(mut brk)) =
+        (ctrl_close(), ctrl_shutdown(), ctrl_break())
+    else {
+        let _ = tokio::signal::ctrl_c().await;
+        return;
+    };
+    tokio::select! {
+        _ = close.recv() => {}
+        _ = shutdown.recv() => {}
+        _ = brk.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+async fn shutdown_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Sends SIGTERM to the daemon named in the pid file and waits for it to exit.
+pub fn stop(path: &str) -> Result<()> {
+    let path = Path::new(path);
+    let pid =
+        read_pid(path)?.ok_or_else(|| anyhow::anyhow!("No pid file at {}", path.display()))?;
+    if !is_running(pid) {
+        let _ = std::fs::remove_file(path);
+        anyhow::bail!("Process {} is not running; removed stale pid file", pid);
+    }
+
+    kill(pid, "TERM")?;
+    let started = Instant::now();
+    while is_running(pid) {
+        if started.elapsed() > STOP_TIMEOUT {
+            anyhow::bail!("Process {} did not exit within {:?}", pid, STOP_TIMEOUT);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    info!("Stopped process {}", pid);
+    Ok(())
+}
+
+fn read_pid(path: &Path) -> Result<Option<u32>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => contents
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| anyhow::anyhow!("Invalid pid file {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// There's no libc binding here, so signals go through kill(1)
+fn kill(pid: u32, signal: &str) -> Result<()> {
+    let status = std::process::Command::new("kill")
+        .arg(format!("-{}", signal))
+        .arg(pid.to_string())
+        .stderr(std::process::Stdio::null())
+        .status()?;
+    if !status.success() {
+        anyhow::bail!("Failed to signal process {}", pid);
+    }
+    Ok(())
+}
+
+fn is_running(pid: u32) -> bool {
+    kill(pid, "0").is_ok()
+}
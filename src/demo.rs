@@ -0,0 +1,338 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+
+use crate::error::ServerError;
+use crate::tools::RegisteredTool;
+use crate::types::{CallToolResult, Tool, ToolAnnotations};
+
+// Todos a session can hold, so a looping client can't grow it forever
+const MAX_TODOS: usize = 1000;
+
+#[derive(Default)]
+struct Counter(AtomicI64);
+
+#[derive(Debug, Clone, Serialize)]
+struct Todo {
+    id: u64,
+    text: String,
+    done: bool,
+}
+
+#[derive(Default)]
+struct Todos {
+    items: Mutex<Vec<Todo>>,
+}
+
+/// Example tools whose state lives on the calling session, so each client
+/// sees its own counter and todo list and loses them when it disconnects.
+pub fn tools() -> Vec<RegisteredTool> {
+    vec![
+        counter_increment(),
+        counter_read(),
+        todo_add(),
+        todo_list(),
+        todo_complete(),
+    ]
+}
+
+fn definition(
+    name: &str,
+    title: &str,
+    description: &str,
+    input_schema: serde_json::Value,
+    read_only: bool,
+) -> Tool {
+    Tool {
+        name: name.to_string(),
+        title: Some(title.to_string()),
+        description: description.to_string(),
+        input_schema,
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(read_only),
+            destructive_hint: Some(false),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    }
+}
+
+fn counter_increment() -> RegisteredTool {
+    let tool = definition(
+        "counter_increment",
+        "Increment Counter",
+        "Adds to this session's counter and returns the new value",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "by": {
+                    "type": "integer",
+                    "description": "Amount to add, which may be negative (default 1)"
+                }
+            }
+        }),
+        false,
+    );
+    RegisteredTool::blocking(tool, |arguments, context| {
+        let by = arguments.get("by").and_then(|v| v.as_i64()).unwrap_or(1);
+        let counter = context.state.get::<Counter>();
+        let value = counter.0.fetch_add(by, Ordering::Relaxed).wrapping_add(by);
+        Ok(CallToolResult::text(value.to_string())
+            .with_structured_content(serde_json::json!({ "value": value })))
+    })
+}
+
+fn counter_read() -> RegisteredTool {
+    let tool = definition(
+        "counter_read",
+        "Read Counter",
+        "Returns this session's counter, which starts at 0",
+        serde_json::json!({ "type": "object", "properties": {} }),
+        true,
+    );
+    RegisteredTool::blocking(tool, |_, context| {
+        let value = context.state.get::<Counter>().0.load(Ordering::Relaxed);
+        Ok(CallToolResult::text(value.to_string())
+            .with_structured_content(serde_json::json!({ "value": value })))
+    })
+}
+
+fn todo_add() -> RegisteredTool {
+    let tool = definition(
+        "todo_add",
+        "Add Todo",
+        "Adds an item to this session's todo list and returns its id",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "text": { "type": "string", "description": "What needs doing" }
+            },
+            "required": ["text"]
+        }),
+        false,
+    );
+    RegisteredTool::blocking(tool, |arguments, context| {
+        let text = arguments
+            .get("text")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'text' argument"))?;
+        let todos = context.state.get::<Todos>();
+        let mut items = todos.items.lock().unwrap();
+        if items.len() >= MAX_TODOS {
+            return Err(ServerError::InvalidParams {
+                details: format!("The list is full at {} items", MAX_TODOS),
+            }
+            .into());
+        }
+        let todo = Todo {
+            id: items.last().map_or(1, |last| last.id + 1),
+            text: text.to_string(),
+            done: false,
+        };
+        let result = CallToolResult::text(format!("Added #{}", todo.id))
+            .with_structured_content(serde_json::to_value(&todo)?);
+        items.push(todo);
+        Ok(result)
+    })
+}
+
+fn todo_list() -> RegisteredTool {
+    let tool = definition(
+        "todo_list",
+        "List Todos",
+        "Lists this session's todo items in the order they were added",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "include_done": {
+                    "type": "boolean",
+                    "description": "Include completed items (default true)"
+                }
+            }
+        }),
+        true,
+    );
+    RegisteredTool::blocking(tool, |arguments, context| {
+        let include_done = arguments
+            .get("include_done")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true);
+        let todos = context.state.get::<Todos>();
+        let items: Vec<Todo> = todos
+            .items
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|todo| include_done || !todo.done)
+            .cloned()
+            .collect();
+        let text = if items.is_empty() {
+            "The todo list is empty".to_string()
+        } else {
+            items
+                .iter()
+                .map(|todo| {
+                    format!(
+                        "[{}] #{} {}",
+                        if todo.done { "x" } else { " " },
+                        todo.id,
+                        todo.text
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        Ok(CallToolResult::text(text)
+            .with_structured_content(serde_json::json!({ "items": items })))
+    })
+}
+
+fn todo_complete() -> RegisteredTool {
+    let tool = definition(
+        "todo_complete",
+        "Complete Todo",
+        "Marks one of this session's todo items as done",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer", "description": "The id todo_add returned" }
+            },
+            "required": ["id"]
+        }),
+        false,
+    );
+    RegisteredTool::blocking(tool, |arguments, context| {
+        let id = arguments
+            .get("id")
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'id' argument"))?;
+        let todos = context.state.get::<Todos>();
+        let mut items = todos.items.lock().unwrap();
+        let todo = items.iter_mut().find(|todo| todo.id == id).ok_or_else(|| {
+            ServerError::InvalidParams {
+                details: format!("There is no todo #{}", id),
+            }
+        })?;
+        todo.done = true;
+        Ok(CallToolResult::text(format!(
+            "Completed #{}: {}",
+            id, todo.text
+        )))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::server::McpServer;
+    use crate::session::Session;
+
+    fn server() -> McpServer {
+        let server = McpServer::new(Config::default());
+        for tool in tools() {
+            server.add_tool(tool);
+        }
+        server
+    }
+
+    // Calls `name` and returns its structured content, or the error text
+    async fn call(
+        server: &McpServer,
+        session: &Session,
+        name: &str,
+        arguments: serde_json::Value,
+    ) -> Result<serde_json::Value, String> {
+        let message = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": { "name": name, "arguments": arguments }
+        });
+        let response = server
+            .handle_message(session, &message.to_string())
+            .await
+            .expect("a response");
+        let response = serde_json::to_value(response).unwrap();
+        if let Some(error) = response.get("error") {
+            return Err(error["message"].to_string());
+        }
+        let result = &response["result"];
+        if result["isError"] == true {
+            return Err(result["content"][0]["text"].to_string());
+        }
+        Ok(result["structuredContent"].clone())
+    }
+
+    #[tokio::test]
+    async fn sessions_have_their_own_counter() {
+        let server = server();
+        let (first, _first_outbound) = server.connect();
+        let (second, _second_outbound) = server.connect();
+        let no_args = serde_json::json!({});
+
+        for _ in 0..3 {
+            call(&server, &first, "counter_increment", no_args.clone())
+                .await
+                .unwrap();
+        }
+        call(&server, &second, "counter_increment", no_args.clone())
+            .await
+            .unwrap();
+
+        let first_count = call(&server, &first, "counter_read", no_args.clone()).await;
+        let second_count = call(&server, &second, "counter_read", no_args).await;
+        assert_eq!(first_count.unwrap()["value"], 3);
+        assert_eq!(second_count.unwrap()["value"], 1);
+    }
+
+    #[tokio::test]
+    async fn sessions_have_their_own_todos() {
+        let server = server();
+        let (first, _first_outbound) = server.connect();
+        let (second, _second_outbound) = server.connect();
+
+        let added = call(
+            &server,
+            &first,
+            "todo_add",
+            serde_json::json!({ "text": "only mine" }),
+        )
+        .await
+        .unwrap();
+        let id = added["id"].clone();
+
+        let listed = call(&server, &second, "todo_list", serde_json::json!({})).await;
+        assert_eq!(listed.unwrap()["items"], serde_json::json!([]));
+        let completed = call(
+            &server,
+            &second,
+            "todo_complete",
+            serde_json::json!({ "id": id }),
+        )
+        .await;
+        assert!(completed.is_err(), "{:?}", completed);
+
+        let listed = call(&server, &first, "todo_list", serde_json::json!({})).await;
+        let items = listed.unwrap()["items"].clone();
+        assert_eq!(items.as_array().unwrap().len(), 1);
+        assert_eq!(items[0]["text"], "only mine");
+        assert_eq!(items[0]["done"], false);
+    }
+
+    #[tokio::test]
+    async fn a_new_session_starts_empty() {
+        let server = server();
+        let (first, _first_outbound) = server.connect();
+        call(&server, &first, "counter_increment", serde_json::json!({}))
+            .await
+            .unwrap();
+        drop(first);
+
+        let (second, _second_outbound) = server.connect();
+        let count = call(&server, &second, "counter_read", serde_json::json!({})).await;
+        assert_eq!(count.unwrap()["value"], 0);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::error::ServerError;
+use crate::types::{JsonRpcRequest, McpResponse, RequestId};
+
+/// A raw message from the client, sorted by what its JSON-RPC envelope says
+/// it is.
+pub enum Incoming {
+    // Has an id, so expects exactly one response
+    Request(JsonRpcRequest),
+    // No id; never answered, even when it fails
+    Notification(JsonRpcRequest),
+    // The client's answer to a request we sent
+    Response(serde_json::Value),
+}
+
+/// Parses a raw message, or returns the parse error to send back, with a
+/// `null` id since none can be read.
+pub fn parse(message: &str) -> Result<serde_json::Value, Box<McpResponse>> {
+    // simd-json parses in place, so it works on a copy. Anything it rejects
+    // goes through serde_json too, which has the final say and the error
+    // message clients expect.
+    #[cfg(feature = "simd-json")]
+    if let Ok(value) = simd_json::serde::from_slice(&mut message.as_bytes().to_vec()) {
+        return Ok(value);
+    }
+    serde_json::from_str(message).map_err(|e| {
+        Box::new(error_response(
+            None,
+            ServerError::ParseError {
+                details: e.to_string(),
+            },
+        ))
+    })
+}
+
+/// Sorts a parsed message into a request, notification or response. JSON
+/// that isn't a usable JSON-RPC message gets an invalid request error,
+/// carrying the message's id if it could be read and `null` otherwise.
+pub fn classify(message: serde_json::Value) -> Result<Incoming, Box<McpResponse>> {
+    let invalid = |id: Option<RequestId>, details: &str| {
+        Box::new(error_response(
+            id,
+            ServerError::InvalidRequest {
+                details: details.to_string(),
+            },
+        ))
+    };
+
+    let object = match &message {
+        serde_json::Value::Object(object) => object,
+        serde_json::Value::Array(_) => return Err(invalid(None, "Batches are not supported")),
+        _ => return Err(invalid(None, "Message must be a JSON object")),
+    };
+    let id = match object.get("id") {
+        None | Some(serde_json::Value::Null) => None,
+        Some(id) => match serde_json::from_value::<RequestId>(id.clone()) {
+            Ok(id) => Some(id),
+            Err(_) => return Err(invalid(None, "Field 'id' must be a string or an integer")),
+        },
+    };
+
+    match object.get("method") {
+        None if object.contains_key("result") || object.contains_key("error") => {
+            return Ok(Incoming::Response(message));
+        }
+        None => return Err(invalid(id, "Missing field 'method'")),
+        Some(serde_json::Value::String(_)) => {}
+        Some(_) => return Err(invalid(id, "Field 'method' must be a string")),
+    }
+    match object.get("params") {
+        None
+        | Some(serde_json::Value::Null)
+        | Some(serde_json::Value::Object(_))
+        | Some(serde_json::Value::Array(_)) => {}
+        Some(_) => return Err(invalid(id, "Field 'params' must be an object or an array")),
+    }
+
+    let request: JsonRpcRequest =
+        serde_json::from_value(message).map_err(|e| invalid(id.clone(), &e.to_string()))?;
+    Ok(match id {
+        Some(_) => Incoming::Request(request),
+        None => Incoming::Notification(request),
+    })
+}
+
+/// Whether a message can be handled while a request is still in flight:
+/// a response to a request the server sent, progress on one, or the
+/// client cancelling a request. Transports that handle requests one at a
+/// time let these through, so a request waiting on the client isn't stuck
+/// and one running long can be stopped.
+pub fn is_out_of_band(message: &serde_json::Value) -> bool {
+    match message.get("method") {
+        None => message.get("result").is_some() || message.get("error").is_some(),
+        Some(method) => method == "notifications/progress" || method == "notifications/cancelled",
+    }
+}
+
+/// The error response for `id`; `None` serializes as `null`, for when the
+/// id couldn't be determined.
+pub fn error_response(id: Option<RequestId>, error: ServerError) -> McpResponse {
+    McpResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: None,
+        error: Some(error.to_mcp_error()),
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+
+use crate::types::McpError;
+
+// JSON-RPC and MCP error codes
+pub const PARSE_ERROR: i32 = -32700;
+pub const INVALID_REQUEST: i32 = -32600;
+pub const METHOD_NOT_FOUND: i32 = -32601;
+pub const INVALID_PARAMS: i32 = -32602;
+pub const INTERNAL_ERROR: i32 = -32603;
+pub const RESOURCE_NOT_FOUND: i32 = -32002;
+pub const RATE_LIMITED: i32 = -32029;
+
+/// Errors the server reports back to clients. Handlers return these wrapped
+/// in `anyhow::Error`; anything else surfaces as an internal error.
+#[derive(Debug)]
+pub enum ServerError {
+    ParseError {
+        details: String,
+    },
+    InvalidRequest {
+        details: String,
+    },
+    MethodNotFound {
+        method: String,
+    },
+    InvalidParams {
+        details: String,
+    },
+    ToolNotFound {
+        tool: String,
+    },
+    InvalidArguments {
+        tool: String,
+        errors: Vec<String>,
+        coercions: Vec<String>,
+    },
+    ToolFailed {
+        tool: String,
+        details: String,
+        retryable: bool,
+    },
+    ResourceNotFound {
+        uri: String,
+    },
+    PromptNotFound {
+        prompt: String,
+    },
+    // A rate limit is used up until its window ends
+    RateLimited {
+        limit: String,
+        max: u32,
+        remaining: u32,
+        retry_after_ms: u64,
+    },
+    Internal {
+        details: String,
+    },
+    // Raised on purpose with any code, e.g. by the `fail` tool
+    Injected {
+        code: i32,
+        message: String,
+        data: Option<serde_json::Value>,
+    },
+}
+
+impl ServerError {
+    pub fn code(&self) -> i32 {
+        match self {
+            ServerError::ParseError { .. } => PARSE_ERROR,
+            ServerError::InvalidRequest { .. } => INVALID_REQUEST,
+            ServerError::MethodNotFound { .. } => METHOD_NOT_FOUND,
+            ServerError::InvalidParams { .. }
+            | ServerError::ToolNotFound { .. }
+            | ServerError::InvalidArguments { .. }
+            | ServerError::PromptNotFound { .. } => INVALID_PARAMS,
+            ServerError::ResourceNotFound { .. } => RESOURCE_NOT_FOUND,
+            ServerError::RateLimited { .. } => RATE_LIMITED,
+            ServerError::ToolFailed { .. } | ServerError::Internal { .. } => INTERNAL_ERROR,
+            ServerError::Injected { code, .. } => *code,
+        }
+    }
+
+    pub fn data(&self) -> Option<serde_json::Value> {
+        match self {
+            ServerError::ParseError { .. } | ServerError::Internal { .. } => None,
+            ServerError::InvalidRequest { details } | ServerError::InvalidParams { details } => {
+                Some(serde_json::json!({ "details": details }))
+            }
+            ServerError::MethodNotFound { method } => Some(serde_json::json!({ "method": method })),
+            ServerError::ToolNotFound { tool } => Some(serde_json::json!({ "tool": tool })),
+            ServerError::InvalidArguments {
+                tool,
+                errors,
+                coercions,
+            } => {
+                let mut data = serde_json::json!({ "tool": tool, "errors": errors });
+                if !coercions.is_empty() {
+                    data["coercions"] = serde_json::json!(coercions);
+                }
+                Some(data)
+            }
+            ServerError::ToolFailed {
+                tool,
+                details,
+                retryable,
+            } => Some(serde_json::json!({
+                "tool": tool,
+                "details": details,
+                "retryable": retryable
+            })),
+            ServerError::ResourceNotFound { uri } => Some(serde_json::json!({ "uri": uri })),
+            ServerError::PromptNotFound { prompt } => Some(serde_json::json!({ "prompt": prompt })),
+            ServerError::RateLimited {
+                limit,
+                max,
+                remaining,
+                retry_after_ms,
+            } => Some(serde_json::json!({
+                "limit": limit,
+                "max": max,
+                "remaining": remaining,
+                "retryAfterMs": retry_after_ms
+            })),
+            ServerError::Injected { data, .. } => data.clone(),
+        }
+    }
+
+    pub fn to_mcp_error(&self) -> McpError {
+        McpError {
+            code: self.code(),
+            message: self.to_string(),
+            data: self.data(),
+        }
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::ParseError { details } => write!(f, "Parse error: {}", details),
+            ServerError::InvalidRequest { details } => write!(f, "Invalid request: {}", details),
+            ServerError::MethodNotFound { method } => write!(f, "Method not found: {}", method),
+            ServerError::InvalidParams { details } => write!(f, "Invalid params: {}", details),
+            ServerError::ToolNotFound { tool } => write!(f, "Unknown tool: {}", tool),
+            ServerError::InvalidArguments { tool, errors, .. } => {
+                write!(f, "Invalid arguments for {}: {}", tool, errors.join("; "))
+            }
+            ServerError::ToolFailed { tool, details, .. } => {
+                write!(f, "Tool {} failed: {}", tool, details)
+            }
+            ServerError::ResourceNotFound { uri } => write!(f, "Resource not found: {}", uri),
+            ServerError::PromptNotFound { prompt } => write!(f, "Unknown prompt: {}", prompt),
+            ServerError::RateLimited {
+                limit,
+                retry_after_ms,
+                ..
+            } => write!(
+                f,
+                "Rate limit {} exceeded, retry in {}ms",
+                limit, retry_after_ms
+            ),
+            ServerError::Internal { details } => write!(f, "Internal error: {}", details),
+            ServerError::Injected { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+// Maps any handler error onto a JSON-RPC error object.
+pub fn to_mcp_error(error: &anyhow::Error) -> McpError {
+    match error.downcast_ref::<ServerError>() {
+        Some(server_error) => server_error.to_mcp_error(),
+        None => ServerError::Internal {
+            details: error.to_string(),
+        }
+        .to_mcp_error(),
+    }
+}
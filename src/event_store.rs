@@ -0,0 +1,95 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// Persists outbound SSE events so a client that reconnects with
+/// `Last-Event-ID` can be sent the messages it missed.
+pub trait EventStore: Send + Sync {
+    /// Stores a message for `stream_id` and returns the event id assigned to it.
+    fn store_event(&self, stream_id: &str, message: &serde_json::Value) -> String;
+
+    /// Returns the stream the event belongs to and every event stored after it,
+    /// or `None` if the event id is unknown (e.g. already evicted).
+    fn replay_events_after(
+        &self,
+        last_event_id: &str,
+    ) -> Option<(String, Vec<(String, serde_json::Value)>)>;
+
+    fn remove_stream(&self, stream_id: &str);
+}
+
+struct StoredStream {
+    next_seq: u64,
+    events: VecDeque<(u64, serde_json::Value)>,
+}
+
+pub struct InMemoryEventStore {
+    max_events_per_stream: usize,
+    streams: Mutex<HashMap<String, StoredStream>>,
+}
+
+impl InMemoryEventStore {
+    pub fn new(max_events_per_stream: usize) -> Self {
+        Self {
+            max_events_per_stream,
+            streams: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+// Event ids are "<stream id>/<sequence>" so the stream can be recovered from
+// the Last-Event-ID header alone.
+pub(crate) fn parse_event_id(event_id: &str) -> Option<(&str, u64)> {
+    let (stream_id, seq) = event_id.rsplit_once('/')?;
+    Some((stream_id, seq.parse().ok()?))
+}
+
+impl EventStore for InMemoryEventStore {
+    fn store_event(&self, stream_id: &str, message: &serde_json::Value) -> String {
+        let mut streams = self.streams.lock().unwrap();
+        let stream = streams
+            .entry(stream_id.to_string())
+            .or_insert_with(|| StoredStream {
+                next_seq: 0,
+                events: VecDeque::new(),
+            });
+
+        let seq = stream.next_seq;
+        stream.next_seq += 1;
+        stream.events.push_back((seq, message.clone()));
+        while stream.events.len() > self.max_events_per_stream {
+            stream.events.pop_front();
+        }
+
+        format!("{}/{}", stream_id, seq)
+    }
+
+    fn replay_events_after(
+        &self,
+        last_event_id: &str,
+    ) -> Option<(String, Vec<(String, serde_json::Value)>)> {
+        let (stream_id, last_seq) = parse_event_id(last_event_id)?;
+        let streams = self.streams.lock().unwrap();
+        let stream = streams.get(stream_id)?;
+
+        // If the requested event has already been evicted we can't guarantee
+        // a gap-free replay.
+        if let Some((oldest, _)) = stream.events.front() {
+            if last_seq + 1 < *oldest {
+                return None;
+            }
+        }
+
+        let events = stream
+            .events
+            .iter()
+            .filter(|(seq, _)| *seq > last_seq)
+            .map(|(seq, message)| (format!("{}/{}", stream_id, seq), message.clone()))
+            .collect();
+
+        Some((stream_id.to_string(), events))
+    }
+
+    fn remove_stream(&self, stream_id: &str) {
+        self.streams.lock().unwrap().remove(stream_id);
+    }
+}
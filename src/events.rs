@@ -0,0 +1,134 @@
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::notification::{ListDelta, Notification};
+
+// Events buffered per subscriber before the slowest one starts missing some
+const EVENT_BUS_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Updated,
+    Removed,
+}
+
+/// Something that happened inside the server. Published on the `EventBus`
+/// for anything that wants to react without being called directly.
+#[derive(Debug, Clone)]
+pub enum ServerEvent {
+    ToolRegistered {
+        name: String,
+        // Whether it took the place of a tool with the same name
+        replaced: bool,
+    },
+    ToolRemoved {
+        name: String,
+    },
+    // A resource or resource template (by its URI template) was added or
+    // removed, or a resource's contents changed
+    ResourceChanged {
+        uri: String,
+        change: Change,
+    },
+    PromptChanged {
+        name: String,
+        change: Change,
+    },
+    TranslationsAdded {
+        locale: String,
+    },
+    SessionOpened {
+        session_id: String,
+    },
+    SessionClosed {
+        session_id: String,
+    },
+    // A tool was switched off or back on for every session
+    ToolToggled {
+        name: String,
+        enabled: bool,
+    },
+    ToolCallCompleted {
+        session_id: String,
+        tool: String,
+        duration: Duration,
+        // Why the call failed, whether with an error or an error result
+        error: Option<String>,
+    },
+}
+
+impl ServerEvent {
+    // The list-changed notifications clients need because of this event,
+    // with what changed
+    pub fn list_changes(&self) -> Vec<Notification> {
+        let delta = |change, name: &str| Some(ListDelta::of(change, name));
+        match self {
+            ServerEvent::ToolRegistered { name, replaced } => {
+                let change = if *replaced {
+                    Change::Updated
+                } else {
+                    Change::Added
+                };
+                vec![Notification::ToolListChanged {
+                    delta: delta(change, name),
+                }]
+            }
+            ServerEvent::ToolRemoved { name } => vec![Notification::ToolListChanged {
+                delta: delta(Change::Removed, name),
+            }],
+            ServerEvent::ToolToggled { name, enabled } => {
+                let change = if *enabled {
+                    Change::Added
+                } else {
+                    Change::Removed
+                };
+                vec![Notification::ToolListChanged {
+                    delta: delta(change, name),
+                }]
+            }
+            ServerEvent::ResourceChanged { uri, change } if *change != Change::Updated => {
+                vec![Notification::ResourceListChanged {
+                    delta: delta(*change, uri),
+                }]
+            }
+            ServerEvent::PromptChanged { name, change } => {
+                vec![Notification::PromptListChanged {
+                    delta: delta(*change, name),
+                }]
+            }
+            // Descriptions in every list may have changed
+            ServerEvent::TranslationsAdded { .. } => vec![
+                Notification::ToolListChanged { delta: None },
+                Notification::ResourceListChanged { delta: None },
+                Notification::PromptListChanged { delta: None },
+            ],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Broadcast channel for `ServerEvent`s. Publishing never blocks; a
+/// subscriber that falls too far behind skips ahead and is told how many
+/// events it missed.
+pub struct EventBus {
+    sender: broadcast::Sender<ServerEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        Self { sender }
+    }
+}
+
+impl EventBus {
+    pub fn publish(&self, event: ServerEvent) {
+        // No subscribers is fine
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ServerEvent> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,32 @@
+use anyhow::Result;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use crate::notification::Notifier;
+
+pub type MethodFuture = Pin<Box<dyn Future<Output = Result<serde_json::Value>> + Send>>;
+pub type MethodHandler =
+    Arc<dyn Fn(Option<serde_json::Value>, Notifier) -> MethodFuture + Send + Sync>;
+
+/// A custom method outside the MCP spec, such as `experimental/foo`. The
+/// handler gets the params as sent and a `Notifier` for the calling session;
+/// whatever it returns becomes the result.
+#[derive(Clone)]
+pub struct RegisteredMethod {
+    pub name: String,
+    pub handler: MethodHandler,
+}
+
+impl RegisteredMethod {
+    pub fn new<F, Fut>(name: impl Into<String>, handler: F) -> Self
+    where
+        F: Fn(Option<serde_json::Value>, Notifier) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<serde_json::Value>> + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            handler: Arc::new(move |params, notifier| Box::pin(handler(params, notifier))),
+        }
+    }
+}
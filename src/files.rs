@@ -0,0 +1,219 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::ServerError;
+use crate::msgpack::base64_encode;
+use crate::resources::RegisteredResourceTemplate;
+use crate::types::{ResourceContents, ResourceTemplate};
+use crate::uri_template::percent_decode;
+
+const DEFAULT_MAX_BYTES: u64 = 20 * 1024 * 1024;
+const DEFAULT_PDF_TIMEOUT_SECS: u64 = 10;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FilesConfig {
+    // Files under this directory can be read as `file://` resources
+    pub dir: PathBuf,
+    // Larger files are refused without being read
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+    // PDF text extraction taking longer than this fails the read
+    #[serde(default = "default_pdf_timeout_secs")]
+    pub pdf_timeout_secs: u64,
+}
+
+fn default_max_bytes() -> u64 {
+    DEFAULT_MAX_BYTES
+}
+
+fn default_pdf_timeout_secs() -> u64 {
+    DEFAULT_PDF_TIMEOUT_SECS
+}
+
+/// The files under a directory, served as `file:///absolute/path`. Text
+/// files come back as text and anything else as a base64 blob, except
+/// PDFs, which come back as their extracted text, one item per page.
+pub fn resource_template(config: &FilesConfig) -> Result<RegisteredResourceTemplate> {
+    let dir = config
+        .dir
+        .canonicalize()
+        .map_err(|e| anyhow::anyhow!("Can't serve {}: {}", config.dir.display(), e))?;
+    let template = ResourceTemplate {
+        uri_template: "file://{+path}".to_string(),
+        name: "Files".to_string(),
+        title: None,
+        description: format!(
+            "Files under {}. PDFs are returned as text, one item per page; add ?pages=1-3,5 to pick pages, or ?raw for the PDF itself",
+            dir.display()
+        ),
+        mime_type: None,
+    };
+
+    let config = config.clone();
+    RegisteredResourceTemplate::new(template, move |uri, _, _| {
+        let dir = dir.clone();
+        let config = config.clone();
+        async move { read(&dir, &config, uri).await }
+    })
+}
+
+async fn read(dir: &Path, config: &FilesConfig, uri: String) -> Result<Vec<ResourceContents>> {
+    let not_found = || ServerError::ResourceNotFound { uri: uri.clone() };
+    // The query is split off before decoding, so an escaped `?` in a file
+    // name stays part of the path
+    let (location, query) = uri.split_once('?').unwrap_or((&uri, ""));
+    let path = location
+        .strip_prefix("file://")
+        .and_then(percent_decode)
+        .ok_or_else(not_found)?;
+    // Symlinks and `..` are resolved before checking the file is inside `dir`
+    let path = PathBuf::from(path)
+        .canonicalize()
+        .map_err(|_| not_found())?;
+    if !path.starts_with(dir) || !path.is_file() {
+        return Err(not_found().into());
+    }
+    let size = tokio::fs::metadata(&path).await?.len();
+    if size > config.max_bytes {
+        return Err(ServerError::InvalidParams {
+            details: format!(
+                "{} is {} bytes, over the {} byte limit",
+                uri, size, config.max_bytes
+            ),
+        }
+        .into());
+    }
+    let bytes = tokio::fs::read(&path).await?;
+    let mime_type = mime_type(&path);
+
+    let raw = query.split('&').any(|param| param == "raw");
+    if mime_type == "application/pdf" && !raw {
+        let pages = query
+            .split('&')
+            .find_map(|param| param.strip_prefix("pages="))
+            .map(str::to_string);
+        let timeout = Duration::from_secs(config.pdf_timeout_secs);
+        let text = pdf_pages(&uri, bytes.clone(), timeout).await?;
+        // A scanned PDF has no text to extract; the PDF itself is more use
+        if text.iter().any(|page| !page.trim().is_empty()) {
+            return page_contents(location, &text, pages.as_deref());
+        }
+    }
+
+    if !mime_type.starts_with("image/") && mime_type != "application/pdf" {
+        if let Ok(text) = String::from_utf8(bytes.clone()) {
+            if !text.contains('\0') {
+                let mime_type = match mime_type {
+                    "application/octet-stream" => "text/plain",
+                    known => known,
+                };
+                return Ok(vec![ResourceContents::text(uri, mime_type, text)]);
+            }
+        }
+    }
+    Ok(vec![ResourceContents {
+        uri,
+        mime_type: Some(mime_type.to_string()),
+        text: None,
+        blob: Some(base64_encode(&bytes)),
+    }])
+}
+
+// The text of each page. Extraction is CPU-bound and can panic on
+// malformed files, so it runs on a blocking thread. A blocking thread can't
+// be stopped, so after `timeout` the read fails and the thread is left to
+// finish on its own.
+async fn pdf_pages(uri: &str, bytes: Vec<u8>, timeout: Duration) -> Result<Vec<String>> {
+    let extraction =
+        tokio::task::spawn_blocking(move || pdf_extract::extract_text_from_mem_by_pages(&bytes));
+    let Ok(extracted) = tokio::time::timeout(timeout, extraction).await else {
+        anyhow::bail!(
+            "Can't extract text from {}: took longer than {} seconds",
+            uri,
+            timeout.as_secs()
+        );
+    };
+    match extracted {
+        Ok(Ok(pages)) => Ok(pages),
+        Ok(Err(e)) => anyhow::bail!("Can't extract text from {}: {}", uri, e),
+        Err(_) => anyhow::bail!("Can't extract text from {}: unsupported PDF", uri),
+    }
+}
+
+// One text item per selected page, addressed as `uri#page=N`
+fn page_contents(
+    location: &str,
+    text: &[String],
+    pages: Option<&str>,
+) -> Result<Vec<ResourceContents>> {
+    let numbers = match pages {
+        Some(ranges) => page_numbers(ranges, text.len())?,
+        None => (1..=text.len()).collect(),
+    };
+    Ok(numbers
+        .into_iter()
+        .map(|number| {
+            ResourceContents::text(
+                format!("{}#page={}", location, number),
+                "text/plain",
+                text[number - 1].trim(),
+            )
+        })
+        .collect())
+}
+
+// Parses `1-3,5,8-` into page numbers, in the order given. An open range
+// runs to the last page.
+fn page_numbers(ranges: &str, count: usize) -> Result<Vec<usize>> {
+    let invalid = |details: String| ServerError::InvalidParams { details };
+    let mut numbers = Vec::new();
+    for range in ranges.split(',').map(str::trim) {
+        let (start, end) = match range.split_once('-') {
+            Some((start, "")) => (start, count.to_string()),
+            Some((start, end)) => (start, end.to_string()),
+            None => (range, range.to_string()),
+        };
+        let (Ok(start), Ok(end)) = (start.trim().parse::<usize>(), end.trim().parse::<usize>())
+        else {
+            return Err(invalid(format!("Invalid page range '{}'", range)).into());
+        };
+        if start == 0 || start > end || end > count {
+            return Err(invalid(format!(
+                "Page range '{}' is outside the document's {} pages",
+                range, count
+            ))
+            .into());
+        }
+        numbers.extend(start..=end);
+    }
+    Ok(numbers)
+}
+
+fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "pdf" => "application/pdf",
+        "md" | "markdown" => "text/markdown",
+        "html" | "htm" => "text/html",
+        "csv" => "text/csv",
+        "tsv" => "text/tab-separated-values",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "xls" => "application/vnd.ms-excel",
+        "ods" => "application/vnd.oasis.opendocument.spreadsheet",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "txt" | "log" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,268 @@
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use crate::envelope;
+use crate::error::ServerError;
+use crate::types::McpResponse;
+
+// Used when the config doesn't set `max_message_bytes`
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 4 * 1024 * 1024;
+
+pub enum Frame {
+    Message,
+    // The message was longer than the limit and has been skipped; holds its
+    // length in bytes
+    TooLong(usize),
+    // The message wasn't valid UTF-8 and has been skipped
+    InvalidUtf8,
+    Eof,
+}
+
+// Where a scan through the buffered bytes stopped
+enum Stop {
+    // The message ends here
+    End,
+    // A newline inside an unclosed object or array: either pretty-printed
+    // JSON continuing on the next line, or a broken message
+    Newline,
+    // Everything buffered belongs to the message
+    More,
+}
+
+// Longer numbers are taken as broken, rather than buffered to be checked
+const MAX_SCALAR_BYTES: usize = 1024;
+
+// What may come next in the message
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    Value,
+    // Right after `[`, where `]` may also come
+    FirstValue,
+    Key,
+    // Right after `{`, where `}` may also come
+    FirstKey,
+    Colon,
+    CommaOrClose,
+}
+
+// Follows the JSON structure of one message as its bytes arrive, checking
+// its syntax as it goes, so a newline can be judged without parsing the
+// message again. Only ASCII bytes are structural, so scanning UTF-8 byte by
+// byte is safe.
+struct Scanner {
+    started: bool,
+    // Open objects (`{`) and arrays (`[`), innermost last
+    open: Vec<u8>,
+    expect: Expect,
+    in_string: bool,
+    in_key: bool,
+    escaped: bool,
+    // The number or literal being read
+    scalar: Vec<u8>,
+    // Not valid JSON: the message ends at the next newline and is sent on to
+    // fail with a parse error, instead of swallowing the messages after it
+    broken: bool,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self {
+            started: false,
+            open: Vec::new(),
+            expect: Expect::Value,
+            in_string: false,
+            in_key: false,
+            escaped: false,
+            scalar: Vec::new(),
+            broken: false,
+        }
+    }
+}
+
+impl Scanner {
+    // Scans `bytes`, returning how many leading whitespace bytes came before
+    // the message, how many bytes to consume in all (up to and including
+    // the one it stopped on) and why it stopped
+    fn scan(&mut self, bytes: &[u8]) -> (usize, usize, Stop) {
+        let mut skipped = 0;
+        for (i, &byte) in bytes.iter().enumerate() {
+            if !self.started {
+                if byte.is_ascii_whitespace() {
+                    skipped += 1;
+                    continue;
+                }
+                self.started = true;
+            }
+            if self.in_string {
+                match byte {
+                    // Strings can't span lines, so the message is broken
+                    b'\n' => return (skipped, i + 1, Stop::End),
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => {
+                        self.in_string = false;
+                        self.expect = if self.in_key {
+                            Expect::Colon
+                        } else {
+                            Expect::CommaOrClose
+                        };
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            if is_scalar_byte(byte) && !self.scalar.is_empty() {
+                // No number or literal is this long
+                if self.scalar.len() >= MAX_SCALAR_BYTES {
+                    self.scalar.clear();
+                    self.broken = true;
+                } else {
+                    self.scalar.push(byte);
+                }
+                continue;
+            }
+            if !self.scalar.is_empty() {
+                self.end_scalar();
+            }
+            if self.broken {
+                if byte == b'\n' {
+                    return (skipped, i + 1, Stop::End);
+                }
+                continue;
+            }
+            let expecting_value = matches!(self.expect, Expect::Value | Expect::FirstValue);
+            match byte {
+                b'\n' if self.open.is_empty() => return (skipped, i + 1, Stop::End),
+                b'\n' => return (skipped, i + 1, Stop::Newline),
+                _ if byte.is_ascii_whitespace() => {}
+                b'"' if expecting_value
+                    || matches!(self.expect, Expect::Key | Expect::FirstKey) =>
+                {
+                    self.in_string = true;
+                    self.in_key = !expecting_value;
+                }
+                b'{' | b'[' if expecting_value => {
+                    self.open.push(byte);
+                    self.expect = if byte == b'{' {
+                        Expect::FirstKey
+                    } else {
+                        Expect::FirstValue
+                    };
+                }
+                b'}' | b']' => {
+                    let (opener, first) = if byte == b'}' {
+                        (b'{', Expect::FirstKey)
+                    } else {
+                        (b'[', Expect::FirstValue)
+                    };
+                    if self.open.last() != Some(&opener)
+                        || self.expect != Expect::CommaOrClose && self.expect != first
+                    {
+                        self.broken = true;
+                        continue;
+                    }
+                    self.open.pop();
+                    self.expect = Expect::CommaOrClose;
+                    if self.open.is_empty() {
+                        return (skipped, i + 1, Stop::End);
+                    }
+                }
+                b':' if self.expect == Expect::Colon => self.expect = Expect::Value,
+                b',' if self.expect == Expect::CommaOrClose && !self.open.is_empty() => {
+                    self.expect = if self.open.last() == Some(&b'{') {
+                        Expect::Key
+                    } else {
+                        Expect::Value
+                    };
+                }
+                _ if expecting_value && is_scalar_byte(byte) => self.scalar.push(byte),
+                _ => self.broken = true,
+            }
+        }
+        (skipped, bytes.len(), Stop::More)
+    }
+
+    // A number or literal has ended; only now can it be checked
+    fn end_scalar(&mut self) {
+        if serde_json::from_slice::<serde::de::IgnoredAny>(&self.scalar).is_err() {
+            self.broken = true;
+        }
+        self.scalar.clear();
+        self.expect = Expect::CommaOrClose;
+    }
+}
+
+// Bytes that can make up a number or `true`, `false` and `null`
+fn is_scalar_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'+' | b'.')
+}
+
+/// Reads one JSON-RPC message into `line`, never buffering more than `max`
+/// bytes. Messages are usually one per line, but a message ends as soon as
+/// its outermost object or array closes, so clients may also send
+/// pretty-printed JSON over several lines, or several messages on one.
+/// Oversized messages and messages that aren't UTF-8 are consumed and
+/// discarded, so the next read starts on the following message.
+pub async fn read_message<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    line: &mut String,
+    max: usize,
+) -> std::io::Result<Frame> {
+    let mut scanner = Scanner::default();
+    let mut bytes = Vec::new();
+    let mut total = 0;
+    let mut too_long = false;
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            if total == 0 {
+                return Ok(Frame::Eof);
+            }
+            break;
+        }
+        let (skipped, consumed, stop) = scanner.scan(available);
+        let chunk = &available[skipped..consumed];
+        total += chunk.len();
+        if !too_long && total > max {
+            too_long = true;
+            bytes = Vec::new();
+        }
+        if !too_long {
+            bytes.extend_from_slice(chunk);
+        }
+        reader.consume(consumed);
+        match stop {
+            Stop::End => break,
+            // The message so far is valid but unfinished, so it goes on
+            // past the newline, unless it's already too long: the next line
+            // is then the best place to pick up again
+            Stop::Newline if too_long => break,
+            Stop::Newline | Stop::More => {}
+        }
+    }
+
+    if too_long {
+        return Ok(Frame::TooLong(total));
+    }
+    match String::from_utf8(bytes) {
+        Ok(text) => {
+            line.push_str(&text);
+            Ok(Frame::Message)
+        }
+        Err(_) => Ok(Frame::InvalidUtf8),
+    }
+}
+
+// The error sent back for a skipped frame, if any. Its id is unknown, so it
+// goes out as null.
+pub fn error_response(frame: &Frame, max: usize) -> Option<McpResponse> {
+    let error = match frame {
+        Frame::TooLong(length) => ServerError::InvalidRequest {
+            details: format!("Message of {} bytes exceeds the {} byte limit", length, max),
+        },
+        Frame::InvalidUtf8 => ServerError::ParseError {
+            details: "Message is not valid UTF-8".to_string(),
+        },
+        Frame::Message | Frame::Eof => return None,
+    };
+    Some(envelope::error_response(None, error))
+}
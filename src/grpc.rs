@@ -0,0 +1,212 @@
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tonic::{Code, Request, Response, Status};
+use tracing::info;
+
+use crate::error::{INVALID_PARAMS, METHOD_NOT_FOUND, RATE_LIMITED, RESOURCE_NOT_FOUND};
+use crate::listener;
+use crate::msgpack::base64_decode;
+use crate::server::McpServer;
+use crate::session::Session;
+use crate::types::{McpError, Tool};
+
+mod proto {
+    tonic::include_proto!("testmcp.v1");
+}
+
+use proto::tools_server::{Tools, ToolsServer};
+
+/// gRPC service mirroring `tools/list`, `tools/call` and `resources/read`,
+/// as defined in `proto/tools.proto`. Every RPC is sent through one MCP
+/// session opened at startup, so the same handlers, middleware and
+/// visibility rules apply as for MCP clients.
+pub struct GrpcGateway {
+    server: Arc<McpServer>,
+    session: Session,
+}
+
+impl GrpcGateway {
+    pub async fn new(server: Arc<McpServer>) -> Result<Self> {
+        let (session, mut outbound) = server.connect();
+        // Nobody listens for the gateway's notifications
+        tokio::spawn(async move { while outbound.recv().await.is_some() {} });
+
+        let gateway = Self { server, session };
+        gateway
+            .request(
+                "initialize",
+                serde_json::json!({
+                    "protocolVersion": "2025-06-18",
+                    "capabilities": {},
+                    "clientInfo": { "name": "grpc-gateway", "version": env!("CARGO_PKG_VERSION") }
+                }),
+            )
+            .await
+            .map_err(|status| {
+                anyhow::anyhow!("gRPC gateway failed to initialize: {}", status.message())
+            })?;
+        gateway
+            .server
+            .handle_value(
+                &gateway.session,
+                serde_json::json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+            )
+            .await;
+        Ok(gateway)
+    }
+
+    pub async fn serve(self, addr: &str) -> Result<()> {
+        let addr = listener::normalize(addr);
+        let listener = TcpListener::bind(&addr)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to bind gRPC gateway to {}: {}", addr, e))?;
+        info!("gRPC gateway listening on {}", listener.local_addr()?);
+
+        tonic::transport::Server::builder()
+            .add_service(ToolsServer::new(self))
+            .serve_with_incoming(tonic::transport::server::TcpIncoming::from(listener))
+            .await?;
+        Ok(())
+    }
+
+    // Sends one MCP request through the gateway session and returns its result
+    async fn request(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, Status> {
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": uuid::Uuid::new_v4().to_string(),
+            "method": method,
+            "params": params,
+        });
+        let response = self
+            .server
+            .handle_value(&self.session, request)
+            .await
+            .ok_or_else(|| Status::internal("No response"))?;
+        match response.error {
+            Some(error) => Err(status(error)),
+            None => Ok(response.result.unwrap_or_default()),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Tools for GrpcGateway {
+    async fn list_tools(
+        &self,
+        _request: Request<proto::ListToolsRequest>,
+    ) -> Result<Response<proto::ListToolsResponse>, Status> {
+        let result = self.request("tools/list", serde_json::json!({})).await?;
+        let tools: Vec<Tool> = serde_json::from_value(result["tools"].clone())
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let tools = tools
+            .into_iter()
+            .map(|tool| proto::Tool {
+                name: tool.name,
+                title: tool.title,
+                description: tool.description,
+                input_schema_json: tool.input_schema.to_string(),
+            })
+            .collect();
+        Ok(Response::new(proto::ListToolsResponse { tools }))
+    }
+
+    async fn call_tool(
+        &self,
+        request: Request<proto::CallToolRequest>,
+    ) -> Result<Response<proto::CallToolResponse>, Status> {
+        let request = request.into_inner();
+        let arguments = if request.arguments_json.trim().is_empty() {
+            serde_json::json!({})
+        } else {
+            serde_json::from_str(&request.arguments_json)
+                .map_err(|e| Status::invalid_argument(format!("Invalid arguments_json: {}", e)))?
+        };
+        let result = self
+            .request(
+                "tools/call",
+                serde_json::json!({ "name": request.name, "arguments": arguments }),
+            )
+            .await?;
+
+        let content = result["content"]
+            .as_array()
+            .map(|items| items.iter().map(content).collect())
+            .unwrap_or_default();
+        Ok(Response::new(proto::CallToolResponse {
+            content,
+            is_error: result["isError"].as_bool().unwrap_or(false),
+        }))
+    }
+
+    async fn read_resource(
+        &self,
+        request: Request<proto::ReadResourceRequest>,
+    ) -> Result<Response<proto::ReadResourceResponse>, Status> {
+        let uri = request.into_inner().uri;
+        let result = self
+            .request("resources/read", serde_json::json!({ "uri": uri }))
+            .await?;
+
+        let contents = result["contents"]
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .map(|item| proto::ResourceContents {
+                        uri: string(&item["uri"]),
+                        mime_type: string(&item["mimeType"]),
+                        text: string(&item["text"]),
+                        blob: bytes(&item["blob"]),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Response::new(proto::ReadResourceResponse { contents }))
+    }
+}
+
+// One item of a tool result. An embedded resource's fields are flattened
+// into the item.
+fn content(item: &serde_json::Value) -> proto::Content {
+    let resource = &item["resource"];
+    if resource.is_object() {
+        return proto::Content {
+            r#type: string(&item["type"]),
+            text: string(&resource["text"]),
+            data: bytes(&resource["blob"]),
+            mime_type: string(&resource["mimeType"]),
+            uri: string(&resource["uri"]),
+        };
+    }
+    proto::Content {
+        r#type: string(&item["type"]),
+        text: string(&item["text"]),
+        data: bytes(&item["data"]),
+        mime_type: string(&item["mimeType"]),
+        uri: string(&item["uri"]),
+    }
+}
+
+fn string(value: &serde_json::Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+fn bytes(value: &serde_json::Value) -> Vec<u8> {
+    value.as_str().and_then(base64_decode).unwrap_or_default()
+}
+
+fn status(error: McpError) -> Status {
+    let code = match error.code {
+        INVALID_PARAMS => Code::InvalidArgument,
+        METHOD_NOT_FOUND => Code::Unimplemented,
+        RESOURCE_NOT_FOUND => Code::NotFound,
+        RATE_LIMITED => Code::ResourceExhausted,
+        _ => Code::Internal,
+    };
+    Status::new(code, error.message)
+}
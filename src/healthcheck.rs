@@ -0,0 +1,168 @@
+use anyhow::Result;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::compat::LATEST_PROTOCOL_VERSION;
+use crate::config::HttpConfig;
+
+/// A locally running instance to check, reached the way it was configured.
+pub enum Target<'a> {
+    Http { addr: &'a str, config: HttpConfig },
+    Tcp { addr: &'a str },
+}
+
+/// Connects to a running server, initializes a session and pings it. Used by
+/// `ping --self`, e.g. as a container healthcheck.
+pub async fn check(target: Target<'_>, timeout: Duration) -> Result<()> {
+    tokio::time::timeout(timeout, async {
+        match target {
+            Target::Http { addr, config } => check_http(&local_addr(addr), &config).await,
+            Target::Tcp { addr } => check_tcp(&local_addr(addr)).await,
+        }
+    })
+    .await
+    .map_err(|_| anyhow::anyhow!("No answer within {:?}", timeout))?
+}
+
+// Where to reach a listen address from this host
+pub fn local_addr(addr: &str) -> String {
+    if let Ok(port) = addr.parse::<u16>() {
+        return format!("127.0.0.1:{}", port);
+    }
+    match addr.rsplit_once(':') {
+        Some(("0.0.0.0", port)) => format!("127.0.0.1:{}", port),
+        Some(("[::]", port)) => format!("[::1]:{}", port),
+        _ => addr.to_string(),
+    }
+}
+
+fn initialize_request() -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": {
+            "protocolVersion": LATEST_PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": { "name": "healthcheck", "version": env!("CARGO_PKG_VERSION") }
+        }
+    })
+}
+
+fn ping_request() -> serde_json::Value {
+    serde_json::json!({ "jsonrpc": "2.0", "id": 2, "method": "ping" })
+}
+
+// A response is only healthy if it carries a result
+fn expect_result(response: &serde_json::Value, method: &str) -> Result<()> {
+    if response.get("result").is_some() {
+        return Ok(());
+    }
+    anyhow::bail!("{} failed: {}", method, response["error"])
+}
+
+async fn check_tcp(addr: &str) -> Result<()> {
+    let stream = TcpStream::connect(addr).await?;
+    let mut stream = BufReader::new(stream);
+    for (request, method) in [
+        (initialize_request(), "initialize"),
+        (ping_request(), "ping"),
+    ] {
+        stream
+            .write_all(format!("{}\n", request).as_bytes())
+            .await?;
+        // Skip any notifications sent ahead of the response
+        let response = loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line).await? == 0 {
+                anyhow::bail!("Connection closed during {}", method);
+            }
+            let message: serde_json::Value = serde_json::from_str(&line)?;
+            if message.get("id").is_some() {
+                break message;
+            }
+        };
+        expect_result(&response, method)?;
+    }
+    Ok(())
+}
+
+async fn check_http(addr: &str, config: &HttpConfig) -> Result<()> {
+    let path = format!(
+        "{}{}",
+        config.base_path.trim_end_matches('/'),
+        config.mcp_path
+    );
+    let (headers, response) =
+        http_request(addr, "POST", &path, None, &initialize_request()).await?;
+    expect_result(&response, "initialize")?;
+    let session_id = headers
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("mcp-session-id")
+                .then(|| value.trim().to_string())
+        })
+        .ok_or_else(|| anyhow::anyhow!("initialize returned no session id"))?;
+
+    let (_, response) =
+        http_request(addr, "POST", &path, Some(&session_id), &ping_request()).await?;
+    expect_result(&response, "ping")?;
+
+    // Don't leave the session behind until it expires
+    let _ = http_request(
+        addr,
+        "DELETE",
+        &path,
+        Some(&session_id),
+        &serde_json::Value::Null,
+    )
+    .await;
+    Ok(())
+}
+
+// Sends one request and returns the raw headers and the JSON body
+async fn http_request(
+    addr: &str,
+    method: &str,
+    path: &str,
+    session_id: Option<&str>,
+    body: &serde_json::Value,
+) -> Result<(String, serde_json::Value)> {
+    let body = match body {
+        serde_json::Value::Null => String::new(),
+        body => body.to_string(),
+    };
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        method,
+        path,
+        addr,
+        body.len()
+    );
+    if let Some(session_id) = session_id {
+        request.push_str(&format!("Mcp-Session-Id: {}\r\n", session_id));
+    }
+    request.push_str("\r\n");
+    request.push_str(&body);
+
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+
+    let (headers, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| anyhow::anyhow!("Malformed HTTP response"))?;
+    let status = headers.lines().next().unwrap_or_default();
+    if !status.contains(" 200 ") && !status.contains(" 202 ") {
+        anyhow::bail!("{} {} returned {}", method, path, status);
+    }
+    let body = if body.is_empty() {
+        serde_json::Value::Null
+    } else {
+        serde_json::from_str(body)?
+    };
+    Ok((headers.to_string(), body))
+}
@@ -0,0 +1,602 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::buffer;
+use crate::compression::{self, Encoding};
+use crate::config::HttpConfig;
+use crate::event_store::EventStore;
+use crate::listener;
+use crate::metrics::Metrics;
+use crate::ratelimit;
+use crate::server::McpServer;
+use crate::session::{KeepAliveAction, KeepAliveConfig, Session};
+
+const SESSION_HEADER: &str = "mcp-session-id";
+const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+
+// Smaller bodies aren't worth compressing
+const MIN_COMPRESS_SIZE: usize = 1024;
+
+type EventSink = mpsc::UnboundedSender<(String, serde_json::Value)>;
+
+struct HttpSession {
+    session: Session,
+    // The currently attached GET stream, if any. Held while storing an event
+    // so a reconnecting client can't miss one between replay and attach.
+    stream: Mutex<Option<EventSink>>,
+    // POSTs still being answered. A session waiting on a long tool call is
+    // busy, not idle.
+    posts_in_flight: AtomicUsize,
+}
+
+impl HttpSession {
+    // Whether a GET stream is attached for pings to go out on
+    fn has_stream(&self) -> bool {
+        self.stream
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|sink| !sink.is_closed())
+    }
+}
+
+pub(crate) struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+    peer: SocketAddr,
+}
+
+impl HttpRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(|value| value.as_str())
+    }
+
+    // The token from `Authorization: Bearer <token>`
+    pub fn bearer_token(&self) -> Option<&str> {
+        self.header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+    }
+
+    // The originating client: the first `X-Forwarded-For` entry if proxy
+    // headers are trusted, otherwise the connecting peer
+    fn client_addr(&self, trust_forwarded: bool) -> String {
+        let forwarded = self
+            .header("x-forwarded-for")
+            .and_then(|value| value.split(',').next())
+            .map(|addr| addr.trim().to_string())
+            .filter(|addr| trust_forwarded && !addr.is_empty());
+        forwarded.unwrap_or_else(|| self.peer.to_string())
+    }
+
+    // How the client reached us, for logging behind a proxy
+    fn origin(&self, trust_forwarded: bool) -> String {
+        let client = self.client_addr(trust_forwarded);
+        if !trust_forwarded {
+            return client;
+        }
+        match (
+            self.header("x-forwarded-proto"),
+            self.header("x-forwarded-host"),
+        ) {
+            (Some(proto), Some(host)) => format!("{} via {}://{}", client, proto, host),
+            _ => client,
+        }
+    }
+}
+
+/// Streamable HTTP transport: clients POST JSON-RPC messages to `/mcp` and
+/// receive server-initiated messages on a GET SSE stream, which can be resumed
+/// after a disconnect via `Last-Event-ID`.
+pub struct HttpTransport {
+    server: Arc<McpServer>,
+    event_store: Arc<dyn EventStore>,
+    keep_alive: KeepAliveConfig,
+    metrics: Option<Arc<Metrics>>,
+    config: HttpConfig,
+    sessions: Mutex<HashMap<String, Arc<HttpSession>>>,
+}
+
+impl HttpTransport {
+    pub fn new(
+        server: Arc<McpServer>,
+        event_store: Arc<dyn EventStore>,
+        keep_alive: KeepAliveConfig,
+    ) -> Self {
+        Self {
+            server,
+            event_store,
+            keep_alive,
+            metrics: None,
+            config: HttpConfig::default(),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Serves the metrics in Prometheus format at `/metrics`
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    // Serves under the paths in `config` instead of the defaults
+    pub fn with_config(mut self, config: HttpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub async fn serve(self: Arc<Self>, addr: &str) -> Result<()> {
+        let listener = listener::bind("http", addr).await?;
+
+        let transport = self.clone();
+        tokio::spawn(async move { transport.run_keep_alive().await });
+
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let transport = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = transport.handle_connection(stream, peer).await {
+                    warn!("HTTP connection from {} failed: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    async fn run_keep_alive(&self) {
+        if !self.keep_alive.enabled() {
+            return;
+        }
+        let mut interval = tokio::time::interval(self.keep_alive.ping_interval);
+        loop {
+            interval.tick().await;
+            let sessions: Vec<Arc<HttpSession>> =
+                self.sessions.lock().unwrap().values().cloned().collect();
+            for http_session in sessions {
+                if http_session.posts_in_flight.load(Ordering::Relaxed) > 0 {
+                    http_session.session.touch();
+                    continue;
+                }
+                match self.keep_alive.check(&http_session.session) {
+                    // Without a stream the ping can't reach the client, so
+                    // only its POSTs keep the session alive
+                    KeepAliveAction::Ping if !http_session.has_stream() => {}
+                    KeepAliveAction::Ping => {
+                        let timeout = self.keep_alive.ping_interval;
+                        tokio::spawn(async move { http_session.session.ping(timeout).await });
+                    }
+                    KeepAliveAction::Expire => {
+                        info!(
+                            "Expiring unresponsive HTTP session {}",
+                            http_session.session.id
+                        );
+                        self.close_session(&http_session.session.id);
+                    }
+                    KeepAliveAction::None => {}
+                }
+            }
+        }
+    }
+
+    async fn handle_connection(&self, stream: TcpStream, peer: SocketAddr) -> Result<()> {
+        let mut stream = BufReader::new(stream);
+        let request = match read_request(&mut stream, peer, self.server.max_message_bytes()).await?
+        {
+            Some(request) => request,
+            None => return Ok(()),
+        };
+
+        let path = request.path.split('?').next().unwrap_or_default();
+        let base_path = self.config.base_path.trim_end_matches('/');
+        let Some(path) = path.strip_prefix(base_path) else {
+            return write_response(&mut stream, "404 Not Found", &[], "").await;
+        };
+        let mcp_path = self.config.mcp_path.as_str();
+        let sse_path = self.config.sse_path.as_deref().unwrap_or(mcp_path);
+
+        if path == self.config.metrics_path && request.method == "GET" {
+            if let Some(metrics) = &self.metrics {
+                let body = metrics.render_prometheus();
+                let headers = [("Content-Type", "text/plain; version=0.0.4")];
+                let encoding = compression::negotiate(request.header("accept-encoding"));
+                return write_encoded(&mut stream, "200 OK", &headers, body.as_bytes(), encoding)
+                    .await;
+            }
+        }
+
+        // A profile path serves all of POST, GET and DELETE
+        let profile = self.config.profile_paths.get(path).cloned();
+        let is_mcp = path == mcp_path || profile.is_some();
+        let is_sse = path == sse_path || profile.is_some();
+        if !is_mcp && !is_sse {
+            return write_response(&mut stream, "404 Not Found", &[], "").await;
+        }
+
+        // Every request is authenticated on its own, and a session only
+        // answers to the principal that opened it. No token is anonymous.
+        let principal = match request.bearer_token() {
+            Some(token) => match self.server.authenticate(token) {
+                Some(principal) => Some(principal),
+                None => {
+                    warn!(
+                        "Rejected an invalid bearer token from {}",
+                        request.origin(self.config.trust_forwarded_headers)
+                    );
+                    let headers = [("WWW-Authenticate", "Bearer")];
+                    return write_response(&mut stream, "401 Unauthorized", &headers, "").await;
+                }
+            },
+            None => None,
+        };
+        match request.method.as_str() {
+            "GET" if is_sse => self.handle_get(&mut stream, request, principal).await,
+            "POST" if is_mcp => {
+                self.handle_post(&mut stream, request, profile, principal)
+                    .await
+            }
+            "DELETE" if is_mcp => self.handle_delete(&mut stream, request, principal).await,
+            _ => write_response(&mut stream, "405 Method Not Allowed", &[], "").await,
+        }
+    }
+
+    async fn handle_post(
+        &self,
+        stream: &mut BufReader<TcpStream>,
+        request: HttpRequest,
+        profile: Option<String>,
+        principal: Option<String>,
+    ) -> Result<()> {
+        let body = String::from_utf8_lossy(&request.body);
+        let is_initialize = serde_json::from_str::<serde_json::Value>(&body)
+            .map(|message| message["method"] == "initialize")
+            .unwrap_or(false);
+
+        let http_session = match request.header(SESSION_HEADER) {
+            Some(id) => match self.find_session(id, &principal) {
+                Some(http_session) => http_session,
+                None => return write_response(stream, "404 Not Found", &[], "").await,
+            },
+            None if is_initialize => self.open_session(&request, profile, principal),
+            None => {
+                return write_response(stream, "400 Bad Request", &[], "Missing Mcp-Session-Id")
+                    .await
+            }
+        };
+
+        let session_id = http_session.session.id.clone();
+        http_session
+            .session
+            .set_remote_addr(request.client_addr(self.config.trust_forwarded_headers));
+        let headers = [("Mcp-Session-Id", session_id.as_str())];
+        let encoding = compression::negotiate(request.header("accept-encoding"));
+        http_session.posts_in_flight.fetch_add(1, Ordering::Relaxed);
+        let response = self
+            .server
+            .handle_message(&http_session.session, &body)
+            .await;
+        http_session.posts_in_flight.fetch_sub(1, Ordering::Relaxed);
+        http_session.session.touch();
+        match response {
+            Some(response) => {
+                let response_json = buffer::json(&response)?;
+                let content_type = ("Content-Type", "application/json");
+                if let Some(secs) = ratelimit::retry_after_secs(&response) {
+                    let retry_after = secs.to_string();
+                    let headers = [headers[0], content_type, ("Retry-After", &retry_after)];
+                    return write_encoded(
+                        stream,
+                        "429 Too Many Requests",
+                        &headers,
+                        &response_json,
+                        encoding,
+                    )
+                    .await;
+                }
+                let headers = [headers[0], content_type];
+                write_encoded(stream, "200 OK", &headers, &response_json, encoding).await
+            }
+            None => write_response(stream, "202 Accepted", &headers, "").await,
+        }
+    }
+
+    async fn handle_get(
+        &self,
+        stream: &mut BufReader<TcpStream>,
+        request: HttpRequest,
+        principal: Option<String>,
+    ) -> Result<()> {
+        let accepts_sse = request
+            .header("accept")
+            .is_some_and(|accept| accept.contains("text/event-stream"));
+        if !accepts_sse {
+            return write_response(stream, "406 Not Acceptable", &[], "").await;
+        }
+
+        let http_session = match request
+            .header(SESSION_HEADER)
+            .and_then(|id| self.find_session(id, &principal))
+        {
+            Some(http_session) => http_session,
+            None => return write_response(stream, "404 Not Found", &[], "").await,
+        };
+        let session_id = http_session.session.id.clone();
+        http_session.session.touch();
+
+        let (sink, mut events) = mpsc::unbounded_channel();
+        {
+            let mut attached = http_session.stream.lock().unwrap();
+            if let Some(last_event_id) = request.header(LAST_EVENT_ID_HEADER) {
+                match self.event_store.replay_events_after(last_event_id) {
+                    Some((stream_id, missed)) if stream_id == session_id => {
+                        info!(
+                            "Replaying {} missed events for session {}",
+                            missed.len(),
+                            session_id
+                        );
+                        for event in missed {
+                            let _ = sink.send(event);
+                        }
+                    }
+                    _ => warn!("Cannot resume from event {}", last_event_id),
+                }
+            }
+            // Replaces (and thereby closes) any previously attached stream.
+            *attached = Some(sink);
+        }
+
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nMcp-Session-Id: {}\r\n\r\n",
+            session_id
+        );
+        stream.write_all(head.as_bytes()).await?;
+        stream.flush().await?;
+
+        // Don't keep the session alive from here: the stream should end as
+        // soon as the session is closed or expires.
+        drop(http_session);
+
+        while let Some((event_id, message)) = events.recv().await {
+            stream
+                .write_all(&buffer::sse_event(&event_id, &message)?)
+                .await?;
+            stream.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn handle_delete(
+        &self,
+        stream: &mut BufReader<TcpStream>,
+        request: HttpRequest,
+        principal: Option<String>,
+    ) -> Result<()> {
+        let closed = request
+            .header(SESSION_HEADER)
+            .filter(|id| self.find_session(id, &principal).is_some())
+            .is_some_and(|id| self.close_session(id));
+
+        if closed {
+            write_response(stream, "200 OK", &[], "").await
+        } else {
+            write_response(stream, "404 Not Found", &[], "").await
+        }
+    }
+
+    // Drops the session and its stored events. Removing the last strong
+    // reference ends the outbound pump, which in turn closes any attached
+    // SSE stream.
+    fn close_session(&self, id: &str) -> bool {
+        let removed = self.sessions.lock().unwrap().remove(id);
+        match removed {
+            Some(http_session) => {
+                info!("Closed HTTP session {}", http_session.session.id);
+                self.server.disconnect(&http_session.session.id);
+                self.event_store.remove_stream(&http_session.session.id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // The session, if it was opened by `principal`. Anyone else gets
+    // nothing, as if it didn't exist.
+    fn find_session(&self, id: &str, principal: &Option<String>) -> Option<Arc<HttpSession>> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(id)
+            .filter(|http_session| http_session.session.principal() == *principal)
+            .cloned()
+    }
+
+    fn open_session(
+        &self,
+        request: &HttpRequest,
+        profile: Option<String>,
+        principal: Option<String>,
+    ) -> Arc<HttpSession> {
+        let (session, mut outbound) = self.server.connect();
+        if let Some(profile) = profile {
+            session.set_profile(profile);
+        }
+        if let Some(principal) = principal {
+            session.set_principal(principal);
+        }
+        let session_id = session.id.clone();
+        let http_session = Arc::new(HttpSession {
+            session,
+            stream: Mutex::new(None),
+            posts_in_flight: AtomicUsize::new(0),
+        });
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session_id.clone(), http_session.clone());
+        info!(
+            "Opened HTTP session {} for {}",
+            session_id,
+            request.origin(self.config.trust_forwarded_headers)
+        );
+
+        // Every outbound message is persisted before delivery so it can be
+        // replayed; the pump only holds a weak reference so it ends once the
+        // session is removed.
+        let weak: Weak<HttpSession> = Arc::downgrade(&http_session);
+        let event_store = self.event_store.clone();
+        tokio::spawn(async move {
+            while let Some(message) = outbound.recv().await {
+                let Some(http_session) = weak.upgrade() else {
+                    break;
+                };
+                let mut attached = http_session.stream.lock().unwrap();
+                let event_id = event_store.store_event(&session_id, &message);
+                if let Some(sink) = attached.as_ref() {
+                    if sink.send((event_id, message)).is_err() {
+                        *attached = None;
+                    }
+                }
+            }
+        });
+
+        http_session
+    }
+}
+
+// Reads one request, with its request line and headers together, and its
+// body, each at most `max` bytes. A request over either limit is answered
+// with 431 or 413 here and comes back as `None`, like a closed connection.
+pub(crate) async fn read_request(
+    stream: &mut BufReader<TcpStream>,
+    peer: SocketAddr,
+    max: usize,
+) -> Result<Option<HttpRequest>> {
+    let mut head_left = max;
+    let Some(request_line) = read_head_line(stream, &mut head_left).await? else {
+        if head_left == 0 {
+            write_response(stream, "431 Request Header Fields Too Large", &[], "").await?;
+        }
+        return Ok(None);
+    };
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let Some(line) = read_head_line(stream, &mut head_left).await? else {
+            if head_left == 0 {
+                warn!("Headers from {} exceed {} bytes", peer, max);
+                write_response(stream, "431 Request Header Fields Too Large", &[], "").await?;
+                return Ok(None);
+            }
+            break;
+        };
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let content_length = headers
+        .get("content-length")
+        .and_then(|len| len.parse::<usize>().ok())
+        .unwrap_or(0);
+    if content_length > max {
+        warn!(
+            "Body of {} bytes from {} exceeds the {} byte limit",
+            content_length, peer, max
+        );
+        write_response(stream, "413 Content Too Large", &[], "").await?;
+        return Ok(None);
+    }
+    let mut body = vec![0; content_length];
+    stream.read_exact(&mut body).await?;
+
+    Ok(Some(HttpRequest {
+        method,
+        path,
+        headers,
+        body,
+        peer,
+    }))
+}
+
+// One line of the request head, or `None` at the end of the stream or when
+// the line doesn't end within the `left` bytes still allowed, which leaves
+// `left` at 0
+async fn read_head_line(
+    stream: &mut BufReader<TcpStream>,
+    left: &mut usize,
+) -> Result<Option<String>> {
+    let mut line = String::new();
+    let read = (&mut *stream)
+        .take(*left as u64)
+        .read_line(&mut line)
+        .await?;
+    *left -= read;
+    if read == 0 || (*left == 0 && !line.ends_with('\n')) {
+        return Ok(None);
+    }
+    Ok(Some(line))
+}
+
+pub(crate) async fn write_response(
+    stream: &mut BufReader<TcpStream>,
+    status: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> Result<()> {
+    write_bytes(stream, status, headers, body.as_bytes()).await
+}
+
+// Compresses the body with the client's preferred encoding, if it's big
+// enough to be worth it
+async fn write_encoded(
+    stream: &mut BufReader<TcpStream>,
+    status: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    encoding: Option<Encoding>,
+) -> Result<()> {
+    let mut headers = headers.to_vec();
+    headers.push(("Vary", "Accept-Encoding"));
+    match encoding {
+        Some(encoding) if body.len() >= MIN_COMPRESS_SIZE => {
+            headers.push(("Content-Encoding", encoding.name()));
+            let compressed = encoding.encode(body)?;
+            write_bytes(stream, status, &headers, &compressed).await
+        }
+        _ => write_bytes(stream, status, &headers, body).await,
+    }
+}
+
+async fn write_bytes(
+    stream: &mut BufReader<TcpStream>,
+    status: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<()> {
+    let mut response = format!("HTTP/1.1 {}\r\n", status);
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    ));
+    stream.write_all(response.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.flush().await?;
+    Ok(())
+}
@@ -0,0 +1,236 @@
+use anyhow::Result;
+use std::sync::{Arc, OnceLock};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+/// An `http://` or `https://host[:port]/path` URL, for the few services
+/// the server calls out to (approval webhooks, embedding and translation
+/// APIs, proxied tools).
+#[derive(Debug, Clone)]
+pub struct HttpUrl {
+    addr: String,
+    host: String,
+    path: String,
+    tls: bool,
+}
+
+impl HttpUrl {
+    pub fn parse(url: &str) -> Result<Self> {
+        let (rest, tls) = match (url.strip_prefix("https://"), url.strip_prefix("http://")) {
+            (Some(rest), _) => (rest, true),
+            (None, Some(rest)) => (rest, false),
+            (None, None) => anyhow::bail!("{} must start with http:// or https://", url),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, "/"),
+        };
+        let addr = if authority.contains(':') {
+            authority.to_string()
+        } else {
+            format!("{}:{}", authority, if tls { 443 } else { 80 })
+        };
+        Ok(Self {
+            addr,
+            host: authority.to_string(),
+            path: path.to_string(),
+            tls,
+        })
+    }
+
+    // `path`, which may carry a query, under this URL's path
+    pub fn join(&self, path: &str) -> Self {
+        Self {
+            path: format!("{}{}", self.path.trim_end_matches('/'), path),
+            ..self.clone()
+        }
+    }
+
+    // `host[:port]` as given
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    // The host name without a port, as TLS verifies it
+    fn server_name(&self) -> Result<ServerName<'static>> {
+        let name = match self.host.rsplit_once(':') {
+            Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+            _ => &self.host,
+        };
+        Ok(ServerName::try_from(name.to_string())?)
+    }
+}
+
+// Trusts the Mozilla root certificates bundled with the binary
+fn tls_connector() -> TlsConnector {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    let config = CONFIG.get_or_init(|| {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        Arc::new(
+            ClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth(),
+        )
+    });
+    TlsConnector::from(config.clone())
+}
+
+// Sends `message` and reads until the server closes the connection
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    message: &[u8],
+) -> Result<Vec<u8>> {
+    stream.write_all(message).await?;
+    let mut response = Vec::new();
+    match stream.read_to_end(&mut response).await {
+        Ok(_) => Ok(response),
+        // Plenty of servers close without a TLS close_notify; with
+        // `Connection: close` the response is complete all the same
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof && !response.is_empty() => {
+            Ok(response)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+// POSTs `body` as JSON and returns the JSON the service answers with. A
+// non-2xx status is an error.
+pub async fn post_json(
+    url: &HttpUrl,
+    headers: &[(&str, &str)],
+    body: &serde_json::Value,
+) -> Result<serde_json::Value> {
+    request_json("POST", url, headers, Some(&body.to_string())).await
+}
+
+// Like `post_json`, for services read with GET
+pub async fn get_json(url: &HttpUrl, headers: &[(&str, &str)]) -> Result<serde_json::Value> {
+    request_json("GET", url, headers, None).await
+}
+
+async fn request_json(
+    method: &str,
+    url: &HttpUrl,
+    headers: &[(&str, &str)],
+    body: Option<&str>,
+) -> Result<serde_json::Value> {
+    let mut all_headers = vec![("Accept", "application/json")];
+    all_headers.extend_from_slice(headers);
+    let response = send(method, url, &all_headers, body).await?;
+    if !response.is_success() {
+        anyhow::bail!("{} returned {}", url.host, response.status_line());
+    }
+    Ok(serde_json::from_slice(&response.body)?)
+}
+
+/// A response as received: the status line and headers, and the body with
+/// any chunked encoding removed.
+pub struct HttpResponse {
+    pub status: u16,
+    head: String,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn status_line(&self) -> &str {
+        self.head.lines().next().unwrap_or_default()
+    }
+
+    // The first header called `name`, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.head.lines().skip(1).find_map(|line| {
+            let (header, value) = line.split_once(':')?;
+            header
+                .trim()
+                .eq_ignore_ascii_case(name)
+                .then(|| value.trim())
+        })
+    }
+}
+
+// Sends one request, with a JSON body if given, and returns whatever the
+// service answers, whatever the status
+pub async fn send(
+    method: &str,
+    url: &HttpUrl,
+    headers: &[(&str, &str)],
+    body: Option<&str>,
+) -> Result<HttpResponse> {
+    let mut message = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+        method, url.path, url.host
+    );
+    if let Some(body) = body {
+        message.push_str(&format!(
+            "Content-Type: application/json\r\nContent-Length: {}\r\n",
+            body.len()
+        ));
+    }
+    for (name, value) in headers {
+        message.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    message.push_str("\r\n");
+    message.push_str(body.unwrap_or_default());
+
+    let stream = TcpStream::connect(&url.addr).await?;
+    let response = if url.tls {
+        let stream = tls_connector().connect(url.server_name()?, stream).await?;
+        exchange(stream, message.as_bytes()).await?
+    } else {
+        exchange(stream, message.as_bytes()).await?
+    };
+
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| anyhow::anyhow!("Malformed response from {}", url.host))?;
+    let head = String::from_utf8_lossy(&response[..split]).into_owned();
+    let body = &response[split + 4..];
+    let status = head
+        .lines()
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("Malformed response from {}", url.host))?;
+    let chunked = head.lines().any(|line| {
+        line.to_ascii_lowercase()
+            .replace(' ', "")
+            .starts_with("transfer-encoding:chunked")
+    });
+    let body = if chunked {
+        dechunk(body)?
+    } else {
+        body.to_vec()
+    };
+    Ok(HttpResponse { status, head, body })
+}
+
+fn dechunk(mut body: &[u8]) -> Result<Vec<u8>> {
+    let mut joined = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or_else(|| anyhow::anyhow!("Malformed chunked response"))?;
+        let size = String::from_utf8_lossy(&body[..line_end]);
+        let size = usize::from_str_radix(size.split(';').next().unwrap_or_default().trim(), 16)?;
+        if size == 0 {
+            return Ok(joined);
+        }
+        let rest = &body[line_end + 2..];
+        let chunk = rest
+            .get(..size)
+            .ok_or_else(|| anyhow::anyhow!("Truncated chunked response"))?;
+        joined.extend_from_slice(chunk);
+        body = rest[size..].strip_prefix(b"\r\n").unwrap_or(&rest[size..]);
+    }
+}
@@ -0,0 +1,379 @@
+use anyhow::Result;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader, Limits};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, Weak};
+
+use crate::error::ServerError;
+use crate::msgpack::{base64_decode, base64_encode};
+use crate::server::McpServer;
+use crate::tools::{RegisteredTool, ToolContext};
+use crate::types::{CallToolResult, Content, Tool, ToolAnnotations};
+
+// Larger inputs are refused before decoding, and larger outputs before
+// resizing
+const MAX_DIMENSION: u32 = 16_384;
+const MAX_DECODE_BYTES: u64 = 256 * 1024 * 1024;
+const DEFAULT_THUMBNAIL_SIZE: u32 = 128;
+const DEFAULT_JPEG_QUALITY: u8 = 85;
+
+/// What to do to the image, read from the tool's arguments.
+enum Operation {
+    Resize {
+        width: Option<u32>,
+        height: Option<u32>,
+        keep_aspect: bool,
+    },
+    Crop {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    Thumbnail {
+        size: u32,
+    },
+    Convert,
+}
+
+impl Operation {
+    fn parse(arguments: &HashMap<String, Value>) -> Result<Self> {
+        let operation = arguments
+            .get("operation")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'operation' argument"))?;
+        Ok(match operation {
+            "resize" => {
+                let width = dimension(arguments, "width")?;
+                let height = dimension(arguments, "height")?;
+                if width.is_none() && height.is_none() {
+                    return Err(invalid("resize needs width, height or both"));
+                }
+                Operation::Resize {
+                    width,
+                    height,
+                    keep_aspect: arguments
+                        .get("keep_aspect")
+                        .and_then(|v| v.as_bool())
+                        .unwrap_or(true),
+                }
+            }
+            "crop" => {
+                let required = |name: &str| {
+                    let value = match name {
+                        "x" | "y" => arguments.get(name).and_then(|v| v.as_u64()),
+                        _ => dimension(arguments, name)?.map(u64::from),
+                    };
+                    value
+                        .map(|value| value.min(u32::MAX as u64) as u32)
+                        .ok_or_else(|| invalid(&format!("crop needs '{}'", name)))
+                };
+                Operation::Crop {
+                    x: required("x")?,
+                    y: required("y")?,
+                    width: required("width")?,
+                    height: required("height")?,
+                }
+            }
+            "thumbnail" => Operation::Thumbnail {
+                size: dimension(arguments, "size")?.unwrap_or(DEFAULT_THUMBNAIL_SIZE),
+            },
+            "convert" => Operation::Convert,
+            other => {
+                return Err(invalid(&format!(
+                    "Unknown operation '{}'; expected resize, crop, thumbnail or convert",
+                    other
+                )))
+            }
+        })
+    }
+
+    fn apply(&self, image: DynamicImage) -> Result<DynamicImage> {
+        Ok(match *self {
+            Operation::Resize {
+                width,
+                height,
+                keep_aspect,
+            } => {
+                // A missing side follows the other, keeping the aspect ratio
+                let scale = |side: u32, from: u32, to: u32| {
+                    ((side as f64 * to as f64 / from as f64).round() as u32).max(1)
+                };
+                let (width, height) = match (width, height) {
+                    (Some(width), Some(height)) => (width, height),
+                    (Some(width), None) => (width, scale(image.height(), image.width(), width)),
+                    (None, Some(height)) => (scale(image.width(), image.height(), height), height),
+                    (None, None) => (image.width(), image.height()),
+                };
+                if width > MAX_DIMENSION || height > MAX_DIMENSION {
+                    return Err(invalid(&format!(
+                        "Images can be at most {0}x{0} pixels",
+                        MAX_DIMENSION
+                    )));
+                }
+                if keep_aspect {
+                    image.resize(width, height, FilterType::Lanczos3)
+                } else {
+                    image.resize_exact(width, height, FilterType::Lanczos3)
+                }
+            }
+            Operation::Crop {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let fits = x
+                    .checked_add(width)
+                    .is_some_and(|right| right <= image.width())
+                    && y.checked_add(height)
+                        .is_some_and(|bottom| bottom <= image.height());
+                if !fits {
+                    return Err(invalid(&format!(
+                        "The crop {}x{} at ({}, {}) doesn't fit in the {}x{} image",
+                        width,
+                        height,
+                        x,
+                        y,
+                        image.width(),
+                        image.height()
+                    )));
+                }
+                image.crop_imm(x, y, width, height)
+            }
+            // Fits in a `size` square; never enlarges
+            Operation::Thumbnail { size } => {
+                if image.width() <= size && image.height() <= size {
+                    image
+                } else {
+                    image.thumbnail(size, size)
+                }
+            }
+            Operation::Convert => image,
+        })
+    }
+}
+
+fn invalid(details: &str) -> anyhow::Error {
+    ServerError::InvalidParams {
+        details: details.to_string(),
+    }
+    .into()
+}
+
+fn dimension(arguments: &HashMap<String, Value>, name: &str) -> Result<Option<u32>> {
+    match arguments.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => match value.as_u64() {
+            Some(n) if n >= 1 && n <= MAX_DIMENSION as u64 => Ok(Some(n as u32)),
+            _ => Err(invalid(&format!(
+                "'{}' must be a whole number from 1 to {}",
+                name, MAX_DIMENSION
+            ))),
+        },
+    }
+}
+
+fn output_format(name: &str) -> Result<ImageFormat> {
+    match name.to_ascii_lowercase().as_str() {
+        "png" => Ok(ImageFormat::Png),
+        "jpeg" | "jpg" => Ok(ImageFormat::Jpeg),
+        "gif" => Ok(ImageFormat::Gif),
+        "webp" => Ok(ImageFormat::WebP),
+        "bmp" => Ok(ImageFormat::Bmp),
+        other => Err(invalid(&format!(
+            "Unsupported format '{}'; expected png, jpeg, gif, webp or bmp",
+            other
+        ))),
+    }
+}
+
+// The image and the format it was in, sniffed from its bytes
+fn decode(bytes: &[u8]) -> Result<(DynamicImage, ImageFormat)> {
+    let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    let format = reader
+        .format()
+        .ok_or_else(|| invalid("The data isn't a PNG, JPEG, GIF, WebP or BMP image"))?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_BYTES);
+    reader.limits(limits);
+    let image = reader
+        .decode()
+        .map_err(|e| invalid(&format!("Can't decode the image: {}", e)))?;
+    Ok((image, format))
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat, quality: u8) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    match format {
+        // JPEG has no alpha channel
+        ImageFormat::Jpeg => image
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, quality))?,
+        ImageFormat::Gif | ImageFormat::WebP => image
+            .to_rgba8()
+            .write_to(&mut Cursor::new(&mut bytes), format)?,
+        _ => image.write_to(&mut Cursor::new(&mut bytes), format)?,
+    }
+    Ok(bytes)
+}
+
+// The image's bytes from `data` or the resource at `uri`
+async fn input(
+    server: &Weak<McpServer>,
+    context: &ToolContext,
+    arguments: &HashMap<String, Value>,
+) -> Result<Vec<u8>> {
+    let (encoded, source) = match (
+        arguments.get("data").and_then(|v| v.as_str()),
+        arguments.get("uri").and_then(|v| v.as_str()),
+    ) {
+        (Some(data), None) => (data.to_string(), "data".to_string()),
+        (None, Some(uri)) => {
+            let server = server
+                .upgrade()
+                .ok_or_else(|| anyhow::anyhow!("The server is shutting down"))?;
+            let blob = server
+                .read_resource_for(context, uri)
+                .await?
+                .into_iter()
+                .find_map(|content| content.blob)
+                .ok_or_else(|| invalid(&format!("{} isn't a binary resource", uri)))?;
+            (blob, uri.to_string())
+        }
+        _ => return Err(invalid("Exactly one of data or uri is required")),
+    };
+    // Data URLs are accepted too
+    let encoded = match encoded.split_once(";base64,") {
+        Some((prefix, rest)) if prefix.starts_with("data:") => rest,
+        _ => &encoded,
+    };
+    base64_decode(encoded.trim()).ok_or_else(|| invalid(&format!("{} isn't valid base64", source)))
+}
+
+// Reads resources through `server`, as the calling session would
+pub fn tool(server: &Arc<McpServer>) -> RegisteredTool {
+    let server: Weak<McpServer> = Arc::downgrade(server);
+    RegisteredTool::new(definition(), move |arguments, context| {
+        let server = server.clone();
+        async move {
+            let operation = Operation::parse(&arguments)?;
+            let format = arguments
+                .get("format")
+                .and_then(|v| v.as_str())
+                .map(output_format)
+                .transpose()?;
+            let quality = arguments
+                .get("quality")
+                .and_then(|v| v.as_u64())
+                .map_or(DEFAULT_JPEG_QUALITY, |quality| quality.clamp(1, 100) as u8);
+            let bytes = input(&server, &context, &arguments).await?;
+
+            // Decoding and resampling are CPU-bound
+            let (encoded, format, before, after) = tokio::task::spawn_blocking(move || {
+                let (image, input_format) = decode(&bytes)?;
+                let before = (image.width(), image.height());
+                let image = operation.apply(image)?;
+                let format = format.unwrap_or(input_format);
+                let encoded = encode(&image, format, quality)?;
+                anyhow::Ok((encoded, format, before, (image.width(), image.height())))
+            })
+            .await??;
+
+            let summary = format!(
+                "{}x{} -> {}x{} {} ({} bytes)",
+                before.0,
+                before.1,
+                after.0,
+                after.1,
+                format.extensions_str().first().copied().unwrap_or("image"),
+                encoded.len()
+            );
+            let mut result = CallToolResult::text(summary);
+            result.content.push(Content::image(
+                base64_encode(&encoded),
+                format.to_mime_type(),
+            ));
+            Ok(result)
+        }
+    })
+}
+
+fn definition() -> Tool {
+    Tool {
+        name: "image".to_string(),
+        title: Some("Image".to_string()),
+        description: "Resizes, crops, thumbnails or converts a PNG, JPEG, GIF, WebP or BMP image given as base64 or a resource URI, and returns the result as an image".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "operation": {
+                    "type": "string",
+                    "enum": ["resize", "crop", "thumbnail", "convert"],
+                    "description": "convert only changes the format"
+                },
+                "data": {
+                    "type": "string",
+                    "description": "The image as base64, or a base64 data URL"
+                },
+                "uri": {
+                    "type": "string",
+                    "description": "Binary resource to read the image from instead"
+                },
+                "width": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "resize: target width; crop: width of the area"
+                },
+                "height": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "resize: target height; crop: height of the area"
+                },
+                "keep_aspect": {
+                    "type": "boolean",
+                    "description": "resize: fit inside width x height instead of stretching to it (default true)"
+                },
+                "x": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "description": "crop: left edge of the area"
+                },
+                "y": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "description": "crop: top edge of the area"
+                },
+                "size": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "thumbnail: longest side (default 128)"
+                },
+                "format": {
+                    "type": "string",
+                    "enum": ["png", "jpeg", "gif", "webp", "bmp"],
+                    "description": "Output format (default the input's)"
+                },
+                "quality": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": 100,
+                    "description": "JPEG quality (default 85)"
+                }
+            },
+            "required": ["operation"]
+        }),
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(true),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    }
+}
@@ -0,0 +1,157 @@
+use anyhow::Result;
+use std::path::Path;
+use tracing::info;
+
+use crate::config::Config;
+use crate::healthcheck;
+use crate::service;
+
+// Flags that belong to `install` itself; everything else is passed through
+// to the generated server command line
+const INSTALL_FLAGS: &[&str] = &["--target", "--output", "--name"];
+
+/// `install --target claude-desktop|cursor|systemd|windows-service`: prints
+/// (or writes to `--output`) what a host needs to launch this server with
+/// the same flags. JSON targets are merged into an existing `--output`
+/// file. `windows-service` registers the service directly instead.
+pub fn run(args: &[String], config: &Config) -> Result<()> {
+    let target = crate::arg_value(args, "--target")?.ok_or_else(|| {
+        anyhow::anyhow!("Usage: install --target claude-desktop|cursor|systemd|windows-service [--output PATH] [--name NAME] [server flags]")
+    })?;
+    let name = crate::arg_value(args, "--name")?.unwrap_or("test-mcp");
+    let output = crate::arg_value(args, "--output")?;
+    let server_args = server_args(args)?;
+    let http = crate::arg_value(&server_args, "--http")?;
+    let tcp = crate::arg_value(&server_args, "--tcp")?;
+
+    let generated = match target {
+        "claude-desktop" | "cursor" => {
+            let entry = match (target, http, tcp) {
+                ("cursor", Some(addr), _) => serde_json::json!({
+                    "url": format!(
+                        "http://{}{}{}",
+                        healthcheck::local_addr(addr),
+                        config.http.base_path.trim_end_matches('/'),
+                        config.http.mcp_path
+                    )
+                }),
+                (_, None, None) => serde_json::json!({
+                    "command": executable()?,
+                    "args": server_args,
+                    "env": environment(),
+                }),
+                _ => anyhow::bail!(
+                    "{} can't reach this transport; use stdio{}",
+                    target,
+                    if target == "cursor" { " or --http" } else { "" }
+                ),
+            };
+            let mut document = match output.filter(|path| Path::new(path).exists()) {
+                Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)
+                    .map_err(|e| anyhow::anyhow!("Can't merge into {}: {}", path, e))?,
+                None => serde_json::json!({}),
+            };
+            if !document["mcpServers"].is_object() {
+                document["mcpServers"] = serde_json::json!({});
+            }
+            document["mcpServers"][name] = entry;
+            format!("{}\n", serde_json::to_string_pretty(&document)?)
+        }
+        "systemd" => {
+            if http.is_none() && tcp.is_none() {
+                anyhow::bail!("A systemd service needs --http or --tcp");
+            }
+            if server_args.iter().any(|arg| arg == "--daemon") {
+                anyhow::bail!("systemd runs the server in the foreground; drop --daemon");
+            }
+            systemd_unit(name, &server_args)?
+        }
+        "windows-service" => {
+            if http.is_none() && tcp.is_none() {
+                anyhow::bail!("A Windows service needs --http or --tcp");
+            }
+            if server_args.iter().any(|arg| arg == "--daemon") {
+                anyhow::bail!("Windows services run in the foreground; drop --daemon");
+            }
+            return service::install(name, &server_args);
+        }
+        _ => anyhow::bail!(
+            "Unknown target '{}', expected claude-desktop, cursor, systemd or windows-service",
+            target
+        ),
+    };
+
+    match output {
+        Some(path) => {
+            std::fs::write(path, generated)?;
+            info!("Wrote {} configuration to {}", target, path);
+        }
+        None => print!("{}", generated),
+    }
+    Ok(())
+}
+
+// The server flags from the `install` command line, with `--config` made
+// absolute so the host can find it from any working directory
+fn server_args(args: &[String]) -> Result<Vec<String>> {
+    let mut server_args = Vec::new();
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        if INSTALL_FLAGS.contains(&arg.as_str()) {
+            iter.next();
+            continue;
+        }
+        server_args.push(arg.clone());
+        if arg == "--config" {
+            if let Some(path) = iter.next() {
+                let path = std::fs::canonicalize(path)
+                    .map_err(|e| anyhow::anyhow!("Can't find config {}: {}", path, e))?;
+                server_args.push(path.display().to_string());
+            }
+        }
+    }
+    Ok(server_args)
+}
+
+fn executable() -> Result<String> {
+    Ok(std::env::current_exe()?.display().to_string())
+}
+
+// Only the log filter is worth carrying over from the current environment
+fn environment() -> serde_json::Map<String, serde_json::Value> {
+    std::env::var("RUST_LOG")
+        .ok()
+        .map(|filter| ("RUST_LOG".to_string(), serde_json::json!(filter)))
+        .into_iter()
+        .collect()
+}
+
+fn systemd_unit(name: &str, server_args: &[String]) -> Result<String> {
+    let quote = |arg: &str| {
+        if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '"') {
+            format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+        } else {
+            arg.to_string()
+        }
+    };
+    let command: Vec<String> = std::iter::once(executable()?)
+        .chain(server_args.iter().cloned())
+        .map(|arg| quote(&arg))
+        .collect();
+
+    let mut unit = format!(
+        "[Unit]\nDescription={} MCP server\nAfter=network.target\n\n[Service]\nExecStart={}\nWorkingDirectory={}\n",
+        name,
+        command.join(" "),
+        std::env::current_dir()?.display()
+    );
+    for (key, value) in environment() {
+        unit.push_str(&format!(
+            "Environment={}={}\n",
+            key,
+            value.as_str().unwrap_or_default()
+        ));
+    }
+    unit.push_str("Restart=on-failure\n\n[Install]\nWantedBy=multi-user.target\n");
+    Ok(unit)
+}
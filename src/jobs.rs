@@ -0,0 +1,421 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Instant;
+use tokio::task::AbortHandle;
+use tracing::info;
+
+use crate::error::ServerError;
+use crate::resources::RegisteredResource;
+use crate::tools::RegisteredTool;
+use crate::types::{CallToolResult, Resource, ResourceContents, Tool};
+
+// Finished jobs kept for `job_result` before the oldest are forgotten
+const MAX_FINISHED_JOBS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Running => "running",
+            JobState::Completed => "completed",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+        }
+    }
+}
+
+struct Job {
+    tool: String,
+    // Only the session that started a job can see or cancel it
+    session_id: String,
+    state: JobState,
+    started: Instant,
+    finished: Option<Instant>,
+    result: Option<CallToolResult>,
+    error: Option<String>,
+    abort: Option<AbortHandle>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobStatus {
+    pub id: String,
+    pub tool: String,
+    pub state: JobState,
+    pub elapsed_secs: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Runs tool work in the background so it can outlive the request that
+/// started it. Clients follow up with the `job_status`, `job_result` and
+/// `job_cancel` tools, or read the state of all their jobs from
+/// `jobs://all`. Each job belongs to the session that started it; to any
+/// other session it is unknown.
+#[derive(Default)]
+pub struct JobManager {
+    jobs: Mutex<HashMap<String, Job>>,
+}
+
+impl JobManager {
+    // Starts `work` in the background for the session and returns the new
+    // job's id
+    pub fn submit<Fut>(self: &Arc<Self>, tool: &str, session_id: &str, work: Fut) -> String
+    where
+        Fut: Future<Output = Result<CallToolResult>> + Send + 'static,
+    {
+        let id = uuid::Uuid::new_v4().to_string();
+        self.jobs.lock().unwrap().insert(
+            id.clone(),
+            Job {
+                tool: tool.to_string(),
+                session_id: session_id.to_string(),
+                state: JobState::Running,
+                started: Instant::now(),
+                finished: None,
+                result: None,
+                error: None,
+                abort: None,
+            },
+        );
+
+        let manager: Weak<JobManager> = Arc::downgrade(self);
+        let job_id = id.clone();
+        let handle = tokio::spawn(async move {
+            let outcome = work.await;
+            if let Some(manager) = manager.upgrade() {
+                manager.finish(&job_id, outcome);
+            }
+        });
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&id) {
+            job.abort = Some(handle.abort_handle());
+        }
+        info!("Started job {} for {}", id, tool);
+        id
+    }
+
+    fn finish(&self, id: &str, outcome: Result<CallToolResult>) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.get_mut(id) {
+            if job.state != JobState::Running {
+                return;
+            }
+            match outcome {
+                Ok(result) => {
+                    job.state = JobState::Completed;
+                    job.result = Some(result);
+                }
+                Err(e) => {
+                    job.state = JobState::Failed;
+                    job.error = Some(e.to_string());
+                }
+            }
+            job.finished = Some(Instant::now());
+            info!("Job {} {}", id, job.state.as_str());
+        }
+        evict_finished(&mut jobs);
+    }
+
+    pub fn status(&self, session_id: &str, id: &str) -> Option<JobStatus> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(id)
+            .filter(|job| job.session_id == session_id)
+            .map(|job| job_status(id, job))
+    }
+
+    pub fn statuses(&self, session_id: &str) -> Vec<JobStatus> {
+        let jobs = self.jobs.lock().unwrap();
+        let mut statuses: Vec<JobStatus> = jobs
+            .iter()
+            .filter(|(_, job)| job.session_id == session_id)
+            .map(|(id, job)| job_status(id, job))
+            .collect();
+        statuses.sort_by(|a, b| b.elapsed_secs.total_cmp(&a.elapsed_secs));
+        statuses
+    }
+
+    // Stops a running job. Returns its state afterwards, or None if unknown.
+    pub fn cancel(&self, session_id: &str, id: &str) -> Option<JobState> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs
+            .get_mut(id)
+            .filter(|job| job.session_id == session_id)?;
+        if job.state == JobState::Running {
+            if let Some(abort) = &job.abort {
+                abort.abort();
+            }
+            job.state = JobState::Cancelled;
+            job.finished = Some(Instant::now());
+            info!("Cancelled job {}", id);
+        }
+        Some(job.state)
+    }
+
+    /// Wraps a tool so each call runs as a job: the call returns at once
+    /// with the job id (also in `_meta.jobId`), and the tool's real result
+    /// is fetched later with `job_result`.
+    pub fn background(self: &Arc<Self>, registered: RegisteredTool) -> RegisteredTool {
+        let manager = self.clone();
+        let name = registered.tool.name.clone();
+        let handler = registered.handler.clone();
+        let mut background = registered;
+        background.tool.description = format!(
+            "{} Runs in the background: returns a job id to pass to job_status, job_result and job_cancel.",
+            background.tool.description
+        );
+        background.handler = Arc::new(move |arguments, context| {
+            let session_id = context.session_id.clone();
+            let id = manager.submit(&name, &session_id, handler(arguments, context));
+            Box::pin(async move {
+                Ok(CallToolResult::text(format!("Started job {}", id))
+                    .with_meta("jobId", serde_json::json!(id)))
+            })
+        });
+        background
+    }
+
+    // The job_status, job_result and job_cancel tools
+    pub fn tools(self: &Arc<Self>) -> Vec<RegisteredTool> {
+        let manager = self.clone();
+        let status = RegisteredTool::new(
+            job_tool(
+                "job_status",
+                "Job Status",
+                "Reports the state of a background job",
+            ),
+            move |arguments, context| {
+                let manager = manager.clone();
+                async move {
+                    let id = job_id(&arguments)?;
+                    let status = manager
+                        .status(&context.session_id, &id)
+                        .ok_or_else(|| unknown_job(&id))?;
+                    Ok(CallToolResult::text(serde_json::to_string(&status)?))
+                }
+            },
+        );
+
+        let manager = self.clone();
+        let result = RegisteredTool::new(
+            job_tool(
+                "job_result",
+                "Job Result",
+                "Returns the result of a finished background job",
+            ),
+            move |arguments, context| {
+                let manager = manager.clone();
+                async move {
+                    let id = job_id(&arguments)?;
+                    manager
+                        .result(&context.session_id, &id)
+                        .ok_or_else(|| unknown_job(&id).into())
+                }
+            },
+        );
+
+        let manager = self.clone();
+        let cancel = RegisteredTool::new(
+            job_tool(
+                "job_cancel",
+                "Cancel Job",
+                "Cancels a running background job",
+            ),
+            move |arguments, context| {
+                let manager = manager.clone();
+                async move {
+                    let id = job_id(&arguments)?;
+                    let state = manager
+                        .cancel(&context.session_id, &id)
+                        .ok_or_else(|| unknown_job(&id))?;
+                    Ok(CallToolResult::text(format!(
+                        "Job {} is {}",
+                        id,
+                        state.as_str()
+                    )))
+                }
+            },
+        );
+
+        vec![status, result, cancel]
+    }
+
+    // The job's own result once completed; otherwise an error result
+    // saying why there is none (yet)
+    fn result(&self, session_id: &str, id: &str) -> Option<CallToolResult> {
+        let jobs = self.jobs.lock().unwrap();
+        let job = jobs.get(id).filter(|job| job.session_id == session_id)?;
+        let result = match (job.state, &job.result) {
+            (JobState::Completed, Some(result)) => result.clone(),
+            (JobState::Failed, _) => error_result(format!(
+                "Job {} failed: {}",
+                id,
+                job.error.as_deref().unwrap_or_default()
+            )),
+            (JobState::Cancelled, _) => error_result(format!("Job {} was cancelled", id)),
+            _ => error_result(format!("Job {} is still running", id)),
+        };
+        Some(result)
+    }
+
+    // Exposes the state of the reading session's jobs as JSON at `jobs://all`
+    pub fn resource(self: &Arc<Self>) -> RegisteredResource {
+        let resource = Resource {
+            uri: "jobs://all".to_string(),
+            name: "Background jobs".to_string(),
+            title: None,
+            description: "State of your running and recently finished background jobs".to_string(),
+            mime_type: "application/json".to_string(),
+            icons: Vec::new(),
+            size: None,
+            annotations: None,
+        };
+
+        let manager = self.clone();
+        RegisteredResource::per_session(resource, move |uri, session_id, _| {
+            let statuses = manager.statuses(&session_id);
+            async move {
+                Ok(vec![ResourceContents::text(
+                    uri,
+                    "application/json",
+                    serde_json::to_string_pretty(&statuses)?,
+                )])
+            }
+        })
+    }
+}
+
+fn job_status(id: &str, job: &Job) -> JobStatus {
+    let elapsed = match job.finished {
+        Some(finished) => finished - job.started,
+        None => job.started.elapsed(),
+    };
+    JobStatus {
+        id: id.to_string(),
+        tool: job.tool.clone(),
+        state: job.state,
+        elapsed_secs: elapsed.as_secs_f64(),
+        error: job.error.clone(),
+    }
+}
+
+fn evict_finished(jobs: &mut HashMap<String, Job>) {
+    let mut finished: Vec<(Instant, String)> = jobs
+        .iter()
+        .filter_map(|(id, job)| job.finished.map(|finished| (finished, id.clone())))
+        .collect();
+    if finished.len() <= MAX_FINISHED_JOBS {
+        return;
+    }
+    finished.sort();
+    for (_, id) in &finished[..finished.len() - MAX_FINISHED_JOBS] {
+        jobs.remove(id);
+    }
+}
+
+fn error_result(text: String) -> CallToolResult {
+    CallToolResult {
+        is_error: true,
+        structured_content: None,
+        ..CallToolResult::text(text)
+    }
+}
+
+fn job_id(arguments: &HashMap<String, serde_json::Value>) -> Result<String> {
+    arguments
+        .get("job_id")
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("Missing 'job_id' argument"))
+}
+
+fn unknown_job(id: &str) -> ServerError {
+    ServerError::InvalidParams {
+        details: format!("Unknown job: {}", id),
+    }
+}
+
+fn job_tool(name: &str, title: &str, description: &str) -> Tool {
+    Tool {
+        name: name.to_string(),
+        title: Some(title.to_string()),
+        description: description.to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "job_id": {
+                    "type": "string",
+                    "description": "The id returned when the job was started"
+                }
+            },
+            "required": ["job_id"]
+        }),
+        output_schema: None,
+        annotations: None,
+        icons: Vec::new(),
+        meta: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::server::McpServer;
+    use crate::tools::RegisteredTool;
+    use crate::types::Tool;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn blocking_tools_run_as_jobs() {
+        let server = McpServer::new(Config::default());
+        let jobs = Arc::new(JobManager::default());
+        let tool = Tool {
+            name: "hash".to_string(),
+            title: None,
+            description: "CPU-bound".to_string(),
+            input_schema: serde_json::json!({ "type": "object" }),
+            output_schema: None,
+            annotations: None,
+            icons: Vec::new(),
+            meta: None,
+        };
+        let (release, wait) = std::sync::mpsc::channel::<()>();
+        let wait = Mutex::new(wait);
+        server.add_tool(jobs.background(RegisteredTool::blocking(tool, move |_, _| {
+            wait.lock().unwrap().recv()?;
+            Ok(CallToolResult::text("hashed"))
+        })));
+
+        // Answered while the blocking work is still waiting
+        let (session, _outbound) = server.connect();
+        let call = r#"{"jsonrpc": "2.0", "id": 1, "method": "tools/call",
+            "params": {"name": "hash", "arguments": {}}}"#;
+        let response = server.handle_message(&session, call).await.unwrap();
+        let response = serde_json::to_value(response).unwrap();
+        let id = response["result"]["_meta"]["jobId"].as_str().unwrap();
+        let state = |jobs: &JobManager| jobs.status(&session.id, id).unwrap().state;
+        assert_eq!(state(&jobs), JobState::Running);
+
+        release.send(()).unwrap();
+        for _ in 0..100 {
+            if state(&jobs) != JobState::Running {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(state(&jobs), JobState::Completed);
+    }
+}
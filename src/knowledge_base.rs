@@ -0,0 +1,330 @@
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::{Arc, Mutex, Weak};
+use tracing::info;
+
+use crate::config::KnowledgeBaseConfig;
+use crate::error::ServerError;
+use crate::resources::RegisteredResource;
+use crate::server::McpServer;
+use crate::tools::RegisteredTool;
+use crate::types::{CallToolResult, Resource, ResourceContents, Tool, ToolAnnotations};
+
+const DEFAULT_SEARCH_LIMIT: usize = 5;
+const MAX_SEARCH_LIMIT: usize = 50;
+
+// `documents_fts` indexes `documents` without a copy of the text; the
+// triggers keep it in step
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS documents (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    body TEXT NOT NULL
+);
+CREATE VIRTUAL TABLE IF NOT EXISTS documents_fts USING fts5(
+    name, body, content = 'documents', content_rowid = 'id'
+);
+CREATE TRIGGER IF NOT EXISTS documents_insert AFTER INSERT ON documents BEGIN
+    INSERT INTO documents_fts (rowid, name, body) VALUES (new.id, new.name, new.body);
+END;
+CREATE TRIGGER IF NOT EXISTS documents_delete AFTER DELETE ON documents BEGIN
+    INSERT INTO documents_fts (documents_fts, rowid, name, body)
+        VALUES ('delete', old.id, old.name, old.body);
+END;
+CREATE TRIGGER IF NOT EXISTS documents_update AFTER UPDATE ON documents BEGIN
+    INSERT INTO documents_fts (documents_fts, rowid, name, body)
+        VALUES ('delete', old.id, old.name, old.body);
+    INSERT INTO documents_fts (rowid, name, body) VALUES (new.id, new.name, new.body);
+END;
+";
+
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    pub uri: String,
+    pub name: String,
+    pub snippet: String,
+    // Higher is a better match
+    pub score: f64,
+}
+
+/// Documents in an SQLite database with an FTS5 index. Each document is a
+/// `kb://documents/{id}` resource, and `kb_search` runs full-text queries
+/// over them.
+pub struct KnowledgeBase {
+    db: Mutex<Connection>,
+}
+
+impl KnowledgeBase {
+    pub fn open(config: &KnowledgeBaseConfig) -> Result<Self> {
+        let db = Connection::open(&config.path).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to open knowledge base {}: {}",
+                config.path.display(),
+                e
+            )
+        })?;
+        db.execute_batch(SCHEMA)?;
+        let kb = Self { db: Mutex::new(db) };
+        if let Some(dir) = &config.import_dir {
+            let imported = kb.import_dir(dir)?;
+            info!("Imported {} documents from {}", imported, dir.display());
+        }
+        Ok(kb)
+    }
+
+    // Adds the document called `name`, or replaces its text, and returns
+    // its id
+    pub fn upsert(&self, name: &str, body: &str) -> Result<i64> {
+        let db = self.db.lock().unwrap();
+        db.execute(
+            "INSERT INTO documents (name, body) VALUES (?1, ?2)
+             ON CONFLICT (name) DO UPDATE SET body = excluded.body",
+            params![name, body],
+        )?;
+        Ok(
+            db.query_row("SELECT id FROM documents WHERE name = ?1", [name], |row| {
+                row.get(0)
+            })?,
+        )
+    }
+
+    // Every `.md`/`.txt` file in `dir`, named after the file
+    fn import_dir(&self, dir: &Path) -> Result<usize> {
+        let mut imported = 0;
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let is_document = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| ext == "md" || ext == "txt");
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if !path.is_file() || !is_document {
+                continue;
+            }
+            self.upsert(name, &std::fs::read_to_string(&path)?)?;
+            imported += 1;
+        }
+        Ok(imported)
+    }
+
+    pub fn list(&self) -> Result<Vec<(i64, String)>> {
+        let db = self.db.lock().unwrap();
+        let mut statement = db.prepare("SELECT id, name FROM documents ORDER BY name")?;
+        let documents = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(documents)
+    }
+
+    pub fn body(&self, id: i64) -> Result<Option<String>> {
+        let db = self.db.lock().unwrap();
+        Ok(db
+            .query_row("SELECT body FROM documents WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .optional()?)
+    }
+
+    // Runs an FTS5 query (words, "phrases", AND/OR/NOT, prefix*), best
+    // matches first
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        let db = self.db.lock().unwrap();
+        let mut statement = db.prepare(
+            "SELECT d.id, d.name, snippet(documents_fts, 1, '**', '**', '…', 16), bm25(documents_fts)
+             FROM documents_fts JOIN documents d ON d.id = documents_fts.rowid
+             WHERE documents_fts MATCH ?1
+             ORDER BY bm25(documents_fts)
+             LIMIT ?2",
+        )?;
+        let hits = statement
+            .query_map(params![query, limit as i64], |row| {
+                let id: i64 = row.get(0)?;
+                let score: f64 = row.get(3)?;
+                Ok(SearchHit {
+                    uri: document_uri(id),
+                    name: row.get(1)?,
+                    snippet: row.get(2)?,
+                    // bm25 is lower for better matches
+                    score: -score,
+                })
+            })
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+            .map_err(|e| ServerError::InvalidParams {
+                details: format!("Invalid search query: {}", e),
+            })?;
+        Ok(hits)
+    }
+
+    pub fn resources(self: &Arc<Self>) -> Result<Vec<RegisteredResource>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .map(|(id, name)| self.resource(id, &name))
+            .collect())
+    }
+
+    fn resource(self: &Arc<Self>, id: i64, name: &str) -> RegisteredResource {
+        let mime_type = if name.ends_with(".md") {
+            "text/markdown"
+        } else {
+            "text/plain"
+        };
+        let resource = Resource {
+            uri: document_uri(id),
+            name: name.to_string(),
+            title: None,
+            description: format!("Knowledge base document {}", name),
+            mime_type: mime_type.to_string(),
+            icons: Vec::new(),
+            size: None,
+            annotations: None,
+        };
+        let kb = self.clone();
+        RegisteredResource::new(resource, move |uri| {
+            let kb = kb.clone();
+            async move {
+                let body = tokio::task::spawn_blocking(move || kb.body(id)).await??;
+                let body =
+                    body.ok_or_else(|| ServerError::ResourceNotFound { uri: uri.clone() })?;
+                Ok(vec![ResourceContents::text(uri, mime_type, body)])
+            }
+        })
+    }
+
+    // `kb_search`, and `kb_add`, which also registers the new document's
+    // resource with `server`
+    pub fn tools(self: &Arc<Self>, server: &Arc<McpServer>) -> Vec<RegisteredTool> {
+        let kb = self.clone();
+        let search = RegisteredTool::blocking(search_tool(), move |arguments, _| {
+            let query = arguments
+                .get("query")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'query' argument"))?;
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .map_or(DEFAULT_SEARCH_LIMIT, |limit| limit as usize)
+                .clamp(1, MAX_SEARCH_LIMIT);
+
+            let hits = kb.search(query, limit)?;
+            let text = if hits.is_empty() {
+                format!("No documents match {}", query)
+            } else {
+                hits.iter()
+                    .map(|hit| format!("{} ({})\n{}", hit.name, hit.uri, hit.snippet))
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            };
+            Ok(CallToolResult::text(text)
+                .with_structured_content(serde_json::json!({ "results": hits })))
+        })
+        .with_output_schema(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "results": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "uri": { "type": "string" },
+                            "name": { "type": "string" },
+                            "snippet": { "type": "string" },
+                            "score": { "type": "number" }
+                        },
+                        "required": ["uri", "name", "snippet", "score"]
+                    }
+                }
+            },
+            "required": ["results"]
+        }));
+
+        let kb = self.clone();
+        let server: Weak<McpServer> = Arc::downgrade(server);
+        let add = RegisteredTool::blocking(add_tool(), move |arguments, _| {
+            let name = arguments
+                .get("name")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'name' argument"))?;
+            let text = arguments
+                .get("text")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'text' argument"))?;
+            let id = kb.upsert(name, text)?;
+            if let Some(server) = server.upgrade() {
+                server.add_resource(kb.resource(id, name));
+            }
+            Ok(CallToolResult::text(format!(
+                "Stored {} as {}",
+                name,
+                document_uri(id)
+            )))
+        });
+
+        vec![search, add]
+    }
+}
+
+fn document_uri(id: i64) -> String {
+    format!("kb://documents/{}", id)
+}
+
+fn search_tool() -> Tool {
+    Tool {
+        name: "kb_search".to_string(),
+        title: Some("Search Knowledge Base".to_string()),
+        description: "Full-text search over the knowledge base. Supports words, \"phrases\", AND/OR/NOT and prefix* queries; returns the best matching documents with snippets".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "Search query"
+                },
+                "limit": {
+                    "type": "integer",
+                    "description": "Most results to return (default 5, at most 50)"
+                }
+            },
+            "required": ["query"]
+        }),
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(true),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    }
+}
+
+fn add_tool() -> Tool {
+    Tool {
+        name: "kb_add".to_string(),
+        title: Some("Add to Knowledge Base".to_string()),
+        description:
+            "Stores a document in the knowledge base, replacing any document with the same name"
+                .to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "name": {
+                    "type": "string",
+                    "description": "Document name, e.g. a file name"
+                },
+                "text": {
+                    "type": "string",
+                    "description": "Document text"
+                }
+            },
+            "required": ["name", "text"]
+        }),
+        output_schema: None,
+        annotations: None,
+        icons: Vec::new(),
+        meta: None,
+    }
+}
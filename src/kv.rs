@@ -0,0 +1,366 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::error::ServerError;
+use crate::tools::{RegisteredTool, ToolContext};
+use crate::types::{CallToolResult, Tool, ToolAnnotations};
+
+const MAX_KEY_BYTES: usize = 256;
+const GLOBAL_NAMESPACE: &str = "global";
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KvScope {
+    // Every session sees the same keys
+    #[default]
+    Global,
+    // Each session has its own keys, found again if it resumes
+    Session,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct KvConfig {
+    // JSON file the values are kept in between runs
+    pub path: PathBuf,
+    pub scope: KvScope,
+    // Largest value, as serialized JSON
+    pub max_value_bytes: usize,
+    pub max_keys: usize,
+}
+
+impl Default for KvConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("kv.json"),
+            scope: KvScope::Global,
+            max_value_bytes: 65_536,
+            max_keys: 1000,
+        }
+    }
+}
+
+/// Small JSON values stored by key, for agents to keep state between
+/// runs. Every change is written through to `path`.
+pub struct KvStore {
+    config: KvConfig,
+    // Namespace -> key -> value
+    namespaces: Mutex<HashMap<String, BTreeMap<String, Value>>>,
+}
+
+impl KvStore {
+    pub fn open(config: &KvConfig) -> Result<Self> {
+        let namespaces = match std::fs::read_to_string(&config.path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| {
+                anyhow::anyhow!("{} isn't a key-value store: {}", config.path.display(), e)
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => anyhow::bail!("Failed to read {}: {}", config.path.display(), e),
+        };
+        Ok(Self {
+            config: config.clone(),
+            namespaces: Mutex::new(namespaces),
+        })
+    }
+
+    fn namespace(&self, context: &ToolContext) -> String {
+        match self.config.scope {
+            KvScope::Global => GLOBAL_NAMESPACE.to_string(),
+            KvScope::Session => format!("session:{}", context.session_id),
+        }
+    }
+
+    // Written to a temporary file and renamed over the old one, so a crash
+    // mid-write can't lose what was there
+    fn save(&self, namespaces: &HashMap<String, BTreeMap<String, Value>>) -> Result<()> {
+        let path = &self.config.path;
+        let temporary = path.with_extension("json.tmp");
+        std::fs::write(&temporary, serde_json::to_string(namespaces)?)
+            .and_then(|_| std::fs::rename(&temporary, path))
+            .map_err(|e| anyhow::anyhow!("Failed to save {}: {}", path.display(), e))
+    }
+
+    fn get(&self, namespace: &str, key: &str) -> Option<Value> {
+        self.namespaces
+            .lock()
+            .unwrap()
+            .get(namespace)
+            .and_then(|keys| keys.get(key))
+            .cloned()
+    }
+
+    fn set(&self, namespace: &str, key: &str, value: Value) -> Result<()> {
+        let size = value.to_string().len();
+        if size > self.config.max_value_bytes {
+            return Err(invalid(format!(
+                "The value is {} bytes; the limit is {}",
+                size, self.config.max_value_bytes
+            )));
+        }
+        let mut namespaces = self.namespaces.lock().unwrap();
+        let keys = namespaces.entry(namespace.to_string()).or_default();
+        if !keys.contains_key(key) && keys.len() >= self.config.max_keys {
+            return Err(invalid(format!(
+                "The store is full at {} keys; delete some first",
+                self.config.max_keys
+            )));
+        }
+        let previous = keys.insert(key.to_string(), value);
+        if let Err(e) = self.save(&namespaces) {
+            // Keep memory in step with the file
+            let keys = namespaces.entry(namespace.to_string()).or_default();
+            match previous {
+                Some(previous) => keys.insert(key.to_string(), previous),
+                None => keys.remove(key),
+            };
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    // Whether the key was there
+    fn delete(&self, namespace: &str, key: &str) -> Result<bool> {
+        let mut namespaces = self.namespaces.lock().unwrap();
+        let Some(keys) = namespaces.get_mut(namespace) else {
+            return Ok(false);
+        };
+        let Some(previous) = keys.remove(key) else {
+            return Ok(false);
+        };
+        if keys.is_empty() {
+            namespaces.remove(namespace);
+        }
+        if let Err(e) = self.save(&namespaces) {
+            namespaces
+                .entry(namespace.to_string())
+                .or_default()
+                .insert(key.to_string(), previous);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    fn list(&self, namespace: &str, prefix: &str) -> Vec<String> {
+        self.namespaces
+            .lock()
+            .unwrap()
+            .get(namespace)
+            .map(|keys| {
+                keys.keys()
+                    .filter(|key| key.starts_with(prefix))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // `kv_get`, `kv_set`, `kv_delete` and `kv_list`
+    pub fn tools(self: &Arc<Self>) -> Vec<RegisteredTool> {
+        let store = self.clone();
+        let get = RegisteredTool::new(get_tool(), move |arguments, context| {
+            let store = store.clone();
+            async move {
+                let key = key(&arguments)?;
+                let namespace = store.namespace(&context);
+                let (text, structured) = match store.get(&namespace, key) {
+                    Some(value) => (
+                        serde_json::to_string_pretty(&value)?,
+                        serde_json::json!({ "key": key, "found": true, "value": value }),
+                    ),
+                    None => (
+                        format!("No value is stored under '{}'", key),
+                        serde_json::json!({ "key": key, "found": false }),
+                    ),
+                };
+                Ok(CallToolResult::text(text).with_structured_content(structured))
+            }
+        })
+        .with_output_schema(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "key": { "type": "string" },
+                "found": { "type": "boolean" },
+                "value": { "description": "The stored JSON value, if found" }
+            },
+            "required": ["key", "found"]
+        }));
+
+        let store = self.clone();
+        let set = RegisteredTool::new(set_tool(), move |arguments, context| {
+            let store = store.clone();
+            async move {
+                let key = key(&arguments)?;
+                let value = arguments
+                    .get("value")
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Missing 'value' argument"))?;
+                store.set(&store.namespace(&context), key, value)?;
+                Ok(CallToolResult::text(format!("Stored '{}'", key)))
+            }
+        });
+
+        let store = self.clone();
+        let delete = RegisteredTool::new(delete_tool(), move |arguments, context| {
+            let store = store.clone();
+            async move {
+                let key = key(&arguments)?;
+                let text = if store.delete(&store.namespace(&context), key)? {
+                    format!("Deleted '{}'", key)
+                } else {
+                    format!("No value is stored under '{}'", key)
+                };
+                Ok(CallToolResult::text(text))
+            }
+        });
+
+        let store = self.clone();
+        let list = RegisteredTool::new(list_tool(), move |arguments, context| {
+            let store = store.clone();
+            async move {
+                let prefix = arguments
+                    .get("prefix")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default();
+                let keys = store.list(&store.namespace(&context), prefix);
+                let text = if keys.is_empty() {
+                    "No keys are stored".to_string()
+                } else {
+                    keys.join("\n")
+                };
+                Ok(CallToolResult::text(text)
+                    .with_structured_content(serde_json::json!({ "keys": keys })))
+            }
+        })
+        .with_output_schema(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "keys": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["keys"]
+        }));
+
+        vec![get, set, delete, list]
+    }
+}
+
+fn invalid(details: String) -> anyhow::Error {
+    ServerError::InvalidParams { details }.into()
+}
+
+fn key(arguments: &HashMap<String, Value>) -> Result<&str> {
+    let key = arguments
+        .get("key")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Missing 'key' argument"))?;
+    if key.is_empty() || key.len() > MAX_KEY_BYTES {
+        return Err(invalid(format!(
+            "Keys must be 1 to {} bytes long",
+            MAX_KEY_BYTES
+        )));
+    }
+    Ok(key)
+}
+
+fn key_schema() -> Value {
+    serde_json::json!({
+        "type": "string",
+        "minLength": 1,
+        "maxLength": MAX_KEY_BYTES,
+        "description": "The key, e.g. \"project/last_run\""
+    })
+}
+
+fn get_tool() -> Tool {
+    Tool {
+        name: "kv_get".to_string(),
+        title: Some("Get Stored Value".to_string()),
+        description: "Reads the value stored under a key by kv_set".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": { "key": key_schema() },
+            "required": ["key"]
+        }),
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(true),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    }
+}
+
+fn set_tool() -> Tool {
+    Tool {
+        name: "kv_set".to_string(),
+        title: Some("Store Value".to_string()),
+        description: "Stores a JSON value under a key, replacing any value there. Stored values last across sessions and restarts".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "key": key_schema(),
+                "value": { "description": "Any JSON value" }
+            },
+            "required": ["key", "value"]
+        }),
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(false),
+            destructive_hint: Some(false),
+            idempotent_hint: Some(true),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    }
+}
+
+fn delete_tool() -> Tool {
+    Tool {
+        name: "kv_delete".to_string(),
+        title: Some("Delete Stored Value".to_string()),
+        description: "Deletes the value stored under a key".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": { "key": key_schema() },
+            "required": ["key"]
+        }),
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(false),
+            destructive_hint: Some(true),
+            idempotent_hint: Some(true),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    }
+}
+
+fn list_tool() -> Tool {
+    Tool {
+        name: "kv_list".to_string(),
+        title: Some("List Stored Keys".to_string()),
+        description: "Lists the keys that have stored values, in order".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "prefix": {
+                    "type": "string",
+                    "description": "Only keys starting with this"
+                }
+            }
+        }),
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(true),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    }
+}
@@ -0,0 +1,89 @@
+//! The MCP server behind the `test-mcp` binary, as a library so the
+//! benchmarks can drive it in-process.
+
+pub mod admin;
+pub mod admin_http;
+pub mod archive;
+pub mod auth;
+pub mod bench;
+pub mod buffer;
+pub mod cache;
+pub mod chaos;
+pub mod client_log;
+pub mod client_testing;
+pub mod compat;
+pub mod completion;
+pub mod compression;
+pub mod config;
+pub mod confirm;
+pub mod daemon;
+pub mod demo;
+pub mod envelope;
+pub mod error;
+pub mod event_store;
+pub mod events;
+pub mod experimental;
+pub mod files;
+pub mod framing;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod healthcheck;
+pub mod http;
+pub mod http_client;
+pub mod images;
+pub mod install;
+pub mod jobs;
+#[cfg(feature = "knowledge-base")]
+pub mod knowledge_base;
+pub mod kv;
+pub mod listener;
+pub mod locale;
+pub mod manifest;
+pub mod markdown;
+pub mod metrics;
+pub mod middleware;
+pub mod msgpack;
+pub mod notification;
+pub mod plugin;
+pub mod priority;
+pub mod prompts;
+pub mod proxy;
+pub mod ratelimit;
+pub mod resources;
+pub mod retrieval;
+pub mod retry;
+pub mod roots;
+pub mod sampling;
+pub mod scheduler;
+pub mod schema;
+pub mod server;
+pub mod server_info;
+pub mod service;
+pub mod session;
+#[cfg(feature = "sqlite-event-store")]
+pub mod sqlite_event_store;
+pub mod tabular;
+pub mod tcp;
+pub mod telemetry;
+pub mod templating;
+pub mod tools;
+pub mod transform;
+pub mod translation;
+pub mod types;
+pub mod uri_template;
+pub mod validation;
+pub mod vector_search;
+pub mod weather;
+
+use anyhow::Result;
+
+// Returns the value following `flag`, if the flag was given
+pub fn arg_value<'a>(args: &'a [String], flag: &str) -> Result<Option<&'a str>> {
+    match args.iter().position(|arg| arg == flag) {
+        Some(index) => args
+            .get(index + 1)
+            .map(|value| Some(value.as_str()))
+            .ok_or_else(|| anyhow::anyhow!("{} requires a value", flag)),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,100 @@
+use anyhow::Result;
+use std::io::Write;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+// systemd passes activated sockets starting at this descriptor
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Binds a network transport's listener. `addr` is `host:port`, or a bare
+/// port for localhost; port 0 picks a free port. Under systemd socket
+/// activation the inherited socket is used instead and `addr` is ignored.
+/// Once bound, the actual address is printed to stdout as one JSON line, e.g.
+/// `{"address":"127.0.0.1:41234","event":"listening","transport":"http"}`,
+/// so test harnesses can find it.
+pub async fn bind(transport: &str, addr: &str) -> Result<TcpListener> {
+    let listener = match activated_socket(transport)? {
+        Some(listener) => {
+            info!("Using socket passed by systemd for {}", transport);
+            listener.set_nonblocking(true)?;
+            TcpListener::from_std(listener)?
+        }
+        None => {
+            let addr = normalize(addr);
+            TcpListener::bind(&addr).await.map_err(|e| {
+                anyhow::anyhow!("Failed to bind {} transport to {}: {}", transport, addr, e)
+            })?
+        }
+    };
+    let local_addr = listener.local_addr()?;
+    info!(
+        "{} transport listening on {}",
+        transport.to_uppercase(),
+        local_addr
+    );
+
+    let line = serde_json::json!({
+        "event": "listening",
+        "transport": transport,
+        "address": local_addr.to_string(),
+    });
+    // Nobody may be reading stdout (e.g. as a daemon); that's not fatal
+    let mut stdout = std::io::stdout().lock();
+    if let Err(e) = writeln!(stdout, "{}", line).and_then(|_| stdout.flush()) {
+        warn!("Can't report bound address on stdout: {}", e);
+    }
+    Ok(listener)
+}
+
+// `host:port` for `addr`, which may be a bare port meaning localhost
+pub fn normalize(addr: &str) -> String {
+    match addr.parse::<u16>() {
+        Ok(port) => format!("127.0.0.1:{}", port),
+        Err(_) => addr.to_string(),
+    }
+}
+
+// Takes the listening socket systemd passed via `LISTEN_FDS`, if any. With
+// several sockets, the one named after the transport in `LISTEN_FDNAMES`
+// (`FileDescriptorName=` in the unit) is used, otherwise the first.
+#[cfg(unix)]
+fn activated_socket(transport: &str) -> Result<Option<std::net::TcpListener>> {
+    use std::os::fd::FromRawFd;
+
+    let for_us = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .is_some_and(|pid| pid == std::process::id());
+    let count = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse::<i32>().ok())
+        .unwrap_or(0);
+    if !for_us || count < 1 {
+        return Ok(None);
+    }
+    let names = std::env::var("LISTEN_FDNAMES").unwrap_or_default();
+    let index = names
+        .split(':')
+        .position(|name| name == transport)
+        .filter(|index| (*index as i32) < count)
+        .unwrap_or(0);
+
+    // Don't let anything we spawn think these sockets are theirs
+    for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(var);
+    }
+
+    // SAFETY: systemd guarantees descriptors LISTEN_FDS_START.. are open and
+    // ours, and nothing else in the process takes ownership of them
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(LISTEN_FDS_START + index as i32) };
+    listener
+        .local_addr()
+        .map_err(|e| anyhow::anyhow!("Socket passed by systemd is not a TCP listener: {}", e))?;
+    Ok(Some(listener))
+}
+
+#[cfg(not(unix))]
+fn activated_socket(_transport: &str) -> Result<Option<std::net::TcpListener>> {
+    Ok(None)
+}
@@ -0,0 +1,64 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Descriptions in one language, keyed by tool name, resource URI and prompt
+/// name. Anything missing falls back to the description the item was
+/// registered with.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Translations {
+    pub tools: HashMap<String, String>,
+    pub resources: HashMap<String, String>,
+    pub prompts: HashMap<String, String>,
+}
+
+impl Translations {
+    fn merge(&mut self, other: Translations) {
+        self.tools.extend(other.tools);
+        self.resources.extend(other.resources);
+        self.prompts.extend(other.prompts);
+    }
+}
+
+/// Translations for every locale the server knows, looked up by the locale
+/// the client asked for (`fr-CA`), then by its language alone (`fr`).
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    locales: HashMap<String, Translations>,
+}
+
+impl Catalog {
+    pub fn new(locales: HashMap<String, Translations>) -> Self {
+        let mut catalog = Self::default();
+        for (locale, translations) in locales {
+            catalog.add(&locale, translations);
+        }
+        catalog
+    }
+
+    pub fn add(&mut self, locale: &str, translations: Translations) {
+        self.locales
+            .entry(normalize(locale))
+            .or_default()
+            .merge(translations);
+    }
+
+    pub fn describe(
+        &self,
+        locale: &str,
+        pick: impl Fn(&Translations) -> Option<&String>,
+    ) -> Option<String> {
+        let locale = normalize(locale);
+        let language = locale.split('-').next().unwrap_or_default();
+        [locale.as_str(), language]
+            .iter()
+            .filter_map(|candidate| self.locales.get(*candidate))
+            .find_map(pick)
+            .cloned()
+    }
+}
+
+// `fr_CA`, `FR-ca` and `fr-CA` are all the same locale
+fn normalize(locale: &str) -> String {
+    locale.trim().replace('_', "-").to_ascii_lowercase()
+}
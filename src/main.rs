@@ -1,92 +1,554 @@
-mod server;
-mod types;
-
 use anyhow::Result;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, Notify};
 use tracing::{error, info, warn};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::reload;
+
+use test_mcp::admin::Admin;
+use test_mcp::admin_http::AdminEndpoint;
+use test_mcp::cache::{CacheLayer, ToolCache};
+use test_mcp::chaos::ChaosLayer;
+use test_mcp::compat::CompatLayer;
+use test_mcp::config::{Config, KeepAliveSettings, KnowledgeBaseConfig};
+use test_mcp::event_store::{EventStore, InMemoryEventStore};
+use test_mcp::framing::Frame;
+use test_mcp::healthcheck::Target;
+use test_mcp::http::HttpTransport;
+use test_mcp::jobs::JobManager;
+use test_mcp::metrics::{Metrics, MetricsLayer};
+use test_mcp::middleware::LoggingLayer;
+use test_mcp::ratelimit::RateLimitLayer;
+use test_mcp::retrieval::Retriever;
+use test_mcp::retry::RetryLayer;
+use test_mcp::scheduler::{Refreshed, Scheduler};
+use test_mcp::server::McpServer;
+use test_mcp::session::KeepAliveConfig;
+use test_mcp::tcp::TcpTransport;
+use test_mcp::vector_search::VectorStore;
+use test_mcp::{
+    admin, arg_value, bench, buffer, client_log, client_testing, confirm, daemon, demo, envelope,
+    files, framing, healthcheck, images, install, kv, manifest, metrics, proxy, server_info,
+    service, tabular, telemetry, translation, weather,
+};
+
+// Number of outbound events kept per SSE stream for Last-Event-ID replay
+const EVENT_STORE_CAPACITY: usize = 1024;
+
+// How long a disconnected TCP session can be resumed, unless overridden
+const DEFAULT_RESUME_WINDOW_SECS: u64 = 300;
 
-use crate::server::McpServer;
-use crate::types::{JsonRpcRequest, McpError, McpResponse};
+// How long `ping --self` waits for the server
+const DEFAULT_PING_TIMEOUT_SECS: u64 = 5;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging to stderr instead of stdout to avoid interfering with JSON-RPC
-    tracing_subscriber::fmt()
-        .with_writer(std::io::stderr)
+    // The level can be changed at runtime through the admin tools
+    let (log_filter, log_level) = reload::Layer::new(LevelFilter::INFO);
+    // Clients can also ask for log messages with `logging/setLevel`
+    let (client_log, log_records) = client_log::layer();
+    tracing_subscriber::registry()
+        .with(log_filter)
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .with(client_log)
         .init();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let mut config = match arg_value(&args, "--config")? {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    match args.first().map(String::as_str) {
+        Some("ping") => return ping_self(&args, config).await,
+        Some("install") => return install::run(&args, &config),
+        Some("stop") => {
+            let pid_file = arg_value(&args, "--pid-file")?
+                .ok_or_else(|| anyhow::anyhow!("Usage: stop --pid-file PATH"))?;
+            return daemon::stop(pid_file);
+        }
+        _ => {}
+    }
+
+    let network = args.iter().any(|arg| arg == "--http" || arg == "--tcp");
+    if args.iter().any(|arg| arg == "--daemon") {
+        if !network {
+            anyhow::bail!("--daemon needs --http or --tcp");
+        }
+        let pid = daemon::spawn(&args, arg_value(&args, "--log-file")?)?;
+        info!("Started daemon with pid {}", pid);
+        println!("{}", pid);
+        return Ok(());
+    }
+    if args.iter().any(|arg| arg == "--service") {
+        if !network {
+            anyhow::bail!("--service needs --http or --tcp");
+        }
+        service::start(arg_value(&args, "--name")?.unwrap_or("test-mcp"))?;
+    }
+    let pid_file = match arg_value(&args, "--pid-file")? {
+        Some(path) => Some(daemon::write_pid_file(path)?),
+        None => None,
+    };
+
     info!("Starting MCP server...");
+    if args.iter().any(|arg| arg == "--strict") {
+        config.strict = true;
+    }
+    if config.strict {
+        info!("Strict protocol validation enabled");
+    }
+    if args.iter().any(|arg| arg == "--chaos") {
+        config.chaos.enabled = true;
+    }
+    let chaos = config.chaos.clone();
+    if chaos.enabled {
+        warn!(
+            "Chaos mode: {}-{}ms latency, {}% errors, {}% of notifications dropped",
+            chaos.min_latency_ms,
+            chaos.max_latency_ms,
+            chaos.error_rate * 100.0,
+            chaos.notification_drop_rate * 100.0
+        );
+    }
+    if let Some(path) = arg_value(&args, "--knowledge-base")? {
+        let import_dir = config
+            .knowledge_base
+            .take()
+            .and_then(|knowledge_base| knowledge_base.import_dir);
+        config.knowledge_base = Some(KnowledgeBaseConfig {
+            path: path.into(),
+            import_dir,
+        });
+    }
+
+    let cache_ttls = config
+        .tool_cache
+        .iter()
+        .map(|(tool, secs)| (tool.clone(), Duration::from_secs(*secs)))
+        .collect();
+    let cache = Arc::new(ToolCache::new(cache_ttls));
+
+    let retry = RetryLayer::new(config.retry.clone());
+    let metrics = Arc::new(Metrics::default());
+
+    let admin_tools = config.admin_tools;
+    let admin_endpoint = config.admin_endpoint.clone();
+    let grpc_gateway = config.grpc_gateway.clone();
+    let config_checksum = config.checksum.clone();
+    let knowledge_base = config.knowledge_base.clone();
+    let vector_search = config.vector_search.clone();
+    let retrieval = config.retrieval.clone();
+    let files = config.files.clone();
+    let translation = config.translation.clone();
+    let weather = config.weather.clone();
+    let kv = config.kv.clone();
+    let demo_tools = config.demo_tools;
+    let imports = config.imports.clone();
+    let telemetry = config
+        .telemetry
+        .as_ref()
+        .map(telemetry::Telemetry::new)
+        .transpose()?
+        .map(Arc::new);
+    // The admin tools can change the server for everyone, so they're only
+    // offered to principals named for them
+    let admin_restricted = config
+        .tool_groups
+        .restricted
+        .get(admin::ADMIN_GROUP)
+        .is_some_and(|principals| !principals.is_empty());
+    if config.admin_tools && !admin_restricted {
+        anyhow::bail!(
+            "admin_tools needs tool_groups.restricted to limit the admin group to authenticated principals"
+        );
+    }
+    let schedules = config.schedules.clone();
+    let rate_limits = config.rate_limits.clone();
+    let http_config = config.http.clone();
+    let tcp_config = config.tcp.clone();
+    let confirmation = config
+        .confirmation
+        .as_ref()
+        .map(confirm::from_config)
+        .transpose()?;
+    let mut server = McpServer::new(config)
+        .layer(LoggingLayer)
+        .layer(CompatLayer)
+        .layer(RateLimitLayer::new(rate_limits))
+        .layer(MetricsLayer::new(metrics.clone()))
+        .layer(CacheLayer::new(cache.clone()))
+        .layer(retry);
+    if chaos.enabled {
+        server = server.layer(ChaosLayer::new(chaos));
+    }
+    if let Some(policy) = confirmation {
+        server = server.with_confirmation(policy);
+    }
+    let server = Arc::new(server);
+    server.add_resource(metrics::metrics_resource(metrics.clone()));
+    server.add_resource(metrics::server_stats_resource(
+        metrics.clone(),
+        cache.clone(),
+    ));
+    let mut transports = vec![if args.iter().any(|arg| arg == "--http") {
+        "http"
+    } else if args.iter().any(|arg| arg == "--tcp") {
+        "tcp"
+    } else {
+        "stdio"
+    }];
+    if grpc_gateway.is_some() {
+        transports.push("grpc");
+    }
+    server.add_resource(server_info::info_resource(
+        &server,
+        transports,
+        config_checksum,
+    ));
+    tokio::spawn(metrics.clone().follow(server.subscribe_events()));
+    if let Some(telemetry) = telemetry {
+        tokio::spawn(
+            telemetry
+                .clone()
+                .follow(Arc::downgrade(&server), server.subscribe_events()),
+        );
+        tokio::spawn(telemetry.run());
+    }
+    tokio::spawn(client_log::forward(server.clone(), log_records));
+    tokio::spawn(daemon::shutdown_on_signal(server.clone(), pid_file));
+    tokio::spawn(daemon::reload_on_signal(server.clone()));
 
-    let server = McpServer::new();
+    let jobs = Arc::new(JobManager::default());
+    for tool in jobs.tools() {
+        server.add_tool(tool);
+    }
+    server.add_resource(jobs.resource());
+    server.add_tool(tabular::tool(&server));
+    server.add_tool(images::tool(&server));
+    if let Some(config) = files {
+        server.add_resource_template(files::resource_template(&config)?);
+    }
+    if let Some(config) = translation {
+        server.add_tool(translation::tool(translation::from_config(&config)?));
+    }
+    if let Some(config) = weather {
+        for tool in Arc::new(weather::WeatherClient::new(&config)?).tools() {
+            server.add_tool(tool);
+        }
+    }
+    if let Some(config) = kv {
+        for tool in Arc::new(kv::KvStore::open(&config)?).tools() {
+            server.add_tool(tool);
+        }
+    }
+    if demo_tools {
+        for tool in demo::tools().into_iter().chain(client_testing::tools()) {
+            server.add_tool(tool);
+        }
+    }
+    for import in &imports {
+        for tool in proxy::tools(import)? {
+            server.add_tool(tool);
+        }
+    }
+    if let Some(config) = knowledge_base {
+        add_knowledge_base(&server, &config)?;
+    }
+    if let Some(config) = vector_search {
+        let store = Arc::new(VectorStore::open(&config).await?);
+        for tool in store.tools() {
+            server.add_tool(tool);
+        }
+    }
+    if let Some(config) = retrieval {
+        server.add_tool(Arc::new(Retriever::open(&config).await?).tool());
+    }
+
+    if args.first().map(String::as_str) == Some("bench") {
+        // Per-request logging would swamp the numbers
+        log_level.reload(LevelFilter::WARN)?;
+        return bench::run(&args, server).await;
+    }
+
+    let admin = Arc::new(Admin::new(&server, cache, log_level));
+    tokio::spawn(admin.clone().follow(server.subscribe_events()));
+    if admin_tools {
+        for tool in admin.tools() {
+            server.add_tool(tool);
+        }
+    }
+
+    if args.first().map(String::as_str) == Some("export-manifest") {
+        return manifest::export(&args, server).await;
+    }
+    if let Some(config) = admin_endpoint {
+        let endpoint = Arc::new(AdminEndpoint::new(server.clone(), admin, &config)?);
+        tokio::spawn(async move {
+            if let Err(e) = endpoint.serve(&config.addr).await {
+                error!("Admin endpoint stopped: {}", e);
+            }
+        });
+    }
 
-    // For simplicity, we'll use stdin/stdout for communication
-    // In a real implementation, you might want to use TCP or other transport
+    if let Some(addr) = grpc_gateway {
+        serve_grpc(server.clone(), addr).await?;
+    }
+
+    // Built-in refresh tasks, for resources whose contents change on their own
+    let mut scheduler = Scheduler::new(server.clone(), schedules);
+    scheduler.register("metrics", || async {
+        Ok(Refreshed {
+            updated: vec!["stats://metrics".to_string(), "stats://server".to_string()],
+            list_changed: false,
+        })
+    })?;
+    scheduler.register("jobs", || async { Ok(Refreshed::updated("jobs://all")) })?;
+    tokio::spawn(scheduler.run());
+
+    if let Some(addr) = arg_value(&args, "--http")? {
+        let event_store = match &http_config.event_store {
+            Some(path) => open_event_store(path)?,
+            None => Arc::new(InMemoryEventStore::new(EVENT_STORE_CAPACITY)),
+        };
+        let keep_alive = keep_alive_config(&args, &http_config.keep_alive)?;
+        Arc::new(
+            HttpTransport::new(server, event_store, keep_alive)
+                .with_metrics(metrics)
+                .with_config(http_config),
+        )
+        .serve(addr)
+        .await
+    } else if let Some(addr) = arg_value(&args, "--tcp")? {
+        let keep_alive = keep_alive_config(&args, &tcp_config.keep_alive)?;
+        let resume_window = match arg_value(&args, "--resume-window")? {
+            Some(secs) => secs.parse()?,
+            None => DEFAULT_RESUME_WINDOW_SECS,
+        };
+        Arc::new(TcpTransport::new(
+            server,
+            keep_alive,
+            Duration::from_secs(resume_window),
+        ))
+        .serve(addr)
+        .await
+    } else {
+        serve_stdio(server).await
+    }
+}
+
+// `ping --self`: checks the instance configured by the other flags and
+// exits 0 if it answers, 1 otherwise
+async fn ping_self(args: &[String], config: Config) -> Result<()> {
+    if !args.iter().any(|arg| arg == "--self") {
+        anyhow::bail!("Usage: ping --self (--http ADDR | --tcp ADDR) [--timeout SECS]");
+    }
+    let target = if let Some(addr) = arg_value(args, "--http")? {
+        Target::Http {
+            addr,
+            config: config.http,
+        }
+    } else if let Some(addr) = arg_value(args, "--tcp")? {
+        Target::Tcp { addr }
+    } else {
+        anyhow::bail!("ping --self needs --http or --tcp; stdio servers can't be reached");
+    };
+    let timeout = match arg_value(args, "--timeout")? {
+        Some(secs) => Duration::from_secs(secs.parse()?),
+        None => Duration::from_secs(DEFAULT_PING_TIMEOUT_SECS),
+    };
+
+    match healthcheck::check(target, timeout).await {
+        Ok(()) => {
+            info!("Server is healthy");
+            Ok(())
+        }
+        Err(e) => {
+            error!("Health check failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// The transport's keep-alive settings, with the command line's overrides
+fn keep_alive_config(args: &[String], settings: &KeepAliveSettings) -> Result<KeepAliveConfig> {
+    let mut config = KeepAliveConfig {
+        ping_interval: Duration::from_secs(settings.ping_interval_secs),
+        max_missed_pings: settings.max_missed_pings,
+    };
+    if let Some(secs) = arg_value(args, "--ping-interval")? {
+        config.ping_interval = Duration::from_secs(secs.parse()?);
+    }
+    if let Some(count) = arg_value(args, "--max-missed-pings")? {
+        config.max_missed_pings = count.parse()?;
+    }
+    Ok(config)
+}
+
+#[cfg(feature = "knowledge-base")]
+fn add_knowledge_base(server: &Arc<McpServer>, config: &KnowledgeBaseConfig) -> Result<()> {
+    let knowledge_base = Arc::new(test_mcp::knowledge_base::KnowledgeBase::open(config)?);
+    for resource in knowledge_base.resources()? {
+        server.add_resource(resource);
+    }
+    for tool in knowledge_base.tools(server) {
+        server.add_tool(tool);
+    }
+    info!("Serving knowledge base {}", config.path.display());
+    Ok(())
+}
+
+#[cfg(not(feature = "knowledge-base"))]
+fn add_knowledge_base(_server: &Arc<McpServer>, _config: &KnowledgeBaseConfig) -> Result<()> {
+    anyhow::bail!("knowledge_base needs a build with `--features knowledge-base`")
+}
+
+#[cfg(feature = "sqlite-event-store")]
+fn open_event_store(path: &Path) -> Result<Arc<dyn EventStore>> {
+    let store = test_mcp::sqlite_event_store::SqliteEventStore::open(path, EVENT_STORE_CAPACITY)?;
+    info!("Keeping SSE events in {}", path.display());
+    Ok(Arc::new(store))
+}
+
+#[cfg(not(feature = "sqlite-event-store"))]
+fn open_event_store(_path: &Path) -> Result<Arc<dyn EventStore>> {
+    anyhow::bail!("http.event_store needs a build with `--features sqlite-event-store`")
+}
+
+#[cfg(feature = "grpc")]
+async fn serve_grpc(server: Arc<McpServer>, addr: String) -> Result<()> {
+    let gateway = test_mcp::grpc::GrpcGateway::new(server).await?;
+    tokio::spawn(async move {
+        if let Err(e) = gateway.serve(&addr).await {
+            error!("gRPC gateway stopped: {}", e);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(feature = "grpc"))]
+async fn serve_grpc(_server: Arc<McpServer>, _addr: String) -> Result<()> {
+    anyhow::bail!("grpc_gateway needs a build with `--features grpc`")
+}
+
+async fn serve_stdio(server: Arc<McpServer>) -> Result<()> {
     let stdin = tokio::io::stdin();
     let mut stdin = tokio::io::BufReader::new(stdin);
-    let mut stdout = tokio::io::stdout();
+    let (session, mut outbound) = server.connect();
 
-    info!("MCP server ready. Waiting for requests...");
+    // Responses and notifications share stdout, so a single task owns it.
+    // If the client goes away (EPIPE), it says so and the loop below stops.
+    let stdout_closed = Arc::new(Notify::new());
+    let closed = stdout_closed.clone();
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(message) = outbound.recv().await {
+            let line = buffer::json_line(&message)?;
+            let written = async {
+                stdout.write_all(&line).await?;
+                stdout.flush().await
+            };
+            if let Err(e) = written.await {
+                warn!("Can't write to stdout ({}), shutting down", e);
+                closed.notify_one();
+                break;
+            }
+        }
+        anyhow::Ok(())
+    });
 
-    let mut line = String::new();
+    info!("MCP server ready. Waiting for requests...");
 
-    while stdin.read_line(&mut line).await? > 0 {
-        let trimmed = line.trim();
-        if trimmed.is_empty() {
-            continue;
+    // Stdin is read on its own task, so that the client's answer to a
+    // request the server makes mid-call (elicitation, sampling) gets through
+    // while the call waits. Malformed messages arrive as the error to send.
+    let max = server.max_message_bytes();
+    let (messages, mut incoming) = mpsc::channel(64);
+    let reader = tokio::spawn(async move {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let message = match framing::read_message(&mut stdin, &mut line, max).await? {
+                Frame::Eof => return anyhow::Ok(()),
+                Frame::Message if line.trim().is_empty() => continue,
+                Frame::Message => Ok(line.trim().to_string()),
+                frame @ (Frame::TooLong(_) | Frame::InvalidUtf8) => {
+                    match framing::error_response(&frame, max) {
+                        Some(response) => Err(response),
+                        None => continue,
+                    }
+                }
+            };
+            if messages.send(message).await.is_err() {
+                return Ok(());
+            }
         }
+    });
+    // Requests that arrived while another was being handled
+    let mut queued = VecDeque::new();
 
-        match serde_json::from_str::<JsonRpcRequest>(trimmed) {
-            Ok(request) => {
-                let request_id = request.id.clone();
-                match server.handle_request(request).await {
-                    Ok(Some(response)) => {
-                        let response_json = serde_json::to_string(&response)?;
-                        stdout.write_all(response_json.as_bytes()).await?;
-                        stdout.write_all(b"\n").await?;
-                        stdout.flush().await?;
-                    }
-                    Ok(None) => {
-                        // No response needed for notifications
-                    }
-                    Err(e) => {
-                        error!("Error handling request: {}", e);
-                        let error_response = McpResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id: request_id
-                                .unwrap_or_else(|| serde_json::Value::String("error".to_string())),
-                            result: None,
-                            error: Some(McpError {
-                                code: -32603,
-                                message: format!("Internal error: {}", e),
-                            }),
-                        };
-                        let error_json = serde_json::to_string(&error_response)?;
-                        stdout.write_all(error_json.as_bytes()).await?;
-                        stdout.write_all(b"\n").await?;
-                        stdout.flush().await?;
+    loop {
+        let message = match queued.pop_front() {
+            Some(message) => message,
+            None => tokio::select! {
+                message = incoming.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                _ = stdout_closed.notified() => break,
+            },
+        };
+        let message = match message {
+            Ok(message) => message,
+            Err(response) => {
+                warn!("Skipped a malformed message");
+                session.send(serde_json::to_value(response)?)?;
+                continue;
+            }
+        };
+
+        // Nobody can receive the response once stdout is gone, so the
+        // request is dropped, which cancels whatever it was doing
+        let handling = server.handle_message(&session, &message);
+        tokio::pin!(handling);
+        let response = loop {
+            tokio::select! {
+                response = &mut handling => break Some(response),
+                Some(next) = incoming.recv() => {
+                    match next.as_deref().ok().map(envelope::parse) {
+                        Some(Ok(value)) if envelope::is_out_of_band(&value) => {
+                            server.handle_value(&session, value).await;
+                        }
+                        _ => queued.push_back(next),
                     }
                 }
+                _ = stdout_closed.notified() => break None,
             }
-            Err(e) => {
-                warn!("Failed to parse request: {}", e);
-                let error_response = McpResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id: serde_json::Value::String("parse_error".to_string()),
-                    result: None,
-                    error: Some(McpError {
-                        code: -32700,
-                        message: format!("Parse error: {}", e),
-                    }),
-                };
-                let error_json = serde_json::to_string(&error_response)?;
-                stdout.write_all(error_json.as_bytes()).await?;
-                stdout.write_all(b"\n").await?;
-                stdout.flush().await?;
+        };
+        let Some(response) = response else {
+            info!("Cancelled in-flight request");
+            break;
+        };
+        if let Some(response) = response {
+            if session.send(serde_json::to_value(&response)?).is_err() {
+                break;
             }
         }
-
-        line.clear();
     }
 
-    Ok(())
+    info!("Client disconnected");
+    server.disconnect(&session.id);
+    drop(session);
+    // A read still blocked on stdin can't be cancelled, so the reader is
+    // only waited for once it has stopped
+    if reader.is_finished() {
+        reader.await??;
+    }
+    writer.await?
 }
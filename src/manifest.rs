@@ -0,0 +1,148 @@
+use anyhow::Result;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::compat::LATEST_PROTOCOL_VERSION;
+use crate::server::McpServer;
+use crate::session::Session;
+use crate::types::Tool;
+
+// Bumped when the manifest's layout changes
+const MANIFEST_VERSION: u64 = 1;
+
+/// `export-manifest`: writes every tool, resource, resource template and
+/// prompt definition, with schemas and annotations, to a JSON file (or
+/// stdout), for generating docs, validating on the client side or diffing
+/// releases. The lists are fetched through the in-process server the way a
+/// client without a profile would see them, sorted so unchanged
+/// definitions diff cleanly.
+pub async fn export(args: &[String], server: Arc<McpServer>) -> Result<()> {
+    let output = crate::arg_value(args, "--output")?;
+    let (session, mut outbound) = server.connect();
+    // Notifications go nowhere, but must not pile up in the channel
+    tokio::spawn(async move { while outbound.recv().await.is_some() {} });
+
+    let manifest = build(&server, &session).await;
+    server.disconnect(&session.id);
+    let manifest = serde_json::to_string_pretty(&manifest?)?;
+
+    match output {
+        Some(path) => {
+            std::fs::write(path, manifest + "\n")
+                .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path, e))?;
+            eprintln!("Wrote manifest to {}", path);
+        }
+        None => println!("{}", manifest),
+    }
+    Ok(())
+}
+
+// The tool definitions in a manifest, as `export-manifest` writes them
+pub fn load_tools(path: &Path) -> Result<Vec<Tool>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read manifest {}: {}", path.display(), e))?;
+    let mut manifest: serde_json::Value = serde_json::from_str(&contents)
+        .map_err(|e| anyhow::anyhow!("Invalid manifest {}: {}", path.display(), e))?;
+    if let Some(version) = manifest["manifestVersion"].as_u64() {
+        if version > MANIFEST_VERSION {
+            anyhow::bail!(
+                "Manifest {} is version {}; this server reads up to {}",
+                path.display(),
+                version,
+                MANIFEST_VERSION
+            );
+        }
+    }
+    serde_json::from_value(manifest["tools"].take())
+        .map_err(|e| anyhow::anyhow!("Invalid tools in manifest {}: {}", path.display(), e))
+}
+
+async fn build(server: &McpServer, session: &Session) -> Result<serde_json::Value> {
+    let initialized = request(
+        server,
+        session,
+        "initialize",
+        serde_json::json!({
+            "protocolVersion": LATEST_PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": { "name": "export-manifest", "version": env!("CARGO_PKG_VERSION") }
+        }),
+    )
+    .await?;
+    server
+        .handle_message(
+            session,
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+        )
+        .await;
+
+    Ok(serde_json::json!({
+        "manifestVersion": MANIFEST_VERSION,
+        "serverInfo": initialized["serverInfo"],
+        "protocolVersion": initialized["protocolVersion"],
+        "capabilities": initialized["capabilities"],
+        "tools": list(server, session, "tools/list", "tools", "name").await?,
+        "resources": list(server, session, "resources/list", "resources", "uri").await?,
+        "resourceTemplates": list(
+            server,
+            session,
+            "resources/templates/list",
+            "resourceTemplates",
+            "uriTemplate",
+        )
+        .await?,
+        "prompts": list(server, session, "prompts/list", "prompts", "name").await?
+    }))
+}
+
+// Every page of a list, sorted by `key`
+async fn list(
+    server: &McpServer,
+    session: &Session,
+    method: &str,
+    field: &str,
+    key: &str,
+) -> Result<Vec<serde_json::Value>> {
+    let mut items = Vec::new();
+    let mut cursor = None;
+    loop {
+        let params = match &cursor {
+            Some(cursor) => serde_json::json!({ "cursor": cursor }),
+            None => serde_json::json!({}),
+        };
+        let mut page = request(server, session, method, params).await?;
+        if let Some(page_items) = page[field].as_array_mut() {
+            items.append(page_items);
+        }
+        match page["nextCursor"].as_str() {
+            Some(next) => cursor = Some(next.to_string()),
+            None => break,
+        }
+    }
+    items.sort_by(|a, b| a[key].as_str().cmp(&b[key].as_str()));
+    Ok(items)
+}
+
+async fn request(
+    server: &McpServer,
+    session: &Session,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<serde_json::Value> {
+    let message = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": method,
+        "params": params
+    });
+    let response = server
+        .handle_message(session, &message.to_string())
+        .await
+        .ok_or_else(|| anyhow::anyhow!("No response to {}", method))?;
+    if let Some(error) = response.error {
+        anyhow::bail!("{} failed: {}", method, error.message);
+    }
+    response
+        .result
+        .ok_or_else(|| anyhow::anyhow!("Empty response to {}", method))
+}
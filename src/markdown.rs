@@ -0,0 +1,351 @@
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag, TagEnd};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::tools::RegisteredTool;
+use crate::types::{CallToolResult, Tool, ToolAnnotations};
+
+#[derive(Debug, Serialize)]
+pub struct Heading {
+    pub level: u8,
+    pub text: String,
+    // The fragment a link to the heading uses, as GitHub generates it
+    pub anchor: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Link {
+    pub text: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub image: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+// GitHub-flavored extensions: tables, strikethrough, task lists,
+// footnotes and `{#id}` heading attributes
+fn parse(markdown: &str) -> Vec<Event<'_>> {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_HEADING_ATTRIBUTES;
+    Parser::new_ext(markdown, options).collect()
+}
+
+// Headings get ids matching the anchors `toc` links to
+pub fn to_html(markdown: &str) -> String {
+    let events = parse(markdown);
+    let mut anchors = headings(&events).into_iter().map(|heading| heading.anchor);
+    let events = events.into_iter().map(|event| match event {
+        Event::Start(Tag::Heading {
+            level,
+            id,
+            classes,
+            attrs,
+        }) => {
+            let anchor = anchors.next().map(CowStr::from);
+            Event::Start(Tag::Heading {
+                level,
+                id: id.or(anchor),
+                classes,
+                attrs,
+            })
+        }
+        event => event,
+    });
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events);
+    html
+}
+
+pub fn from_html(html: &str) -> anyhow::Result<String> {
+    let converter = htmd::HtmlToMarkdown::builder()
+        .options(htmd::options::Options {
+            bullet_list_marker: htmd::options::BulletListMarker::Dash,
+            ul_bullet_spacing: 1,
+            ol_number_spacing: 1,
+            ..Default::default()
+        })
+        .skip_tags(vec!["script", "style", "head"])
+        .build();
+    Ok(converter.convert(html)?.trim().to_string())
+}
+
+fn headings(events: &[Event]) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut slugs = HashMap::new();
+    // Level, explicit id and text of the heading being read
+    let mut current: Option<(u8, Option<String>, String)> = None;
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading { level, id, .. }) => {
+                current = Some((
+                    *level as u8,
+                    id.as_ref().map(|id| id.to_string()),
+                    String::new(),
+                ));
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some((level, id, text)) = current.take() {
+                    let text = text.trim().to_string();
+                    let anchor = id.unwrap_or_else(|| unique_slug(&text, &mut slugs));
+                    headings.push(Heading {
+                        level,
+                        text,
+                        anchor,
+                    });
+                }
+            }
+            event => {
+                if let Some((_, _, text)) = &mut current {
+                    push_text(text, event);
+                }
+            }
+        }
+    }
+    headings
+}
+
+pub fn extract_headings(markdown: &str) -> Vec<Heading> {
+    headings(&parse(markdown))
+}
+
+pub fn extract_links(markdown: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    // Links being read; an image can sit inside a link
+    let mut open: Vec<Link> = Vec::new();
+    for event in parse(markdown) {
+        match &event {
+            Event::Start(Tag::Link {
+                dest_url, title, ..
+            })
+            | Event::Start(Tag::Image {
+                dest_url, title, ..
+            }) => {
+                let image = matches!(event, Event::Start(Tag::Image { .. }));
+                open.push(Link {
+                    text: String::new(),
+                    url: dest_url.to_string(),
+                    title: (!title.is_empty()).then(|| title.to_string()),
+                    image,
+                });
+            }
+            Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => {
+                if let Some(mut link) = open.pop() {
+                    link.text = link.text.trim().to_string();
+                    if let Some(outer) = open.last_mut() {
+                        outer.text.push_str(&link.text);
+                    }
+                    links.push(link);
+                }
+            }
+            event => {
+                if let Some(link) = open.last_mut() {
+                    push_text(&mut link.text, event);
+                }
+            }
+        }
+    }
+    links
+}
+
+pub fn extract_tables(markdown: &str) -> Vec<Table> {
+    let mut tables = Vec::new();
+    let mut table: Option<Table> = None;
+    let mut row: Option<Vec<String>> = None;
+    let mut cell: Option<String> = None;
+    for event in parse(markdown) {
+        match event {
+            Event::Start(Tag::Table(_)) => {
+                table = Some(Table {
+                    headers: Vec::new(),
+                    rows: Vec::new(),
+                });
+            }
+            Event::Start(Tag::TableHead) | Event::Start(Tag::TableRow) => row = Some(Vec::new()),
+            Event::Start(Tag::TableCell) => cell = Some(String::new()),
+            Event::End(TagEnd::TableCell) => {
+                if let (Some(row), Some(cell)) = (&mut row, cell.take()) {
+                    row.push(cell.trim().to_string());
+                }
+            }
+            Event::End(TagEnd::TableHead) => {
+                if let (Some(table), Some(row)) = (&mut table, row.take()) {
+                    table.headers = row;
+                }
+            }
+            Event::End(TagEnd::TableRow) => {
+                if let (Some(table), Some(row)) = (&mut table, row.take()) {
+                    table.rows.push(row);
+                }
+            }
+            Event::End(TagEnd::Table) => tables.extend(table.take()),
+            event => {
+                if let Some(cell) = &mut cell {
+                    push_text(cell, &event);
+                }
+            }
+        }
+    }
+    tables
+}
+
+// A nested list of links to the headings down to `max_level`, indented
+// from the shallowest heading included
+pub fn table_of_contents(markdown: &str, max_level: u8) -> String {
+    let headings: Vec<Heading> = extract_headings(markdown)
+        .into_iter()
+        .filter(|heading| heading.level <= max_level)
+        .collect();
+    let top = headings
+        .iter()
+        .map(|heading| heading.level)
+        .min()
+        .unwrap_or(1);
+    headings
+        .iter()
+        .map(|heading| {
+            format!(
+                "{}- [{}](#{})",
+                "  ".repeat((heading.level - top) as usize),
+                heading.text.replace('[', "\\[").replace(']', "\\]"),
+                heading.anchor
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// The readable text an inline event contributes
+fn push_text(text: &mut String, event: &Event) {
+    match event {
+        Event::Text(part) | Event::Code(part) => text.push_str(part),
+        Event::SoftBreak | Event::HardBreak => text.push(' '),
+        _ => {}
+    }
+}
+
+// Lowercase, punctuation dropped and spaces turned into hyphens, with
+// `-1`, `-2`, ... added to repeats
+fn unique_slug(text: &str, seen: &mut HashMap<String, usize>) -> String {
+    let slug: String = text
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == ' ' || *c == '-' || *c == '_')
+        .map(|c| if c == ' ' { '-' } else { c })
+        .collect();
+    let count = seen.entry(slug.clone()).or_insert(0);
+    let unique = if *count == 0 {
+        slug
+    } else {
+        format!("{}-{}", slug, count)
+    };
+    *count += 1;
+    unique
+}
+
+pub fn tool() -> RegisteredTool {
+    let tool = Tool {
+        name: "markdown".to_string(),
+        title: Some("Markdown".to_string()),
+        description: "Converts Markdown to HTML or HTML to Markdown, extracts headings, links or tables, or renders a table of contents".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "operation": {
+                    "type": "string",
+                    "enum": ["to_html", "from_html", "headings", "links", "tables", "toc"],
+                    "description": "to_html and from_html convert; headings, links and tables extract; toc renders a table of contents"
+                },
+                "text": {
+                    "type": "string",
+                    "description": "The Markdown, or HTML for from_html"
+                },
+                "max_level": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": 6,
+                    "description": "Deepest heading level for headings and toc (default 6)"
+                }
+            },
+            "required": ["operation", "text"]
+        }),
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(true),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    };
+
+    RegisteredTool::blocking(tool, |arguments, _| {
+        let operation = arguments
+            .get("operation")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'operation' argument"))?;
+        let text = arguments
+            .get("text")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'text' argument"))?;
+        let max_level = arguments
+            .get("max_level")
+            .and_then(|v| v.as_u64())
+            .map_or(6, |level| level.clamp(1, 6) as u8);
+
+        Ok(match operation {
+            "to_html" => CallToolResult::text(to_html(text)),
+            "from_html" => CallToolResult::text(from_html(text)?),
+            "headings" => {
+                let headings: Vec<Heading> = extract_headings(text)
+                    .into_iter()
+                    .filter(|heading| heading.level <= max_level)
+                    .collect();
+                let listed = headings
+                    .iter()
+                    .map(|heading| {
+                        format!(
+                            "{} {} (#{})",
+                            "#".repeat(heading.level as usize),
+                            heading.text,
+                            heading.anchor
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                CallToolResult::text(listed)
+                    .with_structured_content(serde_json::json!({ "headings": headings }))
+            }
+            "links" => {
+                let links = extract_links(text);
+                let listed = links
+                    .iter()
+                    .map(|link| {
+                        let bang = if link.image { "!" } else { "" };
+                        format!("{}[{}]({})", bang, link.text, link.url)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                CallToolResult::text(listed)
+                    .with_structured_content(serde_json::json!({ "links": links }))
+            }
+            "tables" => {
+                let tables = extract_tables(text);
+                CallToolResult::text(serde_json::to_string_pretty(&tables)?)
+                    .with_structured_content(serde_json::json!({ "tables": tables }))
+            }
+            "toc" => CallToolResult::text(table_of_contents(text, max_level)),
+            other => anyhow::bail!(
+                "Unknown operation '{}'; expected to_html, from_html, headings, links, tables or toc",
+                other
+            ),
+        })
+    })
+}
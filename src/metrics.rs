@@ -0,0 +1,375 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::cache::ToolCache;
+use crate::error::{ServerError, METHOD_NOT_FOUND};
+use crate::events::ServerEvent;
+use crate::middleware::{HandlerFuture, Layer, Next};
+use crate::resources::RegisteredResource;
+use crate::session::Session;
+use crate::types::{JsonRpcRequest, McpResponse, Resource, ResourceContents};
+
+// Histogram bucket upper bounds, in seconds
+const BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+// Label for methods and tools the server doesn't have
+const UNKNOWN: &str = "unknown";
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Labels {
+    method: String,
+    tool: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Series {
+    count: u64,
+    errors: u64,
+    sum_seconds: f64,
+    // Non-cumulative counts per bucket; the last slot is +Inf
+    buckets: Vec<u64>,
+}
+
+/// Request counts, error counts and latency histograms, labeled by method
+/// and (for `tools/call`) tool name, plus the number of connected sessions.
+#[derive(Default)]
+pub struct Metrics {
+    series: Mutex<BTreeMap<Labels, Series>>,
+    active_sessions: AtomicUsize,
+}
+
+impl Metrics {
+    pub fn record(&self, method: &str, tool: Option<&str>, seconds: f64, failed: bool) {
+        let labels = Labels {
+            method: method.to_string(),
+            tool: tool.map(str::to_string),
+        };
+        let mut series = self.series.lock().unwrap();
+        let series = series.entry(labels).or_default();
+        if series.buckets.is_empty() {
+            series.buckets = vec![0; BUCKETS.len() + 1];
+        }
+        series.count += 1;
+        series.sum_seconds += seconds;
+        if failed {
+            series.errors += 1;
+        }
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUCKETS.len());
+        series.buckets[bucket] += 1;
+    }
+
+    // Tracks connected sessions from server events, until the bus closes
+    pub async fn follow(self: Arc<Self>, mut events: broadcast::Receiver<ServerEvent>) {
+        loop {
+            match events.recv().await {
+                Ok(ServerEvent::SessionOpened { .. }) => {
+                    self.active_sessions.fetch_add(1, Ordering::Relaxed);
+                }
+                Ok(ServerEvent::SessionClosed { .. }) => {
+                    let _ = self.active_sessions.fetch_update(
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                        |count| count.checked_sub(1),
+                    );
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    // Prometheus text exposition format
+    pub fn render_prometheus(&self) -> String {
+        let series = self.series.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str("# HELP mcp_requests_total Requests handled.\n");
+        out.push_str("# TYPE mcp_requests_total counter\n");
+        for (labels, series) in series.iter() {
+            let _ = writeln!(
+                out,
+                "mcp_requests_total{{{}}} {}",
+                labels.render(),
+                series.count
+            );
+        }
+
+        out.push_str("# HELP mcp_request_errors_total Requests that failed.\n");
+        out.push_str("# TYPE mcp_request_errors_total counter\n");
+        for (labels, series) in series.iter() {
+            let _ = writeln!(
+                out,
+                "mcp_request_errors_total{{{}}} {}",
+                labels.render(),
+                series.errors
+            );
+        }
+
+        out.push_str("# HELP mcp_active_sessions Connected sessions.\n");
+        out.push_str("# TYPE mcp_active_sessions gauge\n");
+        let _ = writeln!(
+            out,
+            "mcp_active_sessions {}",
+            self.active_sessions.load(Ordering::Relaxed)
+        );
+
+        out.push_str("# HELP mcp_request_duration_seconds Request handling latency.\n");
+        out.push_str("# TYPE mcp_request_duration_seconds histogram\n");
+        for (labels, series) in series.iter() {
+            let labels = labels.render();
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(&series.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "mcp_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "mcp_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, series.count
+            );
+            let _ = writeln!(
+                out,
+                "mcp_request_duration_seconds_sum{{{}}} {}",
+                labels, series.sum_seconds
+            );
+            let _ = writeln!(
+                out,
+                "mcp_request_duration_seconds_count{{{}}} {}",
+                labels, series.count
+            );
+        }
+
+        out
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        let series = self.series.lock().unwrap();
+        let entries: Vec<serde_json::Value> = series
+            .iter()
+            .map(|(labels, series)| {
+                let mut cumulative = 0;
+                let buckets: Vec<serde_json::Value> = BUCKETS
+                    .iter()
+                    .zip(&series.buckets)
+                    .map(|(bound, count)| {
+                        cumulative += count;
+                        serde_json::json!({ "le": bound, "count": cumulative })
+                    })
+                    .collect();
+                serde_json::json!({
+                    "method": labels.method,
+                    "tool": labels.tool,
+                    "count": series.count,
+                    "errors": series.errors,
+                    "sumSeconds": series.sum_seconds,
+                    "buckets": buckets
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "activeSessions": self.active_sessions.load(Ordering::Relaxed),
+            "requests": entries
+        })
+    }
+
+    // Request and error counts per method, across tools
+    fn totals_by_method(&self) -> BTreeMap<String, (u64, u64)> {
+        let mut totals: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+        for (labels, series) in self.series.lock().unwrap().iter() {
+            let total = totals.entry(labels.method.clone()).or_default();
+            total.0 += series.count;
+            total.1 += series.errors;
+        }
+        totals
+    }
+}
+
+impl Labels {
+    fn render(&self) -> String {
+        match &self.tool {
+            Some(tool) => format!(
+                "method=\"{}\",tool=\"{}\"",
+                escape_label(&self.method),
+                escape_label(tool)
+            ),
+            None => format!("method=\"{}\"", escape_label(&self.method)),
+        }
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Records every request passing through into `Metrics`.
+pub struct MetricsLayer {
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsLayer {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl Layer for MetricsLayer {
+    fn call<'a>(
+        &'a self,
+        session: &'a Session,
+        request: JsonRpcRequest,
+        next: Next<'a>,
+    ) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            // Both names come from the client. Anything the server doesn't
+            // have is labeled `unknown`, so junk can't add series.
+            let server = next.server();
+            let method = request.method.clone();
+            let tool = match method.as_str() {
+                "tools/call" => request
+                    .params
+                    .as_ref()
+                    .and_then(|params| params["name"].as_str())
+                    .map(|name| match server.has_tool(name) {
+                        true => name.to_string(),
+                        false => UNKNOWN.to_string(),
+                    }),
+                _ => None,
+            };
+
+            let started = Instant::now();
+            let result = next.run(session, request).await;
+            let failed = match &result {
+                Ok(Some(response)) => response.error.is_some(),
+                Ok(None) => false,
+                Err(_) => true,
+            };
+            let method = match method_not_found(&result) {
+                true => UNKNOWN,
+                false => method.as_str(),
+            };
+            self.metrics.record(
+                method,
+                tool.as_deref(),
+                started.elapsed().as_secs_f64(),
+                failed,
+            );
+            result
+        })
+    }
+}
+
+// Whether no layer and no handler took the method
+fn method_not_found(result: &Result<Option<McpResponse>>) -> bool {
+    match result {
+        Ok(Some(response)) => response
+            .error
+            .as_ref()
+            .is_some_and(|error| error.code == METHOD_NOT_FOUND),
+        Ok(None) => false,
+        Err(e) => matches!(
+            e.downcast_ref::<ServerError>(),
+            Some(ServerError::MethodNotFound { .. })
+        ),
+    }
+}
+
+// Exposes the current metrics as JSON at `stats://metrics`
+pub fn metrics_resource(metrics: Arc<Metrics>) -> RegisteredResource {
+    let resource = Resource {
+        uri: "stats://metrics".to_string(),
+        name: "Request metrics".to_string(),
+        title: None,
+        description: "Request counts, errors and latency histograms per method and tool"
+            .to_string(),
+        mime_type: "application/json".to_string(),
+        icons: Vec::new(),
+        size: None,
+        annotations: None,
+    };
+
+    RegisteredResource::new(resource, move |uri| {
+        let snapshot = metrics.snapshot();
+        async move {
+            Ok(vec![ResourceContents::text(
+                uri,
+                "application/json",
+                serde_json::to_string_pretty(&snapshot)?,
+            )])
+        }
+    })
+}
+
+// A summary of server health at `stats://server`, worked out afresh on each
+// read: uptime, requests and errors per method, cache hit rates and sessions
+pub fn server_stats_resource(metrics: Arc<Metrics>, cache: Arc<ToolCache>) -> RegisteredResource {
+    let resource = Resource {
+        uri: "stats://server".to_string(),
+        name: "Server statistics".to_string(),
+        title: None,
+        description: "Uptime, requests and errors per method, cache hit rates and active sessions"
+            .to_string(),
+        mime_type: "application/json".to_string(),
+        icons: Vec::new(),
+        size: None,
+        annotations: None,
+    };
+    let started = Instant::now();
+
+    RegisteredResource::new(resource, move |uri| {
+        let totals = metrics.totals_by_method();
+        let requests: BTreeMap<&str, serde_json::Value> = totals
+            .iter()
+            .map(|(method, (count, errors))| {
+                (
+                    method.as_str(),
+                    serde_json::json!({ "count": count, "errors": errors }),
+                )
+            })
+            .collect();
+        let cache = cache.stats();
+        let lookups = cache.hits + cache.misses;
+        let stats = serde_json::json!({
+            "uptimeSeconds": started.elapsed().as_secs(),
+            "activeSessions": metrics.active_sessions.load(Ordering::Relaxed),
+            "requests": {
+                "total": totals.values().map(|(count, _)| count).sum::<u64>(),
+                "errors": totals.values().map(|(_, errors)| errors).sum::<u64>(),
+                "byMethod": requests
+            },
+            "caches": {
+                "toolResults": {
+                    "hits": cache.hits,
+                    "misses": cache.misses,
+                    "entries": cache.entries,
+                    // Null until the first lookup
+                    "hitRate": (lookups > 0).then(|| cache.hits as f64 / lookups as f64)
+                }
+            }
+        });
+        async move {
+            Ok(vec![ResourceContents::text(
+                uri,
+                "application/json",
+                serde_json::to_string_pretty(&stats)?,
+            )])
+        }
+    })
+}
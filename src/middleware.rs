@@ -0,0 +1,75 @@
+use anyhow::Result;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::debug;
+
+use crate::server::McpServer;
+use crate::session::Session;
+use crate::types::{JsonRpcRequest, McpResponse};
+
+pub type HandlerFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<McpResponse>>> + Send + 'a>>;
+
+/// Wraps request handling. A layer can inspect or rewrite the request, skip
+/// the rest of the chain by answering itself, or post-process the response
+/// returned by `next.run(...)`.
+pub trait Layer: Send + Sync {
+    fn call<'a>(
+        &'a self,
+        session: &'a Session,
+        request: JsonRpcRequest,
+        next: Next<'a>,
+    ) -> HandlerFuture<'a>;
+}
+
+/// The remainder of the layer chain, ending in the server's own dispatch.
+/// It is `Copy`, so a layer may run the rest of the chain more than once.
+#[derive(Clone, Copy)]
+pub struct Next<'a> {
+    server: &'a McpServer,
+    layers: &'a [Arc<dyn Layer>],
+}
+
+impl<'a> Next<'a> {
+    pub fn new(server: &'a McpServer, layers: &'a [Arc<dyn Layer>]) -> Self {
+        Self { server, layers }
+    }
+
+    pub fn server(&self) -> &'a McpServer {
+        self.server
+    }
+
+    pub fn run(self, session: &'a Session, request: JsonRpcRequest) -> HandlerFuture<'a> {
+        match self.layers.split_first() {
+            Some((layer, rest)) => layer.call(session, request, Next::new(self.server, rest)),
+            None => Box::pin(self.server.dispatch(session, request)),
+        }
+    }
+}
+
+/// Logs every request with how long it took to handle.
+pub struct LoggingLayer;
+
+impl Layer for LoggingLayer {
+    fn call<'a>(
+        &'a self,
+        session: &'a Session,
+        request: JsonRpcRequest,
+        next: Next<'a>,
+    ) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let method = request.method.clone();
+            let started = Instant::now();
+            let result = next.run(session, request).await;
+            debug!(
+                "{} for session {} handled in {:?} ({})",
+                method,
+                session.id,
+                started.elapsed(),
+                if result.is_ok() { "ok" } else { "error" }
+            );
+            result
+        })
+    }
+}
@@ -0,0 +1,332 @@
+use anyhow::Result;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::framing::Frame;
+
+// Deepest nesting accepted when decoding, so a hostile message can't
+// overflow the stack
+const MAX_DEPTH: usize = 128;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes a JSON-RPC message as a MessagePack frame: a 4-byte big-endian
+/// length, then the value. Base64 `blob` fields and the `data` of image and
+/// audio content go out as `bin`, a quarter smaller than the text.
+pub fn frame(message: &serde_json::Value) -> Vec<u8> {
+    let mut out = vec![0; 4];
+    encode(message, &mut out);
+    let length = (out.len() - 4) as u32;
+    out[..4].copy_from_slice(&length.to_be_bytes());
+    out
+}
+
+/// Reads one length-prefixed frame into `bytes`. A frame longer than `max`
+/// is read past and discarded.
+pub async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    bytes: &mut Vec<u8>,
+    max: usize,
+) -> std::io::Result<Frame> {
+    let mut length = [0; 4];
+    match reader.read_exact(&mut length).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(Frame::Eof),
+        Err(e) => return Err(e),
+    }
+    let length = u32::from_be_bytes(length) as usize;
+    if length > max {
+        tokio::io::copy(&mut reader.take(length as u64), &mut tokio::io::sink()).await?;
+        return Ok(Frame::TooLong(length));
+    }
+    bytes.resize(length, 0);
+    reader.read_exact(bytes).await?;
+    Ok(Frame::Message)
+}
+
+fn encode(value: &serde_json::Value, out: &mut Vec<u8>) {
+    match value {
+        serde_json::Value::Null => out.push(0xc0),
+        serde_json::Value::Bool(false) => out.push(0xc2),
+        serde_json::Value::Bool(true) => out.push(0xc3),
+        serde_json::Value::Number(number) => {
+            if let Some(n) = number.as_u64() {
+                encode_uint(n, out);
+            } else if let Some(n) = number.as_i64() {
+                encode_int(n, out);
+            } else {
+                out.push(0xcb);
+                out.extend_from_slice(&number.as_f64().unwrap_or_default().to_be_bytes());
+            }
+        }
+        serde_json::Value::String(text) => encode_str(text, out),
+        serde_json::Value::Array(items) => {
+            encode_length(items.len(), 0x90, 0xdc, out);
+            for item in items {
+                encode(item, out);
+            }
+        }
+        serde_json::Value::Object(object) => {
+            encode_length(object.len(), 0x80, 0xde, out);
+            let binary_data = object.contains_key("mimeType")
+                && matches!(object["type"].as_str(), Some("image" | "audio"));
+            for (key, item) in object {
+                encode_str(key, out);
+                let binary = key == "blob" || (key == "data" && binary_data);
+                match item.as_str().filter(|_| binary).and_then(base64_decode) {
+                    Some(bytes) => encode_bin(&bytes, out),
+                    None => encode(item, out),
+                }
+            }
+        }
+    }
+}
+
+fn encode_uint(n: u64, out: &mut Vec<u8>) {
+    if n < 0x80 {
+        out.push(n as u8);
+    } else if n <= u8::MAX as u64 {
+        out.extend_from_slice(&[0xcc, n as u8]);
+    } else if n <= u16::MAX as u64 {
+        out.push(0xcd);
+        out.extend_from_slice(&(n as u16).to_be_bytes());
+    } else if n <= u32::MAX as u64 {
+        out.push(0xce);
+        out.extend_from_slice(&(n as u32).to_be_bytes());
+    } else {
+        out.push(0xcf);
+        out.extend_from_slice(&n.to_be_bytes());
+    }
+}
+
+// Only called for negative numbers; non-negative ones are unsigned
+fn encode_int(n: i64, out: &mut Vec<u8>) {
+    if n >= -32 {
+        out.push(n as i8 as u8);
+    } else if n >= i8::MIN as i64 {
+        out.extend_from_slice(&[0xd0, n as i8 as u8]);
+    } else if n >= i16::MIN as i64 {
+        out.push(0xd1);
+        out.extend_from_slice(&(n as i16).to_be_bytes());
+    } else if n >= i32::MIN as i64 {
+        out.push(0xd2);
+        out.extend_from_slice(&(n as i32).to_be_bytes());
+    } else {
+        out.push(0xd3);
+        out.extend_from_slice(&n.to_be_bytes());
+    }
+}
+
+fn encode_str(text: &str, out: &mut Vec<u8>) {
+    let length = text.len();
+    if length < 32 {
+        out.push(0xa0 | length as u8);
+    } else if length <= u8::MAX as usize {
+        out.extend_from_slice(&[0xd9, length as u8]);
+    } else if length <= u16::MAX as usize {
+        out.push(0xda);
+        out.extend_from_slice(&(length as u16).to_be_bytes());
+    } else {
+        out.push(0xdb);
+        out.extend_from_slice(&(length as u32).to_be_bytes());
+    }
+    out.extend_from_slice(text.as_bytes());
+}
+
+fn encode_bin(bytes: &[u8], out: &mut Vec<u8>) {
+    let length = bytes.len();
+    if length <= u8::MAX as usize {
+        out.extend_from_slice(&[0xc4, length as u8]);
+    } else if length <= u16::MAX as usize {
+        out.push(0xc5);
+        out.extend_from_slice(&(length as u16).to_be_bytes());
+    } else {
+        out.push(0xc6);
+        out.extend_from_slice(&(length as u32).to_be_bytes());
+    }
+    out.extend_from_slice(bytes);
+}
+
+// Arrays and maps: a fix form for up to 15 entries, then 16- and 32-bit
+// lengths, whose markers follow each other
+fn encode_length(length: usize, fix: u8, marker16: u8, out: &mut Vec<u8>) {
+    if length < 16 {
+        out.push(fix | length as u8);
+    } else if length <= u16::MAX as usize {
+        out.push(marker16);
+        out.extend_from_slice(&(length as u16).to_be_bytes());
+    } else {
+        out.push(marker16 + 1);
+        out.extend_from_slice(&(length as u32).to_be_bytes());
+    }
+}
+
+/// Decodes one MessagePack value into JSON. `bin` values become base64
+/// strings, as the JSON form of the protocol expects. Map keys must be
+/// strings, and ext types aren't supported.
+pub fn decode(bytes: &[u8]) -> Result<serde_json::Value> {
+    let mut decoder = Decoder { bytes, position: 0 };
+    let value = decoder.value(0)?;
+    if decoder.position != bytes.len() {
+        anyhow::bail!("Trailing bytes after MessagePack value");
+    }
+    Ok(value)
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| anyhow::anyhow!("Truncated MessagePack value"))?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into()?)
+    }
+
+    fn length(&mut self, width: usize) -> Result<usize> {
+        Ok(match width {
+            1 => self.byte()? as usize,
+            2 => u16::from_be_bytes(self.array()?) as usize,
+            _ => u32::from_be_bytes(self.array()?) as usize,
+        })
+    }
+
+    fn value(&mut self, depth: usize) -> Result<serde_json::Value> {
+        if depth > MAX_DEPTH {
+            anyhow::bail!("MessagePack value nested too deeply");
+        }
+        let marker = self.byte()?;
+        Ok(match marker {
+            0x00..=0x7f => serde_json::json!(marker),
+            0x80..=0x8f => self.map((marker & 0x0f) as usize, depth)?,
+            0x90..=0x9f => self.list((marker & 0x0f) as usize, depth)?,
+            0xa0..=0xbf => self.string((marker & 0x1f) as usize)?,
+            0xc0 => serde_json::Value::Null,
+            0xc2 => serde_json::Value::Bool(false),
+            0xc3 => serde_json::Value::Bool(true),
+            0xc4..=0xc6 => {
+                let length = self.length(1 << (marker - 0xc4))?;
+                serde_json::Value::String(base64_encode(self.take(length)?))
+            }
+            0xca => serde_json::json!(f32::from_be_bytes(self.array()?)),
+            0xcb => serde_json::json!(f64::from_be_bytes(self.array()?)),
+            0xcc => serde_json::json!(self.byte()?),
+            0xcd => serde_json::json!(u16::from_be_bytes(self.array()?)),
+            0xce => serde_json::json!(u32::from_be_bytes(self.array()?)),
+            0xcf => serde_json::json!(u64::from_be_bytes(self.array()?)),
+            0xd0 => serde_json::json!(i8::from_be_bytes(self.array()?)),
+            0xd1 => serde_json::json!(i16::from_be_bytes(self.array()?)),
+            0xd2 => serde_json::json!(i32::from_be_bytes(self.array()?)),
+            0xd3 => serde_json::json!(i64::from_be_bytes(self.array()?)),
+            0xd9..=0xdb => {
+                let length = self.length(1 << (marker - 0xd9))?;
+                self.string(length)?
+            }
+            0xdc | 0xdd => {
+                let length = self.length(2 << (marker - 0xdc))?;
+                self.list(length, depth)?
+            }
+            0xde | 0xdf => {
+                let length = self.length(2 << (marker - 0xde))?;
+                self.map(length, depth)?
+            }
+            0xe0..=0xff => serde_json::json!(marker as i8),
+            _ => anyhow::bail!("Unsupported MessagePack type 0x{:02x}", marker),
+        })
+    }
+
+    fn string(&mut self, length: usize) -> Result<serde_json::Value> {
+        let text = std::str::from_utf8(self.take(length)?)?;
+        Ok(serde_json::Value::String(text.to_string()))
+    }
+
+    fn list(&mut self, length: usize, depth: usize) -> Result<serde_json::Value> {
+        // Every item takes at least a byte, which bounds what a bogus
+        // length can make us allocate
+        let mut items = Vec::with_capacity(length.min(self.bytes.len() - self.position));
+        for _ in 0..length {
+            items.push(self.value(depth + 1)?);
+        }
+        Ok(serde_json::Value::Array(items))
+    }
+
+    fn map(&mut self, length: usize, depth: usize) -> Result<serde_json::Value> {
+        let mut object = serde_json::Map::new();
+        for _ in 0..length {
+            let serde_json::Value::String(key) = self.value(depth + 1)? else {
+                anyhow::bail!("MessagePack map keys must be strings");
+            };
+            object.insert(key, self.value(depth + 1)?);
+        }
+        Ok(serde_json::Value::Object(object))
+    }
+}
+
+/// Encodes `bytes` as padded base64.
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// Decodes padded base64, or `None` if `text` isn't canonical base64.
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    for (index, chunk) in text.chunks(4).enumerate() {
+        let last = index == text.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for (i, &c) in chunk[..4 - padding].iter().enumerate() {
+            n |= base64_digit(c)? << (18 - 6 * i);
+        }
+        let decoded = [(n >> 16) as u8, (n >> 8) as u8, n as u8];
+        bytes.extend_from_slice(&decoded[..3 - padding]);
+    }
+    // Only round-trip exact encodings, so the client gets back what was sent
+    (base64_encode(&bytes).as_bytes() == text).then_some(bytes)
+}
+
+fn base64_digit(c: u8) -> Option<u32> {
+    let digit = match c {
+        b'A'..=b'Z' => c - b'A',
+        b'a'..=b'z' => c - b'a' + 26,
+        b'0'..=b'9' => c - b'0' + 52,
+        b'+' => 62,
+        b'/' => 63,
+        _ => return None,
+    };
+    Some(digit as u32)
+}
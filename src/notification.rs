@@ -0,0 +1,461 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{debug, warn};
+
+use crate::chaos;
+use crate::compat::NotificationAliases;
+use crate::events::{Change, ServerEvent};
+
+// How long list-changed notifications are held to coalesce repeats, unless
+// the config says otherwise
+pub const DEFAULT_LIST_CHANGED_DELAY: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+    Alert,
+    Emergency,
+}
+
+/// Every notification the server can send. Method names and params are
+/// derived from the variant, so callers can't get either wrong.
+#[derive(Debug, Clone)]
+pub enum Notification {
+    // `delta` is None when what changed isn't known
+    ToolListChanged {
+        delta: Option<ListDelta>,
+    },
+    ResourceListChanged {
+        delta: Option<ListDelta>,
+    },
+    PromptListChanged {
+        delta: Option<ListDelta>,
+    },
+    ResourceUpdated {
+        uri: String,
+    },
+    Progress {
+        progress_token: serde_json::Value,
+        progress: f64,
+        total: Option<f64>,
+        message: Option<String>,
+    },
+    LogMessage {
+        level: LogLevel,
+        logger: Option<String>,
+        data: serde_json::Value,
+    },
+}
+
+impl Notification {
+    pub fn method(&self) -> &'static str {
+        match self {
+            Notification::ToolListChanged { .. } => "notifications/tools/list_changed",
+            Notification::ResourceListChanged { .. } => "notifications/resources/list_changed",
+            Notification::PromptListChanged { .. } => "notifications/prompts/list_changed",
+            Notification::ResourceUpdated { .. } => "notifications/resources/updated",
+            Notification::Progress { .. } => "notifications/progress",
+            Notification::LogMessage { .. } => "notifications/message",
+        }
+    }
+
+    pub fn params(&self) -> serde_json::Value {
+        match self {
+            Notification::ToolListChanged { delta }
+            | Notification::ResourceListChanged { delta }
+            | Notification::PromptListChanged { delta } => match delta {
+                Some(delta) => serde_json::json!({ "delta": delta }),
+                None => serde_json::json!({}),
+            },
+            Notification::ResourceUpdated { uri } => serde_json::json!({ "uri": uri }),
+            Notification::Progress {
+                progress_token,
+                progress,
+                total,
+                message,
+            } => {
+                let mut params = serde_json::json!({
+                    "progressToken": progress_token,
+                    "progress": progress
+                });
+                if let Some(total) = total {
+                    params["total"] = serde_json::json!(total);
+                }
+                if let Some(message) = message {
+                    params["message"] = serde_json::json!(message);
+                }
+                params
+            }
+            Notification::LogMessage {
+                level,
+                logger,
+                data,
+            } => {
+                let mut params = serde_json::json!({ "level": level, "data": data });
+                if let Some(logger) = logger {
+                    params["logger"] = serde_json::json!(logger);
+                }
+                params
+            }
+        }
+    }
+
+    fn is_list_changed(&self) -> bool {
+        matches!(
+            self,
+            Notification::ToolListChanged { .. }
+                | Notification::ResourceListChanged { .. }
+                | Notification::PromptListChanged { .. }
+        )
+    }
+
+    // The delta slot of a list change; None for other notifications
+    fn delta(&mut self) -> Option<&mut Option<ListDelta>> {
+        match self {
+            Notification::ToolListChanged { delta }
+            | Notification::ResourceListChanged { delta }
+            | Notification::PromptListChanged { delta } => Some(delta),
+            _ => None,
+        }
+    }
+
+    // Folds a later list change of the same kind into this one. If either
+    // doesn't know what changed, neither does the result.
+    fn absorb(&mut self, mut later: Notification) {
+        let later = later.delta().and_then(Option::take);
+        if let Some(delta) = self.delta() {
+            *delta = match (delta.take(), later) {
+                (Some(mut earlier), Some(later)) => {
+                    earlier.merge(later);
+                    Some(earlier)
+                }
+                _ => None,
+            };
+        }
+    }
+
+    pub fn to_message(&self) -> serde_json::Value {
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": self.method(),
+            "params": self.params()
+        })
+    }
+}
+
+/// What changed in a list, sent as `delta` in a list-changed notification's
+/// params so clients can update theirs without fetching it all again.
+/// Tools and prompts are given by name, resources by URI or URI template.
+/// Names in `added` may already be listed, when something was registered
+/// again.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ListDelta {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub updated: Vec<String>,
+}
+
+impl ListDelta {
+    pub fn of(change: Change, name: &str) -> Self {
+        let mut delta = Self::default();
+        match change {
+            Change::Added => delta.added.push(name.to_string()),
+            Change::Removed => delta.removed.push(name.to_string()),
+            Change::Updated => delta.updated.push(name.to_string()),
+        }
+        delta
+    }
+
+    // Adds the changes in `later`, so the result is the net change over both
+    fn merge(&mut self, later: ListDelta) {
+        for name in later.added {
+            if let Some(index) = self.removed.iter().position(|n| *n == name) {
+                // Removed and added back: clients should fetch it again
+                self.removed.remove(index);
+                push_new(&mut self.updated, name);
+            } else {
+                push_new(&mut self.added, name);
+            }
+        }
+        for name in later.removed {
+            if let Some(index) = self.added.iter().position(|n| *n == name) {
+                // Clients never saw it
+                self.added.remove(index);
+            } else {
+                self.updated.retain(|n| *n != name);
+                push_new(&mut self.removed, name);
+            }
+        }
+        for name in later.updated {
+            if !self.added.contains(&name) && !self.removed.contains(&name) {
+                push_new(&mut self.updated, name);
+            }
+        }
+    }
+}
+
+fn push_new(names: &mut Vec<String>, name: String) {
+    if !names.contains(&name) {
+        names.push(name);
+    }
+}
+
+/// Cheap, cloneable handle for sending notifications to one session.
+#[derive(Clone)]
+pub struct Notifier {
+    session_id: String,
+    outbound: mpsc::UnboundedSender<serde_json::Value>,
+    // Chance of losing each notification, for chaos mode
+    drop_rate: f64,
+}
+
+impl Notifier {
+    pub fn new(session_id: String, outbound: mpsc::UnboundedSender<serde_json::Value>) -> Self {
+        Self {
+            session_id,
+            outbound,
+            drop_rate: 0.0,
+        }
+    }
+
+    pub fn with_drop_rate(mut self, drop_rate: f64) -> Self {
+        self.drop_rate = drop_rate;
+        self
+    }
+
+    pub fn notify(&self, notification: Notification) -> Result<()> {
+        self.send(notification.to_message())
+    }
+
+    // Sends under another method name, e.g. one an old client expects
+    pub fn notify_as(&self, notification: Notification, method: &str) -> Result<()> {
+        let mut message = notification.to_message();
+        message["method"] = serde_json::json!(method);
+        self.send(message)
+    }
+
+    fn send(&self, message: serde_json::Value) -> Result<()> {
+        if self.drop_rate > 0.0 && chaos::random() < self.drop_rate {
+            debug!("Chaos: dropped {}", message["method"]);
+            return Ok(());
+        }
+        self.outbound
+            .send(message)
+            .map_err(|_| anyhow::anyhow!("Session {} is closed", self.session_id))
+    }
+}
+
+struct Subscriber {
+    notifier: Notifier,
+    // Set once the client sends `initialized`; list changes aren't sent
+    // before that
+    initialized: bool,
+    // Least severe log message the client wants, once it has asked for any
+    log_level: Option<LogLevel>,
+    // Negotiated in `initialize`; picks the outgoing aliases
+    protocol_version: Option<String>,
+}
+
+/// Fans notifications out to connected sessions. List-changed notifications
+/// are held for a short delay and sent once however often they fired in the
+/// meantime, and only to sessions that have finished initializing.
+pub struct NotificationManager {
+    sessions: RwLock<HashMap<String, Subscriber>>,
+    delay: Duration,
+    aliases: NotificationAliases,
+    // List changes waiting to go out; `Some` while a flush is scheduled
+    pending: Mutex<Option<Vec<Notification>>>,
+    // Whether list changes carry a `ListDelta`; only when every session
+    // sees the same lists
+    deltas: bool,
+}
+
+impl NotificationManager {
+    pub fn new(delay: Duration, aliases: NotificationAliases, deltas: bool) -> Self {
+        Self {
+            sessions: RwLock::new(HashMap::new()),
+            delay,
+            aliases,
+            pending: Mutex::new(None),
+            deltas,
+        }
+    }
+
+    pub fn add(&self, session_id: &str, notifier: Notifier) {
+        self.sessions.write().unwrap().insert(
+            session_id.to_string(),
+            Subscriber {
+                notifier,
+                initialized: false,
+                log_level: None,
+                protocol_version: None,
+            },
+        );
+    }
+
+    // Returns false if the session was already gone
+    pub fn remove(&self, session_id: &str) -> bool {
+        self.sessions.write().unwrap().remove(session_id).is_some()
+    }
+
+    pub fn session_ids(&self) -> Vec<String> {
+        self.sessions.read().unwrap().keys().cloned().collect()
+    }
+
+    pub fn set_initialized(&self, session_id: &str) {
+        if let Some(subscriber) = self.sessions.write().unwrap().get_mut(session_id) {
+            subscriber.initialized = true;
+        }
+    }
+
+    pub fn set_protocol_version(&self, session_id: &str, version: &str) {
+        if let Some(subscriber) = self.sessions.write().unwrap().get_mut(session_id) {
+            subscriber.protocol_version = Some(version.to_string());
+        }
+    }
+
+    pub fn set_log_level(&self, session_id: &str, level: LogLevel) {
+        if let Some(subscriber) = self.sessions.write().unwrap().get_mut(session_id) {
+            subscriber.log_level = Some(level);
+        }
+    }
+
+    // Sends a log message to every session that asked for messages at least
+    // this severe. Failures aren't logged, which would only feed more
+    // messages back in here.
+    pub fn log(&self, level: LogLevel, logger: &str, data: &serde_json::Value) {
+        let sessions = self.sessions.read().unwrap();
+        for subscriber in sessions.values() {
+            if subscriber.log_level.is_some_and(|wanted| level >= wanted) {
+                let _ = self.send(
+                    subscriber,
+                    Notification::LogMessage {
+                        level,
+                        logger: Some(logger.to_string()),
+                        data: data.clone(),
+                    },
+                );
+            }
+        }
+    }
+
+    // Sends straight to one session
+    pub fn notify(&self, session_id: &str, notification: Notification) -> Result<()> {
+        match self.sessions.read().unwrap().get(session_id) {
+            Some(subscriber) => self.send(subscriber, notification),
+            None => anyhow::bail!("Session {} is not connected", session_id),
+        }
+    }
+
+    // Sends to every session. List changes are coalesced; anything else goes
+    // out at once.
+    pub fn broadcast(self: &Arc<Self>, mut notification: Notification) {
+        // e.g. registrations at startup, which nobody needs to hear about
+        if self.sessions.read().unwrap().is_empty() {
+            return;
+        }
+        if !notification.is_list_changed() {
+            self.send_all(&[notification], false);
+            return;
+        }
+        if !self.deltas {
+            if let Some(delta) = notification.delta() {
+                *delta = None;
+            }
+        }
+        let runtime = match tokio::runtime::Handle::try_current() {
+            Ok(runtime) if !self.delay.is_zero() => runtime,
+            _ => {
+                self.send_all(&[notification], true);
+                return;
+            }
+        };
+
+        let mut pending = self.pending.lock().unwrap();
+        match pending.as_mut() {
+            Some(queued) => match queued
+                .iter_mut()
+                .find(|n| n.method() == notification.method())
+            {
+                Some(earlier) => earlier.absorb(notification),
+                None => queued.push(notification),
+            },
+            None => {
+                *pending = Some(vec![notification]);
+                let manager = self.clone();
+                runtime.spawn(async move {
+                    tokio::time::sleep(manager.delay).await;
+                    let queued = manager.pending.lock().unwrap().take().unwrap_or_default();
+                    manager.send_all(&queued, true);
+                });
+            }
+        }
+    }
+
+    // Sends the list changes that server events call for, until the bus
+    // closes
+    pub async fn follow(self: Arc<Self>, mut events: broadcast::Receiver<ServerEvent>) {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    for notification in event.list_changes() {
+                        self.broadcast(notification);
+                    }
+                }
+                // Whatever was missed, clients re-listing everything covers it
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Missed {} server events", missed);
+                    self.broadcast(Notification::ToolListChanged { delta: None });
+                    self.broadcast(Notification::ResourceListChanged { delta: None });
+                    self.broadcast(Notification::PromptListChanged { delta: None });
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    // Sends under the alias configured for the session's protocol version,
+    // if there is one
+    fn send(&self, subscriber: &Subscriber, notification: Notification) -> Result<()> {
+        let alias = subscriber
+            .protocol_version
+            .as_deref()
+            .and_then(|version| self.aliases.outgoing(version, notification.method()));
+        match alias {
+            Some(method) => subscriber.notifier.notify_as(notification, method),
+            None => subscriber.notifier.notify(notification),
+        }
+    }
+
+    fn send_all(&self, notifications: &[Notification], initialized_only: bool) {
+        let sessions = self.sessions.read().unwrap();
+        let recipients = sessions
+            .values()
+            .filter(|subscriber| subscriber.initialized || !initialized_only);
+        let mut sent = 0;
+        for subscriber in recipients {
+            for notification in notifications {
+                if let Err(e) = self.send(subscriber, notification.clone()) {
+                    warn!("Failed to broadcast {}: {}", notification.method(), e);
+                }
+            }
+            sent += 1;
+        }
+        debug!(
+            "Broadcast {} notifications to {} sessions",
+            notifications.len(),
+            sent
+        );
+    }
+}
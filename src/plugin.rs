@@ -0,0 +1,28 @@
+use anyhow::Result;
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::session::Session;
+
+pub type HookFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+/// Hooks into the session lifecycle, for backends that keep per-session
+/// state such as a database connection. Every hook does nothing by default.
+pub trait Plugin: Send + Sync {
+    // Runs while `initialize` is handled, before it is answered. An error
+    // fails the initialize request.
+    fn on_initialize<'a>(&'a self, _session: &'a Session) -> HookFuture<'a> {
+        Box::pin(async { Ok(()) })
+    }
+
+    // Runs once the client sends `initialized`
+    fn on_initialized<'a>(&'a self, _session: &'a Session) -> HookFuture<'a> {
+        Box::pin(async { Ok(()) })
+    }
+
+    // Runs once per session when it ends, whether the client went away or
+    // the server is shutting down
+    fn on_shutdown<'a>(&'a self, _session_id: &'a str) -> HookFuture<'a> {
+        Box::pin(async { Ok(()) })
+    }
+}
@@ -0,0 +1,127 @@
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+struct Waiter {
+    priority: Priority,
+    seq: u64,
+    grant: oneshot::Sender<WorkerPermit>,
+}
+
+// Highest priority first, then first come first served
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.seq == other.seq
+    }
+}
+
+impl Eq for Waiter {}
+
+struct State {
+    free: usize,
+    waiting: BinaryHeap<Waiter>,
+    next_seq: u64,
+}
+
+/// A fixed number of request workers. When all are busy, requests queue up
+/// and a freed worker goes to the highest-priority one waiting, so pings
+/// and list requests don't sit behind a backlog of slow tool calls.
+pub struct WorkerPool {
+    state: Mutex<State>,
+}
+
+/// A worker held by one request, handed on when dropped.
+pub struct WorkerPermit {
+    pool: Option<Arc<WorkerPool>>,
+}
+
+impl WorkerPool {
+    pub fn new(workers: usize) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(State {
+                free: workers.max(1),
+                waiting: BinaryHeap::new(),
+                next_seq: 0,
+            }),
+        })
+    }
+
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> WorkerPermit {
+        let granted = {
+            let mut state = self.state.lock().unwrap();
+            if state.free > 0 {
+                state.free -= 1;
+                return WorkerPermit {
+                    pool: Some(self.clone()),
+                };
+            }
+            let (grant, granted) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiting.push(Waiter {
+                priority,
+                seq,
+                grant,
+            });
+            granted
+        };
+        // The pool outlives every waiter, so the sender is never dropped
+        // without sending
+        granted.await.expect("worker pool dropped a waiter")
+    }
+
+    // Requests waiting for a worker
+    pub fn queued(&self) -> usize {
+        self.state.lock().unwrap().waiting.len()
+    }
+
+    fn release(self: Arc<Self>) {
+        let mut state = self.state.lock().unwrap();
+        while let Some(waiter) = state.waiting.pop() {
+            let permit = WorkerPermit {
+                pool: Some(self.clone()),
+            };
+            match waiter.grant.send(permit) {
+                Ok(()) => return,
+                // That request was cancelled while it waited; the worker
+                // goes to the next one instead
+                Err(mut permit) => {
+                    permit.pool = None;
+                }
+            }
+        }
+        state.free += 1;
+    }
+}
+
+impl Drop for WorkerPermit {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.release();
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use crate::completion::{self, CompletionHandler, CompletionRequest};
+use crate::types::{
+    Content, GetPromptResult, Icon, Meta, Prompt, PromptArgument, PromptMessage, Role,
+};
+
+pub type PromptFuture = Pin<Box<dyn Future<Output = Result<GetPromptResult>> + Send>>;
+pub type PromptHandler = Arc<dyn Fn(HashMap<String, String>, Meta) -> PromptFuture + Send + Sync>;
+
+/// A prompt definition together with the code that renders it from the
+/// client's arguments and the request's `_meta`.
+#[derive(Clone)]
+pub struct RegisteredPrompt {
+    pub prompt: Prompt,
+    pub handler: PromptHandler,
+    // Completion providers by argument name
+    pub completions: HashMap<String, CompletionHandler>,
+}
+
+impl RegisteredPrompt {
+    pub fn new<F, Fut>(prompt: Prompt, handler: F) -> Self
+    where
+        F: Fn(HashMap<String, String>, Meta) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<GetPromptResult>> + Send + 'static,
+    {
+        Self {
+            prompt,
+            handler: Arc::new(move |arguments, meta| Box::pin(handler(arguments, meta))),
+            completions: HashMap::new(),
+        }
+    }
+
+    // Suggests values for `argument` through `completion/complete`. The
+    // provider returns candidates for what has been typed so far, and the
+    // server ranks and trims them; see `completion::rank`.
+    pub fn with_completion<F, Fut>(mut self, argument: &str, provider: F) -> Self
+    where
+        F: Fn(CompletionRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Vec<String>>> + Send + 'static,
+    {
+        self.completions
+            .insert(argument.to_string(), completion::handler(provider));
+        self
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.prompt.title = Some(title.into());
+        self
+    }
+
+    pub fn with_icon(mut self, icon: Icon) -> Self {
+        self.prompt.icons.push(icon);
+        self
+    }
+}
+
+impl GetPromptResult {
+    pub fn user_text(text: impl Into<String>) -> Self {
+        Self {
+            description: None,
+            messages: vec![PromptMessage {
+                role: Role::User,
+                content: Content::text(text),
+            }],
+            meta: None,
+        }
+    }
+}
+
+pub fn builtin_prompts() -> Vec<RegisteredPrompt> {
+    vec![hello()]
+}
+
+fn hello() -> RegisteredPrompt {
+    let prompt = Prompt {
+        name: "hello".to_string(),
+        title: Some("Hello".to_string()),
+        description: "Returns a friendly greeting".to_string(),
+        arguments: Vec::new(),
+        icons: Vec::new(),
+    };
+
+    RegisteredPrompt::new(prompt, |_, _| async move {
+        Ok(GetPromptResult::user_text("Hello from leap-mcp prompts!"))
+    })
+}
+
+// Loads every `.md`/`.txt` file in `dir` as a prompt named after the file.
+// The first line is the description (a leading `#` is dropped), the rest is
+// the prompt text, and each `{{name}}` placeholder becomes a required
+// argument.
+pub fn load_prompt_dir(dir: &Path) -> Result<Vec<RegisteredPrompt>> {
+    let mut prompts = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_prompt_file = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext == "md" || ext == "txt");
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        if !path.is_file() || !is_prompt_file {
+            continue;
+        }
+
+        let contents = std::fs::read_to_string(&path)?;
+        prompts.push(template_prompt(name, &contents));
+    }
+    prompts.sort_by(|a, b| a.prompt.name.cmp(&b.prompt.name));
+    Ok(prompts)
+}
+
+fn template_prompt(name: &str, contents: &str) -> RegisteredPrompt {
+    let (first_line, body) = contents.split_once('\n').unwrap_or((contents, ""));
+    let description = first_line.trim_start_matches('#').trim().to_string();
+    let template = body.trim().to_string();
+
+    let mut arguments: Vec<PromptArgument> = Vec::new();
+    for placeholder in placeholders(&template) {
+        if !arguments
+            .iter()
+            .any(|argument| argument.name == placeholder)
+        {
+            arguments.push(PromptArgument {
+                name: placeholder.to_string(),
+                description: None,
+                required: true,
+            });
+        }
+    }
+
+    let prompt = Prompt {
+        name: name.to_string(),
+        title: None,
+        description,
+        arguments,
+        icons: Vec::new(),
+    };
+    let required: Vec<String> = prompt.arguments.iter().map(|a| a.name.clone()).collect();
+
+    RegisteredPrompt::new(prompt, move |arguments, _| {
+        let template = template.clone();
+        let required = required.clone();
+        async move {
+            let mut text = template;
+            for name in &required {
+                let value = arguments
+                    .get(name)
+                    .ok_or_else(|| anyhow::anyhow!("Missing '{}' argument", name))?;
+                text = text.replace(&format!("{{{{{}}}}}", name), value);
+            }
+            Ok(GetPromptResult::user_text(text))
+        }
+    })
+}
+
+fn placeholders(template: &str) -> Vec<&str> {
+    let mut found = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                let name = after[..end].trim();
+                if !name.is_empty() {
+                    found.push(name);
+                }
+                rest = &after[end + 2..];
+            }
+            None => break,
+        }
+    }
+    found
+}
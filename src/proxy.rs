@@ -0,0 +1,371 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+use crate::compat::LATEST_PROTOCOL_VERSION;
+use crate::error::ServerError;
+use crate::http_client::{self, HttpResponse, HttpUrl};
+use crate::manifest;
+use crate::tools::RegisteredTool;
+use crate::types::CallToolResult;
+use crate::uri_template::percent_encode_path;
+
+const HTTP_METHODS: &[&str] = &["GET", "POST", "PUT", "PATCH", "DELETE"];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportConfig {
+    // A manifest written by `export-manifest`, or by hand in its shape
+    pub manifest: PathBuf,
+    // Put in front of every imported tool's name, to avoid clashes
+    #[serde(default)]
+    pub prefix: String,
+    // Sent with every request, e.g. for authorization
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(flatten)]
+    pub endpoint: ImportEndpoint,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ImportEndpoint {
+    // Each call is one request to `url`, where `{tool}` is the tool's name
+    // in the manifest and `{name}` the argument called `name`
+    Http {
+        url: String,
+        #[serde(default = "default_method")]
+        method: String,
+    },
+    // Calls are forwarded to a downstream MCP server's Streamable HTTP
+    // endpoint, e.g. "http://10.0.0.5:8080/mcp"
+    Mcp {
+        url: String,
+    },
+}
+
+fn default_method() -> String {
+    "POST".to_string()
+}
+
+/// Proxy tools for every tool in an imported manifest, so an aggregator
+/// can offer another service's tools from configuration alone. Nothing is
+/// contacted until a tool is called; downstream MCP sessions are set up on
+/// the first call and kept.
+pub fn tools(config: &ImportConfig) -> Result<Vec<RegisteredTool>> {
+    let definitions = manifest::load_tools(&config.manifest)?;
+    let headers: Vec<(String, String)> = config
+        .headers
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    let target = Arc::new(match &config.endpoint {
+        ImportEndpoint::Http { url, method } => {
+            let method = method.to_ascii_uppercase();
+            if !HTTP_METHODS.contains(&method.as_str()) {
+                anyhow::bail!("Unsupported HTTP method {} for {}", method, url);
+            }
+            // Checked now, with placeholders left in, so typos fail at startup
+            HttpUrl::parse(url)?;
+            Target::Http {
+                url: url.clone(),
+                method,
+                headers,
+            }
+        }
+        ImportEndpoint::Mcp { url } => Target::Mcp(Downstream {
+            url: HttpUrl::parse(url)?,
+            headers,
+            session: Mutex::new(None),
+            next_id: AtomicU64::new(1),
+        }),
+    });
+    info!(
+        "Importing {} tools from {}",
+        definitions.len(),
+        config.manifest.display()
+    );
+
+    Ok(definitions
+        .into_iter()
+        .map(|mut tool| {
+            let remote = tool.name.clone();
+            tool.name = format!("{}{}", config.prefix, remote);
+            let exposed = tool.name.clone();
+            let target = target.clone();
+            RegisteredTool::new(tool, move |arguments, _| {
+                let target = target.clone();
+                let remote = remote.clone();
+                let exposed = exposed.clone();
+                async move {
+                    target
+                        .call(&remote, &exposed, arguments)
+                        .await
+                        .map_err(|e| {
+                            match e.downcast::<ServerError>() {
+                                Ok(e) => e.into(),
+                                // Unreachable or failing services may recover
+                                Err(e) => ServerError::ToolFailed {
+                                    tool: exposed,
+                                    details: e.to_string(),
+                                    retryable: true,
+                                }
+                                .into(),
+                            }
+                        })
+                }
+            })
+        })
+        .collect())
+}
+
+enum Target {
+    Http {
+        url: String,
+        method: String,
+        headers: Vec<(String, String)>,
+    },
+    Mcp(Downstream),
+}
+
+impl Target {
+    // `tool` is the name in the manifest, `exposed` the one clients use
+    async fn call(
+        &self,
+        tool: &str,
+        exposed: &str,
+        arguments: HashMap<String, serde_json::Value>,
+    ) -> Result<CallToolResult> {
+        match self {
+            Target::Http {
+                url,
+                method,
+                headers,
+            } => {
+                let url = HttpUrl::parse(&expand(url, tool, &arguments)?)?;
+                let mut all_headers = vec![("Accept", "application/json")];
+                all_headers.extend(headers.iter().map(|(n, v)| (n.as_str(), v.as_str())));
+                let body = (method != "GET" && method != "DELETE")
+                    .then(|| serde_json::json!(arguments).to_string());
+                let response =
+                    http_client::send(method, &url, &all_headers, body.as_deref()).await?;
+                if !response.is_success() {
+                    // Client errors won't go away by retrying
+                    let details = format!(
+                        "{}: {}",
+                        response.status_line(),
+                        String::from_utf8_lossy(&response.body)
+                    );
+                    if (400..500).contains(&response.status) && response.status != 429 {
+                        return Err(ServerError::ToolFailed {
+                            tool: exposed.to_string(),
+                            details,
+                            retryable: false,
+                        }
+                        .into());
+                    }
+                    anyhow::bail!(details);
+                }
+                Ok(match serde_json::from_slice(&response.body) {
+                    Ok(serde_json::Value::String(text)) => CallToolResult::text(text),
+                    Ok(value @ serde_json::Value::Object(_)) => {
+                        CallToolResult::text(serde_json::to_string_pretty(&value)?)
+                            .with_structured_content(value)
+                    }
+                    Ok(value) => CallToolResult::text(value.to_string()),
+                    Err(_) => CallToolResult::text(String::from_utf8_lossy(&response.body)),
+                })
+            }
+            Target::Mcp(downstream) => downstream.call_tool(tool, exposed, arguments).await,
+        }
+    }
+}
+
+// Fills in `{tool}` and `{argument}` placeholders, percent-encoded
+fn expand(
+    template: &str,
+    tool: &str,
+    arguments: &HashMap<String, serde_json::Value>,
+) -> Result<String> {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..]
+            .find('}')
+            .map(|close| open + close)
+            .ok_or_else(|| anyhow::anyhow!("Unclosed placeholder in {}", template))?;
+        let name = &rest[open + 1..close];
+        let value = match (name, arguments.get(name)) {
+            ("tool", _) => tool.to_string(),
+            (_, Some(serde_json::Value::String(value))) => value.clone(),
+            (_, Some(value)) => value.to_string(),
+            (_, None) => {
+                return Err(ServerError::InvalidParams {
+                    details: format!("Missing '{}' argument", name),
+                }
+                .into())
+            }
+        };
+        expanded.push_str(&rest[..open]);
+        expanded.push_str(&percent_encode_path(&value).replace('/', "%2F"));
+        rest = &rest[close + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[derive(Clone)]
+struct DownstreamSession {
+    // None if the server doesn't use sessions
+    id: Option<String>,
+    protocol_version: String,
+}
+
+/// A client for one downstream MCP server over Streamable HTTP.
+struct Downstream {
+    url: HttpUrl,
+    headers: Vec<(String, String)>,
+    // Set up by the first call, and again if the server forgets it
+    session: Mutex<Option<DownstreamSession>>,
+    next_id: AtomicU64,
+}
+
+impl Downstream {
+    async fn call_tool(
+        &self,
+        tool: &str,
+        exposed: &str,
+        arguments: HashMap<String, serde_json::Value>,
+    ) -> Result<CallToolResult> {
+        let params = serde_json::json!({ "name": tool, "arguments": arguments });
+        // A second try, in case the server restarted or expired the session
+        for _ in 0..2 {
+            let session = self.session().await?;
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let response = self
+                .post(Some(&session), &request(id, "tools/call", &params))
+                .await?;
+            if response.status == 404 && session.id.is_some() {
+                debug!("Downstream session expired; starting another");
+                *self.session.lock().await = None;
+                continue;
+            }
+            let reply = reply(&response, id)?;
+            if let Some(error) = reply.get("error") {
+                return Err(ServerError::ToolFailed {
+                    tool: exposed.to_string(),
+                    details: error["message"]
+                        .as_str()
+                        .unwrap_or("Unknown error")
+                        .to_string(),
+                    retryable: false,
+                }
+                .into());
+            }
+            return Ok(serde_json::from_value(reply["result"].clone())?);
+        }
+        anyhow::bail!("Downstream server keeps rejecting its session")
+    }
+
+    // The current session, initializing one if there is none
+    async fn session(&self) -> Result<DownstreamSession> {
+        let mut session = self.session.lock().await;
+        if let Some(session) = session.as_ref() {
+            return Ok(session.clone());
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let initialize = request(
+            id,
+            "initialize",
+            &serde_json::json!({
+                "protocolVersion": LATEST_PROTOCOL_VERSION,
+                "capabilities": {},
+                "clientInfo": { "name": "test-mcp-proxy", "version": env!("CARGO_PKG_VERSION") }
+            }),
+        );
+        let response = self.post(None, &initialize).await?;
+        let reply = reply(&response, id)?;
+        let result = reply
+            .get("result")
+            .ok_or_else(|| anyhow::anyhow!("initialize failed: {}", reply["error"]))?;
+        let started = DownstreamSession {
+            id: response.header("Mcp-Session-Id").map(str::to_string),
+            protocol_version: result["protocolVersion"]
+                .as_str()
+                .unwrap_or(LATEST_PROTOCOL_VERSION)
+                .to_string(),
+        };
+        let initialized = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/initialized"
+        });
+        self.post(Some(&started), &initialized).await?;
+        info!("Connected to downstream MCP server {}", self.url.host());
+        *session = Some(started.clone());
+        Ok(started)
+    }
+
+    // Statuses are left to the caller, except that errors other than an
+    // unknown session fail here
+    async fn post(
+        &self,
+        session: Option<&DownstreamSession>,
+        message: &serde_json::Value,
+    ) -> Result<HttpResponse> {
+        let mut headers: Vec<(&str, &str)> = self
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        headers.push(("Accept", "application/json, text/event-stream"));
+        if let Some(session) = session {
+            if let Some(id) = &session.id {
+                headers.push(("Mcp-Session-Id", id));
+            }
+            headers.push(("MCP-Protocol-Version", &session.protocol_version));
+        }
+        let response =
+            http_client::send("POST", &self.url, &headers, Some(&message.to_string())).await?;
+        if !response.is_success() && response.status != 404 {
+            anyhow::bail!("{} returned {}", self.url.host(), response.status_line());
+        }
+        Ok(response)
+    }
+}
+
+fn request(id: u64, method: &str, params: &serde_json::Value) -> serde_json::Value {
+    serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+}
+
+// The JSON-RPC response with `id`, whether the server answered with plain
+// JSON or an event stream
+fn reply(response: &HttpResponse, id: u64) -> Result<serde_json::Value> {
+    if !response.is_success() {
+        anyhow::bail!("Downstream server returned {}", response.status_line());
+    }
+    let body = String::from_utf8_lossy(&response.body);
+    let streamed = response
+        .header("Content-Type")
+        .is_some_and(|kind| kind.starts_with("text/event-stream"));
+    if !streamed {
+        return Ok(serde_json::from_str(&body)?);
+    }
+    let body = body.replace("\r\n", "\n");
+    for event in body.split("\n\n") {
+        let data: Vec<&str> = event
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .map(|data| data.strip_prefix(' ').unwrap_or(data))
+            .collect();
+        if let Ok(message) = serde_json::from_str::<serde_json::Value>(&data.join("\n")) {
+            if message["id"] == id {
+                return Ok(message);
+            }
+        }
+    }
+    anyhow::bail!("No response in the downstream server's event stream")
+}
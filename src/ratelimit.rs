@@ -0,0 +1,161 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::error::{ServerError, RATE_LIMITED};
+use crate::middleware::{HandlerFuture, Layer, Next};
+use crate::session::Session;
+use crate::types::{JsonRpcRequest, McpResponse};
+
+// Finished windows are only swept once this many are being tracked
+const SWEEP_THRESHOLD: usize = 1024;
+
+/// A request quota: at most `requests` per `window_secs` for each client.
+/// With neither `methods` nor `tools` set it covers every request except
+/// `initialize` and `ping`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimitConfig {
+    pub requests: u32,
+    pub window_secs: u64,
+    #[serde(default)]
+    pub methods: Vec<String>,
+    // Only `tools/call` requests for these tools
+    #[serde(default)]
+    pub tools: Vec<String>,
+}
+
+impl RateLimitConfig {
+    fn period(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+
+    fn covers(&self, request: &JsonRpcRequest) -> bool {
+        if self.methods.is_empty() && self.tools.is_empty() {
+            return !matches!(request.method.as_str(), "initialize" | "ping");
+        }
+        if self.methods.contains(&request.method) {
+            return true;
+        }
+        request.method == "tools/call"
+            && request
+                .params
+                .as_ref()
+                .and_then(|params| params.get("name"))
+                .and_then(|name| name.as_str())
+                .is_some_and(|name| self.tools.iter().any(|tool| tool == name))
+    }
+}
+
+struct Window {
+    started: Instant,
+    used: u32,
+}
+
+/// Enforces the configured rate limits, counted per authenticated principal,
+/// or per client address for anonymous clients. Requests over a limit fail
+/// with a `RateLimited` error saying when to try again.
+pub struct RateLimitLayer {
+    limits: HashMap<String, RateLimitConfig>,
+    // (limit name, client) -> current window
+    windows: Mutex<HashMap<(String, String), Window>>,
+}
+
+impl RateLimitLayer {
+    pub fn new(limits: HashMap<String, RateLimitConfig>) -> Self {
+        Self {
+            limits,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Counts the request against every limit covering it, unless one of
+    // them is used up
+    fn check(&self, client: &str, request: &JsonRpcRequest) -> Result<(), ServerError> {
+        let covering: Vec<(&String, &RateLimitConfig)> = self
+            .limits
+            .iter()
+            .filter(|(_, limit)| limit.covers(request))
+            .collect();
+        if covering.is_empty() {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() >= SWEEP_THRESHOLD {
+            let limits = &self.limits;
+            windows.retain(|(name, _), window| {
+                limits
+                    .get(name)
+                    .is_some_and(|limit| now < window.started + limit.period())
+            });
+        }
+
+        for (name, limit) in &covering {
+            let window = windows
+                .entry((name.to_string(), client.to_string()))
+                .or_insert(Window {
+                    started: now,
+                    used: 0,
+                });
+            let ends = window.started + limit.period();
+            if now >= ends {
+                window.started = now;
+                window.used = 0;
+            } else if window.used >= limit.requests {
+                return Err(ServerError::RateLimited {
+                    limit: name.to_string(),
+                    max: limit.requests,
+                    remaining: 0,
+                    retry_after_ms: (ends - now).as_millis() as u64,
+                });
+            }
+        }
+        for (name, _) in covering {
+            if let Some(window) = windows.get_mut(&(name.clone(), client.to_string())) {
+                window.used += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Layer for RateLimitLayer {
+    fn call<'a>(
+        &'a self,
+        session: &'a Session,
+        request: JsonRpcRequest,
+        next: Next<'a>,
+    ) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            self.check(&client_key(session), &request)?;
+            next.run(session, request).await
+        })
+    }
+}
+
+// Who a quota is counted against: the principal, otherwise the client's
+// address without the port, so reconnecting doesn't start a fresh quota.
+// Only stdio sessions have neither, and are counted on their own.
+fn client_key(session: &Session) -> String {
+    if let Some(principal) = session.principal() {
+        return format!("principal:{}", principal);
+    }
+    match session.remote_addr() {
+        Some(addr) => match addr.parse::<SocketAddr>() {
+            Ok(addr) => format!("addr:{}", addr.ip()),
+            Err(_) => format!("addr:{}", addr),
+        },
+        None => format!("session:{}", session.id),
+    }
+}
+
+// Whole seconds to wait, rounded up, if the response is a rate limit error.
+// The HTTP transport sends this as `Retry-After`.
+pub fn retry_after_secs(response: &McpResponse) -> Option<u64> {
+    let error = response.error.as_ref().filter(|e| e.code == RATE_LIMITED)?;
+    let retry_after_ms = error.data.as_ref()?["retryAfterMs"].as_u64()?;
+    Some(retry_after_ms.div_ceil(1000))
+}
@@ -0,0 +1,261 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::completion::{self, CompletionHandler, CompletionRequest};
+use crate::types::{Annotations, Icon, Meta, Resource, ResourceContents, ResourceTemplate, Role};
+use crate::uri_template::UriTemplate;
+
+pub type ResourceFuture = Pin<Box<dyn Future<Output = Result<Vec<ResourceContents>>> + Send>>;
+// Gets the requested URI, the id of the session reading it and the
+// request's `_meta`
+pub type ResourceReader = Arc<dyn Fn(String, String, Meta) -> ResourceFuture + Send + Sync>;
+pub type WriteFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+pub type ResourceWriter = Arc<dyn Fn(ResourceContents) -> WriteFuture + Send + Sync>;
+
+/// A resource definition together with the code that reads it. The reader
+/// gets the requested URI. Resources with a writer also accept
+/// `resources/write`, when the server allows it.
+#[derive(Clone)]
+pub struct RegisteredResource {
+    pub resource: Resource,
+    pub reader: ResourceReader,
+    pub writer: Option<ResourceWriter>,
+}
+
+impl RegisteredResource {
+    pub fn new<F, Fut>(resource: Resource, reader: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Vec<ResourceContents>>> + Send + 'static,
+    {
+        Self {
+            resource,
+            reader: Arc::new(move |uri, _, _| Box::pin(reader(uri))),
+            writer: None,
+        }
+    }
+
+    // A resource whose contents depend on who reads it. The reader also
+    // gets the reading session's id and the request's `_meta`.
+    pub fn per_session<F, Fut>(resource: Resource, reader: F) -> Self
+    where
+        F: Fn(String, String, Meta) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Vec<ResourceContents>>> + Send + 'static,
+    {
+        Self {
+            resource,
+            reader: Arc::new(move |uri, session_id, meta| Box::pin(reader(uri, session_id, meta))),
+            writer: None,
+        }
+    }
+
+    // Makes the resource writable. The writer gets the new contents.
+    pub fn with_writer<F, Fut>(mut self, writer: F) -> Self
+    where
+        F: Fn(ResourceContents) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.writer = Some(Arc::new(move |contents| Box::pin(writer(contents))));
+        self
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.resource.title = Some(title.into());
+        self
+    }
+
+    pub fn with_icon(mut self, icon: Icon) -> Self {
+        self.resource.icons.push(icon);
+        self
+    }
+
+    // Who the resource is for, e.g. `&[Role::User]` for something only worth
+    // showing to the user
+    pub fn with_audience(mut self, audience: &[Role]) -> Self {
+        self.annotations().audience = audience.to_vec();
+        self
+    }
+
+    // How important the resource is, from 0.0 to 1.0 (clamped)
+    pub fn with_priority(mut self, priority: f64) -> Self {
+        self.annotations().priority = Some(priority.clamp(0.0, 1.0));
+        self
+    }
+
+    fn annotations(&mut self) -> &mut Annotations {
+        self.resource
+            .annotations
+            .get_or_insert_with(Annotations::default)
+    }
+
+    pub fn with_size(mut self, size: u64) -> Self {
+        self.resource.size = Some(size);
+        self
+    }
+
+    // `last_modified` is an ISO 8601 timestamp, e.g. "2025-01-12T15:00:58Z"
+    pub fn with_last_modified(mut self, last_modified: impl Into<String>) -> Self {
+        self.annotations().last_modified = Some(last_modified.into());
+        self
+    }
+}
+
+pub type TemplateReader =
+    Arc<dyn Fn(String, HashMap<String, String>, Meta) -> ResourceFuture + Send + Sync>;
+
+/// A family of resources addressed by a URI template. The reader gets the
+/// requested URI, the variables extracted from it and the request's `_meta`.
+#[derive(Clone)]
+pub struct RegisteredResourceTemplate {
+    pub template: ResourceTemplate,
+    pub matcher: UriTemplate,
+    pub reader: TemplateReader,
+    // Completion providers by variable name
+    pub completions: HashMap<String, CompletionHandler>,
+}
+
+impl RegisteredResourceTemplate {
+    pub fn new<F, Fut>(template: ResourceTemplate, reader: F) -> Result<Self>
+    where
+        F: Fn(String, HashMap<String, String>, Meta) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Vec<ResourceContents>>> + Send + 'static,
+    {
+        Ok(Self {
+            matcher: UriTemplate::parse(&template.uri_template)?,
+            template,
+            reader: Arc::new(move |uri, params, meta| Box::pin(reader(uri, params, meta))),
+            completions: HashMap::new(),
+        })
+    }
+
+    // Suggests values for a template variable through `completion/complete`;
+    // `request.arguments` holds the variables already resolved.
+    pub fn with_completion<F, Fut>(mut self, variable: &str, provider: F) -> Self
+    where
+        F: Fn(CompletionRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Vec<String>>> + Send + 'static,
+    {
+        self.completions
+            .insert(variable.to_string(), completion::handler(provider));
+        self
+    }
+
+    // Like `with_completion`, for providers that enumerate something
+    // expensive: their candidates are reused for `ttl`. See
+    // `completion::cached`.
+    pub fn with_cached_completion<F, Fut>(
+        mut self,
+        variable: &str,
+        ttl: Duration,
+        provider: F,
+    ) -> Self
+    where
+        F: Fn(CompletionRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Vec<String>>> + Send + 'static,
+    {
+        self.completions.insert(
+            variable.to_string(),
+            completion::cached(ttl, completion::handler(provider)),
+        );
+        self
+    }
+}
+
+impl ResourceContents {
+    pub fn text(uri: impl Into<String>, mime_type: &str, text: impl Into<String>) -> Self {
+        Self {
+            uri: uri.into(),
+            mime_type: Some(mime_type.to_string()),
+            text: Some(text.into()),
+            blob: None,
+        }
+    }
+}
+
+pub fn builtin_resources() -> Vec<RegisteredResource> {
+    vec![example_file(), scratch_file()]
+}
+
+fn example_file() -> RegisteredResource {
+    const TEXT: &str = "This is an example text file content.\nIt contains some sample text for demonstration purposes.";
+    let resource = Resource {
+        uri: "file:///example.txt".to_string(),
+        name: "Example File".to_string(),
+        title: None,
+        description: "An example text file".to_string(),
+        mime_type: "text/plain".to_string(),
+        icons: Vec::new(),
+        size: None,
+        annotations: None,
+    };
+
+    RegisteredResource::new(resource, |uri| async move {
+        Ok(vec![ResourceContents::text(uri, "text/plain", TEXT)])
+    })
+    .with_size(TEXT.len() as u64)
+    .with_priority(0.5)
+}
+
+pub fn builtin_resource_templates() -> Vec<RegisteredResourceTemplate> {
+    vec![greeting()]
+}
+
+fn greeting() -> RegisteredResourceTemplate {
+    let template = ResourceTemplate {
+        uri_template: "greeting://{name}".to_string(),
+        name: "Greeting".to_string(),
+        title: None,
+        description: "A greeting for the given name".to_string(),
+        mime_type: Some("text/plain".to_string()),
+    };
+
+    RegisteredResourceTemplate::new(template, |uri, params, _| async move {
+        let name = params.get("name").cloned().unwrap_or_default();
+        Ok(vec![ResourceContents::text(
+            uri,
+            "text/plain",
+            format!("Hello, {}!", name),
+        )])
+    })
+    .expect("built-in template is valid")
+    .with_completion("name", |_| async move {
+        Ok(["Ada", "Alan", "Grace", "Katherine", "Linus"]
+            .map(String::from)
+            .to_vec())
+    })
+}
+
+// An in-memory text file clients can overwrite with `resources/write`
+fn scratch_file() -> RegisteredResource {
+    let resource = Resource {
+        uri: "file:///scratch.txt".to_string(),
+        name: "Scratch File".to_string(),
+        title: None,
+        description: "A scratch pad clients can write to".to_string(),
+        mime_type: "text/plain".to_string(),
+        icons: Vec::new(),
+        size: None,
+        annotations: None,
+    };
+    let text = Arc::new(RwLock::new(String::new()));
+
+    let contents = text.clone();
+    RegisteredResource::new(resource, move |uri| {
+        let text = contents.read().unwrap().clone();
+        async move { Ok(vec![ResourceContents::text(uri, "text/plain", text)]) }
+    })
+    .with_writer(move |contents| {
+        let text = text.clone();
+        async move {
+            let new_text = contents
+                .text
+                .ok_or_else(|| anyhow::anyhow!("The scratch file only holds text"))?;
+            *text.write().unwrap() = new_text;
+            Ok(())
+        }
+    })
+}
@@ -0,0 +1,513 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::tools::RegisteredTool;
+use crate::types::{CallToolResult, Tool, ToolAnnotations};
+use crate::uri_template::percent_encode_path;
+use crate::vector_search::{self, BackendConfig, VectorStore};
+
+pub const DEFAULT_MAX_CHUNK_CHARS: usize = 1500;
+const DEFAULT_TOP_K: usize = 5;
+const MAX_TOP_K: usize = 20;
+
+const CODE_EXTENSIONS: &[&str] = &[
+    "rs", "py", "js", "jsx", "ts", "tsx", "go", "java", "kt", "c", "h", "cc", "cpp", "hpp", "cs",
+    "rb", "php", "swift", "scala", "sh", "sql", "toml", "yaml", "yml",
+];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RetrievalConfig {
+    // Files, or directories searched recursively for markdown, text and
+    // source files
+    pub sources: Vec<PathBuf>,
+    // Chunks are split to stay under this many characters where the
+    // document's structure allows
+    pub max_chunk_chars: usize,
+    pub backend: BackendConfig,
+    // JSON file the chunk embeddings are kept in between runs
+    pub store_path: Option<PathBuf>,
+}
+
+impl Default for RetrievalConfig {
+    fn default() -> Self {
+        Self {
+            sources: Vec::new(),
+            max_chunk_chars: DEFAULT_MAX_CHUNK_CHARS,
+            backend: BackendConfig::default(),
+            store_path: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Markdown,
+    Code,
+    Text,
+}
+
+impl Kind {
+    fn of(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "md" | "markdown" => Some(Kind::Markdown),
+            "txt" => Some(Kind::Text),
+            ext if CODE_EXTENSIONS.contains(&ext) => Some(Kind::Code),
+            _ => None,
+        }
+    }
+}
+
+/// A run of lines from one source file.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chunk {
+    pub uri: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    // The markdown section, or the item a code chunk starts with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heading: Option<String>,
+    pub text: String,
+}
+
+impl Chunk {
+    fn id(&self) -> String {
+        format!("{}#L{}-L{}", self.uri, self.start_line, self.end_line)
+    }
+}
+
+// Lines `start..end` (0-based) of a document
+#[derive(Debug, Clone)]
+struct Span {
+    start: usize,
+    end: usize,
+    heading: Option<String>,
+}
+
+/// Splits `text` at the boundaries its kind suggests, then packs
+/// neighbouring pieces into chunks of up to `max_chars`. Markdown is split
+/// at headings and blank lines but never inside a fenced code block; code
+/// at top-level items; plain text at paragraphs. A piece longer than
+/// `max_chars` is split between lines.
+fn chunk_document(uri: &str, kind: Kind, text: &str, max_chars: usize) -> Vec<Chunk> {
+    let lines: Vec<&str> = text.lines().collect();
+    let spans = match kind {
+        Kind::Markdown => markdown_spans(&lines),
+        Kind::Code => code_spans(&lines),
+        Kind::Text => paragraph_spans(&lines),
+    };
+    // Code chunks are named after the first item in them
+    pack(&lines, spans, max_chars, kind == Kind::Code)
+        .into_iter()
+        .map(|span| Chunk {
+            uri: uri.to_string(),
+            start_line: span.start + 1,
+            end_line: span.end,
+            heading: span.heading,
+            text: lines[span.start..span.end].join("\n"),
+        })
+        .filter(|chunk| !chunk.text.trim().is_empty())
+        .collect()
+}
+
+fn markdown_spans(lines: &[&str]) -> Vec<Span> {
+    let mut spans = Vec::new();
+    // (level, title) of the headings enclosing the current line
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut block_start: Option<usize> = None;
+
+    let path = |headings: &[(usize, String)]| {
+        (!headings.is_empty()).then(|| {
+            headings
+                .iter()
+                .map(|(_, title)| title.as_str())
+                .collect::<Vec<_>>()
+                .join(" > ")
+        })
+    };
+
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            block_start.get_or_insert(i);
+            continue;
+        }
+
+        let level = trimmed.chars().take_while(|&c| c == '#').count();
+        let is_heading =
+            (1..=6).contains(&level) && trimmed[level..].starts_with(|c: char| c.is_whitespace());
+        if is_heading || trimmed.is_empty() {
+            if let Some(start) = block_start.take() {
+                spans.push(Span {
+                    start,
+                    end: i,
+                    heading: path(&headings),
+                });
+            }
+        }
+        if is_heading {
+            headings.retain(|(enclosing, _)| *enclosing < level);
+            headings.push((level, trimmed[level..].trim().to_string()));
+            block_start = Some(i);
+        } else if !trimmed.is_empty() {
+            block_start.get_or_insert(i);
+        }
+    }
+    if let Some(start) = block_start {
+        spans.push(Span {
+            start,
+            end: lines.len(),
+            heading: path(&headings),
+        });
+    }
+    spans
+}
+
+// A new item starts at an unindented line after a blank one, which
+// catches functions, types and their doc comments in most languages
+fn code_spans(lines: &[&str]) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    let mut start = 0;
+    for i in 1..lines.len() {
+        let line = lines[i];
+        let starts_item = !line.trim().is_empty()
+            && !line.starts_with(char::is_whitespace)
+            && lines[i - 1].trim().is_empty();
+        if starts_item {
+            spans.push(Span {
+                start,
+                end: i,
+                heading: item_name(&lines[start..i]),
+            });
+            start = i;
+        }
+    }
+    if start < lines.len() {
+        spans.push(Span {
+            start,
+            end: lines.len(),
+            heading: item_name(&lines[start..]),
+        });
+    }
+    spans
+}
+
+// The first line of an item that isn't a comment or attribute
+fn item_name(lines: &[&str]) -> Option<String> {
+    const SKIP: &[&str] = &["//", "/*", "*", "#", "--", "\"\"\"", "@"];
+    lines
+        .iter()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !SKIP.iter().any(|prefix| line.starts_with(prefix)))
+        .map(|line| line.trim_end_matches('{').trim_end().to_string())
+}
+
+fn paragraph_spans(lines: &[&str]) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut start: Option<usize> = None;
+    for (i, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            if let Some(start) = start.take() {
+                spans.push(Span {
+                    start,
+                    end: i,
+                    heading: None,
+                });
+            }
+        } else {
+            start.get_or_insert(i);
+        }
+    }
+    if let Some(start) = start {
+        spans.push(Span {
+            start,
+            end: lines.len(),
+            heading: None,
+        });
+    }
+    spans
+}
+
+// Joins neighbouring spans while they fit in `max_chars`, and cuts longer
+// spans between lines. Unless `across_headings` is set, only spans with
+// the same heading are joined. A chunk may stretch over the blank lines
+// between the spans it joins.
+fn pack(lines: &[&str], spans: Vec<Span>, max_chars: usize, across_headings: bool) -> Vec<Span> {
+    let size = |start: usize, end: usize| -> usize {
+        lines[start..end].iter().map(|line| line.len() + 1).sum()
+    };
+
+    let mut pieces = Vec::new();
+    for span in spans {
+        if size(span.start, span.end) <= max_chars {
+            pieces.push(span);
+            continue;
+        }
+        let mut start = span.start;
+        for end in span.start + 1..=span.end {
+            if end == span.end || size(start, end + 1) > max_chars {
+                pieces.push(Span {
+                    start,
+                    end,
+                    heading: span.heading.clone(),
+                });
+                start = end;
+            }
+        }
+    }
+
+    let mut packed: Vec<Span> = Vec::new();
+    for piece in pieces {
+        if let Some(last) = packed.last_mut() {
+            let same_section = across_headings || last.heading == piece.heading;
+            if same_section && size(last.start, piece.end) <= max_chars {
+                last.end = piece.end;
+                continue;
+            }
+        }
+        packed.push(piece);
+    }
+    packed
+}
+
+/// Chunks the configured sources into a vector store and answers
+/// `retrieve_context` from it, so agents can pull in just the passages
+/// relevant to a question, with where each came from.
+pub struct Retriever {
+    store: VectorStore,
+    chunks: HashMap<String, Chunk>,
+}
+
+#[derive(Debug, Serialize)]
+struct RetrievedChunk<'a> {
+    #[serde(flatten)]
+    chunk: &'a Chunk,
+    score: f32,
+}
+
+impl Retriever {
+    pub async fn open(config: &RetrievalConfig) -> Result<Self> {
+        if config.max_chunk_chars == 0 {
+            anyhow::bail!("retrieval.max_chunk_chars must be at least 1");
+        }
+        let store = VectorStore::new(
+            vector_search::from_config(&config.backend)?,
+            config.store_path.clone(),
+        )?;
+
+        let mut files = Vec::new();
+        for source in &config.sources {
+            collect_sources(source, &mut files)?;
+        }
+        let mut chunks = HashMap::new();
+        for (path, kind) in &files {
+            let text = match std::fs::read_to_string(path) {
+                Ok(text) => text,
+                Err(e) => {
+                    warn!("Skipping {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            let uri = file_uri(path)?;
+            for chunk in chunk_document(&uri, *kind, &text, config.max_chunk_chars) {
+                chunks.insert(chunk.id(), chunk);
+            }
+        }
+
+        // Chunks of files that changed or went away
+        store.retain(|id| chunks.contains_key(id))?;
+        let embedded = store
+            .ingest(
+                chunks
+                    .iter()
+                    .map(|(id, chunk)| (id.clone(), embedding_text(chunk)))
+                    .collect(),
+            )
+            .await?;
+        info!(
+            "Indexed {} chunks from {} files ({} embedded at startup)",
+            chunks.len(),
+            files.len(),
+            embedded
+        );
+        Ok(Self { store, chunks })
+    }
+
+    // `retrieve_context`
+    pub fn tool(self: &Arc<Self>) -> RegisteredTool {
+        let retriever = self.clone();
+        RegisteredTool::new(retrieve_tool(), move |arguments, _| {
+            let retriever = retriever.clone();
+            async move {
+                let query = arguments
+                    .get("query")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing 'query' argument"))?;
+                let top_k = arguments
+                    .get("top_k")
+                    .and_then(|v| v.as_u64())
+                    .map_or(DEFAULT_TOP_K, |top_k| top_k as usize)
+                    .clamp(1, MAX_TOP_K);
+                let max_chars = arguments
+                    .get("max_chars")
+                    .and_then(|v| v.as_u64())
+                    .map(|max_chars| max_chars as usize);
+
+                let results = retriever.store.search(query, top_k).await?;
+                let mut retrieved = Vec::new();
+                let mut total = 0;
+                for result in &results {
+                    let Some(chunk) = retriever.chunks.get(&result.id) else {
+                        continue;
+                    };
+                    // Always keep the best chunk, even if it alone is over
+                    if max_chars
+                        .is_some_and(|max| !retrieved.is_empty() && total + chunk.text.len() > max)
+                    {
+                        break;
+                    }
+                    total += chunk.text.len();
+                    retrieved.push(RetrievedChunk {
+                        chunk,
+                        score: result.score,
+                    });
+                }
+
+                let text = if retrieved.is_empty() {
+                    "No indexed content".to_string()
+                } else {
+                    retrieved
+                        .iter()
+                        .map(|retrieved| {
+                            let chunk = retrieved.chunk;
+                            let heading = chunk
+                                .heading
+                                .as_ref()
+                                .map(|heading| format!(" ({})", heading))
+                                .unwrap_or_default();
+                            format!("Source: {}{}\n{}", chunk.id(), heading, chunk.text)
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n\n---\n\n")
+                };
+                Ok(CallToolResult::text(text)
+                    .with_structured_content(serde_json::json!({ "chunks": retrieved })))
+            }
+        })
+        .with_output_schema(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "chunks": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "uri": { "type": "string" },
+                            "startLine": { "type": "integer" },
+                            "endLine": { "type": "integer" },
+                            "heading": { "type": "string" },
+                            "text": { "type": "string" },
+                            "score": { "type": "number" }
+                        },
+                        "required": ["uri", "startLine", "endLine", "text", "score"]
+                    }
+                }
+            },
+            "required": ["chunks"]
+        }))
+    }
+}
+
+// The file name and heading go into the embedding too, since they often
+// say what a chunk is about when its text doesn't
+fn embedding_text(chunk: &Chunk) -> String {
+    let name = chunk.uri.rsplit('/').next().unwrap_or_default();
+    match &chunk.heading {
+        Some(heading) => format!("{}: {}\n\n{}", name, heading, chunk.text),
+        None => format!("{}\n\n{}", name, chunk.text),
+    }
+}
+
+fn file_uri(path: &Path) -> Result<String> {
+    let path = path.canonicalize()?;
+    Ok(format!(
+        "file://{}",
+        percent_encode_path(&path.to_string_lossy())
+    ))
+}
+
+// Files under `path` that can be chunked, skipping hidden entries
+fn collect_sources(path: &Path, files: &mut Vec<(PathBuf, Kind)>) -> Result<()> {
+    if path.is_file() {
+        let kind = Kind::of(path).unwrap_or(Kind::Text);
+        files.push((path.to_path_buf(), kind));
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(path)
+        .map_err(|e| anyhow::anyhow!("Can't read {}: {}", path.display(), e))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<_>>()?;
+    entries.sort();
+    for entry in entries {
+        let hidden = entry
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if entry.is_dir() {
+            collect_sources(&entry, files)?;
+        } else if let Some(kind) = Kind::of(&entry) {
+            files.push((entry, kind));
+        }
+    }
+    Ok(())
+}
+
+fn retrieve_tool() -> Tool {
+    Tool {
+        name: "retrieve_context".to_string(),
+        title: Some("Retrieve Context".to_string()),
+        description: "Finds the passages of the indexed documents and code most relevant to a query. Each passage comes with its source file URI and line range".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "A question or description of what's needed"
+                },
+                "top_k": {
+                    "type": "integer",
+                    "description": "Most passages to return (default 5, at most 20)"
+                },
+                "max_chars": {
+                    "type": "integer",
+                    "description": "Stop adding passages once their text would exceed this many characters"
+                }
+            },
+            "required": ["query"]
+        }),
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(true),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    }
+}
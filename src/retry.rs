@@ -0,0 +1,215 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::config::RetryConfig;
+use crate::error::ServerError;
+use crate::middleware::{HandlerFuture, Layer, Next};
+use crate::session::Session;
+use crate::types::JsonRpcRequest;
+
+/// Retries `tools/call` requests whose tool failed with a `RetryableError`,
+/// backing off exponentially with jitter between attempts. When a call had
+/// to be retried, the attempt count is reported in the result's `_meta` (or
+/// the error's `data` if every attempt failed). Confirmation is asked for on
+/// the first attempt only.
+pub struct RetryLayer {
+    config: RetryConfig,
+}
+
+impl RetryLayer {
+    pub fn new(config: RetryConfig) -> Self {
+        Self { config }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let base = self.config.base_delay_ms;
+        let exponential = base.saturating_mul(1 << attempt.min(16));
+        // Full jitter on top of the exponential delay, using the randomly
+        // keyed std hasher as a cheap source of randomness
+        let jitter = RandomState::new().build_hasher().finish() % base.max(1);
+        Duration::from_millis(exponential + jitter)
+    }
+}
+
+fn is_retryable(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<ServerError>(),
+        Some(ServerError::ToolFailed {
+            retryable: true,
+            ..
+        })
+    )
+}
+
+impl Layer for RetryLayer {
+    fn call<'a>(
+        &'a self,
+        session: &'a Session,
+        request: JsonRpcRequest,
+        next: Next<'a>,
+    ) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            if request.method != "tools/call" {
+                return next.run(session, request).await;
+            }
+
+            // Confirmation is asked for once, not on every attempt
+            let _retrying = next.server().retrying(session, &request);
+            let mut attempt = 1;
+            loop {
+                match next.run(session, request.clone()).await {
+                    Err(e) if is_retryable(&e) && attempt < self.config.max_attempts => {
+                        let delay = self.backoff(attempt - 1);
+                        warn!(
+                            "Attempt {} failed ({}), retrying in {:?}",
+                            attempt, e, delay
+                        );
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    Err(e) if attempt > 1 => {
+                        return Err(match e.downcast::<ServerError>() {
+                            Ok(ServerError::ToolFailed {
+                                tool,
+                                details,
+                                retryable,
+                            }) => ServerError::ToolFailed {
+                                tool,
+                                details: format!("{} (after {} attempts)", details, attempt),
+                                retryable,
+                            }
+                            .into(),
+                            Ok(other) => other.into(),
+                            Err(e) => e,
+                        });
+                    }
+                    Ok(Some(mut response)) if attempt > 1 => {
+                        if let Some(result) = response.result.as_mut() {
+                            result["_meta"]["attempts"] = serde_json::json!(attempt);
+                        }
+                        return Ok(Some(response));
+                    }
+                    other => return other,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, OnLimit};
+    use crate::confirm::{ConfirmFuture, ConfirmationPolicy, ConfirmationRequest, Decision};
+    use crate::server::McpServer;
+    use crate::tools::{RegisteredTool, RetryableError};
+    use crate::types::{CallToolResult, Tool};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // Approves everything, counting how often it was asked
+    #[derive(Default)]
+    struct Counting(AtomicUsize);
+
+    impl ConfirmationPolicy for Counting {
+        fn confirm<'a>(&'a self, _: &'a ConfirmationRequest<'a>) -> ConfirmFuture<'a> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async { Ok(Decision::Approved) })
+        }
+    }
+
+    fn tool(name: &str) -> Tool {
+        Tool {
+            name: name.to_string(),
+            title: None,
+            description: "Test tool".to_string(),
+            input_schema: serde_json::json!({ "type": "object" }),
+            output_schema: None,
+            annotations: None,
+            icons: Vec::new(),
+            meta: None,
+        }
+    }
+
+    fn server() -> McpServer {
+        let config = RetryConfig {
+            max_attempts: 3,
+            base_delay_ms: 1,
+        };
+        McpServer::new(Config::default()).layer(RetryLayer::new(config))
+    }
+
+    async fn call(server: &McpServer, id: u64, name: &str) -> serde_json::Value {
+        let (session, _outbound) = server.connect();
+        let message = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "tools/call",
+            "params": { "name": name, "arguments": {} }
+        });
+        let response = server.handle_message(&session, &message.to_string()).await;
+        serde_json::to_value(response.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn confirmation_is_asked_once_per_call() {
+        let policy = Arc::new(Counting::default());
+        let server = server().with_confirmation(policy.clone());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        server.add_tool(
+            RegisteredTool::new(tool("flaky"), move |_, _| {
+                let attempt = counted.fetch_add(1, Ordering::SeqCst) + 1;
+                async move {
+                    if attempt < 3 {
+                        return Err(RetryableError("not yet".to_string()).into());
+                    }
+                    Ok(CallToolResult::text("done"))
+                }
+            })
+            .destructive(),
+        );
+
+        let response = call(&server, 1, "flaky").await;
+        assert_eq!(response["result"]["_meta"]["attempts"], 3, "{}", response);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(policy.0.load(Ordering::SeqCst), 1);
+
+        // The next call is a new one, and asks again
+        call(&server, 2, "flaky").await;
+        assert_eq!(policy.0.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn concurrency_rejections_are_not_retried() {
+        let server = server();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        server.add_tool(
+            RegisteredTool::new(tool("single"), move |_, _| {
+                counted.fetch_add(1, Ordering::SeqCst);
+                async {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    Ok(CallToolResult::text("done"))
+                }
+            })
+            .with_max_concurrency(1, OnLimit::Reject),
+        );
+
+        let (first, second) = tokio::join!(call(&server, 1, "single"), async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            call(&server, 2, "single").await
+        });
+        assert!(first.get("result").is_some(), "{}", first);
+        let error = &second["error"];
+        assert_eq!(error["data"]["retryable"], false, "{}", second);
+        assert!(
+            !error["message"].as_str().unwrap().contains("attempts"),
+            "{}",
+            second
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,183 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::info;
+
+use crate::error::ServerError;
+use crate::session::Requester;
+use crate::uri_template::percent_decode;
+
+// How long to wait for the client to answer `roots/list`
+const LIST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RootsConfig {
+    // Directories file tools may use; empty leaves them unrestricted
+    // unless the client provides roots
+    pub paths: Vec<PathBuf>,
+    // Also limit file tools to the roots the client provides
+    pub use_client_roots: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Root {
+    pub uri: String,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// The roots a client exposes, fetched with `roots/list` on first use and
+/// again after the client says they changed.
+#[derive(Clone)]
+pub struct ClientRoots {
+    requester: Requester,
+    supported: bool,
+    cached: Arc<Mutex<Option<Vec<Root>>>>,
+}
+
+impl ClientRoots {
+    pub fn new(
+        requester: Requester,
+        supported: bool,
+        cached: Arc<Mutex<Option<Vec<Root>>>>,
+    ) -> Self {
+        Self {
+            requester,
+            supported,
+            cached,
+        }
+    }
+
+    // None if the client doesn't support roots
+    pub async fn list(&self) -> Result<Option<Vec<Root>>> {
+        if !self.supported {
+            return Ok(None);
+        }
+        if let Some(roots) = self.cached.lock().unwrap().clone() {
+            return Ok(Some(roots));
+        }
+        let result = self
+            .requester
+            .request("roots/list", None, LIST_TIMEOUT)
+            .await?;
+        let roots: Vec<Root> = serde_json::from_value(result["roots"].clone())?;
+        info!("Client provided {} roots", roots.len());
+        *self.cached.lock().unwrap() = Some(roots.clone());
+        Ok(Some(roots))
+    }
+
+    pub fn invalidate(&self) {
+        *self.cached.lock().unwrap() = None;
+    }
+}
+
+/// Where file tools may go: the configured directories, narrowed to the
+/// client's roots when `use_client_roots` is set.
+#[derive(Clone)]
+pub struct RootScope {
+    configured: Vec<PathBuf>,
+    client: Option<ClientRoots>,
+}
+
+impl RootScope {
+    pub fn new(config: &RootsConfig, client: ClientRoots) -> Self {
+        Self {
+            configured: config.paths.clone(),
+            client: config.use_client_roots.then_some(client),
+        }
+    }
+
+    // The directories paths must fall under, or None if there are no limits
+    pub async fn allowed(&self) -> Result<Option<Vec<PathBuf>>> {
+        let configured: Vec<PathBuf> = self
+            .configured
+            .iter()
+            .filter_map(|path| path.canonicalize().ok())
+            .collect();
+        let client = match &self.client {
+            Some(client) => client.list().await?.map(|roots| {
+                roots
+                    .iter()
+                    .filter_map(|root| root_path(&root.uri))
+                    .filter_map(|path| path.canonicalize().ok())
+                    .collect::<Vec<_>>()
+            }),
+            None => None,
+        };
+
+        Ok(match client {
+            None if self.configured.is_empty() => None,
+            None => Some(configured),
+            Some(client) if self.configured.is_empty() => Some(client),
+            // Where a configured directory and a client root overlap, the
+            // deeper of the two is what both allow
+            Some(client) => Some(
+                configured
+                    .iter()
+                    .flat_map(|ours| {
+                        client.iter().filter_map(move |theirs| {
+                            if ours.starts_with(theirs) {
+                                Some(ours.clone())
+                            } else if theirs.starts_with(ours) {
+                                Some(theirs.clone())
+                            } else {
+                                None
+                            }
+                        })
+                    })
+                    .collect(),
+            ),
+        })
+    }
+
+    // Resolves `path`, following symlinks, and fails if it is outside the
+    // allowed roots. The path itself need not exist yet, but its parent must.
+    pub async fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let resolved = canonicalize(Path::new(path))?;
+        match self.allowed().await? {
+            Some(allowed) if !allowed.iter().any(|root| resolved.starts_with(root)) => {
+                Err(ServerError::InvalidParams {
+                    details: format!("{} is outside the allowed roots", path),
+                }
+                .into())
+            }
+            _ => Ok(resolved),
+        }
+    }
+
+    // Like `resolve`, but fails when there are no roots at all, for tools
+    // that must never range over the whole filesystem
+    pub async fn resolve_within(&self, path: &str) -> Result<PathBuf> {
+        if self.allowed().await?.is_none() {
+            return Err(ServerError::InvalidParams {
+                details: "No roots are configured or provided by the client".to_string(),
+            }
+            .into());
+        }
+        self.resolve(path).await
+    }
+}
+
+fn canonicalize(path: &Path) -> Result<PathBuf> {
+    if let Ok(resolved) = path.canonicalize() {
+        return Ok(resolved);
+    }
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        anyhow::bail!("Can't resolve {}", path.display());
+    };
+    let parent = if parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent
+    };
+    Ok(parent.canonicalize()?.join(name))
+}
+
+// The local path of a `file://` root; other schemes can't be scoped to
+fn root_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    Some(PathBuf::from(percent_decode(path)?))
+}
@@ -0,0 +1,309 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+use crate::session::{RequestError, Requester};
+use crate::types::{Content, McpError, Role};
+
+// Used when the config doesn't set `sampling.timeout_secs`
+pub const DEFAULT_TIMEOUT_SECS: u64 = 120;
+
+/// What a tool would like the client's model to be, as in the spec. Hints
+/// are model names or families in order of preference; the priorities range
+/// from 0.0 to 1.0.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPreferences {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hints: Vec<ModelHint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_priority: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed_priority: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intelligence_priority: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelHint {
+    pub name: String,
+}
+
+impl ModelPreferences {
+    pub fn with_hint(mut self, name: impl Into<String>) -> Self {
+        self.hints.push(ModelHint { name: name.into() });
+        self
+    }
+
+    // Clamped to 0.0..=1.0, like the other priorities
+    pub fn with_cost_priority(mut self, priority: f64) -> Self {
+        self.cost_priority = Some(priority.clamp(0.0, 1.0));
+        self
+    }
+
+    pub fn with_speed_priority(mut self, priority: f64) -> Self {
+        self.speed_priority = Some(priority.clamp(0.0, 1.0));
+        self
+    }
+
+    pub fn with_intelligence_priority(mut self, priority: f64) -> Self {
+        self.intelligence_priority = Some(priority.clamp(0.0, 1.0));
+        self
+    }
+
+    // The same preferences without hints, for clients that can't honor them
+    fn without_hints(&self) -> Option<Self> {
+        let relaxed = Self {
+            hints: Vec::new(),
+            ..self.clone()
+        };
+        let has_priorities = relaxed.cost_priority.is_some()
+            || relaxed.speed_priority.is_some()
+            || relaxed.intelligence_priority.is_some();
+        (!self.hints.is_empty() && has_priorities).then_some(relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SamplingMessage {
+    pub role: Role,
+    pub content: Content,
+}
+
+/// Parameters of `sampling/createMessage`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateMessageRequest {
+    pub messages: Vec<SamplingMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_preferences: Option<ModelPreferences>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    pub max_tokens: u32,
+}
+
+impl CreateMessageRequest {
+    // A single user message
+    pub fn new(prompt: impl Into<String>, max_tokens: u32) -> Self {
+        Self {
+            messages: vec![SamplingMessage {
+                role: Role::User,
+                content: Content::text(prompt),
+            }],
+            model_preferences: None,
+            system_prompt: None,
+            max_tokens,
+        }
+    }
+
+    pub fn with_model_preferences(mut self, preferences: ModelPreferences) -> Self {
+        self.model_preferences = Some(preferences);
+        self
+    }
+
+    pub fn with_system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.system_prompt = Some(prompt.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateMessageResult {
+    pub role: Role,
+    pub content: Content,
+    pub model: String,
+    #[serde(default)]
+    pub stop_reason: Option<String>,
+}
+
+/// What to do when the client rejects a sampling request.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplingFallback {
+    // Retry without model hints, then without preferences at all
+    #[default]
+    Relax,
+    // Hand the rejection straight to the tool
+    Error,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SamplingConfig {
+    pub fallback: SamplingFallback,
+    // How long each attempt waits for the client, in seconds
+    pub timeout_secs: u64,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            fallback: SamplingFallback::default(),
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+        }
+    }
+}
+
+/// Why a sampling request produced no message. Tools can match on it, or
+/// put `to_value()` in their result.
+#[derive(Debug)]
+pub enum SamplingError {
+    // The client didn't declare the sampling capability
+    Unsupported,
+    // The client answered every attempt with an error; holds the last one
+    Rejected { error: McpError, attempts: usize },
+    TimedOut { timeout: Duration },
+    Closed,
+    // The client's answer wasn't a valid CreateMessageResult
+    InvalidResult { details: String },
+}
+
+impl SamplingError {
+    pub fn to_value(&self) -> serde_json::Value {
+        match self {
+            SamplingError::Unsupported => serde_json::json!({ "reason": "unsupported" }),
+            SamplingError::Rejected { error, attempts } => serde_json::json!({
+                "reason": "rejected",
+                "code": error.code,
+                "message": error.message,
+                "attempts": attempts
+            }),
+            SamplingError::TimedOut { timeout } => serde_json::json!({
+                "reason": "timeout",
+                "timeoutMs": timeout.as_millis() as u64
+            }),
+            SamplingError::Closed => serde_json::json!({ "reason": "closed" }),
+            SamplingError::InvalidResult { details } => serde_json::json!({
+                "reason": "invalid_result",
+                "details": details
+            }),
+        }
+    }
+}
+
+impl fmt::Display for SamplingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SamplingError::Unsupported => write!(f, "The client doesn't support sampling"),
+            SamplingError::Rejected { error, attempts } => write!(
+                f,
+                "The client rejected sampling after {} attempt(s): {} ({})",
+                attempts, error.message, error.code
+            ),
+            SamplingError::TimedOut { timeout } => {
+                write!(f, "No sampling result within {:?}", timeout)
+            }
+            SamplingError::Closed => write!(f, "The session closed during sampling"),
+            SamplingError::InvalidResult { details } => {
+                write!(f, "Invalid sampling result: {}", details)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SamplingError {}
+
+/// Sends `sampling/createMessage`. With the `relax` fallback, a rejected
+/// request is retried with less specific model preferences before giving up.
+pub async fn create_message(
+    requester: &Requester,
+    config: &SamplingConfig,
+    request: CreateMessageRequest,
+) -> Result<CreateMessageResult, SamplingError> {
+    send(requester, config, request, None).await
+}
+
+/// Like `create_message`, but hands over partial text as the client streams
+/// it. Clients stream by sending `notifications/progress` for the request's
+/// `progressToken`, with the text in `message`.
+pub fn create_message_streaming(
+    requester: Requester,
+    config: SamplingConfig,
+    request: CreateMessageRequest,
+) -> SamplingStream {
+    let (token, partials) = requester.watch_progress();
+    let result = tokio::spawn(async move {
+        let result = send(&requester, &config, request, Some(&token)).await;
+        // Ends the partials once those sent before the result are read
+        requester.unwatch_progress(&token);
+        result
+    });
+    SamplingStream { partials, result }
+}
+
+/// A sampling request in flight, yielding partial text as it arrives and
+/// then the final message.
+pub struct SamplingStream {
+    partials: mpsc::UnboundedReceiver<String>,
+    result: JoinHandle<Result<CreateMessageResult, SamplingError>>,
+}
+
+impl SamplingStream {
+    // The next piece of text, or None once the request has finished
+    pub async fn next(&mut self) -> Option<String> {
+        self.partials.recv().await
+    }
+
+    // Waits for the final message. Partials not read yet are dropped.
+    pub async fn result(self) -> Result<CreateMessageResult, SamplingError> {
+        self.result.await.unwrap_or(Err(SamplingError::Closed))
+    }
+}
+
+async fn send(
+    requester: &Requester,
+    config: &SamplingConfig,
+    request: CreateMessageRequest,
+    progress_token: Option<&str>,
+) -> Result<CreateMessageResult, SamplingError> {
+    let mut attempts = vec![request.model_preferences.clone()];
+    if let (SamplingFallback::Relax, Some(preferences)) =
+        (config.fallback, &request.model_preferences)
+    {
+        if let Some(relaxed) = preferences.without_hints() {
+            attempts.push(Some(relaxed));
+        }
+        attempts.push(None);
+    }
+
+    let timeout = Duration::from_secs(config.timeout_secs);
+    let mut request = request;
+    for (attempt, preferences) in attempts.iter().enumerate() {
+        request.model_preferences = preferences.clone();
+        let mut params = serde_json::json!(&request);
+        if let Some(token) = progress_token {
+            params["_meta"] = serde_json::json!({ "progressToken": token });
+        }
+        match requester
+            .request("sampling/createMessage", Some(params), timeout)
+            .await
+        {
+            Ok(result) => {
+                return serde_json::from_value(result).map_err(|e| SamplingError::InvalidResult {
+                    details: e.to_string(),
+                })
+            }
+            Err(RequestError::Rejected { error, .. }) if attempt + 1 < attempts.len() => {
+                info!(
+                    "Client rejected sampling ({}), retrying with relaxed model preferences",
+                    error.message
+                );
+            }
+            Err(RequestError::Rejected { error, .. }) => {
+                return Err(SamplingError::Rejected {
+                    error,
+                    attempts: attempt + 1,
+                })
+            }
+            Err(RequestError::TimedOut { timeout, .. }) => {
+                return Err(SamplingError::TimedOut { timeout })
+            }
+            Err(RequestError::Closed { .. }) => return Err(SamplingError::Closed),
+        }
+    }
+    unreachable!("there is always at least one attempt")
+}
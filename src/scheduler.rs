@@ -0,0 +1,298 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+use crate::notification::Notification;
+use crate::server::McpServer;
+
+pub type RefreshFuture = Pin<Box<dyn Future<Output = Result<Refreshed>> + Send>>;
+pub type RefreshFn = Arc<dyn Fn() -> RefreshFuture + Send + Sync>;
+
+/// What a refresh task changed, so the scheduler can tell clients.
+#[derive(Debug, Default)]
+pub struct Refreshed {
+    // Resources whose contents changed; subscribers get `resources/updated`
+    pub updated: Vec<String>,
+    // Resources were added or removed; everyone gets `list_changed`
+    pub list_changed: bool,
+}
+
+impl Refreshed {
+    pub fn updated(uri: impl Into<String>) -> Self {
+        Self {
+            updated: vec![uri.into()],
+            list_changed: false,
+        }
+    }
+}
+
+/// When a task runs: `@every 30s` (or `5m`, `1h`), `@hourly`, `@daily`, or
+/// a five-field cron expression (minute hour day-of-month month
+/// day-of-week, in UTC) supporting `*`, `*/n`, `a-b` and `a,b,c`.
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    Every(Duration),
+    Cron(Cron),
+}
+
+#[derive(Debug, Clone)]
+pub struct Cron {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+}
+
+impl Schedule {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let expression = expression.trim();
+        match expression {
+            "@hourly" => return Self::parse("0 * * * *"),
+            "@daily" => return Self::parse("0 0 * * *"),
+            _ => {}
+        }
+        if let Some(every) = expression.strip_prefix("@every ") {
+            return parse_duration(every.trim()).map(Schedule::Every);
+        }
+
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            anyhow::bail!("Expected 5 cron fields in '{}'", expression);
+        };
+        Ok(Schedule::Cron(Cron {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays: parse_field(weekday, 0, 6)?,
+        }))
+    }
+}
+
+impl Cron {
+    fn matches(&self, time: &UtcTime) -> bool {
+        self.minutes[time.minute]
+            && self.hours[time.hour]
+            && self.days[time.day - 1]
+            && self.months[time.month - 1]
+            && self.weekdays[time.weekday]
+    }
+}
+
+fn parse_duration(text: &str) -> Result<Duration> {
+    let split = text.len().saturating_sub(1);
+    let (amount, unit) = text.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid interval '{}'", text))?;
+    let secs = match unit {
+        "s" => amount,
+        "m" => amount * 60,
+        "h" => amount * 3600,
+        _ => anyhow::bail!("Invalid interval '{}', expected e.g. 30s, 5m or 1h", text),
+    };
+    if secs == 0 {
+        anyhow::bail!("Interval must be positive");
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+// One cron field as a bitmap over min..=max
+fn parse_field(field: &str, min: usize, max: usize) -> Result<Vec<bool>> {
+    let invalid = || anyhow::anyhow!("Invalid cron field '{}'", field);
+    let mut allowed = vec![false; max - min + 1];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<usize>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (
+                    start.parse().map_err(|_| invalid())?,
+                    end.parse().map_err(|_| invalid())?,
+                ),
+                None => {
+                    let value = range.parse().map_err(|_| invalid())?;
+                    (value, value)
+                }
+            },
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(invalid());
+        }
+        for value in (start..=end).step_by(step) {
+            allowed[value - min] = true;
+        }
+    }
+    Ok(allowed)
+}
+
+struct UtcTime {
+    minute: usize,
+    hour: usize,
+    day: usize,
+    month: usize,
+    // 0 = Sunday
+    weekday: usize,
+}
+
+impl UtcTime {
+    fn now() -> Self {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let days = (secs / 86_400) as i64;
+        let seconds_of_day = secs % 86_400;
+
+        // Civil date from days since the epoch (Howard Hinnant's algorithm)
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = z - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+
+        Self {
+            minute: (seconds_of_day / 60 % 60) as usize,
+            hour: (seconds_of_day / 3600) as usize,
+            day: day as usize,
+            month: month as usize,
+            // 1970-01-01 was a Thursday
+            weekday: (days + 4).rem_euclid(7) as usize,
+        }
+    }
+
+    fn minute_of_epoch() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            / 60
+    }
+}
+
+struct Task {
+    name: String,
+    schedule: Schedule,
+    refresh: RefreshFn,
+    next_due: Option<Instant>,
+    running: Arc<AtomicBool>,
+}
+
+/// Runs refresh tasks on their schedules and notifies clients of what they
+/// changed. Tasks are registered in code by name; the config decides which
+/// run and when.
+pub struct Scheduler {
+    server: Arc<McpServer>,
+    schedules: HashMap<String, String>,
+    tasks: Vec<Task>,
+}
+
+impl Scheduler {
+    pub fn new(server: Arc<McpServer>, schedules: HashMap<String, String>) -> Self {
+        Self {
+            server,
+            schedules,
+            tasks: Vec::new(),
+        }
+    }
+
+    // Adds a refresh task. It only runs if the config gives it a schedule.
+    pub fn register<F, Fut>(&mut self, name: &str, refresh: F) -> Result<()>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Refreshed>> + Send + 'static,
+    {
+        let Some(expression) = self.schedules.get(name) else {
+            return Ok(());
+        };
+        let schedule = Schedule::parse(expression)
+            .map_err(|e| anyhow::anyhow!("Bad schedule for {}: {}", name, e))?;
+        info!("Scheduled {} ({})", name, expression);
+        self.tasks.push(Task {
+            name: name.to_string(),
+            schedule,
+            refresh: Arc::new(move || Box::pin(refresh())),
+            next_due: None,
+            running: Arc::new(AtomicBool::new(false)),
+        });
+        Ok(())
+    }
+
+    pub async fn run(mut self) {
+        for name in self.schedules.keys() {
+            if !self.tasks.iter().any(|task| &task.name == name) {
+                warn!("No refresh task named {}", name);
+            }
+        }
+        if self.tasks.is_empty() {
+            return;
+        }
+
+        let mut last_minute = UtcTime::minute_of_epoch();
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            let now = Instant::now();
+            let minute = UtcTime::minute_of_epoch();
+            let new_minute = minute != last_minute;
+            last_minute = minute;
+            let time = UtcTime::now();
+
+            for task in &mut self.tasks {
+                let due = match &task.schedule {
+                    Schedule::Every(every) => {
+                        let due = task.next_due.is_some_and(|next_due| now >= next_due);
+                        if due || task.next_due.is_none() {
+                            task.next_due = Some(now + *every);
+                        }
+                        due
+                    }
+                    Schedule::Cron(cron) => new_minute && cron.matches(&time),
+                };
+                if due {
+                    run_task(&self.server, task);
+                }
+            }
+        }
+    }
+}
+
+// Runs the task in the background, skipping this turn if the previous run
+// hasn't finished yet.
+fn run_task(server: &Arc<McpServer>, task: &Task) {
+    if task.running.swap(true, Ordering::AcqRel) {
+        warn!("Skipping {}: previous run still in progress", task.name);
+        return;
+    }
+    let server = server.clone();
+    let name = task.name.clone();
+    let refresh = task.refresh.clone();
+    let running = task.running.clone();
+    tokio::spawn(async move {
+        match refresh().await {
+            Ok(refreshed) => {
+                for uri in &refreshed.updated {
+                    server.resource_updated(uri);
+                }
+                if refreshed.list_changed {
+                    server.broadcast(Notification::ResourceListChanged { delta: None });
+                }
+            }
+            Err(e) => warn!("Refresh task {} failed: {}", name, e),
+        }
+        running.store(false, Ordering::Release);
+    });
+}
@@ -0,0 +1,214 @@
+// A small JSON Schema subset, enough for tool input/output schemas: type,
+// properties, required, additionalProperties, items, enum, minimum/maximum
+// and minLength/maxLength.
+
+fn type_matches(value: &serde_json::Value, expected: &str) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn types(schema: &serde_json::Value) -> Vec<&str> {
+    match &schema["type"] {
+        serde_json::Value::String(expected) => vec![expected.as_str()],
+        serde_json::Value::Array(expected) => expected.iter().filter_map(|t| t.as_str()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn display_path(path: &str) -> &str {
+    if path.is_empty() {
+        "(root)"
+    } else {
+        path
+    }
+}
+
+/// Checks `value` against `schema`, returning one message per violation
+/// (empty when valid). Paths are JSON-pointer style, e.g. `/items/0/name`.
+pub fn validate(value: &serde_json::Value, schema: &serde_json::Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    validate_at(value, schema, "", &mut errors);
+    errors
+}
+
+fn validate_at(
+    value: &serde_json::Value,
+    schema: &serde_json::Value,
+    path: &str,
+    errors: &mut Vec<String>,
+) {
+    let expected = types(schema);
+    if !expected.is_empty() && !expected.iter().any(|t| type_matches(value, t)) {
+        errors.push(format!(
+            "{}: expected {}, got {}",
+            display_path(path),
+            expected.join(" or "),
+            value
+        ));
+        return;
+    }
+
+    if let Some(allowed) = schema["enum"].as_array() {
+        if !allowed.contains(value) {
+            errors.push(format!(
+                "{}: {} is not one of {:?}",
+                display_path(path),
+                value,
+                allowed
+            ));
+        }
+    }
+
+    if let Some(number) = value.as_f64() {
+        if let Some(minimum) = schema["minimum"].as_f64() {
+            if number < minimum {
+                errors.push(format!(
+                    "{}: {} is less than {}",
+                    display_path(path),
+                    number,
+                    minimum
+                ));
+            }
+        }
+        if let Some(maximum) = schema["maximum"].as_f64() {
+            if number > maximum {
+                errors.push(format!(
+                    "{}: {} is more than {}",
+                    display_path(path),
+                    number,
+                    maximum
+                ));
+            }
+        }
+    }
+
+    if let Some(text) = value.as_str() {
+        let length = text.chars().count() as u64;
+        if let Some(min_length) = schema["minLength"].as_u64() {
+            if length < min_length {
+                errors.push(format!(
+                    "{}: shorter than {} characters",
+                    display_path(path),
+                    min_length
+                ));
+            }
+        }
+        if let Some(max_length) = schema["maxLength"].as_u64() {
+            if length > max_length {
+                errors.push(format!(
+                    "{}: longer than {} characters",
+                    display_path(path),
+                    max_length
+                ));
+            }
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        let properties = schema["properties"].as_object();
+        if let Some(required) = schema["required"].as_array() {
+            for name in required.iter().filter_map(|name| name.as_str()) {
+                if !object.contains_key(name) {
+                    errors.push(format!("{}/{}: required property is missing", path, name));
+                }
+            }
+        }
+        for (name, property) in object {
+            let property_path = format!("{}/{}", path, name);
+            match properties.and_then(|properties| properties.get(name)) {
+                Some(property_schema) => {
+                    validate_at(property, property_schema, &property_path, errors)
+                }
+                None if schema["additionalProperties"] == false => {
+                    errors.push(format!("{}: unexpected property", property_path))
+                }
+                None => {}
+            }
+        }
+    }
+
+    if let (Some(items), Some(item_schema)) = (value.as_array(), schema.get("items")) {
+        for (index, item) in items.iter().enumerate() {
+            validate_at(item, item_schema, &format!("{}/{}", path, index), errors);
+        }
+    }
+}
+
+/// Rewrites values that have the wrong JSON type but an unambiguous
+/// conversion: numeric strings to numbers, "true"/"false" to booleans, and a
+/// single value to a one-element array. Returns a description of each
+/// coercion made.
+pub fn coerce(value: &mut serde_json::Value, schema: &serde_json::Value) -> Vec<String> {
+    let mut coercions = Vec::new();
+    coerce_at(value, schema, "", &mut coercions);
+    coercions
+}
+
+fn coerce_at(
+    value: &mut serde_json::Value,
+    schema: &serde_json::Value,
+    path: &str,
+    coercions: &mut Vec<String>,
+) {
+    let expected = types(schema);
+    if !expected.is_empty() && !expected.iter().any(|t| type_matches(value, t)) {
+        if let Some(coerced) = expected.iter().find_map(|t| coerce_value(value, t)) {
+            coercions.push(format!("{}: {} -> {}", display_path(path), value, coerced));
+            *value = coerced;
+        }
+    }
+
+    match value {
+        serde_json::Value::Object(object) => {
+            if let Some(properties) = schema["properties"].as_object() {
+                for (name, property) in object.iter_mut() {
+                    if let Some(property_schema) = properties.get(name) {
+                        coerce_at(
+                            property,
+                            property_schema,
+                            &format!("{}/{}", path, name),
+                            coercions,
+                        );
+                    }
+                }
+            }
+        }
+        serde_json::Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter_mut().enumerate() {
+                    coerce_at(item, item_schema, &format!("{}/{}", path, index), coercions);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn coerce_value(value: &serde_json::Value, expected: &str) -> Option<serde_json::Value> {
+    let text = value.as_str().map(str::trim);
+    match expected {
+        "integer" => text?.parse::<i64>().ok().map(serde_json::Value::from),
+        "number" => match text?.parse::<i64>() {
+            Ok(integer) => Some(serde_json::Value::from(integer)),
+            Err(_) => {
+                let number = text?.parse::<f64>().ok()?;
+                serde_json::Number::from_f64(number).map(serde_json::Value::Number)
+            }
+        },
+        "boolean" => match text? {
+            "true" => Some(serde_json::Value::Bool(true)),
+            "false" => Some(serde_json::Value::Bool(false)),
+            _ => None,
+        },
+        "array" => Some(serde_json::Value::Array(vec![value.clone()])),
+        _ => None,
+    }
+}
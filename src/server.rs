@@ -1,327 +1,2358 @@
 use anyhow::Result;
-use std::collections::HashMap;
-use tokio::io::AsyncWriteExt;
-use tracing::info;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::{mpsc, Notify, OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, error, info, warn};
 
+use crate::compat;
+use crate::completion::{self, CompletionRequest};
+use crate::config::{Config, OnLimit, Profile};
+use crate::confirm::{self, ConfirmationPolicy, ConfirmationRequest, Decision};
+use crate::envelope::{self, Incoming};
+use crate::error::{self, ServerError};
+use crate::events::{Change, EventBus, ServerEvent};
+use crate::experimental::{MethodHandler, RegisteredMethod};
+use crate::framing;
+use crate::locale::{Catalog, Translations};
+use crate::middleware::{Layer, Next};
+use crate::notification::{self, LogLevel, Notification, NotificationManager};
+use crate::plugin::Plugin;
+use crate::priority::{Priority, WorkerPool};
+use crate::prompts::{self, RegisteredPrompt};
+use crate::resources::{self, RegisteredResource, RegisteredResourceTemplate};
+use crate::roots::RootScope;
+use crate::schema;
+use crate::session::Session;
+use crate::tools::{self, RegisteredTool, RetryableError, ToolContext};
+use crate::transform;
 use crate::types::*;
+use crate::validation;
+
+// Notifications the server handles itself; custom methods may add more
+const NOTIFICATIONS: &[&str] = &[
+    "notifications/cancelled",
+    "notifications/initialized",
+    "notifications/progress",
+    "notifications/roots/list_changed",
+];
 
 pub struct McpServer {
-    tools: Vec<Tool>,
-    resources: Vec<Resource>,
-    prompts: Vec<Prompt>,
+    config: Config,
+    tools: RwLock<Vec<RegisteredTool>>,
+    // Tools switched off at runtime, by name; hidden and uncallable
+    disabled_tools: RwLock<HashSet<String>>,
+    resources: RwLock<Vec<RegisteredResource>>,
+    resource_templates: RwLock<Vec<RegisteredResourceTemplate>>,
+    prompts: RwLock<Vec<RegisteredPrompt>>,
+    // Names of the prompts that came from `prompt_dir`, so a reload can tell
+    // which ones to drop
+    dir_prompts: Mutex<HashSet<String>>,
+    translations: RwLock<Catalog>,
+    // Advertised under `capabilities.experimental`
+    experimental: HashMap<String, serde_json::Value>,
+    // Custom methods, tried when no built-in method matches
+    methods: RwLock<HashMap<String, MethodHandler>>,
+    layers: Vec<Arc<dyn Layer>>,
+    plugins: Vec<Arc<dyn Plugin>>,
+    // One semaphore per concurrency-limited tool, created on first call
+    tool_permits: Mutex<HashMap<String, (Arc<Semaphore>, OnLimit)>>,
+    // Every connected session, for broadcasts
+    notifications: Arc<NotificationManager>,
+    events: EventBus,
+    // Set once the notification manager follows `events`
+    following_events: AtomicBool,
+    // Resource URIs each session has subscribed to, by session id
+    subscriptions: RwLock<HashMap<String, HashSet<String>>>,
+    // Set once shutdown starts; new tool calls are refused from then on
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+    drained: Notify,
+    // Signalled by `server/shutdown`
+    shutdown_requested: Notify,
+    // Requests being handled, for the admin endpoint
+    active_requests: Mutex<HashMap<u64, ActiveRequest>>,
+    next_request: AtomicU64,
+    // Set with `scheduling.workers`; requests queue for a worker by priority
+    workers: Option<Arc<WorkerPool>>,
+    // Blocking threads available to CPU-bound tools
+    cpu_workers: Arc<Semaphore>,
+    // Approves calls to destructive tools
+    confirmation: Option<Arc<dyn ConfirmationPolicy>>,
+    // Tool calls a layer may run more than once, by session and request id,
+    // and whether each has been approved yet; see `retrying`
+    retried_calls: Mutex<HashMap<(String, RequestId), bool>>,
+}
+
+struct ActiveRequest {
+    session_id: String,
+    method: String,
+    id: Option<RequestId>,
+    started: std::time::Instant,
+    // Signalled by `notifications/cancelled`
+    cancelled: Arc<Notify>,
+}
+
+/// Keeps a tool call's approval for its later attempts until dropped.
+pub struct Retrying<'a> {
+    server: &'a McpServer,
+    key: Option<(String, RequestId)>,
+}
+
+impl Drop for Retrying<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = &self.key {
+            self.server.retried_calls.lock().unwrap().remove(key);
+        }
+    }
+}
+
+// Lists a request as active until dropped
+struct Tracked<'a>(&'a McpServer, u64);
+
+impl Drop for Tracked<'_> {
+    fn drop(&mut self) {
+        self.0.active_requests.lock().unwrap().remove(&self.1);
+    }
+}
+
+/// What a registry reload changed, by name.
+#[derive(Debug, Default, Serialize)]
+pub struct Reloaded {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+// Counts a tool call as in flight until dropped
+struct InFlight<'a>(&'a McpServer);
+
+impl<'a> InFlight<'a> {
+    fn new(server: &'a McpServer) -> Self {
+        server.in_flight.fetch_add(1, Ordering::SeqCst);
+        Self(server)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.drained.notify_waiters();
+        }
+    }
 }
 
 impl McpServer {
-    pub fn new() -> Self {
-        let tools = vec![
-            Tool {
-                name: "echo".to_string(),
-                description: "Echoes back the input message".to_string(),
-                input_schema: serde_json::json!({
-                    "type": "object",
-                    "properties": {
-                        "message": {
-                            "type": "string",
-                            "description": "The message to echo"
-                        }
-                    },
-                    "required": ["message"]
-                }),
+    pub fn new(config: Config) -> Self {
+        let mut prompts = prompts::builtin_prompts();
+        let mut dir_prompts = HashSet::new();
+        if let Some(dir) = &config.prompt_dir {
+            match prompts::load_prompt_dir(dir) {
+                Ok(loaded) => {
+                    info!("Loaded {} prompts from {}", loaded.len(), dir.display());
+                    dir_prompts.extend(loaded.iter().map(|p| p.prompt.name.clone()));
+                    prompts.extend(loaded);
+                }
+                Err(e) => warn!("Failed to load prompts from {}: {}", dir.display(), e),
+            }
+        }
+
+        let translations = Catalog::new(config.translations.clone());
+        let list_changed_delay = config
+            .list_changed_delay_ms
+            .map(std::time::Duration::from_millis)
+            .unwrap_or(notification::DEFAULT_LIST_CHANGED_DELAY);
+        let notifications = Arc::new(NotificationManager::new(
+            list_changed_delay,
+            config.notification_aliases.clone(),
+            lists_are_shared(&config),
+        ));
+        let workers = config.scheduling.workers.map(WorkerPool::new);
+        let cpu_workers = config.cpu_workers.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        });
+        Self {
+            config,
+            tools: RwLock::new(tools::builtin_tools()),
+            disabled_tools: RwLock::new(HashSet::new()),
+            resources: RwLock::new(resources::builtin_resources()),
+            resource_templates: RwLock::new(resources::builtin_resource_templates()),
+            prompts: RwLock::new(prompts),
+            dir_prompts: Mutex::new(dir_prompts),
+            translations: RwLock::new(translations),
+            experimental: HashMap::new(),
+            methods: RwLock::new(HashMap::new()),
+            tool_permits: Mutex::new(HashMap::new()),
+            layers: Vec::new(),
+            plugins: Vec::new(),
+            notifications,
+            events: EventBus::default(),
+            following_events: AtomicBool::new(false),
+            subscriptions: RwLock::new(HashMap::new()),
+            draining: AtomicBool::new(false),
+            in_flight: AtomicUsize::new(0),
+            drained: Notify::new(),
+            shutdown_requested: Notify::new(),
+            active_requests: Mutex::new(HashMap::new()),
+            next_request: AtomicU64::new(0),
+            workers,
+            cpu_workers: Arc::new(Semaphore::new(cpu_workers.max(1))),
+            confirmation: None,
+            retried_calls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Adds a middleware layer. Layers run in the order they were added, the
+    // first one outermost.
+    pub fn layer(mut self, layer: impl Layer + 'static) -> Self {
+        self.layers.push(Arc::new(layer));
+        self
+    }
+
+    // Adds a plugin whose lifecycle hooks run for every session, in the
+    // order plugins were added
+    pub fn plugin(mut self, plugin: impl Plugin + 'static) -> Self {
+        self.plugins.push(Arc::new(plugin));
+        self
+    }
+
+    // Requires approval from `policy` for calls to destructive tools and to
+    // those listed in `confirmation.tools`
+    pub fn with_confirmation(mut self, policy: Arc<dyn ConfirmationPolicy>) -> Self {
+        self.confirmation = Some(policy);
+        self
+    }
+
+    // Advertises an experimental capability in the initialize result
+    pub fn experimental_capability(mut self, name: &str, payload: serde_json::Value) -> Self {
+        self.experimental.insert(name.to_string(), payload);
+        self
+    }
+
+    // Registers a custom method, replacing any with the same name. Built-in
+    // methods can't be overridden.
+    pub fn add_method(&self, method: RegisteredMethod) {
+        info!("Registered method: {}", method.name);
+        self.methods
+            .write()
+            .unwrap()
+            .insert(method.name, method.handler);
+    }
+
+    // Creates a session and registers it for broadcasts. Transports must call
+    // `disconnect` once the session is gone for good.
+    pub fn connect(&self) -> (Session, mpsc::UnboundedReceiver<serde_json::Value>) {
+        let (session, outbound) = Session::new();
+        if self.config.chaos.enabled {
+            session.set_notification_drop_rate(self.config.chaos.notification_drop_rate);
+        }
+        // Until a session connects there's nobody to notify; transports run
+        // inside the runtime, so the follower can be spawned here
+        if !self.following_events.swap(true, Ordering::SeqCst) {
+            tokio::spawn(self.notifications.clone().follow(self.events.subscribe()));
+        }
+        self.notifications.add(&session.id, session.notifier());
+        self.events.publish(ServerEvent::SessionOpened {
+            session_id: session.id.clone(),
+        });
+        (session, outbound)
+    }
+
+    pub fn disconnect(&self, session_id: &str) {
+        if !self.end_session(session_id) {
+            return;
+        }
+        // Transports disconnect from synchronous code, so the hooks run on
+        // their own
+        for plugin in &self.plugins {
+            let plugin = plugin.clone();
+            let session_id = session_id.to_string();
+            tokio::spawn(async move {
+                if let Err(e) = plugin.on_shutdown(&session_id).await {
+                    warn!("Shutdown hook failed for {}: {}", session_id, e);
+                }
+            });
+        }
+    }
+
+    // Also returns what `notifications/cancelled` signals for the request
+    fn track(&self, session: &Session, request: &JsonRpcRequest) -> (Tracked<'_>, Arc<Notify>) {
+        let key = self.next_request.fetch_add(1, Ordering::Relaxed);
+        let cancelled = Arc::new(Notify::new());
+        self.active_requests.lock().unwrap().insert(
+            key,
+            ActiveRequest {
+                session_id: session.id.clone(),
+                method: request.method.clone(),
+                id: request.id.clone(),
+                started: std::time::Instant::now(),
+                cancelled: cancelled.clone(),
             },
-            Tool {
-                name: "add".to_string(),
-                description: "Adds two numbers together".to_string(),
-                input_schema: serde_json::json!({
-                    "type": "object",
-                    "properties": {
-                        "a": {
-                            "type": "number",
-                            "description": "First number"
-                        },
-                        "b": {
-                            "type": "number",
-                            "description": "Second number"
+        );
+        (Tracked(self, key), cancelled)
+    }
+
+    // Control messages come first, then lists, reads and the like, with tool
+    // calls last unless `scheduling.tool_priorities` says otherwise
+    fn priority(&self, request: &JsonRpcRequest) -> Priority {
+        match request.method.as_str() {
+            "ping" | "initialize" | "server/shutdown" | "logging/setLevel" => Priority::High,
+            "tools/call" => request
+                .params
+                .as_ref()
+                .and_then(|params| params["name"].as_str())
+                .and_then(|name| self.config.scheduling.tool_priorities.get(name))
+                .copied()
+                .unwrap_or(Priority::Low),
+            _ => Priority::Normal,
+        }
+    }
+
+    // Requests being handled right now, longest-running first
+    pub fn active_requests(&self) -> serde_json::Value {
+        let active = self.active_requests.lock().unwrap();
+        let mut requests: Vec<&ActiveRequest> = active.values().collect();
+        requests.sort_by_key(|request| request.started);
+        let requests: Vec<serde_json::Value> = requests
+            .into_iter()
+            .map(|request| {
+                serde_json::json!({
+                    "sessionId": request.session_id,
+                    "method": request.method,
+                    "id": request.id,
+                    "elapsedMs": request.started.elapsed().as_millis() as u64
+                })
+            })
+            .collect();
+        let queued = self.workers.as_ref().map_or(0, |workers| workers.queued());
+        serde_json::json!({ "requests": requests, "queued": queued })
+    }
+
+    // Everything registered, as the server sees it (unfiltered by session)
+    pub fn registry(&self) -> serde_json::Value {
+        let disabled = self.disabled_tools.read().unwrap();
+        let tools: Vec<serde_json::Value> = self
+            .tools
+            .read()
+            .unwrap()
+            .iter()
+            .map(|registered| {
+                serde_json::json!({
+                    "name": registered.tool.name,
+                    "group": registered.group,
+                    "enabled": !disabled.contains(&registered.tool.name)
+                })
+            })
+            .collect();
+        let resources: Vec<String> = self
+            .resources
+            .read()
+            .unwrap()
+            .iter()
+            .map(|registered| registered.resource.uri.clone())
+            .collect();
+        let templates: Vec<String> = self
+            .resource_templates
+            .read()
+            .unwrap()
+            .iter()
+            .map(|registered| registered.template.uri_template.clone())
+            .collect();
+        let prompts: Vec<String> = self
+            .prompts
+            .read()
+            .unwrap()
+            .iter()
+            .map(|registered| registered.prompt.name.clone())
+            .collect();
+        serde_json::json!({
+            "tools": tools,
+            "resources": resources,
+            "resourceTemplates": templates,
+            "prompts": prompts
+        })
+    }
+
+    // Resolves once a client has asked the server to shut down
+    pub async fn shutdown_requested(&self) {
+        self.shutdown_requested.notified().await;
+    }
+
+    // Ends every session still connected, running their shutdown hooks.
+    // Called on the way out, after `drain`.
+    pub async fn shutdown(&self) {
+        for session_id in self.notifications.session_ids() {
+            if !self.end_session(&session_id) {
+                continue;
+            }
+            for plugin in &self.plugins {
+                if let Err(e) = plugin.on_shutdown(&session_id).await {
+                    warn!("Shutdown hook failed for {}: {}", session_id, e);
+                }
+            }
+        }
+    }
+
+    // Forgets a session; false if it had already ended
+    fn end_session(&self, session_id: &str) -> bool {
+        if !self.notifications.remove(session_id) {
+            return false;
+        }
+        self.subscriptions.write().unwrap().remove(session_id);
+        self.events.publish(ServerEvent::SessionClosed {
+            session_id: session_id.to_string(),
+        });
+        true
+    }
+
+    // Tells every session subscribed to `uri` that its contents changed
+    pub fn resource_updated(&self, uri: &str) {
+        self.events.publish(ServerEvent::ResourceChanged {
+            uri: uri.to_string(),
+            change: Change::Updated,
+        });
+        let subscriptions = self.subscriptions.read().unwrap();
+        for (session_id, uris) in subscriptions.iter() {
+            if !uris.contains(uri) {
+                continue;
+            }
+            let notification = Notification::ResourceUpdated {
+                uri: uri.to_string(),
+            };
+            if let Err(e) = self.notifications.notify(session_id, notification) {
+                warn!(
+                    "Failed to notify {} of update to {}: {}",
+                    session_id, uri, e
+                );
+            }
+        }
+    }
+
+    // The principal a client's bearer token authenticates as, if any
+    pub fn authenticate(&self, token: &str) -> Option<String> {
+        self.config.auth.principal(token)
+    }
+
+    pub fn max_message_bytes(&self) -> usize {
+        self.config
+            .max_message_bytes
+            .unwrap_or(framing::DEFAULT_MAX_MESSAGE_BYTES)
+    }
+
+    // Stops accepting tool calls and waits for the running ones to finish.
+    // Returns false if some were still running when `timeout` ran out.
+    pub async fn drain(&self, timeout: std::time::Duration) -> bool {
+        self.draining.store(true, Ordering::SeqCst);
+        let running = self.in_flight.load(Ordering::SeqCst);
+        if running > 0 {
+            info!("Waiting for {} in-flight tool calls", running);
+        }
+        tokio::time::timeout(timeout, async {
+            loop {
+                let drained = self.drained.notified();
+                if self.in_flight.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+                drained.await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+
+    // Sends a notification to every connected session. List changes are
+    // coalesced and only go to initialized sessions.
+    pub fn broadcast(&self, notification: Notification) {
+        self.notifications.broadcast(notification);
+    }
+
+    // Forwards a server log message to the sessions that asked for it with
+    // `logging/setLevel`
+    pub fn log_to_clients(&self, level: LogLevel, logger: &str, data: &serde_json::Value) {
+        self.notifications.log(level, logger, data);
+    }
+
+    // A receiver for every event published from now on
+    pub fn subscribe_events(&self) -> tokio::sync::broadcast::Receiver<ServerEvent> {
+        self.events.subscribe()
+    }
+
+    // Adds descriptions in another language, on top of any already known
+    pub fn add_translations(&self, locale: &str, translations: Translations) {
+        self.translations.write().unwrap().add(locale, translations);
+        self.events.publish(ServerEvent::TranslationsAdded {
+            locale: locale.to_string(),
+        });
+    }
+
+    // The client's requested locale, or the configured default
+    fn locale_for(&self, session: &Session) -> Option<String> {
+        session.locale().or_else(|| self.config.locale.clone())
+    }
+
+    // Registers a tool, replacing any existing tool with the same name
+    pub fn add_tool(&self, tool: RegisteredTool) {
+        let (name, replaced) = {
+            let mut tools = self.tools.write().unwrap();
+            let before = tools.len();
+            tools.retain(|existing| existing.tool.name != tool.tool.name);
+            info!("Registered tool: {}", tool.tool.name);
+            // A replacement may come with a different concurrency limit
+            self.tool_permits.lock().unwrap().remove(&tool.tool.name);
+            let name = tool.tool.name.clone();
+            let replaced = tools.len() != before;
+            tools.push(tool);
+            (name, replaced)
+        };
+        self.events
+            .publish(ServerEvent::ToolRegistered { name, replaced });
+    }
+
+    pub fn remove_tool(&self, name: &str) -> bool {
+        let removed = {
+            let mut tools = self.tools.write().unwrap();
+            let before = tools.len();
+            tools.retain(|existing| existing.tool.name != name);
+            tools.len() != before
+        };
+        if removed {
+            info!("Removed tool: {}", name);
+            self.events.publish(ServerEvent::ToolRemoved {
+                name: name.to_string(),
+            });
+        }
+        removed
+    }
+
+    // Whether a tool of that name is registered, enabled or not
+    pub fn has_tool(&self, name: &str) -> bool {
+        self.tools
+            .read()
+            .unwrap()
+            .iter()
+            .any(|registered| registered.tool.name == name)
+    }
+
+    // Switches a tool off for every session, or back on. Returns false if
+    // there is no such tool.
+    pub fn set_tool_enabled(&self, name: &str, enabled: bool) -> bool {
+        if !self.has_tool(name) {
+            return false;
+        }
+        let changed = {
+            let mut disabled = self.disabled_tools.write().unwrap();
+            if enabled {
+                disabled.remove(name)
+            } else {
+                disabled.insert(name.to_string())
+            }
+        };
+        if changed {
+            info!(
+                "Tool {} {}",
+                name,
+                if enabled { "enabled" } else { "disabled" }
+            );
+            self.events.publish(ServerEvent::ToolToggled {
+                name: name.to_string(),
+                enabled,
+            });
+        }
+        true
+    }
+
+    // Registers a resource, replacing any existing resource with the same URI
+    pub fn add_resource(&self, resource: RegisteredResource) {
+        let uri = {
+            let mut resources = self.resources.write().unwrap();
+            resources.retain(|existing| existing.resource.uri != resource.resource.uri);
+            info!("Registered resource: {}", resource.resource.uri);
+            let uri = resource.resource.uri.clone();
+            resources.push(resource);
+            uri
+        };
+        self.events.publish(ServerEvent::ResourceChanged {
+            uri,
+            change: Change::Added,
+        });
+    }
+
+    pub fn remove_resource(&self, uri: &str) -> bool {
+        let removed = {
+            let mut resources = self.resources.write().unwrap();
+            let before = resources.len();
+            resources.retain(|existing| existing.resource.uri != uri);
+            resources.len() != before
+        };
+        if removed {
+            info!("Removed resource: {}", uri);
+            self.events.publish(ServerEvent::ResourceChanged {
+                uri: uri.to_string(),
+                change: Change::Removed,
+            });
+        }
+        removed
+    }
+
+    // Registers a resource template, replacing any with the same URI template
+    pub fn add_resource_template(&self, template: RegisteredResourceTemplate) {
+        let uri = {
+            let mut templates = self.resource_templates.write().unwrap();
+            templates.retain(|existing| {
+                existing.template.uri_template != template.template.uri_template
+            });
+            info!(
+                "Registered resource template: {}",
+                template.template.uri_template
+            );
+            let uri = template.template.uri_template.clone();
+            templates.push(template);
+            uri
+        };
+        self.events.publish(ServerEvent::ResourceChanged {
+            uri,
+            change: Change::Added,
+        });
+    }
+
+    pub fn remove_resource_template(&self, uri_template: &str) -> bool {
+        let removed = {
+            let mut templates = self.resource_templates.write().unwrap();
+            let before = templates.len();
+            templates.retain(|existing| existing.template.uri_template != uri_template);
+            templates.len() != before
+        };
+        if removed {
+            info!("Removed resource template: {}", uri_template);
+            self.events.publish(ServerEvent::ResourceChanged {
+                uri: uri_template.to_string(),
+                change: Change::Removed,
+            });
+        }
+        removed
+    }
+
+    // Registers a prompt, replacing any existing prompt with the same name
+    pub fn add_prompt(&self, prompt: RegisteredPrompt) {
+        let (name, change) = {
+            let mut prompts = self.prompts.write().unwrap();
+            let before = prompts.len();
+            prompts.retain(|existing| existing.prompt.name != prompt.prompt.name);
+            info!("Registered prompt: {}", prompt.prompt.name);
+            let name = prompt.prompt.name.clone();
+            let change = if prompts.len() != before {
+                Change::Updated
+            } else {
+                Change::Added
+            };
+            prompts.push(prompt);
+            (name, change)
+        };
+        self.events
+            .publish(ServerEvent::PromptChanged { name, change });
+    }
+
+    pub fn remove_prompt(&self, name: &str) -> bool {
+        let removed = {
+            let mut prompts = self.prompts.write().unwrap();
+            let before = prompts.len();
+            prompts.retain(|existing| existing.prompt.name != name);
+            prompts.len() != before
+        };
+        if removed {
+            info!("Removed prompt: {}", name);
+            self.events.publish(ServerEvent::PromptChanged {
+                name: name.to_string(),
+                change: Change::Removed,
+            });
+        }
+        removed
+    }
+
+    // Re-scans `prompt_dir` and brings the prompt registry in line with it:
+    // new files are added, changed ones replaced and deleted ones dropped.
+    // Clients are told if the list changed.
+    pub fn reload(&self) -> Result<Reloaded> {
+        let mut reloaded = Reloaded::default();
+        let Some(dir) = &self.config.prompt_dir else {
+            return Ok(reloaded);
+        };
+        let loaded = prompts::load_prompt_dir(dir)?;
+        {
+            let mut dir_prompts = self.dir_prompts.lock().unwrap();
+            let mut prompts = self.prompts.write().unwrap();
+            let names: HashSet<String> = loaded.iter().map(|p| p.prompt.name.clone()).collect();
+
+            reloaded.removed = dir_prompts.difference(&names).cloned().collect();
+            prompts.retain(|existing| !reloaded.removed.contains(&existing.prompt.name));
+            for prompt in loaded {
+                let name = prompt.prompt.name.clone();
+                match prompts
+                    .iter_mut()
+                    .find(|existing| existing.prompt.name == name)
+                {
+                    Some(existing) => {
+                        let changed = serde_json::to_value(&existing.prompt).ok()
+                            != serde_json::to_value(&prompt.prompt).ok();
+                        if changed {
+                            reloaded.updated.push(name);
                         }
-                    },
-                    "required": ["a", "b"]
-                }),
-            },
+                        *existing = prompt;
+                    }
+                    None => {
+                        reloaded.added.push(name);
+                        prompts.push(prompt);
+                    }
+                }
+            }
+            *dir_prompts = names;
+        }
+
+        info!(
+            "Reloaded {}: {} added, {} updated, {} removed",
+            dir.display(),
+            reloaded.added.len(),
+            reloaded.updated.len(),
+            reloaded.removed.len()
+        );
+        let changes = [
+            (&reloaded.added, Change::Added),
+            (&reloaded.updated, Change::Updated),
+            (&reloaded.removed, Change::Removed),
         ];
+        for (names, change) in changes {
+            for name in names {
+                self.events.publish(ServerEvent::PromptChanged {
+                    name: name.clone(),
+                    change,
+                });
+            }
+        }
+        Ok(reloaded)
+    }
 
-        let resources = vec![Resource {
-            uri: "file:///example.txt".to_string(),
-            name: "Example File".to_string(),
-            description: "An example text file".to_string(),
-            mime_type: "text/plain".to_string(),
-        }];
+    // Parses and handles a single raw JSON-RPC message, turning failures into
+    // error responses. Returns `None` when there is nothing to send back,
+    // which is always the case for notifications.
+    pub async fn handle_message(&self, session: &Session, message: &str) -> Option<McpResponse> {
+        match envelope::parse(message) {
+            Ok(value) => self.handle_value(session, value).await,
+            Err(response) => {
+                session.touch();
+                warn!("Failed to parse message");
+                Some(*response)
+            }
+        }
+    }
 
-        let prompts = vec![Prompt {
-            name: "hello".to_string(),
-            description: "Returns a friendly greeting".to_string(),
-        }];
+    // Handles a message that was already decoded, e.g. from MessagePack
+    pub async fn handle_value(
+        &self,
+        session: &Session,
+        value: serde_json::Value,
+    ) -> Option<McpResponse> {
+        session.touch();
 
-        Self {
-            tools,
-            resources,
-            prompts,
+        let mut value = value;
+        if !session.shims_enabled()
+            && (self.config.client_shims.all
+                || (value["method"] == "initialize" && self.config.client_shims.matches(&value)))
+        {
+            info!(
+                "Fixing up known client deviations for session {}",
+                session.id
+            );
+            session.enable_shims();
+        }
+        if session.shims_enabled() {
+            compat::apply_shims(&mut value);
         }
+        let alias = value["method"]
+            .as_str()
+            .and_then(|method| self.config.notification_aliases.canonical(method));
+        if let Some(canonical) = alias {
+            value["method"] = serde_json::json!(canonical);
+        }
+
+        // Checked before the message is sorted, while unknown fields can
+        // still be seen, but acted on once it's known what kind it is
+        let rejected = match self.config.strict {
+            true => validation::validate_strict(&value).err(),
+            false => None,
+        };
+
+        let request = match envelope::classify(value) {
+            Ok(Incoming::Request(request)) => match rejected {
+                Some(e) => {
+                    warn!("Rejected non-conforming request: {}", e);
+                    return Some(envelope::error_response(request.id, e));
+                }
+                None => request,
+            },
+            Ok(Incoming::Notification(request)) => {
+                match rejected {
+                    // A malformed envelope is always answered; bad params aren't
+                    Some(e @ ServerError::InvalidRequest { .. }) => {
+                        warn!("Rejected non-conforming notification: {}", e);
+                        return Some(envelope::error_response(None, e));
+                    }
+                    Some(e) => warn!("Ignoring non-conforming notification: {}", e),
+                    None => self.handle_notification(session, request).await,
+                }
+                return None;
+            }
+            // Responses from the client (e.g. to keep-alive pings) go to
+            // whichever request is waiting for them and need no reply, not
+            // even when they don't conform
+            Ok(Incoming::Response(response)) => {
+                if let Some(e) = rejected {
+                    warn!("Ignoring non-conforming response: {}", e);
+                } else if !session.resolve(&response) {
+                    warn!("Ignoring response to unknown request {}", response["id"]);
+                }
+                return None;
+            }
+            Err(response) => {
+                warn!("Rejected invalid request");
+                return Some(*response);
+            }
+        };
+
+        let request_id = request.id.clone();
+        let (_tracked, cancelled) = self.track(session, &request);
+        let handling = async {
+            let _worker = match &self.workers {
+                Some(workers) => Some(workers.acquire(self.priority(&request)).await),
+                None => None,
+            };
+            self.handle_request(session, request).await
+        };
+        // A cancelled request is dropped, wherever it had got to, and gets
+        // no response
+        let handled = tokio::select! {
+            handled = handling => handled,
+            _ = cancelled.notified() => {
+                info!("Request {:?} was cancelled by the client", request_id);
+                return None;
+            }
+        };
+        match handled {
+            Ok(response) => response,
+            Err(e) => {
+                error!("Error handling request: {}", e);
+                Some(McpResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: request_id,
+                    result: None,
+                    error: Some(error::to_mcp_error(&e)),
+                })
+            }
+        }
+    }
+
+    // Notifications get no response, even on failure. Unlike unknown
+    // requests, which get `MethodNotFound`, unknown notifications are
+    // ignored, as the spec asks.
+    async fn handle_notification(&self, session: &Session, request: JsonRpcRequest) {
+        let method = request.method.clone();
+        let known = NOTIFICATIONS.contains(&method.as_str())
+            || self.methods.read().unwrap().contains_key(&method);
+        if !known {
+            if method.starts_with("notifications/") {
+                debug!("Ignoring unknown notification {}", method);
+            } else {
+                // Most likely a request missing its id; there is no way to answer it
+                warn!("Ignoring {} sent without an id", method);
+            }
+            return;
+        }
+        if let Err(e) = self.handle_request(session, request).await {
+            warn!("Notification {} failed: {}", method, e);
+        }
+    }
+
+    // Runs the request through the middleware layers and then dispatches it
+    pub async fn handle_request(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
+        Next::new(self, &self.layers).run(session, request).await
     }
 
-    pub async fn handle_request(&self, request: JsonRpcRequest) -> Result<Option<McpResponse>> {
+    pub async fn dispatch(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
         match request.method.as_str() {
-            "initialize" => self.handle_initialize(request).await,
-            "tools/list" => self.handle_tools_list(request).await,
-            "tools/call" => self.handle_tools_call(request).await,
-            "resources/list" => self.handle_resources_list(request).await,
-            "resources/read" => self.handle_resources_read(request).await,
-            "prompts/list" => self.handle_prompts_list(request).await,
-            "prompts/get" => self.handle_prompts_get(request).await,
-            "initialized" => self.handle_initialized().await,
-            _ => Err(anyhow::anyhow!("Unknown method: {}", request.method)),
+            "initialize" => self.handle_initialize(session, request).await,
+            "ping" => self.handle_ping(request).await,
+            "tools/list" => self.handle_tools_list(session, request).await,
+            "tools/call" => self.handle_tools_call(session, request).await,
+            "resources/list" => self.handle_resources_list(session, request).await,
+            "resources/read" => self.handle_resources_read(session, request).await,
+            "resources/templates/list" => {
+                self.handle_resource_templates_list(session, request).await
+            }
+            "resources/subscribe" => self.handle_resources_subscribe(session, request).await,
+            "resources/unsubscribe" => self.handle_resources_unsubscribe(session, request).await,
+            "resources/write" if self.config.writable_resources => {
+                self.handle_resources_write(session, request).await
+            }
+            "server/reload" if self.config.reload_method => respond(request.id, self.reload()?),
+            "server/shutdown" if self.config.shutdown_method => {
+                self.handle_shutdown(session, request).await
+            }
+            "prompts/list" => self.handle_prompts_list(session, request).await,
+            "prompts/get" => self.handle_prompts_get(session, request).await,
+            "logging/setLevel" => self.handle_set_level(session, request),
+            "completion/complete" => self.handle_complete(session, request).await,
+            "notifications/cancelled" => self.handle_cancelled(session, request),
+            "notifications/initialized" => self.handle_initialized(session).await,
+            "notifications/progress" => self.handle_progress(session, request).await,
+            "notifications/roots/list_changed" => {
+                info!("Client roots changed");
+                // Fetched again the next time a tool needs them
+                session.client_roots().invalidate();
+                Ok(None)
+            }
+            _ => self.handle_custom_method(session, request).await,
         }
     }
 
-    async fn handle_initialize(&self, request: JsonRpcRequest) -> Result<Option<McpResponse>> {
-        let params: InitializeParams =
-            serde_json::from_value(request.params.unwrap_or_else(|| serde_json::json!({})))?;
+    async fn handle_initialize(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
+        let params: InitializeParams = parse_params(Some(
+            request.params.unwrap_or_else(|| serde_json::json!({})),
+        ))?;
         info!(
             "Initializing MCP server with protocol version: {}",
             params.protocol_version
         );
+        session.set_locale(params.client_info.and_then(|client| client.locale));
+        session.set_client_capabilities(params.capabilities);
+        let protocol_version = compat::negotiate(&params.protocol_version);
+        session.set_protocol_version(protocol_version);
+        self.notifications
+            .set_protocol_version(&session.id, protocol_version);
+        for plugin in &self.plugins {
+            plugin.on_initialize(session).await?;
+        }
 
-        Ok(Some(McpResponse {
-            jsonrpc: "2.0".to_string(),
-            id: request.id.unwrap_or(serde_json::Value::Null),
-            result: Some(serde_json::json!({
-                "protocolVersion": "2024-11-05",
-                "capabilities": {
-                    "tools": {
-                        "listChanged": true
-                    },
-                    "resources": {
-                        "listChanged": true
-                    },
-                    "prompts": {
-                        "listChanged": true
-                    }
-                },
-                "serverInfo": {
-                    "name": "leap-mcp",
-                    "version": "0.1.0"
-                }
-            })),
-            error: None,
-        }))
+        respond(
+            request.id,
+            InitializeResult {
+                protocol_version: protocol_version.to_string(),
+                capabilities: self.capabilities(),
+                server_info: self.current_server_info(),
+                instructions: self.render_instructions(session),
+            },
+        )
+    }
+
+    // What `initialize` advertises to every client
+    pub fn capabilities(&self) -> ServerCapabilities {
+        ServerCapabilities {
+            tools: Some(ListChangedCapability { list_changed: true }),
+            resources: Some(ResourcesCapability {
+                subscribe: true,
+                list_changed: true,
+            }),
+            prompts: Some(ListChangedCapability { list_changed: true }),
+            logging: Some(LoggingCapability {}),
+            completions: Some(CompletionsCapability {}),
+            experimental: self.experimental_capabilities(),
+        }
+    }
+
+    pub fn current_server_info(&self) -> ServerInfo {
+        let configured = &self.config.server_info;
+        ServerInfo {
+            name: configured
+                .name
+                .clone()
+                .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string()),
+            version: configured
+                .version
+                .clone()
+                .unwrap_or_else(|| env!("CARGO_PKG_VERSION").to_string()),
+            commit: option_env!("GIT_COMMIT").map(str::to_string),
+        }
     }
 
-    async fn handle_tools_list(&self, request: JsonRpcRequest) -> Result<Option<McpResponse>> {
+    fn experimental_capabilities(&self) -> Option<HashMap<String, serde_json::Value>> {
+        let mut experimental = self.experimental.clone();
+        if self.config.writable_resources {
+            experimental.insert("resourcesWrite".to_string(), serde_json::json!({}));
+        }
+        if lists_are_shared(&self.config) {
+            experimental.insert("listChangedDelta".to_string(), serde_json::json!({}));
+        }
+        (!experimental.is_empty()).then_some(experimental)
+    }
+
+    // Expands the instruction template against the current registries
+    fn render_instructions(&self, session: &Session) -> Option<String> {
+        let template = self.config.instructions.as_ref()?;
+        let list = |entries: Vec<(String, String)>| {
+            entries
+                .iter()
+                .map(|(name, description)| format!("- {}: {}", name, description))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        let mut instructions = template.clone();
+        if instructions.contains("{{tools}}") {
+            let tools = self.tools.read().unwrap();
+            let entries = tools
+                .iter()
+                .filter(|t| self.tool_visible(session, t))
+                .map(|t| (self.exposed_name(t), t.tool.description.clone()))
+                .collect();
+            instructions = instructions.replace("{{tools}}", &list(entries));
+        }
+        if instructions.contains("{{resources}}") {
+            let resources = self.resources.read().unwrap();
+            let entries = resources
+                .iter()
+                .map(|r| (r.resource.uri.clone(), r.resource.description.clone()))
+                .collect();
+            instructions = instructions.replace("{{resources}}", &list(entries));
+        }
+        if instructions.contains("{{prompts}}") {
+            let prompts = self.prompts.read().unwrap();
+            let entries = prompts
+                .iter()
+                .map(|p| (p.prompt.name.clone(), p.prompt.description.clone()))
+                .collect();
+            instructions = instructions.replace("{{prompts}}", &list(entries));
+        }
+        Some(instructions)
+    }
+
+    async fn handle_ping(&self, request: JsonRpcRequest) -> Result<Option<McpResponse>> {
+        respond(request.id, EmptyResult {})
+    }
+
+    async fn handle_tools_list(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
         info!("Listing tools");
-        let tools_json: Vec<serde_json::Value> = self
+        let locale = self.locale_for(session);
+        let translations = self.translations.read().unwrap();
+        let tools: Vec<Tool> = self
             .tools
+            .read()
+            .unwrap()
             .iter()
-            .map(|tool| {
-                serde_json::json!({
-                    "name": tool.name,
-                    "description": tool.description,
-                    "inputSchema": tool.input_schema
-                })
+            .filter(|registered| self.tool_visible(session, registered))
+            .filter(|registered| {
+                !(self.config.hide_deprecated_tools && registered.deprecation.is_some())
+            })
+            .map(|registered| {
+                let mut tool = registered.listed();
+                if let Some(description) = locale.as_ref().and_then(|locale| {
+                    translations.describe(locale, |t| t.tools.get(&registered.tool.name))
+                }) {
+                    tool.description = description;
+                }
+                tool.name = self.exposed_name(registered);
+                tool
             })
             .collect();
+        drop(translations);
 
-        Ok(Some(McpResponse {
-            jsonrpc: "2.0".to_string(),
-            id: request.id.unwrap_or(serde_json::Value::Null),
-            result: Some(serde_json::json!({
-                "tools": tools_json
-            })),
-            error: None,
-        }))
+        respond(request.id, ListToolsResult { tools })
     }
 
-    async fn handle_tools_call(&self, request: JsonRpcRequest) -> Result<Option<McpResponse>> {
-        let params: ToolCallParams = serde_json::from_value(
-            request
-                .params
-                .ok_or_else(|| anyhow::anyhow!("Missing params"))?,
-        )?;
+    async fn handle_tools_call(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
+        let params: ToolCallParams = parse_params(request.params)?;
+        // Counted before the check, so `drain` either sees this call or
+        // this call sees the server draining
+        let _in_flight = InFlight::new(self);
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(ServerError::ToolFailed {
+                tool: params.name,
+                details: "Server is shutting down".to_string(),
+                retryable: false,
+            }
+            .into());
+        }
         info!("Calling tool: {}", params.name);
-        let result = self.execute_tool(&params.name, &params.arguments).await?;
-
-        Ok(Some(McpResponse {
-            jsonrpc: "2.0".to_string(),
-            id: request.id.unwrap_or(serde_json::Value::Null),
-            result: Some(serde_json::json!({
-                "content": [
-                    {
-                        "type": "text",
-                        "text": result
-                    }
-                ],
-                "isError": false
-            })),
-            error: None,
-        }))
+        let started = std::time::Instant::now();
+        let context = ToolContext {
+            session_id: session.id.clone(),
+            notifier: session.notifier(),
+            meta: params.meta.unwrap_or_default(),
+            client_capabilities: session.client_capabilities(),
+            partials_sent: Arc::new(AtomicU64::new(0)),
+            requester: session.requester(),
+            sampling: self.config.sampling.clone(),
+            roots: RootScope::new(&self.config.roots, session.client_roots()),
+            profile: self.profile_name(session),
+            state: session.state(),
+            cpu_workers: self.cpu_workers.clone(),
+        };
+        let result = self
+            .execute_tool(
+                session,
+                request.id.as_ref(),
+                &params.name,
+                params.arguments,
+                context,
+            )
+            .await;
+        self.events.publish(ServerEvent::ToolCallCompleted {
+            session_id: session.id.clone(),
+            tool: params.name.clone(),
+            duration: started.elapsed(),
+            error: match &result {
+                Ok(result) if result.is_error => Some(error_text(result)),
+                Ok(_) => None,
+                Err(e) => Some(e.to_string()),
+            },
+        });
+        let result = result.map_err(|e| match e.downcast::<ServerError>() {
+            Ok(server_error) => server_error,
+            Err(e) => ServerError::ToolFailed {
+                tool: params.name.clone(),
+                details: e.to_string(),
+                retryable: e.is::<RetryableError>(),
+            },
+        })?;
+
+        respond(request.id, result)
     }
 
-    async fn handle_resources_list(&self, request: JsonRpcRequest) -> Result<Option<McpResponse>> {
+    async fn handle_resources_list(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
         info!("Listing resources");
-        let resources_json: Vec<serde_json::Value> = self
+        let mut resources: Vec<Resource> = self
             .resources
+            .read()
+            .unwrap()
             .iter()
-            .map(|resource| {
-                serde_json::json!({
-                    "uri": resource.uri,
-                    "name": resource.name,
-                    "description": resource.description,
-                    "mimeType": resource.mime_type
-                })
-            })
+            .filter(|registered| self.resource_visible(session, &registered.resource.uri))
+            .map(|registered| registered.resource.clone())
             .collect();
+        if let Some(locale) = self.locale_for(session) {
+            let translations = self.translations.read().unwrap();
+            for resource in &mut resources {
+                if let Some(description) =
+                    translations.describe(&locale, |t| t.resources.get(&resource.uri))
+                {
+                    resource.description = description;
+                }
+            }
+        }
 
-        Ok(Some(McpResponse {
-            jsonrpc: "2.0".to_string(),
-            id: request.id.unwrap_or(serde_json::Value::Null),
-            result: Some(serde_json::json!({
-                "resources": resources_json
-            })),
-            error: None,
-        }))
+        respond(request.id, ListResourcesResult { resources })
     }
 
-    async fn handle_resources_read(&self, request: JsonRpcRequest) -> Result<Option<McpResponse>> {
-        let params: ResourceReadParams = serde_json::from_value(
-            request
-                .params
-                .ok_or_else(|| anyhow::anyhow!("Missing params"))?,
-        )?;
+    async fn handle_resource_templates_list(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
+        info!("Listing resource templates");
+        let mut resource_templates: Vec<ResourceTemplate> = self
+            .resource_templates
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|registered| self.resource_visible(session, &registered.template.uri_template))
+            .map(|registered| registered.template.clone())
+            .collect();
+        if let Some(locale) = self.locale_for(session) {
+            let translations = self.translations.read().unwrap();
+            for template in &mut resource_templates {
+                if let Some(description) =
+                    translations.describe(&locale, |t| t.resources.get(&template.uri_template))
+                {
+                    template.description = description;
+                }
+            }
+        }
+
+        respond(
+            request.id,
+            ListResourceTemplatesResult { resource_templates },
+        )
+    }
+
+    async fn handle_resources_read(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
+        let meta = request.meta().cloned().unwrap_or_default();
+        let params: ResourceReadParams = parse_params(request.params)?;
         info!("Reading resource: {}", params.uri);
-        let content = self.read_resource(&params.uri).await?;
-
-        Ok(Some(McpResponse {
-            jsonrpc: "2.0".to_string(),
-            id: request.id.unwrap_or(serde_json::Value::Null),
-            result: Some(serde_json::json!({
-                "contents": [
-                    {
-                        "uri": params.uri,
-                        "mimeType": "text/plain",
-                        "text": content
-                    }
-                ]
-            })),
-            error: None,
-        }))
+        if !self.resource_visible(session, &params.uri) {
+            return Err(ServerError::ResourceNotFound { uri: params.uri }.into());
+        }
+        let contents = self.read_resource(&session.id, &params.uri, meta).await?;
+
+        respond(
+            request.id,
+            ReadResourceResult {
+                contents,
+                meta: self.resource_meta(&params.uri),
+            },
+        )
+    }
+
+    async fn handle_resources_write(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
+        let params: ResourceWriteParams = parse_params(request.params)?;
+        if !self.resource_visible(session, &params.uri) {
+            return Err(ServerError::ResourceNotFound { uri: params.uri }.into());
+        }
+        if params.text.is_some() == params.blob.is_some() {
+            return Err(ServerError::InvalidParams {
+                details: "Exactly one of text or blob is required".to_string(),
+            }
+            .into());
+        }
+        // Clone the writer out so the registry isn't locked while it runs
+        let writer = {
+            let resources = self.resources.read().unwrap();
+            let registered = resources
+                .iter()
+                .find(|registered| registered.resource.uri == params.uri)
+                .ok_or_else(|| ServerError::ResourceNotFound {
+                    uri: params.uri.clone(),
+                })?;
+            registered
+                .writer
+                .clone()
+                .ok_or_else(|| ServerError::InvalidParams {
+                    details: format!("Resource is read-only: {}", params.uri),
+                })?
+        };
+        info!("Writing resource: {}", params.uri);
+        writer(ResourceContents {
+            uri: params.uri.clone(),
+            mime_type: params.mime_type,
+            text: params.text,
+            blob: params.blob,
+        })
+        .await
+        .map_err(|e| ServerError::InvalidParams {
+            details: e.to_string(),
+        })?;
+        self.resource_updated(&params.uri);
+
+        respond(request.id, EmptyResult {})
+    }
+
+    async fn handle_resources_subscribe(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
+        let params: ResourceReadParams = parse_params(request.params)?;
+        let known = self
+            .resources
+            .read()
+            .unwrap()
+            .iter()
+            .any(|registered| registered.resource.uri == params.uri)
+            || self.match_template(&params.uri).is_some();
+        if !known || !self.resource_visible(session, &params.uri) {
+            return Err(ServerError::ResourceNotFound { uri: params.uri }.into());
+        }
+        info!("Session {} subscribed to {}", session.id, params.uri);
+        self.subscriptions
+            .write()
+            .unwrap()
+            .entry(session.id.clone())
+            .or_default()
+            .insert(params.uri);
+
+        respond(request.id, EmptyResult {})
+    }
+
+    async fn handle_resources_unsubscribe(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
+        let params: ResourceReadParams = parse_params(request.params)?;
+        if let Some(uris) = self.subscriptions.write().unwrap().get_mut(&session.id) {
+            uris.remove(&params.uri);
+        }
+
+        respond(request.id, EmptyResult {})
     }
 
-    async fn handle_prompts_list(&self, request: JsonRpcRequest) -> Result<Option<McpResponse>> {
+    async fn handle_prompts_list(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
         info!("Listing prompts");
-        let prompts_json: Vec<serde_json::Value> = self
+        let mut prompts: Vec<Prompt> = self
             .prompts
+            .read()
+            .unwrap()
             .iter()
-            .map(|prompt| {
-                serde_json::json!({
-                    "name": prompt.name,
-                    "description": prompt.description
-                })
-            })
+            .filter(|registered| self.prompt_visible(session, &registered.prompt.name))
+            .map(|registered| registered.prompt.clone())
             .collect();
+        if let Some(locale) = self.locale_for(session) {
+            let translations = self.translations.read().unwrap();
+            for prompt in &mut prompts {
+                if let Some(description) =
+                    translations.describe(&locale, |t| t.prompts.get(&prompt.name))
+                {
+                    prompt.description = description;
+                }
+            }
+        }
 
-        Ok(Some(McpResponse {
-            jsonrpc: "2.0".to_string(),
-            id: request.id.unwrap_or(serde_json::Value::Null),
-            result: Some(serde_json::json!({
-                "prompts": prompts_json
-            })),
-            error: None,
-        }))
+        respond(request.id, ListPromptsResult { prompts })
     }
 
-    async fn handle_prompts_get(&self, request: JsonRpcRequest) -> Result<Option<McpResponse>> {
-        let params: PromptGetParams = serde_json::from_value(
-            request
-                .params
-                .ok_or_else(|| anyhow::anyhow!("Missing params"))?,
-        )?;
+    async fn handle_prompts_get(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
+        let meta = request.meta().cloned().unwrap_or_default();
+        let params: PromptGetParams = parse_params(request.params)?;
         info!("Getting prompt: {}", params.name);
+        if !self.prompt_visible(session, &params.name) {
+            return Err(ServerError::PromptNotFound {
+                prompt: params.name,
+            }
+            .into());
+        }
+
+        // Clone the handler out so the registry isn't locked while it runs
+        let handler = self
+            .prompts
+            .read()
+            .unwrap()
+            .iter()
+            .find(|registered| registered.prompt.name == params.name)
+            .map(|registered| registered.handler.clone())
+            .ok_or_else(|| ServerError::PromptNotFound {
+                prompt: params.name.clone(),
+            })?;
+        let result = handler(params.arguments.unwrap_or_default(), meta)
+            .await
+            .map_err(|e| ServerError::InvalidParams {
+                details: e.to_string(),
+            })?;
+
+        respond(request.id, result)
+    }
+
+    async fn handle_complete(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
+        let meta = request.meta().cloned().unwrap_or_default();
+        let params: CompleteParams = parse_params(request.params)?;
+        let provider = match &params.reference {
+            CompletionReference::Prompt { name } => {
+                if !self.prompt_visible(session, name) {
+                    return Err(ServerError::PromptNotFound {
+                        prompt: name.clone(),
+                    }
+                    .into());
+                }
+                self.prompts
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .find(|registered| &registered.prompt.name == name)
+                    .ok_or_else(|| ServerError::PromptNotFound {
+                        prompt: name.clone(),
+                    })?
+                    .completions
+                    .get(&params.argument.name)
+                    .cloned()
+            }
+            CompletionReference::Resource { uri } => {
+                if !self.resource_visible(session, uri) {
+                    return Err(ServerError::ResourceNotFound { uri: uri.clone() }.into());
+                }
+                self.resource_templates
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .find(|registered| &registered.template.uri_template == uri)
+                    .ok_or_else(|| ServerError::ResourceNotFound { uri: uri.clone() })?
+                    .completions
+                    .get(&params.argument.name)
+                    .cloned()
+            }
+        };
 
-        let content_text = match params.name.as_str() {
-            "hello" => "Hello from leap-mcp prompts!".to_string(),
-            _ => return Err(anyhow::anyhow!("Unknown prompt: {}", params.name)),
+        let completion = match provider {
+            Some(provider) => {
+                let candidates = provider(CompletionRequest {
+                    argument: params.argument.name,
+                    value: params.argument.value.clone(),
+                    arguments: params.context.unwrap_or_default().arguments,
+                    meta,
+                })
+                .await
+                .map_err(|e| ServerError::Internal {
+                    details: format!("Completion failed: {}", e),
+                })?;
+                completion::rank(candidates, &params.argument.value)
+            }
+            None => completion::empty(),
         };
+        respond(request.id, CompleteResult { completion })
+    }
+
+    async fn handle_custom_method(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
+        let handler = self
+            .methods
+            .read()
+            .unwrap()
+            .get(&request.method)
+            .cloned()
+            .ok_or_else(|| ServerError::MethodNotFound {
+                method: request.method.clone(),
+            })?;
+        let result = handler(request.params, session.notifier()).await?;
+
+        match request.id {
+            Some(id) => respond(Some(id), result),
+            None => Ok(None),
+        }
+    }
+
+    async fn handle_shutdown(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
+        // Only authenticated principals on the list may; an empty list
+        // allows no one
+        let allowed = &self.config.shutdown_principals;
+        if !session.principal().is_some_and(|p| allowed.contains(&p)) {
+            warn!("Refused shutdown request from session {}", session.id);
+            return Err(ServerError::InvalidRequest {
+                details: "Not allowed to shut down the server".to_string(),
+            }
+            .into());
+        }
+        info!("Shutdown requested by session {}", session.id);
+        // `notify_one` keeps the wakeup even if nobody is waiting yet
+        self.shutdown_requested.notify_one();
+        respond(request.id, serde_json::json!({}))
+    }
+
+    fn handle_set_level(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
+        let params: SetLevelParams = parse_params(request.params)?;
+        info!(
+            "Session {} set its log level to {:?}",
+            session.id, params.level
+        );
+        self.notifications.set_log_level(&session.id, params.level);
+        respond(request.id, serde_json::json!({}))
+    }
+
+    // Progress the client reports on a request the server sent, such as
+    // partial text of a streamed sampling response
+    async fn handle_progress(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
+        let params = request.params.unwrap_or_default();
+        if !session.relay_progress(&params) {
+            debug!(
+                "Ignoring progress for unknown token {}",
+                params["progressToken"]
+            );
+        }
+        Ok(None)
+    }
 
-        Ok(Some(McpResponse {
-            jsonrpc: "2.0".to_string(),
-            id: request.id.unwrap_or(serde_json::Value::Null),
-            result: Some(serde_json::json!({
-                "messages": [
-                    { "role": "user", "content": [ { "type": "text", "text": content_text } ] }
-                ]
-            })),
-            error: None,
-        }))
+    // Cancels the session's request with that id, if it is still running.
+    // `initialize` can't be cancelled.
+    fn handle_cancelled(
+        &self,
+        session: &Session,
+        request: JsonRpcRequest,
+    ) -> Result<Option<McpResponse>> {
+        let params = request.params.unwrap_or_default();
+        let Ok(id) = serde_json::from_value::<RequestId>(params["requestId"].clone()) else {
+            debug!("Ignoring cancellation without a requestId");
+            return Ok(None);
+        };
+        let active = self.active_requests.lock().unwrap();
+        let running = active.values().find(|running| {
+            running.session_id == session.id
+                && running.id.as_ref() == Some(&id)
+                && running.method != "initialize"
+        });
+        match running {
+            Some(running) => {
+                debug!(
+                    "Cancelling request {:?}: {}",
+                    id,
+                    params["reason"].as_str().unwrap_or("no reason given")
+                );
+                running.cancelled.notify_one();
+            }
+            None => debug!("Ignoring cancellation of unknown request {:?}", id),
+        }
+        Ok(None)
     }
 
-    async fn handle_initialized(&self) -> Result<Option<McpResponse>> {
+    async fn handle_initialized(&self, session: &Session) -> Result<Option<McpResponse>> {
         info!("Received initialized notification");
-        // After client is initialized, notify that lists changed
-        self.send_notification("tools/listChanged", serde_json::json!({}))
-            .await?;
-        self.send_notification("resources/listChanged", serde_json::json!({}))
-            .await?;
-        self.send_notification("prompts/listChanged", serde_json::json!({}))
-            .await?;
+        // From now on the client hears about list changes
+        self.notifications.set_initialized(&session.id);
+        for plugin in &self.plugins {
+            if let Err(e) = plugin.on_initialized(session).await {
+                warn!("Initialized hook failed for {}: {}", session.id, e);
+            }
+        }
         // No response for notifications
         Ok(None)
     }
 
     async fn execute_tool(
         &self,
+        session: &Session,
+        id: Option<&RequestId>,
         name: &str,
+        arguments: HashMap<String, serde_json::Value>,
+        context: ToolContext,
+    ) -> Result<CallToolResult> {
+        // Clone the tool out so the registry isn't locked while it runs
+        let registered = self
+            .tools
+            .read()
+            .unwrap()
+            .iter()
+            .find(|registered| {
+                self.exposed_name(registered) == name && self.tool_visible(session, registered)
+            })
+            .cloned()
+            .ok_or_else(|| ServerError::ToolNotFound {
+                tool: name.to_string(),
+            })?;
+        let arguments = self.check_arguments(&registered.tool, arguments)?;
+        if let Some(denied) = self
+            .confirm(session, id, &registered.tool, &arguments)
+            .await
+        {
+            return Ok(denied);
+        }
+        let _permit = self.acquire_permit(&registered).await?;
+        let mut result = (registered.handler)(arguments, context).await?;
+
+        if let Some(deprecation) = &registered.deprecation {
+            warn!("Deprecated tool {} called", name);
+            result = result.with_meta("deprecation", serde_json::json!(deprecation));
+        }
+
+        transform::apply_all(&mut result, &registered.output_transforms);
+        if let Some(transforms) = self.config.output_transforms.get(&registered.tool.name) {
+            transform::apply_all(&mut result, transforms);
+        }
+        Ok(check_output(&registered.tool, result))
+    }
+
+    // Marks the tool call `request` as one a layer may run more than once,
+    // so the confirmation policy is asked on the first attempt only
+    pub fn retrying(&self, session: &Session, request: &JsonRpcRequest) -> Retrying<'_> {
+        let key = request.id.clone().map(|id| (session.id.clone(), id));
+        if let Some(key) = &key {
+            self.retried_calls
+                .lock()
+                .unwrap()
+                .insert(key.clone(), false);
+        }
+        Retrying { server: self, key }
+    }
+
+    // Asks the confirmation policy, if there is one, about a call that
+    // needs it. Returns the result to send instead if it isn't approved.
+    async fn confirm(
+        &self,
+        session: &Session,
+        id: Option<&RequestId>,
+        tool: &Tool,
         arguments: &HashMap<String, serde_json::Value>,
-    ) -> Result<String> {
-        match name {
-            "echo" => {
-                let message = arguments
-                    .get("message")
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow::anyhow!("Missing 'message' argument"))?;
-                Ok(format!("Echo: {}", message))
+    ) -> Option<CallToolResult> {
+        let policy = self.confirmation.as_ref()?;
+        let config = self.config.confirmation.as_ref();
+        if !self.needs_confirmation(tool) {
+            return None;
+        }
+        // An earlier attempt of the same call was already approved
+        let key = id.map(|id| (session.id.clone(), id.clone()));
+        if let Some(key) = &key {
+            if self.retried_calls.lock().unwrap().get(key) == Some(&true) {
+                return None;
+            }
+        }
+
+        let timeout = std::time::Duration::from_secs(
+            config.map_or(confirm::DEFAULT_TIMEOUT_SECS, |config| config.timeout_secs),
+        );
+        let request = ConfirmationRequest {
+            session,
+            tool: &tool.name,
+            arguments,
+            timeout,
+        };
+        info!("Asking for approval to run {}", tool.name);
+        let decision = match tokio::time::timeout(timeout, policy.confirm(&request)).await {
+            Ok(Ok(decision)) => decision,
+            Ok(Err(e)) => Decision::Denied(e.to_string()),
+            Err(_) => Decision::Denied(format!("no decision within {:?}", timeout)),
+        };
+        match decision {
+            Decision::Approved => {
+                info!("Call to {} approved", tool.name);
+                if let Some(key) = &key {
+                    if let Some(approved) = self.retried_calls.lock().unwrap().get_mut(key) {
+                        *approved = true;
+                    }
+                }
+                None
             }
-            "add" => {
-                let a = arguments
-                    .get("a")
-                    .and_then(|v| v.as_f64())
-                    .ok_or_else(|| anyhow::anyhow!("Missing 'a' argument"))?;
-                let b = arguments
-                    .get("b")
-                    .and_then(|v| v.as_f64())
-                    .ok_or_else(|| anyhow::anyhow!("Missing 'b' argument"))?;
-                Ok(format!("{} + {} = {}", a, b, a + b))
+            Decision::Denied(reason) => {
+                warn!("Call to {} denied: {}", tool.name, reason);
+                let mut denied = CallToolResult::text(format!(
+                    "Call to {} was not approved: {}",
+                    tool.name, reason
+                ));
+                denied.is_error = true;
+                Some(denied)
             }
-            _ => Err(anyhow::anyhow!("Unknown tool: {}", name)),
         }
     }
 
-    async fn read_resource(&self, uri: &str) -> Result<String> {
-        match uri {
-            "file:///example.txt" => Ok("This is an example text file content.\nIt contains some sample text for demonstration purposes.".to_string()),
-            _ => Err(anyhow::anyhow!("Resource not found: {}", uri))
+    // Destructive tools and those listed in `confirmation.tools` wait for
+    // approval, if there is a policy
+    fn needs_confirmation(&self, tool: &Tool) -> bool {
+        if self.confirmation.is_none() {
+            return false;
+        }
+        let destructive = tool
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.destructive_hint)
+            == Some(true);
+        destructive
+            || self
+                .config
+                .confirmation
+                .as_ref()
+                .is_some_and(|config| config.tools.contains(&tool.name))
+    }
+
+    // Whether a call to `name` may be answered from the tool cache, and if
+    // so the tool's own name, which `name` may be a prefixed form of, and
+    // the scope its results are shared in: the session's profile, since
+    // sessions without one see the same tools. `None` when the call has to
+    // be dispatched, because the server is draining, the session can't see
+    // the tool or the call needs approval.
+    pub fn cache_scope(&self, session: &Session, name: &str) -> Option<(String, String)> {
+        if self.draining.load(Ordering::SeqCst) {
+            return None;
         }
+        let tools = self.tools.read().unwrap();
+        let registered = tools.iter().find(|registered| {
+            self.exposed_name(registered) == name && self.tool_visible(session, registered)
+        })?;
+        if self.needs_confirmation(&registered.tool) {
+            return None;
+        }
+        let scope = self.profile_name(session).unwrap_or_default();
+        Some((registered.tool.name.clone(), scope))
     }
 
-    async fn send_notification(&self, method: &str, params: serde_json::Value) -> Result<()> {
-        let notification = serde_json::json!({
+    // Waits for (or, with `OnLimit::Reject`, fails fast without) a free slot
+    // if the tool has a concurrency limit. The slot is held until the
+    // returned permit is dropped.
+    async fn acquire_permit(
+        &self,
+        registered: &RegisteredTool,
+    ) -> Result<Option<OwnedSemaphorePermit>, ServerError> {
+        let name = &registered.tool.name;
+        let limit = self
+            .config
+            .tool_concurrency
+            .get(name)
+            .or(registered.concurrency_limit.as_ref());
+        let Some(limit) = limit else {
+            return Ok(None);
+        };
+
+        let (semaphore, on_limit) = self
+            .tool_permits
+            .lock()
+            .unwrap()
+            .entry(name.clone())
+            .or_insert_with(|| {
+                (
+                    Arc::new(Semaphore::new(limit.max_concurrency)),
+                    limit.on_limit,
+                )
+            })
+            .clone();
+        let permit = match on_limit {
+            OnLimit::Queue => semaphore.acquire_owned().await.ok(),
+            OnLimit::Reject => semaphore.try_acquire_owned().ok(),
+        };
+        match permit {
+            Some(permit) => Ok(Some(permit)),
+            None => Err(ServerError::ToolFailed {
+                tool: name.clone(),
+                details: format!(
+                    "Too many concurrent calls (limit {})",
+                    limit.max_concurrency
+                ),
+                retryable: false,
+            }),
+        }
+    }
+
+    // The name clients see, prefixed with the group if `prefix_names` is on
+    fn exposed_name(&self, registered: &RegisteredTool) -> String {
+        match &registered.group {
+            Some(group) if self.config.tool_groups.prefix_names => {
+                format!("{}_{}", group, registered.tool.name)
+            }
+            _ => registered.tool.name.clone(),
+        }
+    }
+
+    // The session's own profile, or the one its principal maps to
+    fn profile_name(&self, session: &Session) -> Option<String> {
+        session.profile().or_else(|| {
+            session
+                .principal()
+                .and_then(|principal| self.config.profile_principals.get(&principal).cloned())
+        })
+    }
+
+    fn profile_for(&self, session: &Session) -> Option<Result<&Profile, String>> {
+        self.named_profile(self.profile_name(session)?)
+    }
+
+    fn named_profile(&self, name: String) -> Option<Result<&Profile, String>> {
+        Some(self.config.profiles.get(&name).ok_or(name))
+    }
+
+    // Whether the session's profile, if any, offers `name`. An unknown
+    // profile offers nothing.
+    fn profile_allows(
+        &self,
+        session: &Session,
+        name: &str,
+        list: impl Fn(&Profile) -> &Option<Vec<String>>,
+    ) -> bool {
+        match self.profile_for(session) {
+            None => true,
+            Some(Ok(profile)) => Profile::allows(list(profile), name),
+            Some(Err(profile)) => {
+                warn!("Session {} has unknown profile {}", session.id, profile);
+                false
+            }
+        }
+    }
+
+    fn resource_visible(&self, session: &Session, uri: &str) -> bool {
+        self.profile_allows(session, uri, |profile| &profile.resources)
+    }
+
+    fn prompt_visible(&self, session: &Session, name: &str) -> bool {
+        self.profile_allows(session, name, |profile| &profile.prompts)
+    }
+
+    // Tools outside the session's profile or switched off are never offered.
+    // Otherwise ungrouped tools are. Grouped ones are unless the group is
+    // disabled, restricted to other principals, or the session's principal
+    // is limited to other groups.
+    fn tool_visible(&self, session: &Session, registered: &RegisteredTool) -> bool {
+        let name = self.exposed_name(registered);
+        if !self.profile_allows(session, &name, |profile| &profile.tools) {
+            return false;
+        }
+        if self
+            .disabled_tools
+            .read()
+            .unwrap()
+            .contains(&registered.tool.name)
+        {
+            return false;
+        }
+        match &registered.group {
+            Some(group) => self.group_allowed(session, group),
+            None => true,
+        }
+    }
+
+    // Whether the session may use tools in `group`: it isn't disabled,
+    // restricted to other principals, or outside the groups the session's
+    // principal is limited to.
+    pub fn group_allowed(&self, session: &Session, group: &str) -> bool {
+        let groups = &self.config.tool_groups;
+        if groups.disabled.iter().any(|disabled| disabled == group) {
+            return false;
+        }
+        if let Some(allowed) = groups.restricted.get(group) {
+            if !session.principal().is_some_and(|p| allowed.contains(&p)) {
+                return false;
+            }
+        }
+        match session
+            .principal()
+            .and_then(|principal| groups.principals.get(&principal))
+        {
+            Some(allowed) => allowed.iter().any(|allowed| allowed == group),
+            None => true,
+        }
+    }
+
+    // Validates arguments against the tool's input schema, first coercing
+    // them if `coerce_arguments` is on.
+    fn check_arguments(
+        &self,
+        tool: &Tool,
+        arguments: HashMap<String, serde_json::Value>,
+    ) -> Result<HashMap<String, serde_json::Value>, ServerError> {
+        let mut arguments = serde_json::Value::Object(arguments.into_iter().collect());
+        let coercions = if self.config.coerce_arguments {
+            schema::coerce(&mut arguments, &tool.input_schema)
+        } else {
+            Vec::new()
+        };
+        if !coercions.is_empty() {
+            debug!("Coerced arguments for {}: {:?}", tool.name, coercions);
+        }
+
+        let errors = schema::validate(&arguments, &tool.input_schema);
+        if !errors.is_empty() {
+            return Err(ServerError::InvalidArguments {
+                tool: tool.name.clone(),
+                errors,
+                coercions,
+            });
+        }
+
+        match arguments {
+            serde_json::Value::Object(arguments) => Ok(arguments.into_iter().collect()),
+            _ => unreachable!("arguments were built as an object"),
+        }
+    }
+
+    // Size and modification time of a registered resource, where known
+    fn resource_meta(&self, uri: &str) -> Option<Meta> {
+        let resources = self.resources.read().unwrap();
+        let resource = &resources
+            .iter()
+            .find(|registered| registered.resource.uri == uri)?
+            .resource;
+        let mut meta = Meta::new();
+        if let Some(size) = resource.size {
+            meta.insert("size".to_string(), serde_json::json!(size));
+        }
+        if let Some(last_modified) = resource
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.last_modified.as_ref())
+        {
+            meta.insert("lastModified".to_string(), serde_json::json!(last_modified));
+        }
+        (!meta.is_empty()).then_some(meta)
+    }
+
+    // Reads a resource on behalf of a tool, which sees only what the
+    // calling session could read with `resources/read`
+    pub async fn read_resource_for(
+        &self,
+        context: &ToolContext,
+        uri: &str,
+    ) -> Result<Vec<ResourceContents>> {
+        let visible = match context
+            .profile
+            .clone()
+            .and_then(|name| self.named_profile(name))
+        {
+            None => true,
+            Some(Ok(profile)) => Profile::allows(&profile.resources, uri),
+            Some(Err(_)) => false,
+        };
+        if !visible {
+            return Err(ServerError::ResourceNotFound {
+                uri: uri.to_string(),
+            }
+            .into());
+        }
+        self.read_resource(&context.session_id, uri, context.meta.clone())
+            .await
+    }
+
+    // Static resources win over templates
+    async fn read_resource(
+        &self,
+        session_id: &str,
+        uri: &str,
+        meta: Meta,
+    ) -> Result<Vec<ResourceContents>> {
+        // Clone the reader out so the registry isn't locked while it runs
+        let reader = self
+            .resources
+            .read()
+            .unwrap()
+            .iter()
+            .find(|registered| registered.resource.uri == uri)
+            .map(|registered| registered.reader.clone());
+        if let Some(reader) = reader {
+            return reader(uri.to_string(), session_id.to_string(), meta).await;
+        }
+
+        let (reader, params) =
+            self.match_template(uri)
+                .ok_or_else(|| ServerError::ResourceNotFound {
+                    uri: uri.to_string(),
+                })?;
+        reader(uri.to_string(), params, meta).await
+    }
+
+    // Finds the most specific template matching `uri`. Equally specific
+    // templates are tried in registration order.
+    fn match_template(
+        &self,
+        uri: &str,
+    ) -> Option<(resources::TemplateReader, HashMap<String, String>)> {
+        let templates = self.resource_templates.read().unwrap();
+        let mut best: Option<(&RegisteredResourceTemplate, HashMap<String, String>)> = None;
+        for registered in templates.iter() {
+            let Some(params) = registered.matcher.matches(uri) else {
+                continue;
+            };
+            let better = best.as_ref().is_none_or(|(current, _)| {
+                registered.matcher.specificity() > current.matcher.specificity()
+            });
+            if better {
+                best = Some((registered, params));
+            }
+        }
+        best.map(|(registered, params)| (registered.reader.clone(), params))
+    }
+}
+
+// Whether every session sees the same tool, resource and prompt lists, so
+// one delta in a list-changed notification is right for all of them.
+// Profiles, group settings and hidden deprecated tools each shape the
+// lists differently, or under other names.
+fn lists_are_shared(config: &Config) -> bool {
+    let groups = &config.tool_groups;
+    config.profiles.is_empty()
+        && !config.hide_deprecated_tools
+        && groups.disabled.is_empty()
+        && !groups.prefix_names
+        && groups.principals.is_empty()
+        && groups.restricted.is_empty()
+}
+
+// Deserializes request params, reporting missing or malformed ones as
+// invalid params rather than internal errors
+fn parse_params<T: DeserializeOwned>(params: Option<serde_json::Value>) -> Result<T> {
+    let params = params.ok_or_else(|| ServerError::InvalidParams {
+        details: "Missing params".to_string(),
+    })?;
+    serde_json::from_value(params).map_err(|e| {
+        ServerError::InvalidParams {
+            details: e.to_string(),
+        }
+        .into()
+    })
+}
+
+// What an error result says, for reporting it elsewhere
+fn error_text(result: &CallToolResult) -> String {
+    let text: Vec<&str> = result
+        .content
+        .iter()
+        .filter_map(|content| match content {
+            Content::Text { text, .. } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect();
+    if text.is_empty() {
+        "Tool returned an error result".to_string()
+    } else {
+        text.join("\n")
+    }
+}
+
+// A result whose `structuredContent` doesn't match the tool's output schema
+// (or is missing) is replaced by an error result naming the offending
+// fields, so clients never see malformed output. Error results are passed
+// through as they are.
+fn check_output(tool: &Tool, result: CallToolResult) -> CallToolResult {
+    let Some(output_schema) = &tool.output_schema else {
+        return result;
+    };
+    if result.is_error {
+        return result;
+    }
+    let errors = match &result.structured_content {
+        Some(structured) => schema::validate(structured, output_schema),
+        None => vec!["structuredContent is missing".to_string()],
+    };
+    if errors.is_empty() {
+        return result;
+    }
+
+    warn!(
+        "Tool {} returned output that doesn't match its outputSchema: {}",
+        tool.name,
+        errors.join("; ")
+    );
+    let mut invalid = CallToolResult::text(format!(
+        "Tool {} returned invalid output:\n{}",
+        tool.name,
+        errors.join("\n")
+    ));
+    invalid.is_error = true;
+    invalid.meta = result.meta;
+    invalid.with_meta("outputSchemaErrors", serde_json::json!(errors))
+}
+
+// Wraps a typed result into a successful response for `id`
+fn respond<T: Serialize>(id: Option<RequestId>, result: T) -> Result<Option<McpResponse>> {
+    Ok(Some(McpResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: Some(serde_json::to_value(result)?),
+        error: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR};
+
+    fn server() -> McpServer {
+        McpServer::new(Config::default())
+    }
+
+    // The response to `message` as JSON, or `None` if there was none
+    async fn answer(server: &McpServer, message: &str) -> Option<serde_json::Value> {
+        let (session, _outbound) = server.connect();
+        let response = server.handle_message(&session, message).await;
+        response.map(|response| serde_json::to_value(response).unwrap())
+    }
+
+    // The error code and id of an error response
+    async fn error(server: &McpServer, message: &str) -> (i64, serde_json::Value) {
+        let response = answer(server, message).await.expect("an error response");
+        assert!(response.get("result").is_none(), "{}", response);
+        (
+            response["error"]["code"].as_i64().unwrap(),
+            response["id"].clone(),
+        )
+    }
+
+    #[tokio::test]
+    async fn malformed_messages_get_the_right_error_and_id() {
+        let server = server();
+        let null = serde_json::Value::Null;
+        let cases: &[(&str, i32, serde_json::Value)] = &[
+            // Not JSON at all, or cut short
+            ("{", PARSE_ERROR, null.clone()),
+            ("not json", PARSE_ERROR, null.clone()),
+            (
+                r#"{"jsonrpc": "2.0", "id": 1, "method": "ping""#,
+                PARSE_ERROR,
+                null.clone(),
+            ),
+            // JSON, but not a message
+            ("42", INVALID_REQUEST, null.clone()),
+            (r#""ping""#, INVALID_REQUEST, null.clone()),
+            (
+                r#"[{"jsonrpc": "2.0", "id": 1, "method": "ping"}]"#,
+                INVALID_REQUEST,
+                null.clone(),
+            ),
+            (
+                r#"{"jsonrpc": "2.0", "id": {}, "method": "ping"}"#,
+                INVALID_REQUEST,
+                null.clone(),
+            ),
+            (
+                r#"{"jsonrpc": "2.0", "id": 1.5, "method": "ping"}"#,
+                INVALID_REQUEST,
+                null.clone(),
+            ),
+            // The id can be read, so it is echoed
+            (
+                r#"{"jsonrpc": "2.0", "id": 7}"#,
+                INVALID_REQUEST,
+                serde_json::json!(7),
+            ),
+            (
+                r#"{"jsonrpc": "2.0", "id": "a", "method": 3}"#,
+                INVALID_REQUEST,
+                serde_json::json!("a"),
+            ),
+            (
+                r#"{"jsonrpc": "2.0", "id": 8, "method": "ping", "params": 1}"#,
+                INVALID_REQUEST,
+                serde_json::json!(8),
+            ),
+            // Well-formed requests that fail later keep their id too
+            (
+                r#"{"jsonrpc": "2.0", "id": 9, "method": "no/such"}"#,
+                METHOD_NOT_FOUND,
+                serde_json::json!(9),
+            ),
+            (
+                r#"{"jsonrpc": "2.0", "id": 10, "method": "prompts/get", "params": {}}"#,
+                INVALID_PARAMS,
+                serde_json::json!(10),
+            ),
+        ];
+        for (message, code, id) in cases {
+            assert_eq!(
+                error(&server, message).await,
+                (*code as i64, id.clone()),
+                "{}",
+                message
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn parse_errors_serialize_a_null_id() {
+        let response = answer(&server(), "{").await.unwrap();
+        assert_eq!(response.get("id"), Some(&serde_json::Value::Null));
+    }
+
+    #[tokio::test]
+    async fn failing_notifications_are_never_answered() {
+        let server = server();
+        let notifications = [
+            // Known, with params it can't use
+            r#"{"jsonrpc": "2.0", "method": "notifications/cancelled", "params": {}}"#,
+            r#"{"jsonrpc": "2.0", "method": "notifications/progress", "params": {"progress": "x"}}"#,
+            r#"{"jsonrpc": "2.0", "method": "notifications/cancelled", "params": [1]}"#,
+            // A null id is a notification as well
+            r#"{"jsonrpc": "2.0", "id": null, "method": "notifications/progress", "params": {}}"#,
+        ];
+        for message in notifications {
+            assert_eq!(answer(&server, message).await, None, "{}", message);
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_requests_get_method_not_found() {
+        let server = server();
+        for method in ["no/such", "notifications/unknown", "tools/frobnicate"] {
+            let message = serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": method});
+            let response = answer(&server, &message.to_string()).await.unwrap();
+            assert_eq!(response["error"]["code"], METHOD_NOT_FOUND, "{}", method);
+            assert_eq!(response["error"]["data"]["method"], method);
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_notifications_are_ignored() {
+        let server = server();
+        // Unknown under `notifications/`, unknown elsewhere, and a request
+        // method sent without an id
+        for method in ["notifications/unknown", "no/such", "tools/list"] {
+            let message = serde_json::json!({"jsonrpc": "2.0", "method": method});
+            assert_eq!(
+                answer(&server, &message.to_string()).await,
+                None,
+                "{}",
+                method
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_notifications_are_not_dispatched() {
+        let server = server();
+        let (session, _outbound) = server.connect();
+        // `initialize` sent as a notification would otherwise set the
+        // session's protocol version
+        let message = serde_json::json!({
             "jsonrpc": "2.0",
-            "method": method,
-            "params": params
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2025-06-18",
+                "capabilities": {},
+                "clientInfo": {"name": "test", "version": "1"}
+            }
         });
+        assert!(server
+            .handle_message(&session, &message.to_string())
+            .await
+            .is_none());
+        assert_eq!(session.protocol_version(), None);
+    }
+
+    #[tokio::test]
+    async fn responses_from_the_client_are_never_answered() {
+        let server = server();
+        let responses = [
+            r#"{"jsonrpc": "2.0", "id": "unknown", "result": {}}"#,
+            r#"{"jsonrpc": "2.0", "id": 1, "error": {"code": -1, "message": "no"}}"#,
+        ];
+        for message in responses {
+            assert_eq!(answer(&server, message).await, None, "{}", message);
+        }
+    }
+
+    #[tokio::test]
+    async fn prompts_and_completions_get_the_request_meta() {
+        let server = server();
+        let prompt = Prompt {
+            name: "meta".to_string(),
+            title: None,
+            description: "Echoes a _meta entry".to_string(),
+            arguments: Vec::new(),
+            icons: Vec::new(),
+        };
+        server.add_prompt(
+            RegisteredPrompt::new(prompt, |_, meta| async move {
+                Ok(GetPromptResult::user_text(meta["vendor"].to_string()))
+            })
+            .with_completion("topic", |request| async move {
+                Ok(vec![request.meta["vendor"].to_string()])
+            }),
+        );
+
+        let get = r#"{"jsonrpc": "2.0", "id": 1, "method": "prompts/get",
+            "params": {"name": "meta", "_meta": {"vendor": "acme"}}}"#;
+        let response = answer(&server, get).await.unwrap();
+        let text = &response["result"]["messages"][0]["content"]["text"];
+        assert_eq!(text, r#""acme""#, "{}", response);
+
+        let complete = r#"{"jsonrpc": "2.0", "id": 2, "method": "completion/complete",
+            "params": {"ref": {"type": "ref/prompt", "name": "meta"},
+                "argument": {"name": "topic", "value": ""},
+                "_meta": {"vendor": "acme"}}}"#;
+        let response = answer(&server, complete).await.unwrap();
+        let values = &response["result"]["completion"]["values"];
+        assert_eq!(values, &serde_json::json!([r#""acme""#]), "{}", response);
+    }
+
+    #[tokio::test]
+    async fn custom_methods_and_experimental_capabilities() {
+        let server = McpServer::new(Config::default())
+            .experimental_capability("myExtension", serde_json::json!({ "version": 1 }));
+        server.add_method(RegisteredMethod::new(
+            "experimental/myExtension/status",
+            |params, _notifier| async move { Ok(serde_json::json!({ "echo": params })) },
+        ));
+        // Built-in methods win
+        server.add_method(RegisteredMethod::new("ping", |_, _| async move {
+            Ok(serde_json::json!({ "overridden": true }))
+        }));
+
+        let initialize = r#"{"jsonrpc": "2.0", "id": 1, "method": "initialize",
+            "params": {"protocolVersion": "2025-06-18", "capabilities": {},
+                "clientInfo": {"name": "test", "version": "1"}}}"#;
+        let response = answer(&server, initialize).await.unwrap();
+        let experimental = &response["result"]["capabilities"]["experimental"];
+        assert_eq!(experimental["myExtension"]["version"], 1, "{}", response);
+
+        let status = r#"{"jsonrpc": "2.0", "id": 2,
+            "method": "experimental/myExtension/status", "params": {"x": 1}}"#;
+        let response = answer(&server, status).await.unwrap();
+        assert_eq!(response["result"]["echo"]["x"], 1, "{}", response);
+
+        let ping = r#"{"jsonrpc": "2.0", "id": 3, "method": "ping"}"#;
+        let response = answer(&server, ping).await.unwrap();
+        assert_eq!(response["result"], serde_json::json!({}), "{}", response);
+    }
+
+    #[tokio::test]
+    async fn drain_waits_for_running_calls_and_refuses_new_ones() {
+        let server = Arc::new(server());
+        let release = Arc::new(Notify::new());
+        let wait = release.clone();
+        let tool = Tool {
+            name: "wait".to_string(),
+            title: None,
+            description: "Waits to be released".to_string(),
+            input_schema: serde_json::json!({ "type": "object" }),
+            output_schema: None,
+            annotations: None,
+            icons: Vec::new(),
+            meta: None,
+        };
+        server.add_tool(RegisteredTool::new(tool, move |_, _| {
+            let wait = wait.clone();
+            async move {
+                wait.notified().await;
+                Ok(CallToolResult::text("released"))
+            }
+        }));
+        let call = r#"{"jsonrpc": "2.0", "id": 1, "method": "tools/call",
+            "params": {"name": "wait", "arguments": {}}}"#;
+
+        let running = tokio::spawn({
+            let server = server.clone();
+            async move { answer(&server, call).await.unwrap() }
+        });
+        while server.in_flight.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert!(!server.drain(std::time::Duration::from_millis(50)).await);
 
-        let mut stdout = tokio::io::stdout();
-        let notification_json = serde_json::to_string(&notification)?;
-        stdout.write_all(notification_json.as_bytes()).await?;
-        stdout.write_all(b"\n").await?;
-        stdout.flush().await?;
+        let refused = answer(&server, call).await.unwrap();
+        assert!(refused["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("shutting down"));
 
-        Ok(())
+        release.notify_one();
+        assert!(server.drain(std::time::Duration::from_secs(5)).await);
+        let done = running.await.unwrap();
+        assert_eq!(done["result"]["content"][0]["text"], "released");
     }
 }
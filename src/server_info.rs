@@ -0,0 +1,58 @@
+use std::sync::{Arc, Weak};
+
+use crate::compat::SUPPORTED_PROTOCOL_VERSIONS;
+use crate::resources::RegisteredResource;
+use crate::server::McpServer;
+use crate::types::{Resource, ResourceContents};
+
+/// Describes the running server at `mcp://server/info`, so clients and
+/// aggregators can see what they're connected to beyond `initialize`:
+/// its version, transports, capabilities, how much is registered and a
+/// checksum of its config.
+pub fn info_resource(
+    server: &Arc<McpServer>,
+    transports: Vec<&'static str>,
+    config_checksum: Option<String>,
+) -> RegisteredResource {
+    let resource = Resource {
+        uri: "mcp://server/info".to_string(),
+        name: "Server info".to_string(),
+        title: None,
+        description: "Version, transports, capabilities and config checksum of this server"
+            .to_string(),
+        mime_type: "application/json".to_string(),
+        icons: Vec::new(),
+        size: None,
+        annotations: None,
+    };
+    // Weak, since the server holds this resource
+    let server: Weak<McpServer> = Arc::downgrade(server);
+
+    RegisteredResource::new(resource, move |uri| {
+        let info = server.upgrade().map(|server| {
+            let registry = server.registry();
+            let count = |key: &str| registry[key].as_array().map_or(0, Vec::len);
+            serde_json::json!({
+                "serverInfo": server.current_server_info(),
+                "protocolVersions": SUPPORTED_PROTOCOL_VERSIONS,
+                "transports": transports,
+                "capabilities": server.capabilities(),
+                "registered": {
+                    "tools": count("tools"),
+                    "resources": count("resources"),
+                    "resourceTemplates": count("resourceTemplates"),
+                    "prompts": count("prompts")
+                },
+                "configChecksum": config_checksum
+            })
+        });
+        async move {
+            let info = info.ok_or_else(|| anyhow::anyhow!("The server is shutting down"))?;
+            Ok(vec![ResourceContents::text(
+                uri,
+                "application/json",
+                serde_json::to_string_pretty(&info)?,
+            )])
+        }
+    })
+}
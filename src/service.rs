@@ -0,0 +1,190 @@
+use anyhow::Result;
+
+/// Runs the process as a Windows service: hands the main work back to the
+/// caller once the service control manager knows it is running, and turns
+/// its stop and shutdown requests into `stop_requested`.
+#[cfg(windows)]
+pub fn start(name: &str) -> Result<()> {
+    windows::start(name)
+}
+
+#[cfg(not(windows))]
+pub fn start(_name: &str) -> Result<()> {
+    anyhow::bail!("--service is only supported on Windows")
+}
+
+/// Resolves when the service control manager asks the service to stop.
+/// Never resolves outside service mode.
+#[cfg(windows)]
+pub async fn stop_requested() {
+    windows::stop().notified().await
+}
+
+#[cfg(not(windows))]
+pub async fn stop_requested() {
+    std::future::pending::<()>().await
+}
+
+// Tells the service control manager the service is stopping, and how long
+// it may take
+#[cfg(windows)]
+pub fn report_stopping(wait: std::time::Duration) {
+    windows::report(windows_service::service::ServiceState::StopPending, wait);
+}
+
+#[cfg(not(windows))]
+pub fn report_stopping(_wait: std::time::Duration) {}
+
+#[cfg(windows)]
+pub fn report_stopped() {
+    windows::report(
+        windows_service::service::ServiceState::Stopped,
+        std::time::Duration::ZERO,
+    );
+}
+
+#[cfg(not(windows))]
+pub fn report_stopped() {}
+
+/// Registers a service that runs this executable with `server_args` and
+/// `--service`, started automatically at boot.
+#[cfg(windows)]
+pub fn install(name: &str, server_args: &[String]) -> Result<()> {
+    windows::install(name, server_args)
+}
+
+#[cfg(not(windows))]
+pub fn install(_name: &str, _server_args: &[String]) -> Result<()> {
+    anyhow::bail!("Windows services can only be installed on Windows")
+}
+
+#[cfg(windows)]
+mod windows {
+    use anyhow::Result;
+    use std::ffi::OsString;
+    use std::sync::mpsc;
+    use std::sync::{Mutex, OnceLock};
+    use std::time::Duration;
+    use tokio::sync::Notify;
+    use tracing::{info, warn};
+    use windows_service::service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+        ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+    };
+    use windows_service::service_control_handler::{
+        self, ServiceControlHandlerResult, ServiceStatusHandle,
+    };
+    use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    use windows_service::{define_windows_service, service_dispatcher};
+
+    // The service main runs on a thread the dispatcher starts, so what it
+    // needs is handed over through statics
+    static NAME: OnceLock<String> = OnceLock::new();
+    static STARTED: Mutex<Option<mpsc::Sender<Result<(), String>>>> = Mutex::new(None);
+    static STATUS: OnceLock<ServiceStatusHandle> = OnceLock::new();
+    static STOP: OnceLock<Notify> = OnceLock::new();
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    pub fn stop() -> &'static Notify {
+        STOP.get_or_init(Notify::new)
+    }
+
+    pub fn start(name: &str) -> Result<()> {
+        let _ = NAME.set(name.to_string());
+        let (started, starting) = mpsc::channel();
+        *STARTED.lock().unwrap() = Some(started.clone());
+        let name = name.to_string();
+        // The dispatcher blocks until the service has stopped
+        std::thread::spawn(move || {
+            if let Err(e) = service_dispatcher::start(&name, ffi_service_main) {
+                let _ = started.send(Err(format!(
+                    "Not started by the service control manager: {}",
+                    e
+                )));
+            }
+        });
+        starting
+            .recv()
+            .map_err(|_| anyhow::anyhow!("The service dispatcher stopped unexpectedly"))?
+            .map_err(|e| anyhow::anyhow!(e))?;
+        info!("Running as Windows service {}", NAME.get().unwrap());
+        Ok(())
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        let started = STARTED.lock().unwrap().take();
+        let outcome = register().map_err(|e| e.to_string());
+        if let Some(started) = started {
+            let _ = started.send(outcome);
+        }
+    }
+
+    fn register() -> windows_service::Result<()> {
+        let name = NAME.get().cloned().unwrap_or_default();
+        let handle = service_control_handler::register(name, |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                // Stored as a permit, in case nothing is waiting yet
+                stop().notify_one();
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        })?;
+        let _ = STATUS.set(handle);
+        report(ServiceState::Running, Duration::ZERO);
+        Ok(())
+    }
+
+    pub fn report(state: ServiceState, wait_hint: Duration) {
+        let Some(handle) = STATUS.get() else {
+            return;
+        };
+        let controls_accepted = match state {
+            ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            _ => ServiceControlAccept::empty(),
+        };
+        let status = ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted,
+            exit_code: ServiceExitCode::Win32(0),
+            checkpoint: 0,
+            wait_hint,
+            process_id: None,
+        };
+        if let Err(e) = handle.set_service_status(status) {
+            warn!("Failed to report service status: {}", e);
+        }
+    }
+
+    pub fn install(name: &str, server_args: &[String]) -> Result<()> {
+        let manager = ServiceManager::local_computer(
+            None::<&str>,
+            ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+        )?;
+        let launch_arguments = server_args
+            .iter()
+            .map(OsString::from)
+            .chain(std::iter::once(OsString::from("--service")))
+            .chain([OsString::from("--name"), OsString::from(name)])
+            .collect();
+        let info = ServiceInfo {
+            name: OsString::from(name),
+            display_name: OsString::from(format!("{} MCP server", name)),
+            service_type: ServiceType::OWN_PROCESS,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: std::env::current_exe()?,
+            launch_arguments,
+            dependencies: Vec::new(),
+            account_name: None,
+            account_password: None,
+        };
+        manager
+            .create_service(&info, ServiceAccess::QUERY_STATUS)
+            .map_err(|e| anyhow::anyhow!("Failed to create service {}: {}", name, e))?;
+        info!("Installed Windows service {}", name);
+        Ok(())
+    }
+}
@@ -0,0 +1,405 @@
+use anyhow::Result;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tracing::debug;
+
+use crate::notification::Notifier;
+use crate::roots::{ClientRoots, Root};
+use crate::types::{ClientCapabilities, McpError};
+
+type PendingResponse = oneshot::Sender<Result<serde_json::Value, McpError>>;
+// Where the text of the client's progress notifications goes, by token
+type ProgressWatchers = Arc<Mutex<HashMap<String, mpsc::UnboundedSender<String>>>>;
+
+/// Values a session's tools share, one of each type, dropped with the
+/// session. Tools keep their state in a type of their own so packs can't
+/// see each other's.
+#[derive(Clone, Default)]
+pub struct SessionState {
+    values: Arc<Mutex<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>>,
+}
+
+impl SessionState {
+    // The session's `T`, created with `T::default()` on first use
+    pub fn get<T: Any + Default + Send + Sync>(&self) -> Arc<T> {
+        let value = self
+            .values
+            .lock()
+            .unwrap()
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Arc::new(T::default()))
+            .clone();
+        value
+            .downcast()
+            .unwrap_or_else(|_| unreachable!("session state is keyed by its type"))
+    }
+}
+
+/// A connected client. Everything the server wants to push to the client
+/// (notifications, server-initiated requests) goes through `send`, and the
+/// transport that owns the receiving end decides how to deliver it.
+pub struct Session {
+    pub id: String,
+    outbound: mpsc::UnboundedSender<serde_json::Value>,
+    last_activity: Mutex<Instant>,
+    missed_pings: AtomicU32,
+    // Set for clients known to deviate from the spec; see `compat::apply_shims`
+    shims: AtomicBool,
+    locale: Mutex<Option<String>>,
+    client_capabilities: Mutex<ClientCapabilities>,
+    protocol_version: Mutex<Option<String>>,
+    principal: Mutex<Option<String>>,
+    // Client address as seen by the transport, after any proxy headers
+    remote_addr: Mutex<Option<String>>,
+    // Profile chosen by the transport, e.g. from the HTTP path; takes
+    // precedence over the principal's
+    profile: Mutex<Option<String>>,
+    // Server-initiated requests waiting for the client's response, by id
+    pending: Arc<Mutex<HashMap<String, PendingResponse>>>,
+    progress: ProgressWatchers,
+    // The client's roots once fetched; see `ClientRoots`
+    roots: Arc<Mutex<Option<Vec<Root>>>>,
+    state: SessionState,
+    // Chance its notifiers lose each notification; see `ChaosConfig`
+    notification_drop_rate: Mutex<f64>,
+}
+
+impl Session {
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<serde_json::Value>) {
+        let (outbound, receiver) = mpsc::unbounded_channel();
+        let session = Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            outbound,
+            last_activity: Mutex::new(Instant::now()),
+            missed_pings: AtomicU32::new(0),
+            shims: AtomicBool::new(false),
+            locale: Mutex::new(None),
+            client_capabilities: Mutex::new(ClientCapabilities::default()),
+            protocol_version: Mutex::new(None),
+            principal: Mutex::new(None),
+            remote_addr: Mutex::new(None),
+            profile: Mutex::new(None),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            progress: Arc::new(Mutex::new(HashMap::new())),
+            roots: Arc::new(Mutex::new(None)),
+            state: SessionState::default(),
+            notification_drop_rate: Mutex::new(0.0),
+        };
+        (session, receiver)
+    }
+
+    pub fn send(&self, message: serde_json::Value) -> Result<()> {
+        self.outbound
+            .send(message)
+            .map_err(|_| anyhow::anyhow!("Session {} is closed", self.id))
+    }
+
+    // Sends a request to the client and waits for its response. Fails if
+    // the client answers with an error, doesn't answer within `timeout`, or
+    // the session closes first.
+    pub async fn request(
+        &self,
+        method: &str,
+        params: Option<serde_json::Value>,
+        timeout: Duration,
+    ) -> Result<serde_json::Value> {
+        Ok(self.requester().request(method, params, timeout).await?)
+    }
+
+    // Hands a response from the client to the request waiting for it.
+    // Returns false if nothing was waiting for that id.
+    pub fn resolve(&self, response: &serde_json::Value) -> bool {
+        let Some(id) = response["id"].as_str() else {
+            return false;
+        };
+        let Some(sender) = self.pending.lock().unwrap().remove(id) else {
+            return false;
+        };
+        let outcome = match serde_json::from_value::<McpError>(response["error"].clone()) {
+            Ok(error) => Err(error),
+            Err(_) => Ok(response["result"].clone()),
+        };
+        let _ = sender.send(outcome);
+        true
+    }
+
+    // Passes the message of a client progress notification to whoever
+    // watches its token. Returns false if nobody does.
+    pub fn relay_progress(&self, params: &serde_json::Value) -> bool {
+        let Some(token) = params["progressToken"].as_str() else {
+            return false;
+        };
+        let progress = self.progress.lock().unwrap();
+        let Some(watcher) = progress.get(token) else {
+            return false;
+        };
+        if let Some(message) = params["message"].as_str() {
+            let _ = watcher.send(message.to_string());
+        }
+        true
+    }
+
+    // Pings the client, returning whether it answered within `timeout`
+    pub async fn ping(&self, timeout: Duration) -> bool {
+        match self.request("ping", None, timeout).await {
+            Ok(_) => true,
+            Err(e) => {
+                debug!("Ping to session {} failed: {}", self.id, e);
+                false
+            }
+        }
+    }
+
+    pub fn notifier(&self) -> Notifier {
+        Notifier::new(self.id.clone(), self.outbound.clone())
+            .with_drop_rate(*self.notification_drop_rate.lock().unwrap())
+    }
+
+    // Applies to notifiers made after this is called
+    pub fn set_notification_drop_rate(&self, rate: f64) {
+        *self.notification_drop_rate.lock().unwrap() = rate;
+    }
+
+    pub fn requester(&self) -> Requester {
+        Requester {
+            session_id: self.id.clone(),
+            outbound: self.outbound.clone(),
+            pending: self.pending.clone(),
+            progress: self.progress.clone(),
+        }
+    }
+
+    pub fn client_roots(&self) -> ClientRoots {
+        ClientRoots::new(
+            self.requester(),
+            self.client_supports_roots(),
+            self.roots.clone(),
+        )
+    }
+
+    // Records inbound traffic from the client, which also counts as an
+    // answer to any outstanding keep-alive pings.
+    pub fn touch(&self) {
+        *self.last_activity.lock().unwrap() = Instant::now();
+        self.missed_pings.store(0, Ordering::Relaxed);
+    }
+
+    pub fn idle_for(&self) -> Duration {
+        self.last_activity.lock().unwrap().elapsed()
+    }
+
+    // The locale the client asked for in `clientInfo`, if any
+    pub fn locale(&self) -> Option<String> {
+        self.locale.lock().unwrap().clone()
+    }
+
+    pub fn set_locale(&self, locale: Option<String>) {
+        *self.locale.lock().unwrap() = locale;
+    }
+
+    // The version agreed in `initialize`, if the client has initialized
+    pub fn protocol_version(&self) -> Option<String> {
+        self.protocol_version.lock().unwrap().clone()
+    }
+
+    pub fn set_protocol_version(&self, version: &str) {
+        *self.protocol_version.lock().unwrap() = Some(version.to_string());
+    }
+
+    pub fn shims_enabled(&self) -> bool {
+        self.shims.load(Ordering::Relaxed)
+    }
+
+    pub fn enable_shims(&self) {
+        self.shims.store(true, Ordering::Relaxed);
+    }
+
+    // Who the client authenticated as, set by its transport
+    pub fn principal(&self) -> Option<String> {
+        self.principal.lock().unwrap().clone()
+    }
+
+    pub fn set_principal(&self, principal: impl Into<String>) {
+        *self.principal.lock().unwrap() = Some(principal.into());
+    }
+
+    pub fn remote_addr(&self) -> Option<String> {
+        self.remote_addr.lock().unwrap().clone()
+    }
+
+    pub fn set_remote_addr(&self, remote_addr: impl Into<String>) {
+        *self.remote_addr.lock().unwrap() = Some(remote_addr.into());
+    }
+
+    pub fn profile(&self) -> Option<String> {
+        self.profile.lock().unwrap().clone()
+    }
+
+    pub fn set_profile(&self, profile: impl Into<String>) {
+        *self.profile.lock().unwrap() = Some(profile.into());
+    }
+
+    // State tools keep for this session; see `SessionState`
+    pub fn state(&self) -> SessionState {
+        self.state.clone()
+    }
+
+    // Capabilities the client declared in `initialize`; empty until then
+    pub fn client_capabilities(&self) -> ClientCapabilities {
+        self.client_capabilities.lock().unwrap().clone()
+    }
+
+    pub fn set_client_capabilities(&self, capabilities: ClientCapabilities) {
+        *self.client_capabilities.lock().unwrap() = capabilities;
+    }
+
+    pub fn client_supports_sampling(&self) -> bool {
+        self.client_capabilities.lock().unwrap().sampling.is_some()
+    }
+
+    pub fn client_supports_roots(&self) -> bool {
+        self.client_capabilities.lock().unwrap().roots.is_some()
+    }
+
+    pub fn client_supports_elicitation(&self) -> bool {
+        self.client_capabilities
+            .lock()
+            .unwrap()
+            .elicitation
+            .is_some()
+    }
+}
+
+/// Why a server-initiated request got no result.
+#[derive(Debug)]
+pub enum RequestError {
+    Rejected { method: String, error: McpError },
+    TimedOut { method: String, timeout: Duration },
+    Closed { session_id: String },
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Rejected { method, error } => write!(
+                f,
+                "Client rejected {}: {} ({})",
+                method, error.message, error.code
+            ),
+            RequestError::TimedOut { method, timeout } => {
+                write!(f, "No response to {} within {:?}", method, timeout)
+            }
+            RequestError::Closed { session_id } => write!(f, "Session {} is closed", session_id),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+/// Sends requests to one session's client, for code that outlives a borrow
+/// of the session, like tool handlers.
+#[derive(Clone)]
+pub struct Requester {
+    session_id: String,
+    outbound: mpsc::UnboundedSender<serde_json::Value>,
+    pending: Arc<Mutex<HashMap<String, PendingResponse>>>,
+    progress: ProgressWatchers,
+}
+
+impl Requester {
+    // A fresh progress token to put in a request's `_meta`, and the
+    // messages of the progress notifications the client sends for it. The
+    // stream ends once `unwatch_progress` is called.
+    pub fn watch_progress(&self) -> (String, mpsc::UnboundedReceiver<String>) {
+        let token = uuid::Uuid::new_v4().to_string();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.progress.lock().unwrap().insert(token.clone(), sender);
+        (token, receiver)
+    }
+
+    pub fn unwatch_progress(&self, token: &str) {
+        self.progress.lock().unwrap().remove(token);
+    }
+
+    pub async fn request(
+        &self,
+        method: &str,
+        params: Option<serde_json::Value>,
+        timeout: Duration,
+    ) -> Result<serde_json::Value, RequestError> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id.clone(), sender);
+
+        let mut message = serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method });
+        if let Some(params) = params {
+            message["params"] = params;
+        }
+        let closed = || RequestError::Closed {
+            session_id: self.session_id.clone(),
+        };
+        if self.outbound.send(message).is_err() {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(closed());
+        }
+
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(Ok(result))) => Ok(result),
+            Ok(Ok(Err(error))) => Err(RequestError::Rejected {
+                method: method.to_string(),
+                error,
+            }),
+            Ok(Err(_)) => Err(closed()),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                Err(RequestError::TimedOut {
+                    method: method.to_string(),
+                    timeout,
+                })
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum KeepAliveAction {
+    None,
+    Ping,
+    Expire,
+}
+
+/// Keep-alive timers for a network transport. A session that has been quiet
+/// for `ping_interval` is pinged once per interval, and expired after
+/// `max_missed_pings` pings go unanswered. A zero `ping_interval` turns
+/// keep-alive off.
+#[derive(Debug, Clone)]
+pub struct KeepAliveConfig {
+    pub ping_interval: Duration,
+    pub max_missed_pings: u32,
+}
+
+impl KeepAliveConfig {
+    pub fn enabled(&self) -> bool {
+        !self.ping_interval.is_zero()
+    }
+
+    // Called once per `ping_interval` for every session on the transport.
+    // On `Ping` the transport should send one with `Session::ping`; any
+    // answer, or any other traffic from the client, resets the count.
+    pub fn check(&self, session: &Session) -> KeepAliveAction {
+        if session.idle_for() < self.ping_interval {
+            return KeepAliveAction::None;
+        }
+        if session.missed_pings.load(Ordering::Relaxed) >= self.max_missed_pings {
+            return KeepAliveAction::Expire;
+        }
+
+        session.missed_pings.fetch_add(1, Ordering::Relaxed);
+        KeepAliveAction::Ping
+    }
+}
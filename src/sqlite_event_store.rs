@@ -0,0 +1,126 @@
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::warn;
+
+use crate::event_store::{parse_event_id, EventStore};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS events (
+    stream_id TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    message TEXT NOT NULL,
+    PRIMARY KEY (stream_id, seq)
+);
+";
+
+/// Keeps SSE events in an SQLite database instead of memory, with the same
+/// per-stream cap and event ids as `InMemoryEventStore`.
+pub struct SqliteEventStore {
+    max_events_per_stream: usize,
+    db: Mutex<Connection>,
+}
+
+impl SqliteEventStore {
+    pub fn open(path: &Path, max_events_per_stream: usize) -> Result<Self> {
+        let db = Connection::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open event store {}: {}", path.display(), e))?;
+        db.execute_batch(SCHEMA)?;
+        Ok(Self {
+            max_events_per_stream,
+            db: Mutex::new(db),
+        })
+    }
+
+    fn insert(&self, stream_id: &str, message: &serde_json::Value) -> rusqlite::Result<i64> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        let seq: i64 = tx.query_row(
+            "SELECT COALESCE(MAX(seq) + 1, 0) FROM events WHERE stream_id = ?1",
+            [stream_id],
+            |row| row.get(0),
+        )?;
+        tx.execute(
+            "INSERT INTO events (stream_id, seq, message) VALUES (?1, ?2, ?3)",
+            params![stream_id, seq, message.to_string()],
+        )?;
+        tx.execute(
+            "DELETE FROM events WHERE stream_id = ?1 AND seq <= ?2",
+            params![stream_id, seq - self.max_events_per_stream as i64],
+        )?;
+        tx.commit()?;
+        Ok(seq)
+    }
+
+    fn events_after(
+        &self,
+        stream_id: &str,
+        last_seq: i64,
+    ) -> rusqlite::Result<Option<Vec<(String, serde_json::Value)>>> {
+        let db = self.db.lock().unwrap();
+        let oldest: Option<i64> = db
+            .query_row(
+                "SELECT MIN(seq) FROM events WHERE stream_id = ?1",
+                [stream_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+        // An unknown stream, or one whose requested event has already been
+        // evicted, can't be replayed without a gap
+        match oldest {
+            Some(oldest) if last_seq.saturating_add(1) >= oldest => {}
+            _ => return Ok(None),
+        }
+
+        let mut statement = db.prepare(
+            "SELECT seq, message FROM events WHERE stream_id = ?1 AND seq > ?2 ORDER BY seq",
+        )?;
+        let rows = statement.query_map(params![stream_id, last_seq], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut events = Vec::new();
+        for row in rows {
+            let (seq, message) = row?;
+            // Only this store writes the messages, so they always parse
+            if let Ok(message) = serde_json::from_str(&message) {
+                events.push((format!("{}/{}", stream_id, seq), message));
+            }
+        }
+        Ok(Some(events))
+    }
+}
+
+impl EventStore for SqliteEventStore {
+    fn store_event(&self, stream_id: &str, message: &serde_json::Value) -> String {
+        // Sent anyway, so a failure only costs the client its replay
+        let seq = self.insert(stream_id, message).unwrap_or_else(|e| {
+            warn!("Failed to store event for stream {}: {}", stream_id, e);
+            i64::MAX
+        });
+        format!("{}/{}", stream_id, seq)
+    }
+
+    fn replay_events_after(
+        &self,
+        last_event_id: &str,
+    ) -> Option<(String, Vec<(String, serde_json::Value)>)> {
+        let (stream_id, last_seq) = parse_event_id(last_event_id)?;
+        let last_seq = i64::try_from(last_seq).ok()?;
+        match self.events_after(stream_id, last_seq) {
+            Ok(events) => Some((stream_id.to_string(), events?)),
+            Err(e) => {
+                warn!("Failed to replay events for stream {}: {}", stream_id, e);
+                None
+            }
+        }
+    }
+
+    fn remove_stream(&self, stream_id: &str) {
+        let db = self.db.lock().unwrap();
+        if let Err(e) = db.execute("DELETE FROM events WHERE stream_id = ?1", [stream_id]) {
+            warn!("Failed to remove stream {}: {}", stream_id, e);
+        }
+    }
+}
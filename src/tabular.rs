@@ -0,0 +1,631 @@
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, Weak};
+
+use crate::error::ServerError;
+use crate::msgpack::base64_decode;
+use crate::server::McpServer;
+use crate::tools::{RegisteredTool, ToolContext};
+use crate::types::{CallToolResult, ResourceContents, Tool, ToolAnnotations};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 10_000;
+// Rows shown in the markdown table of the text result
+const PREVIEW_ROWS: usize = 20;
+
+/// A parsed table. Cells are JSON: numbers and booleans keep their type,
+/// empty cells are null and everything else is a string.
+struct Table {
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
+}
+
+impl Table {
+    fn column(&self, name: &str) -> Result<usize> {
+        self.columns
+            .iter()
+            .position(|column| column == name)
+            .ok_or_else(|| {
+                ServerError::InvalidParams {
+                    details: format!(
+                        "No column '{}'; the columns are {}",
+                        name,
+                        self.columns.join(", ")
+                    ),
+                }
+                .into()
+            })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FilterOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Contains,
+    StartsWith,
+    IsEmpty,
+    NotEmpty,
+}
+
+#[derive(Debug, Deserialize)]
+struct Filter {
+    column: String,
+    op: FilterOp,
+    #[serde(default)]
+    value: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Function {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Distinct,
+}
+
+impl Function {
+    fn name(&self) -> &'static str {
+        match self {
+            Function::Count => "count",
+            Function::Sum => "sum",
+            Function::Avg => "avg",
+            Function::Min => "min",
+            Function::Max => "max",
+            Function::Distinct => "distinct",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Aggregate {
+    function: Function,
+    // Count may leave it out to count rows
+    #[serde(default)]
+    column: Option<String>,
+}
+
+impl Aggregate {
+    fn output_name(&self) -> String {
+        match &self.column {
+            Some(column) => format!("{}_{}", self.function.name(), column),
+            None => self.function.name().to_string(),
+        }
+    }
+}
+
+fn parse_csv(text: &str, delimiter: u8) -> Result<Table> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let columns = unique_columns(reader.headers()?.iter().map(str::to_string).collect());
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let mut row: Vec<Value> = record.iter().map(infer).collect();
+        row.resize(columns.len(), Value::Null);
+        rows.push(row);
+    }
+    Ok(Table { columns, rows })
+}
+
+fn parse_spreadsheet(bytes: Vec<u8>, sheet: Option<&str>) -> Result<Table> {
+    use calamine::{Data, Reader};
+
+    let mut workbook = calamine::open_workbook_auto_from_rs(Cursor::new(bytes))?;
+    let names = workbook.sheet_names();
+    let name = match sheet {
+        Some(sheet) if names.iter().any(|name| name == sheet) => sheet.to_string(),
+        Some(sheet) => {
+            return Err(ServerError::InvalidParams {
+                details: format!("No sheet '{}'; the sheets are {}", sheet, names.join(", ")),
+            }
+            .into())
+        }
+        None => names
+            .first()
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("The workbook has no sheets"))?,
+    };
+    let range = workbook.worksheet_range(&name)?;
+
+    let cell = |data: &Data| match data {
+        Data::Empty => Value::Null,
+        Data::Int(n) => Value::from(*n),
+        // Spreadsheets store every number as a float
+        Data::Float(n) if n.fract() == 0.0 && n.abs() < 9e15 => Value::from(*n as i64),
+        Data::Float(n) => serde_json::Number::from_f64(*n).map_or(Value::Null, Value::Number),
+        Data::Bool(b) => Value::Bool(*b),
+        Data::String(text) if text.is_empty() => Value::Null,
+        other => Value::String(other.to_string()),
+    };
+    let mut rows = range.rows();
+    let columns = match rows.next() {
+        Some(header) => unique_columns(
+            header
+                .iter()
+                .map(|data| match cell(data) {
+                    Value::String(text) => text,
+                    Value::Null => String::new(),
+                    other => other.to_string(),
+                })
+                .collect(),
+        ),
+        None => Vec::new(),
+    };
+    Ok(Table {
+        columns,
+        rows: rows.map(|row| row.iter().map(cell).collect()).collect(),
+    })
+}
+
+// Blank header cells become `column_3` and repeats `name_2`, so every
+// column can be named in arguments and output
+fn unique_columns(headers: Vec<String>) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    headers
+        .into_iter()
+        .enumerate()
+        .map(|(index, header)| {
+            let header = header.trim();
+            let base = if header.is_empty() {
+                format!("column_{}", index + 1)
+            } else {
+                header.to_string()
+            };
+            let count = seen.entry(base.clone()).or_insert(0);
+            *count += 1;
+            if *count == 1 {
+                base
+            } else {
+                format!("{}_{}", base, count)
+            }
+        })
+        .collect()
+}
+
+fn infer(cell: &str) -> Value {
+    let trimmed = cell.trim();
+    if trimmed.is_empty() {
+        return Value::Null;
+    }
+    if let Ok(n) = trimmed.parse::<i64>() {
+        return Value::from(n);
+    }
+    if let Some(n) = trimmed
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite())
+        .and_then(serde_json::Number::from_f64)
+    {
+        return Value::Number(n);
+    }
+    match trimmed {
+        "true" | "TRUE" | "True" => Value::Bool(true),
+        "false" | "FALSE" | "False" => Value::Bool(false),
+        _ => Value::String(cell.to_string()),
+    }
+}
+
+fn as_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+// Numbers compare as numbers, anything else as text
+fn compare(a: &Value, b: &Value) -> Ordering {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        _ => as_text(a).cmp(&as_text(b)),
+    }
+}
+
+fn matches(filter: &Filter, cell: &Value) -> bool {
+    let value = &filter.value;
+    match filter.op {
+        FilterOp::IsEmpty => cell.is_null(),
+        FilterOp::NotEmpty => !cell.is_null(),
+        // Empty cells match only `ne`
+        _ if cell.is_null() => matches!(filter.op, FilterOp::Ne),
+        FilterOp::Eq => compare(cell, value).is_eq(),
+        FilterOp::Ne => compare(cell, value).is_ne(),
+        FilterOp::Gt => compare(cell, value).is_gt(),
+        FilterOp::Gte => compare(cell, value).is_ge(),
+        FilterOp::Lt => compare(cell, value).is_lt(),
+        FilterOp::Lte => compare(cell, value).is_le(),
+        FilterOp::Contains => as_text(cell)
+            .to_lowercase()
+            .contains(&as_text(value).to_lowercase()),
+        FilterOp::StartsWith => as_text(cell)
+            .to_lowercase()
+            .starts_with(&as_text(value).to_lowercase()),
+    }
+}
+
+fn compute(function: &Function, cells: &[&Value]) -> Value {
+    let present: Vec<&Value> = cells
+        .iter()
+        .copied()
+        .filter(|cell| !cell.is_null())
+        .collect();
+    let numbers: Vec<f64> = present.iter().filter_map(|cell| cell.as_f64()).collect();
+    let number = |n: f64| serde_json::Number::from_f64(n).map_or(Value::Null, Value::Number);
+    match function {
+        Function::Count => Value::from(present.len()),
+        Function::Distinct => {
+            let mut distinct: Vec<String> = present.iter().map(|cell| as_text(cell)).collect();
+            distinct.sort();
+            distinct.dedup();
+            Value::from(distinct.len())
+        }
+        // Whole numbers stay whole unless the total overflows
+        Function::Sum => present
+            .iter()
+            .try_fold(0i64, |total, cell| {
+                cell.as_i64().and_then(|n| total.checked_add(n))
+            })
+            .map_or_else(
+                || number(numbers.iter().fold(0.0, |total, n| total + n)),
+                Value::from,
+            ),
+        Function::Avg if numbers.is_empty() => Value::Null,
+        Function::Avg => number(numbers.iter().sum::<f64>() / numbers.len() as f64),
+        Function::Min => present
+            .iter()
+            .min_by(|a, b| compare(a, b))
+            .map_or(Value::Null, |cell| (*cell).clone()),
+        Function::Max => present
+            .iter()
+            .max_by(|a, b| compare(a, b))
+            .map_or(Value::Null, |cell| (*cell).clone()),
+    }
+}
+
+/// What `tabular` was asked to do with the rows, in the order it happens:
+/// filter, then group and aggregate, or else pick columns.
+struct Query {
+    filters: Vec<Filter>,
+    columns: Vec<String>,
+    group_by: Vec<String>,
+    aggregates: Vec<Aggregate>,
+}
+
+impl Query {
+    // Output column names, and the matching rows before any limit
+    fn run(&self, table: &Table) -> Result<(Vec<String>, Vec<Vec<Value>>)> {
+        let filters = self
+            .filters
+            .iter()
+            .map(|filter| Ok((table.column(&filter.column)?, filter)))
+            .collect::<Result<Vec<_>>>()?;
+        let rows: Vec<&Vec<Value>> = table
+            .rows
+            .iter()
+            .filter(|row| {
+                filters
+                    .iter()
+                    .all(|(index, filter)| matches(filter, &row[*index]))
+            })
+            .collect();
+
+        if self.group_by.is_empty() && self.aggregates.is_empty() {
+            let names = if self.columns.is_empty() {
+                table.columns.clone()
+            } else {
+                self.columns.clone()
+            };
+            let indexes = names
+                .iter()
+                .map(|name| table.column(name))
+                .collect::<Result<Vec<_>>>()?;
+            let rows = rows
+                .into_iter()
+                .map(|row| indexes.iter().map(|index| row[*index].clone()).collect())
+                .collect();
+            return Ok((names, rows));
+        }
+
+        let keys = self
+            .group_by
+            .iter()
+            .map(|name| table.column(name))
+            .collect::<Result<Vec<_>>>()?;
+        let measured = self
+            .aggregates
+            .iter()
+            .map(|aggregate| match &aggregate.column {
+                Some(column) => table.column(column).map(Some),
+                None if matches!(aggregate.function, Function::Count) => Ok(None),
+                None => Err(ServerError::InvalidParams {
+                    details: format!("{} needs a column", aggregate.function.name()),
+                }
+                .into()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Groups in the order their first row appears
+        let mut groups: Vec<(Vec<Value>, Vec<&Vec<Value>>)> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for row in rows {
+            let key: Vec<Value> = keys.iter().map(|index| row[*index].clone()).collect();
+            let position = *positions
+                .entry(serde_json::to_string(&key)?)
+                .or_insert_with(|| {
+                    groups.push((key, Vec::new()));
+                    groups.len() - 1
+                });
+            groups[position].1.push(row);
+        }
+        if groups.is_empty() && keys.is_empty() {
+            groups.push((Vec::new(), Vec::new()));
+        }
+
+        let mut names = self.group_by.clone();
+        names.extend(self.aggregates.iter().map(Aggregate::output_name));
+        let rows = groups
+            .into_iter()
+            .map(|(mut key, rows)| {
+                for (aggregate, column) in self.aggregates.iter().zip(&measured) {
+                    key.push(match column {
+                        Some(index) => {
+                            let cells: Vec<&Value> = rows.iter().map(|row| &row[*index]).collect();
+                            compute(&aggregate.function, &cells)
+                        }
+                        None => Value::from(rows.len()),
+                    });
+                }
+                key
+            })
+            .collect();
+        Ok((names, rows))
+    }
+}
+
+fn markdown_table(columns: &[String], rows: &[Map<String, Value>]) -> String {
+    let escape = |text: String| text.replace('|', "\\|").replace('\n', " ");
+    let mut lines = vec![
+        format!("| {} |", columns.join(" | ")),
+        format!("|{}", " --- |".repeat(columns.len())),
+    ];
+    for row in rows.iter().take(PREVIEW_ROWS) {
+        let cells: Vec<String> = columns
+            .iter()
+            .map(|column| escape(as_text(&row[column])))
+            .collect();
+        lines.push(format!("| {} |", cells.join(" | ")));
+    }
+    lines.join("\n")
+}
+
+// The table in `uri`: a text resource is CSV, a blob a spreadsheet
+async fn read_table(
+    server: &McpServer,
+    context: &ToolContext,
+    uri: &str,
+    delimiter: Option<u8>,
+    sheet: Option<&str>,
+) -> Result<Table> {
+    let contents: Vec<ResourceContents> = server.read_resource_for(context, uri).await?;
+    let content = contents
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("{} has no contents", uri))?;
+    if let Some(text) = content.text {
+        let tab_separated = content.mime_type.as_deref() == Some("text/tab-separated-values")
+            || uri.ends_with(".tsv");
+        let delimiter = delimiter.unwrap_or(if tab_separated { b'\t' } else { b',' });
+        return parse_csv(&text, delimiter);
+    }
+    let blob = content
+        .blob
+        .ok_or_else(|| anyhow::anyhow!("{} has no contents", uri))?;
+    let bytes =
+        base64_decode(&blob).ok_or_else(|| anyhow::anyhow!("{} isn't valid base64", uri))?;
+    tokio::task::spawn_blocking({
+        let sheet = sheet.map(str::to_string);
+        move || parse_spreadsheet(bytes, sheet.as_deref())
+    })
+    .await?
+}
+
+fn parse_argument<T: DeserializeOwned + Default>(
+    arguments: &HashMap<String, Value>,
+    name: &str,
+) -> Result<T> {
+    match arguments.get(name) {
+        None | Some(Value::Null) => Ok(T::default()),
+        Some(value) => serde_json::from_value(value.clone()).map_err(|e| {
+            ServerError::InvalidParams {
+                details: format!("Invalid '{}': {}", name, e),
+            }
+            .into()
+        }),
+    }
+}
+
+// Reads resources through `server`, as the calling session would
+pub fn tool(server: &Arc<McpServer>) -> RegisteredTool {
+    let server: Weak<McpServer> = Arc::downgrade(server);
+    RegisteredTool::new(definition(), move |arguments, context| {
+        let server = server.clone();
+        async move {
+            let delimiter = match arguments.get("delimiter").and_then(|v| v.as_str()) {
+                Some("\\t") | Some("\t") => Some(b'\t'),
+                Some(delimiter) if delimiter.len() == 1 => Some(delimiter.as_bytes()[0]),
+                Some(delimiter) => {
+                    return Err(ServerError::InvalidParams {
+                        details: format!("delimiter must be one character, not '{}'", delimiter),
+                    }
+                    .into())
+                }
+                None => None,
+            };
+            let sheet = arguments.get("sheet").and_then(|v| v.as_str());
+            let table = match (
+                arguments.get("csv").and_then(|v| v.as_str()),
+                arguments.get("uri").and_then(|v| v.as_str()),
+            ) {
+                (Some(csv), None) => parse_csv(csv, delimiter.unwrap_or(b','))?,
+                (None, Some(uri)) => {
+                    let server = server
+                        .upgrade()
+                        .ok_or_else(|| anyhow::anyhow!("The server is shutting down"))?;
+                    read_table(&server, &context, uri, delimiter, sheet).await?
+                }
+                _ => {
+                    return Err(ServerError::InvalidParams {
+                        details: "Exactly one of csv or uri is required".to_string(),
+                    }
+                    .into())
+                }
+            };
+
+            let query = Query {
+                filters: parse_argument(&arguments, "filter")?,
+                columns: parse_argument(&arguments, "columns")?,
+                group_by: parse_argument(&arguments, "group_by")?,
+                aggregates: parse_argument(&arguments, "aggregate")?,
+            };
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .map_or(DEFAULT_LIMIT, |limit| limit as usize)
+                .clamp(1, MAX_LIMIT);
+
+            let (columns, rows) = query.run(&table)?;
+            let total = rows.len();
+            let rows: Vec<Map<String, Value>> = rows
+                .into_iter()
+                .take(limit)
+                .map(|row| columns.iter().cloned().zip(row).collect())
+                .collect();
+
+            let mut text = markdown_table(&columns, &rows);
+            let shown = PREVIEW_ROWS.min(rows.len());
+            if total > shown {
+                text.push_str(&format!(
+                    "\n\nShowing {} of {} rows; structuredContent has {}",
+                    shown,
+                    total,
+                    rows.len()
+                ));
+            }
+            Ok(
+                CallToolResult::text(text).with_structured_content(serde_json::json!({
+                    "columns": columns,
+                    "rows": rows,
+                    "totalRows": total,
+                    "truncated": total > rows.len()
+                })),
+            )
+        }
+    })
+    .with_output_schema(serde_json::json!({
+        "type": "object",
+        "properties": {
+            "columns": { "type": "array", "items": { "type": "string" } },
+            "rows": { "type": "array", "items": { "type": "object" } },
+            "totalRows": { "type": "integer" },
+            "truncated": { "type": "boolean" }
+        },
+        "required": ["columns", "rows", "totalRows", "truncated"]
+    }))
+}
+
+fn definition() -> Tool {
+    Tool {
+        name: "tabular".to_string(),
+        title: Some("Tabular Data".to_string()),
+        description: "Parses CSV, or an xlsx/xls/ods spreadsheet resource, and returns rows as JSON objects. Rows can be filtered, narrowed to some columns, or grouped and aggregated".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "csv": {
+                    "type": "string",
+                    "description": "CSV text with a header row"
+                },
+                "uri": {
+                    "type": "string",
+                    "description": "Resource to read instead: a text resource is parsed as CSV, a blob as a spreadsheet"
+                },
+                "delimiter": {
+                    "type": "string",
+                    "description": "Field separator (default ',', or tab for .tsv resources)"
+                },
+                "sheet": {
+                    "type": "string",
+                    "description": "Spreadsheet sheet to read (default the first)"
+                },
+                "columns": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Columns to return, in this order (default all)"
+                },
+                "filter": {
+                    "type": "array",
+                    "description": "Conditions every returned row meets",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "column": { "type": "string" },
+                            "op": {
+                                "type": "string",
+                                "enum": ["eq", "ne", "gt", "gte", "lt", "lte", "contains", "starts_with", "is_empty", "not_empty"]
+                            },
+                            "value": {}
+                        },
+                        "required": ["column", "op"]
+                    }
+                },
+                "group_by": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Columns to group rows by before aggregating"
+                },
+                "aggregate": {
+                    "type": "array",
+                    "description": "Values to compute per group, or over all rows without group_by. Each becomes a column named function_column, e.g. sum_amount",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "function": {
+                                "type": "string",
+                                "enum": ["count", "sum", "avg", "min", "max", "distinct"]
+                            },
+                            "column": { "type": "string" }
+                        },
+                        "required": ["function"]
+                    }
+                },
+                "limit": {
+                    "type": "integer",
+                    "description": "Most rows to return (default 100, at most 10000)"
+                }
+            }
+        }),
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(true),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    }
+}
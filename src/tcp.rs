@@ -0,0 +1,581 @@
+use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, Notify};
+use tracing::{info, warn};
+
+use crate::auth;
+use crate::buffer;
+use crate::envelope;
+use crate::error::ServerError;
+use crate::framing::{self, Frame};
+use crate::listener;
+use crate::msgpack;
+use crate::server::McpServer;
+use crate::session::{KeepAliveAction, KeepAliveConfig, Session};
+
+// Notifications buffered per detached session before the oldest are dropped
+const MAX_PENDING_MESSAGES: usize = 1024;
+
+type Connection = mpsc::UnboundedSender<Outgoing>;
+
+// How messages are framed on a connection, switched with
+// `transport/setEncoding`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WireEncoding {
+    Json,
+    MessagePack,
+}
+
+enum Outgoing {
+    Message(serde_json::Value),
+    // Messages queued after this one go out in the new encoding
+    Switch(WireEncoding),
+    // The session was resumed elsewhere; stop writing to this connection
+    Close,
+}
+
+struct TcpSession {
+    session: Session,
+    // Secret for `session/resume`. Unlike the session id, it is never
+    // logged or shown to anyone but the client.
+    resume_token: String,
+    state: Mutex<ConnectionState>,
+    // Signalled when the session expires, to drop its live connection
+    closed: Notify,
+}
+
+// Either attached to a live connection, or detached and buffering messages
+// until the client resumes or the resumption window runs out.
+struct ConnectionState {
+    connection: Option<Connection>,
+    detached_at: Option<Instant>,
+    pending: VecDeque<serde_json::Value>,
+}
+
+impl TcpSession {
+    fn deliver(&self, message: serde_json::Value) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(connection) = state.connection.as_ref() {
+            if connection.send(Outgoing::Message(message.clone())).is_ok() {
+                return;
+            }
+            state.connection = None;
+            state.detached_at = Some(Instant::now());
+        }
+        state.pending.push_back(message);
+        while state.pending.len() > MAX_PENDING_MESSAGES {
+            state.pending.pop_front();
+        }
+    }
+
+    // Detaches `connection` if it is still the session's. The client may
+    // already have resumed on another one, which stays attached. Returns
+    // whether it was detached.
+    fn detach(&self, connection: &Connection) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if !state
+            .connection
+            .as_ref()
+            .is_some_and(|current| current.same_channel(connection))
+        {
+            return false;
+        }
+        state.connection = None;
+        state.detached_at = Some(Instant::now());
+        true
+    }
+
+    // Attaches a new connection and hands it everything queued meanwhile.
+    // A connection still attached is told to close. Returns how many
+    // messages were queued.
+    fn attach(&self, connection: Connection) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let pending = state.pending.len();
+        for message in state.pending.drain(..) {
+            let _ = connection.send(Outgoing::Message(message));
+        }
+        if let Some(previous) = state.connection.take() {
+            if !previous.same_channel(&connection) {
+                let _ = previous.send(Outgoing::Close);
+            }
+        }
+        state.connection = Some(connection);
+        state.detached_at = None;
+        pending
+    }
+
+    fn attached_to(&self, connection: &Connection) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .connection
+            .as_ref()
+            .is_some_and(|current| current.same_channel(connection))
+    }
+}
+
+/// Line-delimited JSON-RPC over TCP. The initialize result carries a
+/// `sessionToken` in `_meta`; a client that drops can reconnect and send
+/// `session/resume` with that token to pick the session back up, including
+/// notifications sent while it was away. A connection can switch to
+/// length-prefixed MessagePack frames with `transport/setEncoding`.
+pub struct TcpTransport {
+    server: Arc<McpServer>,
+    keep_alive: KeepAliveConfig,
+    resumption_window: Duration,
+    sessions: Mutex<HashMap<String, Arc<TcpSession>>>,
+}
+
+impl TcpTransport {
+    pub fn new(
+        server: Arc<McpServer>,
+        keep_alive: KeepAliveConfig,
+        resumption_window: Duration,
+    ) -> Self {
+        Self {
+            server,
+            keep_alive,
+            resumption_window,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn serve(self: Arc<Self>, addr: &str) -> Result<()> {
+        let listener = listener::bind("tcp", addr).await?;
+
+        let transport = self.clone();
+        tokio::spawn(async move { transport.run_keep_alive().await });
+
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let transport = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = transport.handle_connection(stream, peer).await {
+                    warn!("TCP connection from {} failed: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    // Expires sessions that were detached for longer than the resumption
+    // window, and pings attached ones if keep-alive is on.
+    async fn run_keep_alive(&self) {
+        let period = if self.keep_alive.enabled() {
+            self.keep_alive.ping_interval
+        } else {
+            self.resumption_window
+        };
+        let mut interval = tokio::time::interval(period.max(Duration::from_secs(1)));
+        loop {
+            interval.tick().await;
+            let sessions: Vec<Arc<TcpSession>> =
+                self.sessions.lock().unwrap().values().cloned().collect();
+            for tcp_session in sessions {
+                let detached_at = tcp_session.state.lock().unwrap().detached_at;
+                let expired = match detached_at {
+                    Some(detached_at) => detached_at.elapsed() > self.resumption_window,
+                    None if self.keep_alive.enabled() => {
+                        match self.keep_alive.check(&tcp_session.session) {
+                            KeepAliveAction::Ping => {
+                                let timeout = self.keep_alive.ping_interval;
+                                let tcp_session = tcp_session.clone();
+                                tokio::spawn(
+                                    async move { tcp_session.session.ping(timeout).await },
+                                );
+                                false
+                            }
+                            KeepAliveAction::Expire => true,
+                            KeepAliveAction::None => false,
+                        }
+                    }
+                    None => false,
+                };
+                if expired {
+                    info!("Expiring TCP session {}", tcp_session.session.id);
+                    self.sessions
+                        .lock()
+                        .unwrap()
+                        .remove(&tcp_session.session.id);
+                    self.server.disconnect(&tcp_session.session.id);
+                    tcp_session.closed.notify_one();
+                }
+            }
+        }
+    }
+
+    async fn handle_connection(&self, stream: TcpStream, peer: SocketAddr) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let reader = BufReader::new(reader);
+        let (connection, mut outgoing) = mpsc::unbounded_channel::<Outgoing>();
+
+        tokio::spawn(async move {
+            let mut encoding = WireEncoding::Json;
+            while let Some(outgoing) = outgoing.recv().await {
+                let message = match outgoing {
+                    Outgoing::Message(message) => message,
+                    Outgoing::Switch(to) => {
+                        encoding = to;
+                        continue;
+                    }
+                    Outgoing::Close => {
+                        writer.shutdown().await?;
+                        break;
+                    }
+                };
+                match encoding {
+                    WireEncoding::Json => writer.write_all(&buffer::json_line(&message)?).await?,
+                    WireEncoding::MessagePack => {
+                        writer.write_all(&msgpack::frame(&message)).await?
+                    }
+                }
+                writer.flush().await?;
+            }
+            anyhow::Ok(())
+        });
+        let send = |message: serde_json::Value| {
+            let _ = connection.send(Outgoing::Message(message));
+        };
+
+        // Messages are read on their own task, so that the client's answer to
+        // a request the server makes mid-call (elicitation, sampling) gets
+        // through while the call waits
+        let (messages, mut incoming) = mpsc::channel(64);
+        let reader = tokio::spawn(read_messages(
+            reader,
+            connection.clone(),
+            self.server.max_message_bytes(),
+            messages,
+        ));
+        // Requests that arrived while another was being handled
+        let mut queued = VecDeque::new();
+        let mut tcp_session: Option<Arc<TcpSession>> = None;
+
+        loop {
+            let message = match queued.pop_front() {
+                Some(message) => message,
+                None => {
+                    let next = match &tcp_session {
+                        Some(current) => tokio::select! {
+                            next = incoming.recv() => next,
+                            _ = current.closed.notified() => {
+                                info!("Closing connection of expired TCP session {}", current.session.id);
+                                reader.abort();
+                                return Ok(());
+                            }
+                        },
+                        None => incoming.recv().await,
+                    };
+                    match next {
+                        Some(message) => message,
+                        None => break,
+                    }
+                }
+            };
+
+            if message["method"] == "session/resume" {
+                send(self.resume(&message, &connection, &mut tcp_session));
+                continue;
+            }
+            // The session was resumed on another connection; this one has
+            // no say in it any more
+            if tcp_session
+                .as_ref()
+                .is_some_and(|current| !current.attached_to(&connection))
+            {
+                info!("Closing a TCP connection whose session was resumed elsewhere");
+                reader.abort();
+                return Ok(());
+            }
+
+            let current = match &tcp_session {
+                Some(current) => current.clone(),
+                None => {
+                    let principal = match self.authenticate(&message) {
+                        Ok(principal) => principal,
+                        Err(response) => {
+                            warn!("Rejected an invalid auth token from {}", peer);
+                            send(response);
+                            continue;
+                        }
+                    };
+                    let opened = self.open_session(connection.clone(), peer, principal);
+                    tcp_session = Some(opened.clone());
+                    opened
+                }
+            };
+
+            let initialize = message["method"] == "initialize";
+            let handling = self.server.handle_value(&current.session, message);
+            tokio::pin!(handling);
+            let response = loop {
+                tokio::select! {
+                    response = &mut handling => break response,
+                    Some(next) = incoming.recv() => {
+                        if envelope::is_out_of_band(&next) {
+                            self.server.handle_value(&current.session, next).await;
+                        } else {
+                            queued.push_back(next);
+                        }
+                    }
+                }
+            };
+            if let Some(response) = response {
+                let mut response = serde_json::to_value(&response)?;
+                if initialize && response["result"].is_object() {
+                    response["result"]["_meta"]["sessionToken"] =
+                        serde_json::Value::String(current.resume_token.clone());
+                }
+                send(response);
+            }
+        }
+
+        if let Some(tcp_session) = tcp_session {
+            if tcp_session.detach(&connection) {
+                info!(
+                    "TCP session {} disconnected, resumable for {:?}",
+                    tcp_session.session.id, self.resumption_window
+                );
+            }
+        }
+
+        reader.await?
+    }
+
+    fn resume(
+        &self,
+        message: &serde_json::Value,
+        connection: &Connection,
+        tcp_session: &mut Option<Arc<TcpSession>>,
+    ) -> serde_json::Value {
+        let id = message["id"].clone();
+        let resumed = message["params"]["sessionToken"]
+            .as_str()
+            .and_then(|token| self.find_by_token(token));
+
+        match resumed {
+            Some(resumed) => {
+                // The connection lets go of the session it had, which can
+                // in turn be resumed until it expires
+                if let Some(previous) = tcp_session.as_ref() {
+                    if !Arc::ptr_eq(previous, &resumed) && previous.detach(connection) {
+                        info!(
+                            "TCP session {} detached, resumable for {:?}",
+                            previous.session.id, self.resumption_window
+                        );
+                    }
+                }
+                let pending = resumed.attach(connection.clone());
+                resumed.session.touch();
+                info!(
+                    "Resumed TCP session {} with {} pending messages",
+                    resumed.session.id, pending
+                );
+                *tcp_session = Some(resumed);
+                serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": { "resumed": true, "pendingMessages": pending }
+                })
+            }
+            None => serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": ServerError::InvalidParams {
+                    details: "Unknown or expired session token".to_string(),
+                }
+                .to_mcp_error()
+            }),
+        }
+    }
+
+    // Every token is compared in full, so the time taken doesn't tell how
+    // close a guess was
+    fn find_by_token(&self, token: &str) -> Option<Arc<TcpSession>> {
+        let mut found = None;
+        for tcp_session in self.sessions.lock().unwrap().values() {
+            if auth::constant_time_eq(token, &tcp_session.resume_token) {
+                found = Some(tcp_session.clone());
+            }
+        }
+        found
+    }
+
+    // The principal the message that opens a session authenticates as,
+    // from `_meta.authToken` in its params. No token is anonymous; a wrong
+    // one is answered with the error.
+    fn authenticate(
+        &self,
+        message: &serde_json::Value,
+    ) -> Result<Option<String>, serde_json::Value> {
+        let Some(token) = message["params"]["_meta"]["authToken"].as_str() else {
+            return Ok(None);
+        };
+        match self.server.authenticate(token) {
+            Some(principal) => Ok(Some(principal)),
+            None => Err(serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": message["id"],
+                "error": ServerError::InvalidRequest {
+                    details: "Invalid auth token".to_string(),
+                }
+                .to_mcp_error()
+            })),
+        }
+    }
+
+    fn open_session(
+        &self,
+        connection: Connection,
+        peer: SocketAddr,
+        principal: Option<String>,
+    ) -> Arc<TcpSession> {
+        let (session, mut outbound) = self.server.connect();
+        session.set_remote_addr(peer.to_string());
+        if let Some(principal) = principal {
+            session.set_principal(principal);
+        }
+        let session_id = session.id.clone();
+        let tcp_session = Arc::new(TcpSession {
+            session,
+            resume_token: uuid::Uuid::new_v4().simple().to_string(),
+            state: Mutex::new(ConnectionState {
+                connection: Some(connection),
+                detached_at: None,
+                pending: VecDeque::new(),
+            }),
+            closed: Notify::new(),
+        });
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session_id.clone(), tcp_session.clone());
+        info!("Opened TCP session {}", session_id);
+
+        let weak: Weak<TcpSession> = Arc::downgrade(&tcp_session);
+        tokio::spawn(async move {
+            while let Some(message) = outbound.recv().await {
+                let Some(tcp_session) = weak.upgrade() else {
+                    break;
+                };
+                tcp_session.deliver(message);
+            }
+        });
+
+        tcp_session
+    }
+}
+
+// Reads and decodes the connection's messages until it closes, answering
+// malformed ones and `transport/setEncoding` itself since both depend on
+// the framing
+async fn read_messages(
+    mut reader: BufReader<OwnedReadHalf>,
+    connection: Connection,
+    max: usize,
+    messages: mpsc::Sender<serde_json::Value>,
+) -> Result<()> {
+    let send = |message: serde_json::Value| {
+        let _ = connection.send(Outgoing::Message(message));
+    };
+    let mut encoding = WireEncoding::Json;
+    let mut line = String::new();
+    let mut packed = Vec::new();
+
+    loop {
+        line.clear();
+        let frame = match encoding {
+            WireEncoding::Json => framing::read_message(&mut reader, &mut line, max).await?,
+            WireEncoding::MessagePack => msgpack::read_frame(&mut reader, &mut packed, max).await?,
+        };
+        match frame {
+            Frame::Eof => return Ok(()),
+            Frame::Message => {}
+            Frame::TooLong(_) | Frame::InvalidUtf8 => {
+                warn!("Skipped a malformed message");
+                if let Some(response) = framing::error_response(&frame, max) {
+                    send(serde_json::to_value(response)?);
+                }
+                continue;
+            }
+        }
+
+        let decoded = match encoding {
+            WireEncoding::Json if line.trim().is_empty() => continue,
+            WireEncoding::Json => envelope::parse(&line),
+            WireEncoding::MessagePack => msgpack::decode(&packed).map_err(|e| {
+                Box::new(envelope::error_response(
+                    None,
+                    ServerError::ParseError {
+                        details: e.to_string(),
+                    },
+                ))
+            }),
+        };
+        let message = match decoded {
+            Ok(message) => message,
+            Err(response) => {
+                warn!("Failed to parse message");
+                send(serde_json::to_value(*response)?);
+                continue;
+            }
+        };
+
+        if message["method"] == "transport/setEncoding" {
+            let (response, switched) = set_encoding(&message);
+            send(response);
+            if let Some(to) = switched {
+                info!("TCP connection switched to {:?}", to);
+                let _ = connection.send(Outgoing::Switch(to));
+                // A JSON frame ends at its closing brace, so the newline
+                // after the request would be taken for a length prefix
+                if encoding == WireEncoding::Json {
+                    let ending = reader
+                        .buffer()
+                        .iter()
+                        .take_while(|&&b| b == b'\r' || b == b'\n')
+                        .count();
+                    reader.consume(ending);
+                }
+                encoding = to;
+            }
+            continue;
+        }
+
+        if messages.send(message).await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+// Answers `transport/setEncoding` (`{"encoding": "json" | "msgpack"}`). The
+// answer goes out in the old encoding, and everything after it in the new.
+fn set_encoding(message: &serde_json::Value) -> (serde_json::Value, Option<WireEncoding>) {
+    let id = message["id"].clone();
+    let to = match message["params"]["encoding"].as_str() {
+        Some("json") => WireEncoding::Json,
+        Some("msgpack") => WireEncoding::MessagePack,
+        _ => {
+            let error = ServerError::InvalidParams {
+                details: "encoding must be \"json\" or \"msgpack\"".to_string(),
+            };
+            let response = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": error.to_mcp_error()
+            });
+            return (response, None);
+        }
+    };
+    let response = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "result": { "encoding": message["params"]["encoding"] }
+    });
+    (response, Some(to))
+}
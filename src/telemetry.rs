@@ -0,0 +1,211 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+
+use crate::events::ServerEvent;
+use crate::http_client::{self, HttpUrl};
+use crate::server::McpServer;
+
+// Latency histogram bucket upper bounds, in milliseconds
+const BUCKETS_MS: &[u64] = &[1, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+// Where calls to tools that aren't registered are counted
+const UNKNOWN_TOOL: &str = "unknown";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    // Rewritten with the latest report every interval
+    pub report_path: Option<PathBuf>,
+    // Each report is POSTed here as JSON
+    pub endpoint: Option<String>,
+    pub interval_secs: u64,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            report_path: None,
+            endpoint: None,
+            interval_secs: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ToolUsage {
+    calls: u64,
+    errors: u64,
+    total_ms: u64,
+    max_ms: u64,
+    // Non-cumulative counts per bucket; the last slot is over the largest
+    buckets: [u64; BUCKETS_MS.len() + 1],
+}
+
+impl ToolUsage {
+    // The bucket bound `fraction` of calls finished within, or the slowest
+    // call's time if that is past the last bucket
+    fn percentile(&self, fraction: f64) -> u64 {
+        let wanted = (self.calls as f64 * fraction).ceil() as u64;
+        let mut seen = 0;
+        for (bound, count) in BUCKETS_MS.iter().zip(&self.buckets) {
+            seen += count;
+            if seen >= wanted {
+                return (*bound).min(self.max_ms);
+            }
+        }
+        self.max_ms
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ToolReport {
+    calls: u64,
+    errors: u64,
+    mean_ms: u64,
+    p50_ms: u64,
+    p95_ms: u64,
+    max_ms: u64,
+}
+
+/// Opt-in usage statistics: how often each tool is called, how often it
+/// fails and how long it takes. Only tool names, counts and timings are
+/// kept, never arguments, results, error text or who called.
+pub struct Telemetry {
+    config: TelemetryConfig,
+    endpoint: Option<HttpUrl>,
+    // Lets an endpoint tell restarts apart, since counts start from zero
+    instance: String,
+    since: u64,
+    tools: Mutex<BTreeMap<String, ToolUsage>>,
+}
+
+impl Telemetry {
+    pub fn new(config: &TelemetryConfig) -> Result<Self> {
+        if config.report_path.is_none() && config.endpoint.is_none() {
+            anyhow::bail!("telemetry needs a report_path, an endpoint or both");
+        }
+        Ok(Self {
+            config: config.clone(),
+            endpoint: config.endpoint.as_deref().map(HttpUrl::parse).transpose()?,
+            instance: uuid::Uuid::new_v4().to_string(),
+            since: unix_now(),
+            tools: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    fn record(&self, tool: &str, duration: Duration, failed: bool) {
+        let ms = duration.as_millis() as u64;
+        let mut tools = self.tools.lock().unwrap();
+        let usage = tools.entry(tool.to_string()).or_default();
+        usage.calls += 1;
+        if failed {
+            usage.errors += 1;
+        }
+        usage.total_ms += ms;
+        usage.max_ms = usage.max_ms.max(ms);
+        let bucket = BUCKETS_MS
+            .iter()
+            .position(|bound| ms <= *bound)
+            .unwrap_or(BUCKETS_MS.len());
+        usage.buckets[bucket] += 1;
+    }
+
+    // Totals since the server started
+    pub fn report(&self) -> serde_json::Value {
+        let usage = self.tools.lock().unwrap();
+        let tools: BTreeMap<&str, ToolReport> = usage
+            .iter()
+            .map(|(name, usage)| {
+                let report = ToolReport {
+                    calls: usage.calls,
+                    errors: usage.errors,
+                    mean_ms: usage.total_ms / usage.calls.max(1),
+                    p50_ms: usage.percentile(0.5),
+                    p95_ms: usage.percentile(0.95),
+                    max_ms: usage.max_ms,
+                };
+                (name.as_str(), report)
+            })
+            .collect();
+        serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "instance": self.instance,
+            "since": self.since,
+            "generatedAt": unix_now(),
+            "tools": tools
+        })
+    }
+
+    // Counts tool calls from server events, until the bus closes. The name
+    // comes from the client, so calls to tools `server` doesn't have are
+    // counted as unknown rather than reported under whatever was sent.
+    pub async fn follow(
+        self: Arc<Self>,
+        server: Weak<McpServer>,
+        mut events: broadcast::Receiver<ServerEvent>,
+    ) {
+        loop {
+            match events.recv().await {
+                Ok(ServerEvent::ToolCallCompleted {
+                    tool,
+                    duration,
+                    error,
+                    ..
+                }) => {
+                    let known = server
+                        .upgrade()
+                        .is_some_and(|server| server.has_tool(&tool));
+                    let tool = if known { tool.as_str() } else { UNKNOWN_TOOL };
+                    self.record(tool, duration, error.is_some())
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => {
+                    debug!("Telemetry missed {} events", missed);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    // Writes or sends a report every `interval_secs`
+    pub async fn run(self: Arc<Self>) {
+        let period = Duration::from_secs(self.config.interval_secs.max(1));
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.publish().await {
+                warn!("Failed to publish telemetry: {}", e);
+            }
+        }
+    }
+
+    async fn publish(&self) -> Result<()> {
+        let report = self.report();
+        if let Some(path) = &self.config.report_path {
+            // Renamed into place so readers never see half a report
+            let temporary = path.with_extension("json.tmp");
+            std::fs::write(&temporary, serde_json::to_string_pretty(&report)?)
+                .and_then(|_| std::fs::rename(&temporary, path))
+                .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+        }
+        if let Some(endpoint) = &self.endpoint {
+            http_client::post_json(endpoint, &[], &report).await?;
+        }
+        Ok(())
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
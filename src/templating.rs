@@ -0,0 +1,183 @@
+use minijinja::syntax::SyntaxConfig;
+use minijinja::value::{Serde, Value};
+use minijinja::{Environment, ErrorKind, UndefinedBehavior};
+use std::io::{self, Write};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::error::ServerError;
+use crate::tools::RegisteredTool;
+use crate::types::{CallToolResult, Tool, ToolAnnotations};
+
+// Limits that keep a template from running away with the server's time.
+// Fuel is spent per instruction, so loops and recursion both run out of
+// it. None of them bounds memory: an instruction like `s ~ s` can double a
+// string, and values built that way are only capped when written out.
+const MAX_TEMPLATE_BYTES: usize = 100_000;
+const MAX_OUTPUT_BYTES: usize = 1_000_000;
+const FUEL: u64 = 1_000_000;
+const RECURSION_LIMIT: usize = 64;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Collects rendered output, failing once it passes `MAX_OUTPUT_BYTES`.
+#[derive(Default)]
+struct CappedOutput {
+    bytes: Vec<u8>,
+    overflowed: bool,
+}
+
+impl Write for CappedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.bytes.len() + buf.len() > MAX_OUTPUT_BYTES {
+            self.overflowed = true;
+            return Err(io::Error::other("output limit reached"));
+        }
+        self.bytes.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn invalid(details: String) -> anyhow::Error {
+    ServerError::InvalidParams { details }.into()
+}
+
+// Renders on a thread of its own, giving up after `TIMEOUT`. A template
+// still rendering then is left to run out of fuel.
+pub fn render(
+    template: &str,
+    context: &serde_json::Value,
+    strict: bool,
+    html: bool,
+) -> anyhow::Result<String> {
+    let (sender, receiver) = mpsc::channel();
+    let template = template.to_string();
+    let context = context.clone();
+    std::thread::spawn(move || {
+        let _ = sender.send(render_now(&template, &context, strict, html));
+    });
+    receiver.recv_timeout(TIMEOUT).map_err(|_| {
+        invalid(format!(
+            "The template took longer than {:?} to render",
+            TIMEOUT
+        ))
+    })?
+}
+
+// Templates can't include or import others: there is no loader, so they
+// see nothing but `context`
+fn render_now(
+    template: &str,
+    context: &serde_json::Value,
+    strict: bool,
+    html: bool,
+) -> anyhow::Result<String> {
+    if template.len() > MAX_TEMPLATE_BYTES {
+        return Err(invalid(format!(
+            "The template is {} bytes; the limit is {}",
+            template.len(),
+            MAX_TEMPLATE_BYTES
+        )));
+    }
+    let mut env = Environment::new();
+    env.set_fuel(Some(FUEL));
+    env.set_recursion_limit(RECURSION_LIMIT);
+    // As Ansible renders configs: a block tag's own newline is dropped, the
+    // file's last one kept
+    env.set_syntax(
+        SyntaxConfig::builder()
+            .trim_blocks(true)
+            .keep_trailing_newline(true)
+            .build()?,
+    );
+    if strict {
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+    }
+    env.set_auto_escape_callback(move |_| {
+        if html {
+            minijinja::AutoEscape::Html
+        } else {
+            minijinja::AutoEscape::None
+        }
+    });
+
+    let compiled = env
+        .template_from_str(template)
+        .map_err(|e| invalid(format!("Invalid template: {}", e)))?;
+    let mut output = CappedOutput::default();
+    let rendered = compiled.render_captured_to(Value::from(Serde(context)), &mut output);
+    match rendered {
+        Ok(_) => Ok(String::from_utf8(output.bytes)?),
+        Err(_) if output.overflowed => Err(invalid(format!(
+            "The output passed the {} byte limit",
+            MAX_OUTPUT_BYTES
+        ))),
+        Err(e) if e.kind() == ErrorKind::OutOfFuel => Err(invalid(
+            "The template took too many steps to render".to_string(),
+        )),
+        Err(e) => Err(invalid(format!("Can't render the template: {}", e))),
+    }
+}
+
+pub fn tool() -> RegisteredTool {
+    let tool = Tool {
+        name: "render_template".to_string(),
+        title: Some("Render Template".to_string()),
+        description: "Renders a Jinja2/Tera-style template with a JSON context, for generating configs and boilerplate".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "template": {
+                    "type": "string",
+                    "description": "The template, e.g. 'Hello {{ name }}!' or '{% for host in hosts %}{{ host }}\\n{% endfor %}'"
+                },
+                "context": {
+                    "type": "object",
+                    "description": "Variables the template can use"
+                },
+                "strict": {
+                    "type": "boolean",
+                    "description": "Fail on undefined variables instead of rendering them empty (default false)"
+                },
+                "escape_html": {
+                    "type": "boolean",
+                    "description": "HTML-escape interpolated values (default false)"
+                }
+            },
+            "required": ["template"]
+        }),
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(true),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    };
+
+    RegisteredTool::blocking(tool, |arguments, _| {
+        let template = arguments
+            .get("template")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'template' argument"))?;
+        let context = arguments
+            .get("context")
+            .cloned()
+            .unwrap_or_else(|| serde_json::json!({}));
+        let flag = |name: &str| {
+            arguments
+                .get(name)
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false)
+        };
+        Ok(CallToolResult::text(render(
+            template,
+            &context,
+            flag("strict"),
+            flag("escape_html"),
+        )?))
+    })
+}
@@ -0,0 +1,385 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+use crate::archive;
+use crate::config::{ConcurrencyLimit, OnLimit};
+use crate::markdown;
+use crate::notification::{Notification, Notifier};
+use crate::roots::RootScope;
+use crate::sampling::{
+    self, CreateMessageRequest, CreateMessageResult, SamplingConfig, SamplingError, SamplingStream,
+};
+use crate::session::{Requester, SessionState};
+use crate::templating;
+use crate::transform::OutputTransform;
+use crate::types::{CallToolResult, ClientCapabilities, Content, Icon, Meta, Tool};
+
+pub type ToolFuture = Pin<Box<dyn Future<Output = Result<CallToolResult>> + Send>>;
+pub type ToolHandler =
+    Arc<dyn Fn(HashMap<String, serde_json::Value>, ToolContext) -> ToolFuture + Send + Sync>;
+pub type BlockingHandler = Arc<
+    dyn Fn(HashMap<String, serde_json::Value>, ToolContext) -> Result<CallToolResult> + Send + Sync,
+>;
+
+/// What a tool handler gets besides its arguments.
+#[derive(Clone)]
+pub struct ToolContext {
+    // For looking up per-session state kept by a plugin
+    pub session_id: String,
+    pub notifier: Notifier,
+    // The request's `_meta`, empty if the client sent none
+    pub meta: Meta,
+    // What the calling client supports, so tools can skip features it lacks
+    pub client_capabilities: ClientCapabilities,
+    // Chunks sent with `yield_partial`, which doubles as the progress value
+    pub partials_sent: Arc<AtomicU64>,
+    // For requests to the calling client, such as sampling
+    pub requester: Requester,
+    pub sampling: SamplingConfig,
+    // Where file tools may go; see `RootScope::resolve`
+    pub roots: RootScope,
+    // The session's profile, which limits the resources
+    // `McpServer::read_resource_for` reads
+    pub profile: Option<String>,
+    // State kept for the calling session; see `SessionState`
+    pub state: SessionState,
+    // Blocking threads CPU-bound tools take turns on; see
+    // `RegisteredTool::blocking`
+    pub cpu_workers: Arc<Semaphore>,
+}
+
+impl ToolContext {
+    pub fn progress_token(&self) -> Option<&serde_json::Value> {
+        self.meta.get("progressToken")
+    }
+
+    // Reports progress if the client asked for it with a `progressToken`;
+    // does nothing otherwise.
+    pub fn report_progress(
+        &self,
+        progress: f64,
+        total: Option<f64>,
+        message: Option<String>,
+    ) -> Result<()> {
+        match self.progress_token() {
+            Some(progress_token) => self.notifier.notify(Notification::Progress {
+                progress_token: progress_token.clone(),
+                progress,
+                total,
+                message,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // Streams a piece of output to the client ahead of the final result, as
+    // a progress notification carrying the text in its `message`. Like
+    // `report_progress`, it does nothing unless the client sent a
+    // `progressToken`, so the final result should still contain everything.
+    pub fn yield_partial(&self, text: impl Into<String>) -> Result<()> {
+        let sent = self.partials_sent.fetch_add(1, Ordering::Relaxed) + 1;
+        self.report_progress(sent as f64, None, Some(text.into()))
+    }
+
+    // Asks the calling client's model for a message, falling back as
+    // configured in `sampling` if the client rejects the request.
+    pub async fn sample(
+        &self,
+        request: CreateMessageRequest,
+    ) -> Result<CreateMessageResult, SamplingError> {
+        if self.client_capabilities.sampling.is_none() {
+            return Err(SamplingError::Unsupported);
+        }
+        sampling::create_message(&self.requester, &self.sampling, request).await
+    }
+
+    // Like `sample`, but yields the model's output as the client streams it
+    pub fn sample_streaming(
+        &self,
+        request: CreateMessageRequest,
+    ) -> Result<SamplingStream, SamplingError> {
+        if self.client_capabilities.sampling.is_none() {
+            return Err(SamplingError::Unsupported);
+        }
+        Ok(sampling::create_message_streaming(
+            self.requester.clone(),
+            self.sampling.clone(),
+            request,
+        ))
+    }
+}
+
+/// A tool definition together with the code that runs it.
+#[derive(Clone)]
+pub struct RegisteredTool {
+    pub tool: Tool,
+    pub handler: ToolHandler,
+    pub output_transforms: Vec<OutputTransform>,
+    pub version: Option<String>,
+    pub deprecation: Option<Deprecation>,
+    // Named group such as "fs" or "admin", for enabling tools together
+    pub group: Option<String>,
+    pub concurrency_limit: Option<ConcurrencyLimit>,
+}
+
+/// Marks a tool as on its way out. It keeps working, but results carry the
+/// notice in `_meta.deprecation` so agents can migrate.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Deprecation {
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replaced_by: Option<String>,
+}
+
+impl RegisteredTool {
+    pub fn new<F, Fut>(tool: Tool, handler: F) -> Self
+    where
+        F: Fn(HashMap<String, serde_json::Value>, ToolContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<CallToolResult>> + Send + 'static,
+    {
+        Self {
+            tool,
+            handler: Arc::new(move |arguments, context| Box::pin(handler(arguments, context))),
+            output_transforms: Vec::new(),
+            version: None,
+            deprecation: None,
+            group: None,
+            concurrency_limit: None,
+        }
+    }
+
+    // A CPU-bound tool (hashing, parsing, compression), run on a blocking
+    // thread so it doesn't stall other requests. At most `cpu_workers` such
+    // calls run at once.
+    pub fn blocking<F>(tool: Tool, handler: F) -> Self
+    where
+        F: Fn(HashMap<String, serde_json::Value>, ToolContext) -> Result<CallToolResult>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let run: BlockingHandler = Arc::new(handler);
+        Self::new(tool, move |arguments, context| {
+            let run = run.clone();
+            async move {
+                // The worker is held by the thread, so a call that is
+                // cancelled still counts until the thread finishes
+                let worker = context.cpu_workers.clone().acquire_owned().await?;
+                tokio::task::spawn_blocking(move || {
+                    let _worker = worker;
+                    run(arguments, context)
+                })
+                .await?
+            }
+        })
+    }
+
+    // Caps how many calls to this tool run at once
+    pub fn with_max_concurrency(mut self, max_concurrency: usize, on_limit: OnLimit) -> Self {
+        self.concurrency_limit = Some(ConcurrencyLimit {
+            max_concurrency,
+            on_limit,
+        });
+        self
+    }
+
+    pub fn in_group(mut self, group: impl Into<String>) -> Self {
+        self.group = Some(group.into());
+        self
+    }
+
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    pub fn deprecated(mut self, message: impl Into<String>, replaced_by: Option<&str>) -> Self {
+        self.deprecation = Some(Deprecation {
+            message: message.into(),
+            replaced_by: replaced_by.map(str::to_string),
+        });
+        self
+    }
+
+    // The definition as listed to clients, with version and deprecation
+    // status in `_meta`
+    pub fn listed(&self) -> Tool {
+        let mut tool = self.tool.clone();
+        if self.version.is_none() && self.deprecation.is_none() {
+            return tool;
+        }
+        let meta = tool.meta.get_or_insert_with(Meta::new);
+        if let Some(version) = &self.version {
+            meta.insert("version".to_string(), serde_json::json!(version));
+        }
+        if let Some(deprecation) = &self.deprecation {
+            meta.insert("deprecated".to_string(), serde_json::json!(true));
+            meta.insert("deprecation".to_string(), serde_json::json!(deprecation));
+        }
+        tool
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.tool.title = Some(title.into());
+        self
+    }
+
+    pub fn with_icon(mut self, icon: Icon) -> Self {
+        self.tool.icons.push(icon);
+        self
+    }
+
+    // Marks the tool as making changes that can't be undone. With a
+    // `confirmation` policy configured, each call needs approval first.
+    pub fn destructive(mut self) -> Self {
+        let annotations = self.tool.annotations.get_or_insert_with(Default::default);
+        annotations.read_only_hint = Some(false);
+        annotations.destructive_hint = Some(true);
+        self
+    }
+
+    // Declares the shape of the tool's `structuredContent`. Results that
+    // don't match are replaced by an error result.
+    pub fn with_output_schema(mut self, schema: serde_json::Value) -> Self {
+        self.tool.output_schema = Some(schema);
+        self
+    }
+
+    // Post-processes this tool's text output; transforms run in the order added
+    pub fn with_output_transform(mut self, transform: OutputTransform) -> Self {
+        self.output_transforms.push(transform);
+        self
+    }
+}
+
+/// Returned by a tool handler for failures worth retrying (timeouts,
+/// temporarily unavailable backends). See `RetryLayer`.
+#[derive(Debug)]
+pub struct RetryableError(pub String);
+
+impl std::fmt::Display for RetryableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for RetryableError {}
+
+impl CallToolResult {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            content: vec![Content::text(text)],
+            is_error: false,
+            structured_content: None,
+            meta: None,
+        }
+    }
+
+    // Machine-readable output alongside the text, following the tool's
+    // output schema if it has one
+    pub fn with_structured_content(mut self, value: serde_json::Value) -> Self {
+        self.structured_content = Some(value);
+        self
+    }
+
+    // Attaches a `_meta` entry to the result
+    pub fn with_meta(mut self, key: &str, value: serde_json::Value) -> Self {
+        self.meta
+            .get_or_insert_with(Meta::new)
+            .insert(key.to_string(), value);
+        self
+    }
+}
+
+pub fn builtin_tools() -> Vec<RegisteredTool> {
+    vec![
+        echo(),
+        add(),
+        markdown::tool(),
+        archive::tool(),
+        templating::tool(),
+    ]
+}
+
+fn echo() -> RegisteredTool {
+    let tool = Tool {
+        name: "echo".to_string(),
+        title: Some("Echo".to_string()),
+        description: "Echoes back the input message".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "message": {
+                    "type": "string",
+                    "description": "The message to echo"
+                }
+            },
+            "required": ["message"]
+        }),
+        output_schema: None,
+        annotations: None,
+        icons: Vec::new(),
+        meta: None,
+    };
+
+    RegisteredTool::new(tool, |arguments, _| async move {
+        let message = arguments
+            .get("message")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'message' argument"))?;
+        Ok(CallToolResult::text(format!("Echo: {}", message)))
+    })
+}
+
+fn add() -> RegisteredTool {
+    let tool = Tool {
+        name: "add".to_string(),
+        title: Some("Add Numbers".to_string()),
+        description: "Adds two numbers together".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "a": {
+                    "type": "number",
+                    "description": "First number"
+                },
+                "b": {
+                    "type": "number",
+                    "description": "Second number"
+                }
+            },
+            "required": ["a", "b"]
+        }),
+        output_schema: None,
+        annotations: None,
+        icons: Vec::new(),
+        meta: None,
+    };
+
+    RegisteredTool::new(tool, |arguments, _| async move {
+        let a = arguments
+            .get("a")
+            .and_then(|v| v.as_f64())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'a' argument"))?;
+        let b = arguments
+            .get("b")
+            .and_then(|v| v.as_f64())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'b' argument"))?;
+        Ok(CallToolResult::text(format!("{} + {} = {}", a, b, a + b))
+            .with_structured_content(serde_json::json!({ "sum": a + b })))
+    })
+    .with_output_schema(serde_json::json!({
+        "type": "object",
+        "properties": {
+            "sum": { "type": "number" }
+        },
+        "required": ["sum"]
+    }))
+}
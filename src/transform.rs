@@ -0,0 +1,143 @@
+use serde::Deserialize;
+
+use crate::types::{CallToolResult, Content};
+
+/// A rewrite applied to the text content of a tool's result before it is
+/// sent. Configured per tool, either in code with
+/// `RegisteredTool::with_output_transform` or under `output_transforms` in
+/// the config file, e.g. `["strip_ansi", {"truncate": 2000}]`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputTransform {
+    // Keep at most this many characters
+    Truncate(usize),
+    // Remove terminal colour and cursor escape sequences
+    StripAnsi,
+    // Turn tab- or space-aligned tables into markdown tables
+    TablesToMarkdown,
+}
+
+impl OutputTransform {
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            OutputTransform::Truncate(max_chars) => truncate(text, *max_chars),
+            OutputTransform::StripAnsi => strip_ansi(text),
+            OutputTransform::TablesToMarkdown => tables_to_markdown(text),
+        }
+    }
+}
+
+/// Runs every transform, in order, over each text item in the result.
+pub fn apply_all(result: &mut CallToolResult, transforms: &[OutputTransform]) {
+    for content in &mut result.content {
+        if let Content::Text { text, .. } = content {
+            for transform in transforms {
+                *text = transform.apply(text);
+            }
+        }
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    let total = text.chars().count();
+    if total <= max_chars {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(max_chars).collect();
+    format!("{}\n[truncated {} characters]", kept, total - max_chars)
+}
+
+fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters, then a final byte in @..~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: runs until BEL or ESC \
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            // Any other escape is two characters long
+            _ => {}
+        }
+    }
+    stripped
+}
+
+// Splits a line into cells on tabs, or failing that on runs of two or more
+// spaces. Lines with a single cell aren't table rows.
+fn table_cells(line: &str) -> Option<Vec<String>> {
+    let cells: Vec<String> = if line.contains('\t') {
+        line.split('\t')
+            .map(|cell| cell.trim().to_string())
+            .collect()
+    } else {
+        line.trim()
+            .split("  ")
+            .map(str::trim)
+            .filter(|cell| !cell.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+    (cells.len() > 1).then_some(cells)
+}
+
+// A table is two or more consecutive lines with the same number of cells;
+// the first becomes the header row.
+fn tables_to_markdown(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut output = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let Some(header) = table_cells(lines[index]) else {
+            output.push(lines[index].to_string());
+            index += 1;
+            continue;
+        };
+        let mut rows = vec![header];
+        while let Some(row) = lines
+            .get(index + rows.len())
+            .and_then(|line| table_cells(line))
+        {
+            if row.len() != rows[0].len() {
+                break;
+            }
+            rows.push(row);
+        }
+        if rows.len() < 2 {
+            output.push(lines[index].to_string());
+            index += 1;
+            continue;
+        }
+
+        index += rows.len();
+        let escape = |cell: &String| cell.replace('|', "\\|");
+        for (row_index, row) in rows.iter().enumerate() {
+            let cells: Vec<String> = row.iter().map(escape).collect();
+            output.push(format!("| {} |", cells.join(" | ")));
+            if row_index == 0 {
+                output.push(format!("|{}", " --- |".repeat(row.len())));
+            }
+        }
+    }
+    output.join("\n")
+}
@@ -0,0 +1,362 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use crate::error::ServerError;
+use crate::http_client::{self, HttpUrl};
+use crate::tools::RegisteredTool;
+use crate::types::{CallToolResult, Tool, ToolAnnotations};
+
+// Longer texts are refused rather than sent, since providers bill by the
+// character
+const MAX_TEXT_CHARS: usize = 50_000;
+const DEEPL_FREE_URL: &str = "https://api-free.deepl.com/v2/translate";
+const DEEPL_PRO_URL: &str = "https://api.deepl.com/v2/translate";
+const OPENAI_URL: &str = "https://api.openai.com/v1/chat/completions";
+const DEFAULT_OPENAI_MODEL: &str = "gpt-4o-mini";
+
+pub type TranslateFuture<'a> = Pin<Box<dyn Future<Output = Result<Translation>> + Send + 'a>>;
+
+pub struct Translation {
+    pub text: String,
+    // Lowercase, e.g. "en", when the provider reports it
+    pub detected_source_language: Option<String>,
+}
+
+/// A translation service. Languages are ISO 639-1 codes such as "de",
+/// optionally with a region, such as "pt-BR".
+pub trait TranslationProvider: Send + Sync {
+    // Without `source`, the provider detects the language
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        target: &'a str,
+        source: Option<&'a str>,
+    ) -> TranslateFuture<'a>;
+
+    fn name(&self) -> &'static str;
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "provider", rename_all = "lowercase")]
+pub enum TranslationConfig {
+    Deepl {
+        // Defaults to the free or pro API, whichever the key is for
+        #[serde(default)]
+        url: Option<String>,
+        #[serde(default)]
+        api_key: Option<String>,
+        #[serde(default)]
+        api_key_env: Option<String>,
+    },
+    LibreTranslate {
+        // The `/translate` endpoint, e.g. "http://localhost:5000/translate"
+        url: String,
+        #[serde(default)]
+        api_key: Option<String>,
+        #[serde(default)]
+        api_key_env: Option<String>,
+    },
+    Openai {
+        // Any OpenAI-style `/v1/chat/completions` endpoint
+        #[serde(default)]
+        url: Option<String>,
+        #[serde(default)]
+        model: Option<String>,
+        #[serde(default)]
+        api_key: Option<String>,
+        #[serde(default)]
+        api_key_env: Option<String>,
+    },
+}
+
+// The key given in the config file, or else read from the environment
+// variable it names
+fn api_key(api_key: &Option<String>, api_key_env: &Option<String>) -> Result<Option<String>> {
+    if let Some(key) = api_key {
+        return Ok(Some(key.clone()));
+    }
+    match api_key_env {
+        Some(name) => std::env::var(name)
+            .map(Some)
+            .map_err(|_| anyhow::anyhow!("{} (translation.api_key_env) isn't set", name)),
+        None => Ok(None),
+    }
+}
+
+pub fn from_config(config: &TranslationConfig) -> Result<Arc<dyn TranslationProvider>> {
+    Ok(match config {
+        TranslationConfig::Deepl {
+            url,
+            api_key: key,
+            api_key_env,
+        } => {
+            let key = api_key(key, api_key_env)?
+                .ok_or_else(|| anyhow::anyhow!("DeepL needs translation.api_key"))?;
+            // Free-plan keys end in ":fx"
+            let default_url = if key.ends_with(":fx") {
+                DEEPL_FREE_URL
+            } else {
+                DEEPL_PRO_URL
+            };
+            Arc::new(Deepl {
+                url: HttpUrl::parse(url.as_deref().unwrap_or(default_url))?,
+                api_key: key,
+            })
+        }
+        TranslationConfig::LibreTranslate {
+            url,
+            api_key: key,
+            api_key_env,
+        } => Arc::new(LibreTranslate {
+            url: HttpUrl::parse(url)?,
+            api_key: api_key(key, api_key_env)?,
+        }),
+        TranslationConfig::Openai {
+            url,
+            model,
+            api_key: key,
+            api_key_env,
+        } => Arc::new(OpenAi {
+            url: HttpUrl::parse(url.as_deref().unwrap_or(OPENAI_URL))?,
+            model: model
+                .clone()
+                .unwrap_or_else(|| DEFAULT_OPENAI_MODEL.to_string()),
+            api_key: api_key(key, api_key_env)?,
+        }),
+    })
+}
+
+pub struct Deepl {
+    url: HttpUrl,
+    api_key: String,
+}
+
+impl TranslationProvider for Deepl {
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        target: &'a str,
+        source: Option<&'a str>,
+    ) -> TranslateFuture<'a> {
+        Box::pin(async move {
+            let authorization = format!("DeepL-Auth-Key {}", self.api_key);
+            let mut body = serde_json::json!({
+                "text": [text],
+                "target_lang": target.to_uppercase(),
+            });
+            // DeepL takes source languages without a region
+            if let Some(source) = source {
+                let language = source.split('-').next().unwrap_or(source);
+                body["source_lang"] = language.to_uppercase().into();
+            }
+            let response = http_client::post_json(
+                &self.url,
+                &[("Authorization", authorization.as_str())],
+                &body,
+            )
+            .await?;
+            let translation = &response["translations"][0];
+            Ok(Translation {
+                text: translation["text"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Unexpected DeepL response: {}", response))?
+                    .to_string(),
+                detected_source_language: translation["detected_source_language"]
+                    .as_str()
+                    .map(str::to_lowercase),
+            })
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "deepl"
+    }
+}
+
+pub struct LibreTranslate {
+    url: HttpUrl,
+    api_key: Option<String>,
+}
+
+impl TranslationProvider for LibreTranslate {
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        target: &'a str,
+        source: Option<&'a str>,
+    ) -> TranslateFuture<'a> {
+        Box::pin(async move {
+            let mut body = serde_json::json!({
+                "q": text,
+                "source": source.unwrap_or("auto"),
+                "target": target,
+                "format": "text",
+            });
+            if let Some(key) = &self.api_key {
+                body["api_key"] = key.as_str().into();
+            }
+            let response = http_client::post_json(&self.url, &[], &body).await?;
+            Ok(Translation {
+                text: response["translatedText"]
+                    .as_str()
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Unexpected LibreTranslate response: {}", response)
+                    })?
+                    .to_string(),
+                detected_source_language: response["detectedLanguage"]["language"]
+                    .as_str()
+                    .map(str::to_lowercase),
+            })
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "libretranslate"
+    }
+}
+
+/// Translates by asking a chat model, which reports the source language
+/// alongside the translation as JSON.
+pub struct OpenAi {
+    url: HttpUrl,
+    model: String,
+    api_key: Option<String>,
+}
+
+impl TranslationProvider for OpenAi {
+    fn translate<'a>(
+        &'a self,
+        text: &'a str,
+        target: &'a str,
+        source: Option<&'a str>,
+    ) -> TranslateFuture<'a> {
+        Box::pin(async move {
+            let source = match source {
+                Some(source) => format!("from the language with code '{}' ", source),
+                None => String::new(),
+            };
+            let instructions = format!(
+                "Translate the user's text {}into the language with code '{}'. Keep formatting, placeholders and markup as they are. Reply with a JSON object: {{\"translation\": the translated text, \"source_language\": the ISO 639-1 code of the text's language}}.",
+                source, target
+            );
+            let body = serde_json::json!({
+                "model": self.model,
+                "messages": [
+                    { "role": "system", "content": instructions },
+                    { "role": "user", "content": text }
+                ],
+                "response_format": { "type": "json_object" },
+                "temperature": 0
+            });
+            let authorization = self.api_key.as_ref().map(|key| format!("Bearer {}", key));
+            let headers: Vec<(&str, &str)> = authorization
+                .iter()
+                .map(|value| ("Authorization", value.as_str()))
+                .collect();
+            let response = http_client::post_json(&self.url, &headers, &body).await?;
+
+            let content = response["choices"][0]["message"]["content"]
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("Unexpected chat response: {}", response))?;
+            let reply: serde_json::Value = serde_json::from_str(content)
+                .map_err(|_| anyhow::anyhow!("The model didn't reply with JSON: {}", content))?;
+            Ok(Translation {
+                text: reply["translation"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("The model's reply has no translation"))?
+                    .to_string(),
+                detected_source_language: reply["source_language"].as_str().map(str::to_lowercase),
+            })
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+}
+
+pub fn tool(provider: Arc<dyn TranslationProvider>) -> RegisteredTool {
+    let tool = Tool {
+        name: "translate".to_string(),
+        title: Some("Translate".to_string()),
+        description: format!(
+            "Translates text into another language with {}, reporting the language it was in",
+            provider.name()
+        ),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "text": {
+                    "type": "string",
+                    "description": "The text to translate"
+                },
+                "target_language": {
+                    "type": "string",
+                    "description": "ISO 639-1 code to translate into, e.g. \"de\" or \"pt-BR\""
+                },
+                "source_language": {
+                    "type": "string",
+                    "description": "ISO 639-1 code of the text's language (detected if omitted)"
+                }
+            },
+            "required": ["text", "target_language"]
+        }),
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(true),
+            open_world_hint: Some(true),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    };
+
+    RegisteredTool::new(tool, move |arguments, _| {
+        let provider = provider.clone();
+        async move {
+            let text = arguments
+                .get("text")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'text' argument"))?;
+            let target = arguments
+                .get("target_language")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'target_language' argument"))?;
+            let source = arguments.get("source_language").and_then(|v| v.as_str());
+            let chars = text.chars().count();
+            if chars > MAX_TEXT_CHARS {
+                return Err(ServerError::InvalidParams {
+                    details: format!(
+                        "The text is {} characters; at most {} can be translated at once",
+                        chars, MAX_TEXT_CHARS
+                    ),
+                }
+                .into());
+            }
+
+            let translation = provider.translate(text, target, source).await?;
+            let mut structured = serde_json::json!({
+                "translation": translation.text,
+                "targetLanguage": target,
+                "provider": provider.name()
+            });
+            if let Some(language) = &translation.detected_source_language {
+                structured["detectedSourceLanguage"] = language.as_str().into();
+            }
+            Ok(CallToolResult::text(translation.text).with_structured_content(structured))
+        }
+    })
+    .with_output_schema(serde_json::json!({
+        "type": "object",
+        "properties": {
+            "translation": { "type": "string" },
+            "targetLanguage": { "type": "string" },
+            "detectedSourceLanguage": { "type": "string" },
+            "provider": { "type": "string" }
+        },
+        "required": ["translation", "targetLanguage", "provider"]
+    }))
+}
@@ -0,0 +1,143 @@
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// A URI template like `repo://{branch}/{+path}`. `{name}` matches one or
+/// more characters other than `/`; `{+name}` may also span `/`. Matched
+/// values are percent-decoded.
+#[derive(Debug, Clone)]
+pub struct UriTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Variable { name: String, reserved: bool },
+}
+
+impl UriTemplate {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| anyhow::anyhow!("Unclosed '{{' in {}", template))?;
+            let expression = &rest[start + 1..end];
+            let (name, reserved) = match expression.strip_prefix('+') {
+                Some(name) => (name, true),
+                None => (expression, false),
+            };
+            if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                anyhow::bail!("Invalid variable '{}' in {}", expression, template);
+            }
+            parts.push(Part::Variable {
+                name: name.to_string(),
+                reserved,
+            });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+
+    // Extracts the variables if `uri` matches the template
+    pub fn matches(&self, uri: &str) -> Option<HashMap<String, String>> {
+        let mut raw = Vec::new();
+        if !match_parts(&self.parts, uri, &mut raw) {
+            return None;
+        }
+        raw.into_iter()
+            .map(|(name, value)| Some((name.to_string(), percent_decode(value)?)))
+            .collect()
+    }
+
+    // Ranks overlapping templates: more literal text is more specific, and
+    // between equals, fewer variables that can span `/`
+    pub fn specificity(&self) -> (usize, usize) {
+        let mut literal = 0;
+        let mut unreserved = 0;
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => literal += text.len(),
+                Part::Variable { reserved, .. } => {
+                    if !reserved {
+                        unreserved += 1;
+                    }
+                }
+            }
+        }
+        (literal, unreserved)
+    }
+}
+
+// Tries the shortest value for each variable first, backtracking as needed
+fn match_parts<'a>(
+    parts: &'a [Part],
+    uri: &'a str,
+    captures: &mut Vec<(&'a str, &'a str)>,
+) -> bool {
+    let Some((part, rest)) = parts.split_first() else {
+        return uri.is_empty();
+    };
+    match part {
+        Part::Literal(text) => uri
+            .strip_prefix(text.as_str())
+            .is_some_and(|remaining| match_parts(rest, remaining, captures)),
+        Part::Variable { name, reserved } => {
+            for (end, c) in uri.char_indices().skip(1).chain([(uri.len(), ' ')]) {
+                let value = &uri[..end];
+                if value.is_empty() || (!reserved && value.contains('/')) {
+                    break;
+                }
+                captures.push((name, value));
+                if match_parts(rest, &uri[end..], captures) {
+                    return true;
+                }
+                captures.pop();
+                if !reserved && c == '/' {
+                    break;
+                }
+            }
+            false
+        }
+    }
+}
+
+// Decodes `%XX` escapes; None if an escape is malformed
+pub fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+// Escapes everything in a path but unreserved characters and `/`, for
+// building `file://` URIs
+pub fn percent_encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
@@ -0,0 +1,87 @@
+use crate::compat::SUPPORTED_PROTOCOL_VERSIONS;
+use crate::error::ServerError;
+
+const ENVELOPE_FIELDS: &[&str] = &["jsonrpc", "id", "method", "params"];
+const RESPONSE_FIELDS: &[&str] = &["jsonrpc", "id", "result", "error"];
+
+// Params fields per method, for strict mode. Methods not listed here are
+// only checked at the envelope level.
+fn known_params(method: &str) -> Option<&'static [&'static str]> {
+    match method {
+        "initialize" => Some(&["protocolVersion", "capabilities", "clientInfo", "_meta"]),
+        "tools/call" => Some(&["name", "arguments", "_meta"]),
+        "resources/read" | "resources/subscribe" | "resources/unsubscribe" => {
+            Some(&["uri", "_meta"])
+        }
+        "resources/write" => Some(&["uri", "mimeType", "text", "blob", "_meta"]),
+        "prompts/get" => Some(&["name", "arguments", "_meta"]),
+        "logging/setLevel" => Some(&["level", "_meta"]),
+        "ping" | "tools/list" | "resources/list" | "resources/templates/list" | "prompts/list" => {
+            Some(&["_meta", "cursor"])
+        }
+        _ => None,
+    }
+}
+
+fn reject_unknown_fields(
+    object: &serde_json::Map<String, serde_json::Value>,
+    allowed: &[&str],
+    error: impl Fn(String) -> ServerError,
+) -> Result<(), ServerError> {
+    match object.keys().find(|key| !allowed.contains(&key.as_str())) {
+        Some(key) => Err(error(format!("Unknown field '{}'", key))),
+        None => Ok(()),
+    }
+}
+
+/// Strict-mode checks applied to a raw message before it is dispatched: the
+/// envelope must carry `"jsonrpc": "2.0"`, no unknown fields may appear in
+/// the envelope or in params of known methods, and `initialize` must ask for
+/// a protocol version we support. Responses from the client are held to the
+/// response envelope instead.
+pub fn validate_strict(message: &serde_json::Value) -> Result<(), ServerError> {
+    let invalid_request = |details: String| ServerError::InvalidRequest { details };
+    let invalid_params = |details: String| ServerError::InvalidParams { details };
+
+    let object = message
+        .as_object()
+        .ok_or_else(|| invalid_request("Message must be a JSON object".to_string()))?;
+
+    if object.get("jsonrpc").and_then(|v| v.as_str()) != Some("2.0") {
+        return Err(invalid_request(
+            "Field 'jsonrpc' must be \"2.0\"".to_string(),
+        ));
+    }
+    let is_response = !object.contains_key("method")
+        && (object.contains_key("result") || object.contains_key("error"));
+    if is_response {
+        return reject_unknown_fields(object, RESPONSE_FIELDS, invalid_request);
+    }
+    reject_unknown_fields(object, ENVELOPE_FIELDS, invalid_request)?;
+
+    let method = object.get("method").and_then(|v| v.as_str()).unwrap_or("");
+    let params = match object.get("params") {
+        Some(serde_json::Value::Object(params)) => params,
+        Some(serde_json::Value::Null) | None => return Ok(()),
+        Some(_) => return Err(invalid_params("Params must be an object".to_string())),
+    };
+
+    if let Some(allowed) = known_params(method) {
+        reject_unknown_fields(params, allowed, invalid_params)?;
+    }
+
+    if method == "initialize" {
+        let version = params
+            .get("protocolVersion")
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+        if !SUPPORTED_PROTOCOL_VERSIONS.contains(&version) {
+            return Err(invalid_params(format!(
+                "Unsupported protocol version '{}'",
+                version
+            )));
+        }
+    }
+
+    Ok(())
+}
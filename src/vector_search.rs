@@ -0,0 +1,528 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+use crate::http_client::{self, HttpUrl};
+use crate::tools::RegisteredTool;
+use crate::types::{CallToolResult, Tool, ToolAnnotations};
+
+pub const DEFAULT_DIMENSIONS: usize = 256;
+const DEFAULT_TOP_K: usize = 5;
+const MAX_TOP_K: usize = 50;
+// Texts sent to the backend in one call
+const EMBED_BATCH: usize = 64;
+
+pub type EmbedFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<Vec<f32>>>> + Send + 'a>>;
+
+/// Turns text into embedding vectors, one per input, all of the same
+/// length.
+pub trait EmbeddingBackend: Send + Sync {
+    fn embed<'a>(&'a self, texts: &'a [String]) -> EmbedFuture<'a>;
+
+    // Identifies the backend and model, so stored embeddings aren't mixed
+    // with ones computed differently
+    fn id(&self) -> String;
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BackendConfig {
+    Hashing {
+        #[serde(default = "default_dimensions")]
+        dimensions: usize,
+    },
+    Api {
+        // An OpenAI-style `/v1/embeddings` endpoint
+        url: String,
+        model: String,
+        // Environment variable holding a bearer token, if the API needs one
+        #[serde(default)]
+        api_key_env: Option<String>,
+    },
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        BackendConfig::Hashing {
+            dimensions: DEFAULT_DIMENSIONS,
+        }
+    }
+}
+
+fn default_dimensions() -> usize {
+    DEFAULT_DIMENSIONS
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct VectorSearchConfig {
+    pub backend: BackendConfig,
+    // JSON file the embeddings are kept in between runs; in memory only if
+    // unset
+    pub store_path: Option<PathBuf>,
+    // Files, or directories of `.md`/`.txt` files, ingested at startup
+    pub documents: Vec<PathBuf>,
+}
+
+pub fn from_config(config: &BackendConfig) -> Result<Arc<dyn EmbeddingBackend>> {
+    Ok(match config {
+        BackendConfig::Hashing { dimensions } => {
+            if *dimensions == 0 {
+                anyhow::bail!("vector_search.backend.dimensions must be at least 1");
+            }
+            Arc::new(Hashing {
+                dimensions: *dimensions,
+            })
+        }
+        BackendConfig::Api {
+            url,
+            model,
+            api_key_env,
+        } => {
+            let api_key = match api_key_env {
+                Some(name) => Some(std::env::var(name).map_err(|_| {
+                    anyhow::anyhow!("{} (vector_search.backend.api_key_env) isn't set", name)
+                })?),
+                None => None,
+            };
+            Arc::new(Api {
+                url: HttpUrl::parse(url)?,
+                model: model.clone(),
+                api_key,
+            })
+        }
+    })
+}
+
+/// A local backend needing no model: words and their character trigrams
+/// are hashed into a fixed number of dimensions. It matches shared
+/// vocabulary and word forms rather than meaning, which makes it a
+/// dependency-free default for tests and offline use.
+pub struct Hashing {
+    dimensions: usize,
+}
+
+impl Hashing {
+    fn embed_one(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; self.dimensions];
+        let lowered = text.to_lowercase();
+        for word in lowered
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+        {
+            self.add_feature(&mut vector, word, 1.0);
+            let padded: Vec<char> = format!("<{}>", word).chars().collect();
+            for trigram in padded.windows(3) {
+                self.add_feature(&mut vector, &trigram.iter().collect::<String>(), 0.5);
+            }
+        }
+        normalize(&mut vector);
+        vector
+    }
+
+    fn add_feature(&self, vector: &mut [f32], feature: &str, weight: f32) {
+        let hash = fnv1a(feature);
+        let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        vector[(hash % self.dimensions as u64) as usize] += sign * weight;
+    }
+}
+
+impl EmbeddingBackend for Hashing {
+    fn embed<'a>(&'a self, texts: &'a [String]) -> EmbedFuture<'a> {
+        Box::pin(async move { Ok(texts.iter().map(|text| self.embed_one(text)).collect()) })
+    }
+
+    fn id(&self) -> String {
+        format!("hashing/{}", self.dimensions)
+    }
+}
+
+/// An OpenAI-compatible embeddings API, such as a local Ollama or
+/// llama.cpp server. Hosted APIs need a plain-HTTP proxy in front.
+pub struct Api {
+    url: HttpUrl,
+    model: String,
+    api_key: Option<String>,
+}
+
+impl EmbeddingBackend for Api {
+    fn embed<'a>(&'a self, texts: &'a [String]) -> EmbedFuture<'a> {
+        Box::pin(async move {
+            let authorization = self.api_key.as_ref().map(|key| format!("Bearer {}", key));
+            let headers: Vec<(&str, &str)> = authorization
+                .iter()
+                .map(|value| ("Authorization", value.as_str()))
+                .collect();
+            let body = serde_json::json!({ "model": self.model, "input": texts });
+            let response = http_client::post_json(&self.url, &headers, &body).await?;
+
+            let mut data: Vec<ApiEmbedding> = serde_json::from_value(response["data"].clone())
+                .map_err(|e| anyhow::anyhow!("Unexpected embeddings response: {}", e))?;
+            if data.len() != texts.len() {
+                anyhow::bail!(
+                    "Asked for {} embeddings but got {}",
+                    texts.len(),
+                    data.len()
+                );
+            }
+            data.sort_by_key(|embedding| embedding.index);
+            Ok(data
+                .into_iter()
+                .map(|embedding| {
+                    let mut vector = embedding.embedding;
+                    normalize(&mut vector);
+                    vector
+                })
+                .collect())
+        })
+    }
+
+    fn id(&self) -> String {
+        format!("api/{}", self.model)
+    }
+}
+
+#[derive(Deserialize)]
+struct ApiEmbedding {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    id: String,
+    text: String,
+    embedding: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct StoreFile {
+    backend: String,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub id: String,
+    // Cosine similarity, from -1.0 to 1.0
+    pub score: f32,
+    pub text: String,
+}
+
+/// Documents with their embeddings, searched by cosine similarity.
+pub struct VectorStore {
+    backend: Arc<dyn EmbeddingBackend>,
+    entries: Mutex<Vec<Entry>>,
+    path: Option<PathBuf>,
+}
+
+impl VectorStore {
+    // A store embedding with `backend`, loaded from `path` if it exists.
+    // Embeddings another backend made are dropped.
+    pub fn new(backend: Arc<dyn EmbeddingBackend>, path: Option<PathBuf>) -> Result<Self> {
+        let mut entries = Vec::new();
+        if let Some(path) = path.as_ref().filter(|path| path.exists()) {
+            let stored: StoreFile = serde_json::from_str(&std::fs::read_to_string(path)?)
+                .map_err(|e| anyhow::anyhow!("Failed to load {}: {}", path.display(), e))?;
+            if stored.backend == backend.id() {
+                entries = stored.entries;
+            } else {
+                warn!(
+                    "{} holds embeddings from {}, not {}; re-embedding",
+                    path.display(),
+                    stored.backend,
+                    backend.id()
+                );
+            }
+        }
+        Ok(Self {
+            backend,
+            entries: Mutex::new(entries),
+            path,
+        })
+    }
+
+    // The store `config` describes, with its documents ingested
+    pub async fn open(config: &VectorSearchConfig) -> Result<Self> {
+        let store = Self::new(from_config(&config.backend)?, config.store_path.clone())?;
+
+        let mut documents = Vec::new();
+        for path in &config.documents {
+            collect_documents(path, &mut documents)?;
+        }
+        let added = store.ingest(documents).await?;
+        info!(
+            "Vector store has {} documents ({} embedded at startup)",
+            store.entries.lock().unwrap().len(),
+            added
+        );
+        Ok(store)
+    }
+
+    // Adds or replaces documents by id and returns how many needed new
+    // embeddings. Unchanged documents keep the ones they have.
+    pub async fn ingest(&self, documents: Vec<(String, String)>) -> Result<usize> {
+        let changed: Vec<(String, String)> = {
+            let entries = self.entries.lock().unwrap();
+            let stored: HashMap<&str, &str> = entries
+                .iter()
+                .map(|entry| (entry.id.as_str(), entry.text.as_str()))
+                .collect();
+            documents
+                .into_iter()
+                .filter(|(id, text)| stored.get(id.as_str()) != Some(&text.as_str()))
+                .collect()
+        };
+        if changed.is_empty() {
+            return Ok(0);
+        }
+
+        let mut embedded = Vec::with_capacity(changed.len());
+        for batch in changed.chunks(EMBED_BATCH) {
+            let texts: Vec<String> = batch.iter().map(|(_, text)| text.clone()).collect();
+            let embeddings = self.backend.embed(&texts).await?;
+            for ((id, text), embedding) in batch.iter().zip(embeddings) {
+                embedded.push(Entry {
+                    id: id.clone(),
+                    text: text.clone(),
+                    embedding,
+                });
+            }
+        }
+        {
+            let ids: HashSet<&str> = changed.iter().map(|(id, _)| id.as_str()).collect();
+            let mut entries = self.entries.lock().unwrap();
+            entries.retain(|entry| !ids.contains(entry.id.as_str()));
+            entries.extend(embedded);
+        }
+        self.save()?;
+        Ok(changed.len())
+    }
+
+    // Drops every document whose id `keep` rejects
+    pub fn retain(&self, keep: impl Fn(&str) -> bool) -> Result<()> {
+        let removed = {
+            let mut entries = self.entries.lock().unwrap();
+            let before = entries.len();
+            entries.retain(|entry| keep(&entry.id));
+            before - entries.len()
+        };
+        if removed > 0 {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    // The `top_k` documents closest to `query`, best first
+    pub async fn search(&self, query: &str, top_k: usize) -> Result<Vec<SearchResult>> {
+        let query = self
+            .backend
+            .embed(&[query.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("The embedding backend returned nothing"))?;
+        let entries = self.entries.lock().unwrap();
+        let mut results: Vec<SearchResult> = entries
+            .iter()
+            .filter(|entry| entry.embedding.len() == query.len())
+            .map(|entry| SearchResult {
+                id: entry.id.clone(),
+                score: dot(&entry.embedding, &query),
+                text: entry.text.clone(),
+            })
+            .collect();
+        results.sort_by(|a, b| b.score.total_cmp(&a.score));
+        results.truncate(top_k);
+        Ok(results)
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_string(&serde_json::json!({
+            "backend": self.backend.id(),
+            "entries": &*self.entries.lock().unwrap(),
+        }))?;
+        std::fs::write(path, json)
+            .map_err(|e| anyhow::anyhow!("Failed to save {}: {}", path.display(), e))
+    }
+
+    // `vector_search`, and `vector_add` for ingesting documents at runtime
+    pub fn tools(self: &Arc<Self>) -> Vec<RegisteredTool> {
+        let store = self.clone();
+        let search = RegisteredTool::new(search_tool(), move |arguments, _| {
+            let store = store.clone();
+            async move {
+                let query = arguments
+                    .get("query")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing 'query' argument"))?;
+                let top_k = arguments
+                    .get("top_k")
+                    .and_then(|v| v.as_u64())
+                    .map_or(DEFAULT_TOP_K, |top_k| top_k as usize)
+                    .clamp(1, MAX_TOP_K);
+
+                let results = store.search(query, top_k).await?;
+                let text = if results.is_empty() {
+                    "The vector store is empty".to_string()
+                } else {
+                    results
+                        .iter()
+                        .map(|result| {
+                            format!("{} ({:.3})\n{}", result.id, result.score, result.text)
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n\n")
+                };
+                Ok(CallToolResult::text(text)
+                    .with_structured_content(serde_json::json!({ "results": results })))
+            }
+        })
+        .with_output_schema(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "results": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "id": { "type": "string" },
+                            "score": { "type": "number" },
+                            "text": { "type": "string" }
+                        },
+                        "required": ["id", "score", "text"]
+                    }
+                }
+            },
+            "required": ["results"]
+        }));
+
+        let store = self.clone();
+        let add = RegisteredTool::new(add_tool(), move |arguments, _| {
+            let store = store.clone();
+            async move {
+                let id = arguments
+                    .get("id")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing 'id' argument"))?;
+                let text = arguments
+                    .get("text")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing 'text' argument"))?;
+                store
+                    .ingest(vec![(id.to_string(), text.to_string())])
+                    .await?;
+                Ok(CallToolResult::text(format!("Stored {}", id)))
+            }
+        });
+
+        vec![search, add]
+    }
+}
+
+// Every `.md`/`.txt` file under `path`, or `path` itself if it's a file,
+// as (path, text)
+fn collect_documents(path: &Path, documents: &mut Vec<(String, String)>) -> Result<()> {
+    if path.is_file() {
+        documents.push((path.display().to_string(), std::fs::read_to_string(path)?));
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(path)
+        .map_err(|e| anyhow::anyhow!("Can't read {}: {}", path.display(), e))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<_>>()?;
+    entries.sort();
+    for entry in entries {
+        let is_document = entry
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext == "md" || ext == "txt");
+        if entry.is_dir() || is_document {
+            collect_documents(&entry, documents)?;
+        }
+    }
+    Ok(())
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+// Cosine similarity, since stored and query vectors are normalized
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+// FNV-1a, which unlike the std hasher is stable across Rust versions, so
+// stored hashing embeddings stay valid
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn search_tool() -> Tool {
+    Tool {
+        name: "vector_search".to_string(),
+        title: Some("Semantic Search".to_string()),
+        description: "Finds the stored documents closest in meaning to a query, ranked by cosine similarity of their embeddings".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "What to look for"
+                },
+                "top_k": {
+                    "type": "integer",
+                    "description": "How many results to return (default 5, at most 50)"
+                }
+            },
+            "required": ["query"]
+        }),
+        output_schema: None,
+        annotations: Some(ToolAnnotations {
+            read_only_hint: Some(true),
+            ..Default::default()
+        }),
+        icons: Vec::new(),
+        meta: None,
+    }
+}
+
+fn add_tool() -> Tool {
+    Tool {
+        name: "vector_add".to_string(),
+        title: Some("Add to Vector Store".to_string()),
+        description: "Embeds a document and stores it for vector_search, replacing any document with the same id".to_string(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "id": {
+                    "type": "string",
+                    "description": "Document id, e.g. a path or URI"
+                },
+                "text": {
+                    "type": "string",
+                    "description": "Document text"
+                }
+            },
+            "required": ["id", "text"]
+        }),
+        output_schema: None,
+        annotations: None,
+        icons: Vec::new(),
+        meta: None,
+    }
+}
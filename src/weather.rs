@@ -0,0 +1,597 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::ServerError;
+use crate::http_client::{self, HttpUrl};
+use crate::tools::RegisteredTool;
+use crate::types::{CallToolResult, Tool, ToolAnnotations};
+use crate::uri_template::percent_encode_path;
+
+const DEFAULT_URL: &str = "https://api.openweathermap.org";
+// Place coordinates don't change, so lookups are kept for a day
+const GEOCODE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+// Expired responses are only swept once this many are cached
+const CACHE_SWEEP_THRESHOLD: usize = 256;
+const MAX_PLACES: u64 = 10;
+// The forecast comes in 3-hour steps, up to 5 days ahead
+const FORECAST_STEP_HOURS: u64 = 3;
+const MAX_FORECAST_HOURS: u64 = 120;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+impl Units {
+    fn name(self) -> &'static str {
+        match self {
+            Units::Metric => "metric",
+            Units::Imperial => "imperial",
+        }
+    }
+
+    // The units each measurement is reported in
+    fn describe(self) -> Value {
+        let (temperature, wind_speed) = match self {
+            Units::Metric => ("°C", "m/s"),
+            Units::Imperial => ("°F", "mph"),
+        };
+        serde_json::json!({
+            "temperature": temperature,
+            "windSpeed": wind_speed,
+            "windDirection": "degrees from north",
+            "humidity": "%",
+            "pressure": "hPa",
+            "precipitationProbability": "%"
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WeatherConfig {
+    // An OpenWeather-compatible API; defaults to OpenWeather itself
+    pub url: String,
+    pub api_key: Option<String>,
+    pub api_key_env: Option<String>,
+    // Default units; callers can ask for the other system
+    pub units: Units,
+    // How long weather responses are reused
+    pub cache_secs: u64,
+    // Calls made to the API per minute, across all sessions; OpenWeather's
+    // free plan allows 60
+    pub requests_per_minute: u32,
+}
+
+impl Default for WeatherConfig {
+    fn default() -> Self {
+        Self {
+            url: DEFAULT_URL.to_string(),
+            api_key: None,
+            api_key_env: None,
+            units: Units::Metric,
+            cache_secs: 600,
+            requests_per_minute: 60,
+        }
+    }
+}
+
+struct Window {
+    started: Instant,
+    used: u32,
+}
+
+/// Geocoding and weather from an OpenWeather-style API. Responses are
+/// cached and calls are held to `requests_per_minute`, so agents asking
+/// the same thing repeatedly don't run through the API quota.
+pub struct WeatherClient {
+    base: HttpUrl,
+    api_key: String,
+    units: Units,
+    cache_ttl: Duration,
+    // Request path and query, without the key -> when fetched, response
+    cache: Mutex<HashMap<String, (Instant, Value)>>,
+    requests_per_minute: u32,
+    window: Mutex<Window>,
+}
+
+impl WeatherClient {
+    pub fn new(config: &WeatherConfig) -> Result<Self> {
+        let api_key = match (&config.api_key, &config.api_key_env) {
+            (Some(key), _) => key.clone(),
+            (None, Some(name)) => std::env::var(name)
+                .map_err(|_| anyhow::anyhow!("{} (weather.api_key_env) isn't set", name))?,
+            (None, None) => anyhow::bail!("The weather tools need weather.api_key"),
+        };
+        Ok(Self {
+            base: HttpUrl::parse(&config.url)?,
+            api_key,
+            units: config.units,
+            cache_ttl: Duration::from_secs(config.cache_secs),
+            cache: Mutex::new(HashMap::new()),
+            requests_per_minute: config.requests_per_minute.max(1),
+            window: Mutex::new(Window {
+                started: Instant::now(),
+                used: 0,
+            }),
+        })
+    }
+
+    // Counts a call against the per-minute quota, unless it's used up
+    fn take_quota(&self) -> Result<(), ServerError> {
+        let now = Instant::now();
+        let mut window = self.window.lock().unwrap();
+        let ends = window.started + Duration::from_secs(60);
+        if now >= ends {
+            window.started = now;
+            window.used = 0;
+        } else if window.used >= self.requests_per_minute {
+            return Err(ServerError::RateLimited {
+                limit: "weather_api".to_string(),
+                max: self.requests_per_minute,
+                remaining: 0,
+                retry_after_ms: (ends - now).as_millis() as u64,
+            });
+        }
+        window.used += 1;
+        Ok(())
+    }
+
+    async fn get(&self, path: &str, query: &[(&str, String)], ttl: Duration) -> Result<Value> {
+        let query: Vec<String> = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, percent_encode_path(value)))
+            .collect();
+        let request = format!("{}?{}", path, query.join("&"));
+        if let Some((fetched, response)) = self.cache.lock().unwrap().get(&request) {
+            if fetched.elapsed() < ttl {
+                return Ok(response.clone());
+            }
+        }
+
+        self.take_quota()?;
+        let url = self.base.join(&format!(
+            "{}&appid={}",
+            request,
+            percent_encode_path(&self.api_key)
+        ));
+        let response = http_client::get_json(&url, &[]).await?;
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= CACHE_SWEEP_THRESHOLD {
+            let longest = ttl.max(GEOCODE_TTL);
+            cache.retain(|_, (fetched, _)| fetched.elapsed() < longest);
+        }
+        cache.insert(request, (Instant::now(), response.clone()));
+        Ok(response)
+    }
+
+    async fn geocode(&self, place: &str, limit: u64) -> Result<Vec<Value>> {
+        let response = self
+            .get(
+                "/geo/1.0/direct",
+                &[("q", place.to_string()), ("limit", limit.to_string())],
+                GEOCODE_TTL,
+            )
+            .await?;
+        let places = response
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("Unexpected geocoding response: {}", response))?;
+        Ok(places
+            .iter()
+            .map(|place| {
+                let mut entry = serde_json::json!({
+                    "name": place["name"],
+                    "country": place["country"],
+                    "latitude": place["lat"],
+                    "longitude": place["lon"]
+                });
+                if let Some(state) = place["state"].as_str() {
+                    entry["state"] = state.into();
+                }
+                entry
+            })
+            .collect())
+    }
+
+    // The coordinates and a display name for `location` or
+    // `latitude`/`longitude`
+    async fn locate(&self, arguments: &HashMap<String, Value>) -> Result<(f64, f64, String)> {
+        let latitude = arguments.get("latitude").and_then(|v| v.as_f64());
+        let longitude = arguments.get("longitude").and_then(|v| v.as_f64());
+        match (
+            arguments.get("location").and_then(|v| v.as_str()),
+            latitude,
+            longitude,
+        ) {
+            (Some(location), None, None) => {
+                let place = self
+                    .geocode(location, 1)
+                    .await?
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| invalid(format!("No place called '{}' was found", location)))?;
+                Ok((
+                    place["latitude"].as_f64().unwrap_or_default(),
+                    place["longitude"].as_f64().unwrap_or_default(),
+                    place_name(&place),
+                ))
+            }
+            (None, Some(latitude), Some(longitude)) => {
+                if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+                    return Err(invalid(
+                        "latitude must be from -90 to 90 and longitude from -180 to 180"
+                            .to_string(),
+                    ));
+                }
+                Ok((
+                    latitude,
+                    longitude,
+                    format!("{:.4}, {:.4}", latitude, longitude),
+                ))
+            }
+            _ => Err(invalid(
+                "Give either location or both latitude and longitude".to_string(),
+            )),
+        }
+    }
+
+    fn units(&self, arguments: &HashMap<String, Value>) -> Result<Units> {
+        match arguments.get("units") {
+            None | Some(Value::Null) => Ok(self.units),
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|_| invalid("units must be metric or imperial".to_string())),
+        }
+    }
+
+    pub fn tools(self: &Arc<Self>) -> Vec<RegisteredTool> {
+        vec![
+            self.geocode_tool(),
+            self.current_tool(),
+            self.forecast_tool(),
+        ]
+    }
+
+    fn geocode_tool(self: &Arc<Self>) -> RegisteredTool {
+        let tool = Tool {
+            name: "geocode".to_string(),
+            title: Some("Geocode".to_string()),
+            description: "Finds places by name and returns their coordinates".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "A place name, optionally with state and country code, e.g. \"Portland, OR, US\""
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": MAX_PLACES,
+                        "description": "Most places to return (default 5)"
+                    }
+                },
+                "required": ["query"]
+            }),
+            output_schema: None,
+            annotations: Some(read_only()),
+            icons: Vec::new(),
+            meta: None,
+        };
+
+        let client = self.clone();
+        RegisteredTool::new(tool, move |arguments, _| {
+            let client = client.clone();
+            async move {
+                let query = arguments
+                    .get("query")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing 'query' argument"))?;
+                let limit = arguments
+                    .get("limit")
+                    .and_then(|v| v.as_u64())
+                    .map_or(5, |limit| limit.clamp(1, MAX_PLACES));
+                let places = client.geocode(query, limit).await?;
+                let text = if places.is_empty() {
+                    format!("No places called '{}' were found", query)
+                } else {
+                    places
+                        .iter()
+                        .map(|place| {
+                            format!(
+                                "{} ({}, {})",
+                                place_name(place),
+                                place["latitude"],
+                                place["longitude"]
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                };
+                Ok(CallToolResult::text(text)
+                    .with_structured_content(serde_json::json!({ "places": places })))
+            }
+        })
+        .with_output_schema(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "places": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "name": { "type": "string" },
+                            "state": { "type": "string" },
+                            "country": { "type": "string", "description": "ISO 3166 country code" },
+                            "latitude": { "type": "number", "description": "Degrees north" },
+                            "longitude": { "type": "number", "description": "Degrees east" }
+                        },
+                        "required": ["name", "latitude", "longitude"]
+                    }
+                }
+            },
+            "required": ["places"]
+        }))
+    }
+
+    fn current_tool(self: &Arc<Self>) -> RegisteredTool {
+        let tool = Tool {
+            name: "current_weather".to_string(),
+            title: Some("Current Weather".to_string()),
+            description: "Current weather for a place name or coordinates".to_string(),
+            input_schema: location_schema(serde_json::json!({})),
+            output_schema: None,
+            annotations: Some(read_only()),
+            icons: Vec::new(),
+            meta: None,
+        };
+
+        let client = self.clone();
+        RegisteredTool::new(tool, move |arguments, _| {
+            let client = client.clone();
+            async move {
+                let units = client.units(&arguments)?;
+                let (latitude, longitude, name) = client.locate(&arguments).await?;
+                let response = client
+                    .get(
+                        "/data/2.5/weather",
+                        &coordinates_query(latitude, longitude, units),
+                        client.cache_ttl,
+                    )
+                    .await?;
+                let conditions = conditions(&response);
+                let labels = units.describe();
+                let text = format!(
+                    "{}: {}, {}{} (feels like {}{}), wind {} {}, humidity {}%",
+                    name,
+                    conditions["description"].as_str().unwrap_or("unknown"),
+                    conditions["temperature"],
+                    labels["temperature"].as_str().unwrap_or_default(),
+                    conditions["feelsLike"],
+                    labels["temperature"].as_str().unwrap_or_default(),
+                    conditions["windSpeed"],
+                    labels["windSpeed"].as_str().unwrap_or_default(),
+                    conditions["humidity"]
+                );
+                Ok(
+                    CallToolResult::text(text).with_structured_content(serde_json::json!({
+                        "location": name,
+                        "latitude": latitude,
+                        "longitude": longitude,
+                        "current": conditions,
+                        "units": labels
+                    })),
+                )
+            }
+        })
+        .with_output_schema(report_schema(
+            "current",
+            serde_json::json!(conditions_schema()),
+        ))
+    }
+
+    fn forecast_tool(self: &Arc<Self>) -> RegisteredTool {
+        let tool = Tool {
+            name: "weather_forecast".to_string(),
+            title: Some("Weather Forecast".to_string()),
+            description: "Weather forecast in 3-hour steps, up to 5 days ahead, for a place name or coordinates".to_string(),
+            input_schema: location_schema(serde_json::json!({
+                "hours": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": MAX_FORECAST_HOURS,
+                    "description": "How far ahead to forecast (default 24)"
+                }
+            })),
+            output_schema: None,
+            annotations: Some(read_only()),
+            icons: Vec::new(),
+            meta: None,
+        };
+
+        let client = self.clone();
+        RegisteredTool::new(tool, move |arguments, _| {
+            let client = client.clone();
+            async move {
+                let units = client.units(&arguments)?;
+                let hours = arguments
+                    .get("hours")
+                    .and_then(|v| v.as_u64())
+                    .map_or(24, |hours| hours.clamp(1, MAX_FORECAST_HOURS));
+                let (latitude, longitude, name) = client.locate(&arguments).await?;
+                let mut query = coordinates_query(latitude, longitude, units);
+                query.push(("cnt", hours.div_ceil(FORECAST_STEP_HOURS).to_string()));
+                let response = client
+                    .get("/data/2.5/forecast", &query, client.cache_ttl)
+                    .await?;
+                let steps: Vec<Value> = response["list"]
+                    .as_array()
+                    .ok_or_else(|| anyhow::anyhow!("Unexpected forecast response: {}", response))?
+                    .iter()
+                    .map(conditions)
+                    .collect();
+                let labels = units.describe();
+                let mut lines = vec![format!("{}:", name)];
+                lines.extend(steps.iter().map(|step| {
+                    format!(
+                        "{}  {}{}, {}, {}% chance of precipitation",
+                        step["time"].as_str().unwrap_or_default(),
+                        step["temperature"],
+                        labels["temperature"].as_str().unwrap_or_default(),
+                        step["description"].as_str().unwrap_or("unknown"),
+                        step["precipitationProbability"]
+                    )
+                }));
+                Ok(
+                    CallToolResult::text(lines.join("\n")).with_structured_content(
+                        serde_json::json!({
+                            "location": name,
+                            "latitude": latitude,
+                            "longitude": longitude,
+                            "forecast": steps,
+                            "units": labels
+                        }),
+                    ),
+                )
+            }
+        })
+        .with_output_schema(report_schema(
+            "forecast",
+            serde_json::json!({ "type": "array", "items": conditions_schema() }),
+        ))
+    }
+}
+
+fn invalid(details: String) -> anyhow::Error {
+    ServerError::InvalidParams { details }.into()
+}
+
+fn read_only() -> ToolAnnotations {
+    ToolAnnotations {
+        read_only_hint: Some(true),
+        open_world_hint: Some(true),
+        ..Default::default()
+    }
+}
+
+fn place_name(place: &Value) -> String {
+    [&place["name"], &place["state"], &place["country"]]
+        .iter()
+        .filter_map(|part| part.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn coordinates_query(latitude: f64, longitude: f64, units: Units) -> Vec<(&'static str, String)> {
+    vec![
+        ("lat", format!("{:.4}", latitude)),
+        ("lon", format!("{:.4}", longitude)),
+        ("units", units.name().to_string()),
+    ]
+}
+
+// The measurements in a current-weather or forecast entry
+fn conditions(entry: &Value) -> Value {
+    let mut conditions = serde_json::json!({
+        "time": entry["dt"].as_i64().map(utc_timestamp),
+        "description": entry["weather"][0]["description"],
+        "temperature": entry["main"]["temp"],
+        "feelsLike": entry["main"]["feels_like"],
+        "humidity": entry["main"]["humidity"],
+        "pressure": entry["main"]["pressure"],
+        "windSpeed": entry["wind"]["speed"],
+        "windDirection": entry["wind"]["deg"]
+    });
+    // Only forecasts give a chance of precipitation, as 0 to 1
+    if let Some(pop) = entry["pop"].as_f64() {
+        conditions["precipitationProbability"] = ((pop * 100.0).round() as i64).into();
+    }
+    conditions
+}
+
+fn conditions_schema() -> Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "time": { "type": "string", "description": "When the measurement or forecast is for, in UTC (ISO 8601)" },
+            "description": { "type": "string" },
+            "temperature": { "type": "number", "description": "In units.temperature" },
+            "feelsLike": { "type": "number", "description": "In units.temperature" },
+            "humidity": { "type": "number", "description": "Relative humidity in %" },
+            "pressure": { "type": "number", "description": "Sea-level pressure in hPa" },
+            "windSpeed": { "type": "number", "description": "In units.windSpeed" },
+            "windDirection": { "type": "number", "description": "Degrees from north the wind blows from" },
+            "precipitationProbability": { "type": "integer", "description": "Chance of precipitation in %; forecasts only" }
+        }
+    })
+}
+
+fn location_schema(extra: Value) -> Value {
+    let mut properties = serde_json::json!({
+        "location": {
+            "type": "string",
+            "description": "Place name, e.g. \"Oslo, NO\"; the best match is used"
+        },
+        "latitude": { "type": "number", "description": "Degrees north, with longitude instead of location" },
+        "longitude": { "type": "number", "description": "Degrees east" },
+        "units": {
+            "type": "string",
+            "enum": ["metric", "imperial"],
+            "description": "metric: °C and m/s; imperial: °F and mph"
+        }
+    });
+    if let (Some(properties), Some(extra)) = (properties.as_object_mut(), extra.as_object()) {
+        properties.extend(extra.clone());
+    }
+    serde_json::json!({ "type": "object", "properties": properties })
+}
+
+fn report_schema(field: &str, schema: Value) -> Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "location": { "type": "string" },
+            "latitude": { "type": "number" },
+            "longitude": { "type": "number" },
+            field: schema,
+            "units": {
+                "type": "object",
+                "description": "The unit of each measurement",
+                "additionalProperties": { "type": "string" }
+            }
+        },
+        "required": ["location", "latitude", "longitude", field, "units"]
+    })
+}
+
+// Seconds since the Unix epoch as `YYYY-MM-DDTHH:MM:SSZ`
+fn utc_timestamp(secs: i64) -> String {
+    let days = secs.div_euclid(86_400);
+    let time = secs.rem_euclid(86_400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}